@@ -0,0 +1,179 @@
+//! Building connectors, handshakes, bulk throughput and small writes over
+//! in-memory streams and loopback TCP.
+//!
+//! Run with `cargo bench --features test-utils`, passing a name to run only
+//! the benchmarks containing it, e.g. `-- bulk`. criterion compares each run
+//! with the previous one on the same machine.
+
+use async_std::net::{TcpListener, TcpStream};
+use async_tls::test_utils::{self, DuplexStream};
+use async_tls::{client, server, HandshakeFlush, TlsAcceptor, TlsConnector, WriteChunking};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures_executor::block_on;
+use futures_util::future::join;
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use rustls::{OwnedTrustAnchor, RootCertStore};
+use std::io;
+
+/// The bytes moved by each bulk transfer.
+const BULK: usize = 8 << 20;
+
+type Pair = (
+    client::TlsStream<DuplexStream>,
+    server::TlsStream<DuplexStream>,
+);
+
+/// A client and server connected over loopback TCP, wrapped by `wrap`.
+fn tcp_pair<T, F>(
+    connector: &TlsConnector,
+    acceptor: &TlsAcceptor,
+    wrap: F,
+) -> (client::TlsStream<T>, server::TlsStream<T>)
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    F: Fn(TcpStream) -> T,
+{
+    block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?);
+        let (client, (server, _)) = futures_util::try_join!(client, listener.accept())?;
+        futures_util::try_join!(
+            connector.connect("localhost", wrap(client)),
+            acceptor.accept(wrap(server))
+        )
+    })
+    .unwrap()
+}
+
+/// The WebPKI roots as rustls takes them, converted like the connector does.
+fn webpki_roots_store() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    roots
+}
+
+fn connect(connector: &TlsConnector, acceptor: &TlsAcceptor) -> Pair {
+    block_on(test_utils::connect(connector, acceptor, "localhost")).unwrap()
+}
+
+/// Write `total` bytes in `chunk` sized writes from the client, and read
+/// them on the server.
+fn transfer<C, S>((client, server): &mut (C, S), total: usize, chunk: usize)
+where
+    C: AsyncWrite + Unpin,
+    S: AsyncRead + Unpin,
+{
+    let data = vec![0x5a; chunk];
+    let write = async {
+        for _ in 0..total / chunk {
+            client.write_all(&data).await?;
+        }
+        client.flush().await
+    };
+    let read = async {
+        let mut buf = vec![0; 64 * 1024];
+        let mut left = total;
+        while left > 0 {
+            let len = buf.len().min(left);
+            left -= server.read(&mut buf[..len]).await?;
+        }
+        Ok(()) as io::Result<()>
+    };
+    let (written, read) = block_on(join(write, read));
+    written.and(read).unwrap();
+}
+
+/// Send `len` bytes from the client and echo them back, flushing each time.
+fn round_trip((client, server): &mut Pair, len: usize) {
+    let data = vec![0x5a; len];
+    let mut buf = vec![0; len];
+    block_on(async {
+        client.write_all(&data).await?;
+        client.flush().await?;
+        server.read_exact(&mut buf).await?;
+        server.write_all(&buf).await?;
+        server.flush().await?;
+        client.read_exact(&mut buf).await
+    })
+    .unwrap();
+}
+
+/// Converting the WebPKI roots, which `TlsConnector::new` does only once.
+fn roots(c: &mut Criterion) {
+    let mut group = c.benchmark_group("roots");
+    group.bench_function("convert", |b| b.iter(webpki_roots_store));
+    group.bench_function("new-connector", |b| b.iter(TlsConnector::new));
+    group.bench_function("extra-roots", |b| {
+        b.iter(|| TlsConnector::with_extra_roots(&[]).unwrap())
+    });
+    group.finish();
+}
+
+fn memory(c: &mut Criterion) {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    c.bench_function("handshake", |b| b.iter(|| connect(&connector, &acceptor)));
+
+    let mut pair = connect(&connector, &acceptor);
+    let mut group = c.benchmark_group("bulk");
+    group.throughput(Throughput::Bytes(BULK as u64));
+    group.sample_size(20);
+    for &(name, chunk) in &[
+        ("64KiB-writes", 64 * 1024),
+        ("16KiB-writes", 16 * 1024),
+        ("1KiB-writes", 1024),
+    ] {
+        group.bench_function(name, |b| b.iter(|| transfer(&mut pair, BULK, chunk)));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("round-trip");
+    for &(name, len) in &[("32B", 32), ("4KiB", 4096)] {
+        group.bench_function(name, |b| b.iter(|| round_trip(&mut pair, len)));
+    }
+    group.finish();
+}
+
+fn tcp(c: &mut Criterion) {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+
+    // real sockets, where each write to the IO is a system call
+    let mut group = c.benchmark_group("tcp/bulk");
+    group.throughput(Throughput::Bytes(BULK as u64));
+    group.sample_size(20);
+    let mut tcp = tcp_pair(&connector, &acceptor, |stream| stream);
+    group.bench_function("64KiB-writes", |b| {
+        b.iter(|| transfer(&mut tcp, BULK, 64 * 1024))
+    });
+    let chunked = connector
+        .clone()
+        .write_chunking(WriteChunking::Chunk(16 * 1024));
+    let mut tcp = tcp_pair(&chunked, &acceptor, |stream| stream);
+    group.bench_function("16KiB-chunks", |b| {
+        b.iter(|| transfer(&mut tcp, BULK, 64 * 1024))
+    });
+    group.finish();
+
+    // the handshake flush policies, over buffered sockets, where flushing is
+    // a system call as well
+    let mut group = c.benchmark_group("tcp/handshake-flush");
+    for &(name, flush) in &[
+        ("eager", HandshakeFlush::Eager),
+        ("batched", HandshakeFlush::Batched),
+    ] {
+        let connector = connector.clone().handshake_flush(flush);
+        let acceptor = acceptor.clone().handshake_flush(flush);
+        group.bench_function(name, |b| {
+            b.iter(|| tcp_pair(&connector, &acceptor, BufWriter::new))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, roots, memory, tcp);
+criterion_main!(benches);
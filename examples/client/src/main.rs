@@ -4,13 +4,8 @@ use async_std::prelude::*;
 use async_std::task;
 use async_tls::TlsConnector;
 
-use rustls::ClientConfig;
-use rustls_pemfile::certs;
-
-use std::io::{BufReader, Cursor};
 use std::net::ToSocketAddrs;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::path::PathBuf;
 
 use structopt::StructOpt;
 
@@ -55,7 +50,7 @@ fn main() -> io::Result<()> {
         // Create default connector comes preconfigured with all you need to safely connect
         // to remote servers!
         let connector = if let Some(cafile) = cafile {
-            connector_for_ca_file(cafile).await?
+            TlsConnector::with_ca_file(cafile).await?
         } else {
             TlsConnector::default()
         };
@@ -80,16 +75,3 @@ fn main() -> io::Result<()> {
         Ok(())
     })
 }
-
-async fn connector_for_ca_file(cafile: &Path) -> io::Result<TlsConnector> {
-    let mut root_store = rustls::RootCertStore::empty();
-    let ca_bytes = async_std::fs::read(cafile).await?;
-    let cert = certs(&mut BufReader::new(Cursor::new(ca_bytes))).unwrap();
-    debug_assert_eq!((1, 0), root_store.add_parsable_certificates(&cert));
-
-    let config = ClientConfig::builder()
-        .with_safe_defaults()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
-    Ok(TlsConnector::from(Arc::new(config)))
-}
@@ -1,13 +1,53 @@
+use crate::admission::{self, Admission, AdmissionCallback, ClientInfo};
+use crate::cert_store::CertStore;
+use crate::common::cancel;
+use crate::common::chunking::WriteChunking;
+use crate::common::client_hello::ClientHelloSummary;
+use crate::common::close::{CloseMode, CloseTimer};
+use crate::common::coalesce::Coalescer;
+#[cfg(feature = "tokio")]
+use crate::common::compat::TokioIo;
+use crate::common::flush::HandshakeFlush;
+use crate::common::hello::HelloSniffer;
+use crate::common::policy::Policy;
+use crate::common::probe::ClientProbe;
+use crate::common::records;
 use crate::common::tls_state::TlsState;
+use crate::error::{HandshakeError, HandshakeStage};
+use crate::expiry::{self, ExpiryMonitor};
+#[cfg(feature = "fingerprint")]
+use crate::fingerprint::ClientHelloFingerprint;
+use crate::lazy::LazyConfigAcceptor;
+use crate::observer::{Observation, Observer};
+use crate::preset::SecurityPreset;
+use crate::reload::Reloader;
 use crate::server;
+#[cfg(feature = "session-tickets")]
+use crate::ticketer::RotatingTicketer;
+use crate::tls_alpn::TlsAlpn01Responder;
+use crate::Error;
 
+use futures_core::future::FusedFuture;
+use futures_core::ready;
 use futures_io::{AsyncRead, AsyncWrite};
-use rustls::{ServerConfig, ServerConnection};
+use rustls::server::{Acceptor, ProducesTickets};
+use rustls::{
+    AlertDescription, ServerConfig, ServerConnection, SupportedCipherSuite,
+    SupportedProtocolVersion,
+};
+use std::fmt;
 use std::future::Future;
 use std::io;
+use std::mem;
+use std::net::SocketAddr;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// How many configs from the reloader and `config_by_local_addr` an acceptor
+/// keeps configured.
+const CONFIGURED_CACHE: usize = 8;
 
 /// The TLS accepting part. The acceptor drives
 /// the server side of the TLS handshake process. It works
@@ -22,10 +62,391 @@ use std::task::{Context, Poll};
 /// See /examples/server for an example.
 #[derive(Clone)]
 pub struct TlsAcceptor {
+    /// The config as given, before the options below are applied.
+    base: Arc<ServerConfig>,
     inner: Arc<ServerConfig>,
+    reloader: Option<Arc<Reloader>>,
+    tls_alpn_01: Option<Arc<TlsAlpn01Responder>>,
+    strict_close_notify: bool,
+    send_close_notify: bool,
+    close_mode: CloseMode,
+    handshake_flush: HandshakeFlush,
+    write_chunking: WriteChunking,
+    coalesce_writes: (usize, Duration),
+    observer: Option<Arc<dyn Observer>>,
+    #[cfg(feature = "fingerprint")]
+    fingerprint_clients: bool,
+    admission: Option<Arc<AdmissionCallback>>,
+    by_local_addr: Option<Arc<ConfigByLocalAddr>>,
+    policy: Policy,
+    /// Whether clients get session tickets, in configs from the reloader as well.
+    session_tickets: bool,
+    /// Installed in configs from the reloader as well.
+    #[cfg(feature = "session-tickets")]
+    ticketer: Option<Arc<dyn ProducesTickets>>,
+    /// Installed in configs from the reloader as well.
+    max_fragment_size: Option<usize>,
+    /// Watches the certificates of configs from the reloader as well.
+    expiry_monitor: Option<Arc<ExpiryMonitor>>,
+    /// The configs from the reloader and `config_by_local_addr`, configured.
+    configured: Arc<Mutex<Configured>>,
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alpn: Vec<_> = (self.inner.alpn_protocols.iter())
+            .map(|protocol| String::from_utf8_lossy(protocol))
+            .collect();
+        f.debug_struct("TlsAcceptor")
+            .field("alpn_protocols", &alpn)
+            .field("max_early_data_size", &self.inner.max_early_data_size)
+            .field("max_fragment_size", &self.max_fragment_size)
+            .field("session_tickets", &self.session_tickets)
+            .field("strict_close_notify", &self.strict_close_notify)
+            .field("send_close_notify", &self.send_close_notify)
+            .field("close_mode", &self.close_mode)
+            .field("handshake_flush", &self.handshake_flush)
+            .field("write_chunking", &self.write_chunking)
+            .field("coalesce_writes", &self.coalesce_writes)
+            .field("reloading", &self.reloader.is_some())
+            .field("tls_alpn_01", &self.tls_alpn_01.is_some())
+            .field("admitting", &self.admission.is_some())
+            .field("by_local_addr", &self.by_local_addr.is_some())
+            .field("observed", &self.observer.is_some())
+            .field("expiry_monitor", &self.expiry_monitor)
+            .finish_non_exhaustive()
+    }
 }
 
 impl TlsAcceptor {
+    pub(crate) fn from_reloader(reloader: Arc<Reloader>) -> TlsAcceptor {
+        TlsAcceptor {
+            base: reloader.config(),
+            inner: reloader.config(),
+            reloader: Some(reloader),
+            tls_alpn_01: None,
+            strict_close_notify: true,
+            send_close_notify: true,
+            close_mode: CloseMode::Send,
+            handshake_flush: HandshakeFlush::Eager,
+            write_chunking: WriteChunking::Whole,
+            coalesce_writes: (0, Duration::ZERO),
+            observer: None,
+            #[cfg(feature = "fingerprint")]
+            fingerprint_clients: false,
+            admission: None,
+            by_local_addr: None,
+            policy: Policy::default(),
+            session_tickets: true,
+            max_fragment_size: None,
+            #[cfg(feature = "session-tickets")]
+            ticketer: None,
+            expiry_monitor: None,
+            configured: Arc::default(),
+        }
+    }
+
+    /// Answer ACME `tls-alpn-01` challenges registered with `responder`.
+    ///
+    /// The `ClientHello` is read before the config is chosen: validation requests
+    /// for pending challenges get the challenge certificate, all other clients
+    /// handshake with the normal config.
+    pub fn tls_alpn_01(mut self, responder: Arc<TlsAlpn01Responder>) -> Self {
+        self.tls_alpn_01 = Some(responder);
+        self
+    }
+
+    /// Fail reads with `Error::Truncated` when the client closes the connection
+    /// without a `close_notify` alert. Enabled by default.
+    ///
+    /// See `TlsConnector::strict_close_notify`.
+    pub fn strict_close_notify(mut self, flag: bool) -> Self {
+        self.strict_close_notify = flag;
+        self
+    }
+
+    /// Send a `close_notify` alert when the stream is closed. Enabled by default.
+    ///
+    /// See `TlsConnector::send_close_notify`.
+    pub fn send_close_notify(mut self, flag: bool) -> Self {
+        self.send_close_notify = flag;
+        self
+    }
+
+    /// What closing a stream waits for. Defaults to `CloseMode::Send`.
+    ///
+    /// See `TlsConnector::close_mode`.
+    pub fn close_mode(mut self, mode: CloseMode) -> Self {
+        self.close_mode = mode;
+        self
+    }
+
+    /// When the handshake flushes the IO. Defaults to `HandshakeFlush::Eager`.
+    ///
+    /// See `TlsConnector::handshake_flush`.
+    pub fn handshake_flush(mut self, flush: HandshakeFlush) -> Self {
+        self.handshake_flush = flush;
+        self
+    }
+
+    /// How much of a caller's buffer a write to the stream takes. Defaults to
+    /// `WriteChunking::Whole`.
+    ///
+    /// See `TlsConnector::write_chunking`.
+    pub fn write_chunking(mut self, chunking: WriteChunking) -> Self {
+        self.write_chunking = chunking;
+        self
+    }
+
+    /// Coalesce writes shorter than `threshold` bytes into fewer, larger
+    /// records. Disabled by default.
+    ///
+    /// See `TlsConnector::coalesce_writes`.
+    pub fn coalesce_writes(mut self, threshold: usize, max_delay: Duration) -> Self {
+        self.coalesce_writes = (threshold, max_delay);
+        self
+    }
+
+    /// Refuse clients negotiating a TLS version before `version`, e.g.
+    /// `rustls::version::TLS13`.
+    ///
+    /// The version is checked once the `ClientHello` was read, and clients are
+    /// refused with a `protocol_version` alert before anything else is sent,
+    /// failing the handshake with `Error::UnsupportedVersion`. Unlike a
+    /// `ClientConfig`, a `ServerConfig` cannot be built anew without knowing
+    /// how it verifies clients, so its versions are kept: build it
+    /// `with_protocol_versions` to negotiate within the bounds instead.
+    pub fn min_tls_version(mut self, version: &'static SupportedProtocolVersion) -> Self {
+        self.policy.set_min(version);
+        self
+    }
+
+    /// Refuse clients negotiating a TLS version after `version`.
+    ///
+    /// See `min_tls_version`. Clients speaking a later version of the
+    /// `ServerConfig` are refused rather than settle for an earlier one.
+    pub fn max_tls_version(mut self, version: &'static SupportedProtocolVersion) -> Self {
+        self.policy.set_max(version);
+        self
+    }
+
+    /// Only speak TLS 1.3, the same as `min_tls_version(&rustls::version::TLS13)`.
+    pub fn tls13_only(self) -> Self {
+        self.min_tls_version(&rustls::version::TLS13)
+    }
+
+    /// Refuse clients negotiating a cipher suite other than `suites`.
+    ///
+    /// See `TlsConnector::cipher_suites`. Like the version, the suite is
+    /// checked once the `ClientHello` was read, and clients are refused with
+    /// a `handshake_failure` alert, failing the handshake with
+    /// `PeerIncompatible::NoCipherSuitesInCommon`. Build the `ServerConfig`
+    /// `with_cipher_suites` to only negotiate `suites` instead.
+    pub fn cipher_suites(mut self, suites: &[SupportedCipherSuite]) -> io::Result<Self> {
+        self.policy.set_suites(suites)?;
+        Ok(self)
+    }
+
+    /// Like `cipher_suites`, with the suites named as in the IANA registry.
+    ///
+    /// See `TlsConnector::cipher_suite_names`.
+    pub fn cipher_suite_names(mut self, names: &[&str]) -> io::Result<Self> {
+        self.policy.set_suite_names(names)?;
+        Ok(self)
+    }
+
+    /// Refuse clients negotiating a version or cipher suite outside of `preset`,
+    /// replacing earlier bounds and suites.
+    ///
+    /// See `min_tls_version` and `cipher_suites`. The presets allow every key
+    /// exchange group rustls implements. Build the `ServerConfig` with
+    /// `SecurityPreset::configure` to only negotiate what `preset` allows.
+    pub fn security_preset(mut self, preset: SecurityPreset) -> Self {
+        self.policy = Policy::preset(preset);
+        self
+    }
+
+    /// Report the handshakes and connections of this acceptor to `observer`.
+    ///
+    /// See `TlsConnector::observer`.
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Compute the JA3 and JA4 fingerprints of clients, see
+    /// `server::TlsStream::client_hello_fingerprint`. Disabled by default.
+    ///
+    /// The `ClientHello` is read before the handshake starts then, as with
+    /// `LazyConfigAcceptor`.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint_clients(mut self, flag: bool) -> Self {
+        self.fingerprint_clients = flag;
+        self
+    }
+
+    /// Decide by their `ClientHello` which clients may handshake, e.g. to turn
+    /// away obsolete clients before spending CPU on them.
+    ///
+    /// `admit` is called once the `ClientHello` has been read, as with
+    /// `LazyConfigAcceptor`. Rejected clients get the alert of
+    /// `Admission::Reject`, and `accept` fails with `Error::Rejected`.
+    ///
+    /// ```rust
+    /// use async_tls::{Admission, TlsAcceptor};
+    /// use rustls::AlertDescription;
+    ///
+    /// fn require_sni(acceptor: TlsAcceptor) -> TlsAcceptor {
+    ///     acceptor.admit(|client| match client.server_name() {
+    ///         Some(_) => Admission::Accept,
+    ///         None => Admission::Reject(AlertDescription::UnrecognisedName),
+    ///     })
+    /// }
+    /// ```
+    pub fn admit<F>(mut self, admit: F) -> Self
+    where
+        F: Fn(&ClientInfo<'_>) -> Admission + Send + Sync + 'static,
+    {
+        self.admission = Some(Arc::new(admit));
+        self
+    }
+
+    /// Choose the config by the local address a connection was accepted on,
+    /// for servers with listeners of different identities sharing one
+    /// acceptor. Connections accepted with `accept_at` get the config `select`
+    /// returns, or the one of this acceptor for `None`.
+    ///
+    /// The options of this acceptor apply to the chosen configs as well.
+    ///
+    /// ```rust,no_run
+    /// use async_tls::TlsAcceptor;
+    /// use std::sync::Arc;
+    /// # fn configs() -> (TlsAcceptor, Arc<rustls::ServerConfig>) { todo!() }
+    ///
+    /// # async_std::task::block_on(async {
+    /// let (acceptor, admin) = configs();
+    /// let acceptor = acceptor.config_by_local_addr(move |addr| match addr.port() {
+    ///     9443 => Some(admin.clone()),
+    ///     _ => None,
+    /// });
+    /// let listener = async_std::net::TcpListener::bind("0.0.0.0:9443").await?;
+    /// let (tcp_stream, _) = listener.accept().await?;
+    /// let stream = acceptor.accept_at(tcp_stream.local_addr()?, tcp_stream).await?;
+    /// # Ok(()) as std::io::Result<()>
+    /// # });
+    /// ```
+    pub fn config_by_local_addr<F>(mut self, select: F) -> Self
+    where
+        F: Fn(SocketAddr) -> Option<Arc<ServerConfig>> + Send + Sync + 'static,
+    {
+        self.by_local_addr = Some(Arc::new(select));
+        self
+    }
+
+    /// Issue session tickets, so clients can resume their sessions. Enabled by default.
+    ///
+    /// Tickets let a server recognize returning clients, and let whoever holds
+    /// the ticket key decrypt resumed sessions. Without them, TLS 1.3 sessions
+    /// are not resumed at all; TLS 1.2 clients can still resume by session id
+    /// unless the config's `session_storage` is `NoServerSessionStorage`.
+    pub fn session_tickets(mut self, flag: bool) -> Self {
+        self.session_tickets = flag;
+        self.reconfigure();
+        self
+    }
+
+    /// Send TLS records of at most `size` bytes, including their 5-byte header.
+    ///
+    /// See `TlsConnector::max_fragment_size`. Also applies to configs reloaded
+    /// by a `PollingReloader`.
+    pub fn max_fragment_size(mut self, size: usize) -> io::Result<Self> {
+        self.max_fragment_size = Some(records::max_fragment_size(size)?);
+        self.reconfigure();
+        Ok(self)
+    }
+
+    /// Encrypt session tickets with keys that rotate every `rotation`,
+    /// accepting tickets of the `previous` keys too.
+    ///
+    /// See `RotatingTicketer`. Also applies to configs reloaded by a
+    /// `PollingReloader`.
+    #[cfg(feature = "session-tickets")]
+    pub fn rotate_ticket_keys(mut self, rotation: Duration, previous: usize) -> Self {
+        self.ticketer = Some(Arc::new(RotatingTicketer::new(rotation, previous)));
+        self.reconfigure();
+        self
+    }
+
+    /// Warn with `monitor` before the certificates served expire.
+    ///
+    /// Also applies to configs reloaded by a `PollingReloader`, or chosen by
+    /// local address.
+    pub fn expiry_monitor(mut self, monitor: Arc<ExpiryMonitor>) -> Self {
+        self.expiry_monitor = Some(monitor);
+        self.reconfigure();
+        self
+    }
+
+    /// Apply the options to the config given, once they changed. Copies of the
+    /// acceptor made before keep their configs.
+    fn reconfigure(&mut self) {
+        self.inner = self.configure(self.base.clone());
+        self.configured = Arc::default();
+    }
+
+    /// `configure`, reusing the result for a config seen before, so the
+    /// configs of the reloader and `config_by_local_addr` are not copied for
+    /// every connection.
+    fn configured(&self, config: Arc<ServerConfig>) -> Arc<ServerConfig> {
+        let mut configured = self.configured.lock().unwrap();
+        if let Some((_, done)) = (configured.iter()).find(|(from, _)| Arc::ptr_eq(from, &config)) {
+            return done.clone();
+        }
+        let done = self.configure(config.clone());
+        // a reloader replaces its config, so old ones are dropped in turn
+        if configured.len() == CONFIGURED_CACHE {
+            configured.remove(0);
+        }
+        configured.push((config, done.clone()));
+        done
+    }
+
+    /// Apply the options set on this acceptor to `config`, copying it only if needed.
+    fn configure(&self, mut config: Arc<ServerConfig>) -> Arc<ServerConfig> {
+        #[cfg(feature = "session-tickets")]
+        if let Some(ref ticketer) = self.ticketer {
+            if !Arc::ptr_eq(&config.ticketer, ticketer) {
+                Arc::make_mut(&mut config).ticketer = ticketer.clone();
+            }
+        }
+        if self.max_fragment_size.is_some() && config.max_fragment_size != self.max_fragment_size {
+            Arc::make_mut(&mut config).max_fragment_size = self.max_fragment_size;
+        }
+        if !self.session_tickets && (config.send_tls13_tickets > 0 || config.ticketer.enabled()) {
+            let config = Arc::make_mut(&mut config);
+            config.send_tls13_tickets = 0;
+            config.ticketer = Arc::new(NoTickets);
+        }
+        if let Some(ref monitor) = self.expiry_monitor {
+            let config = Arc::make_mut(&mut config);
+            config.cert_resolver = expiry::watching(config.cert_resolver.clone(), monitor.clone());
+        }
+        config
+    }
+
+    /// Re-read the certificate and key files right away, without waiting
+    /// for the polling interval.
+    ///
+    /// Returns `Ok(true)` if the files changed and the new config is now used for
+    /// subsequent connections, `Ok(false)` if nothing changed or this acceptor was not
+    /// created by a `PollingReloader`.
+    pub fn reload(&self) -> io::Result<bool> {
+        match self.reloader {
+            Some(ref reloader) => reloader.reload(),
+            None => Ok(false),
+        }
+    }
+
     /// Accept a client connections. `stream` can be any type implementing `AsyncRead` and `AsyncWrite`,
     /// such as TcpStreams or Unix domain sockets.
     ///
@@ -39,52 +460,604 @@ impl TlsAcceptor {
         self.accept_with(stream, |_| ())
     }
 
-    // Currently private, as exposing ServerConnections exposes rusttls
-    fn accept_with<IO, F>(&self, stream: IO, f: F) -> Accept<IO>
+    /// Accept a client connection like `accept`, over a tokio IO object such
+    /// as `tokio::net::TcpStream`.
+    ///
+    /// The stream implements the IO traits of both tokio and futures-io.
+    #[cfg(feature = "tokio")]
+    pub fn accept_tokio<IO>(&self, stream: IO) -> Accept<TokioIo<IO>>
+    where
+        IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        self.accept(TokioIo::new(stream))
+    }
+
+    /// Accept a client connection, like `accept`, calling `f` on the rustls
+    /// session before the handshake starts.
+    ///
+    /// This is the place for per-connection options, such as the buffer limit:
+    ///
+    /// ```rust,no_run
+    /// # fn acceptor() -> async_tls::TlsAcceptor { todo!() }
+    /// # async_std::task::block_on(async {
+    /// let acceptor = acceptor();
+    /// let listener = async_std::net::TcpListener::bind("0.0.0.0:8443").await?;
+    /// let (tcp_stream, _) = listener.accept().await?;
+    /// let stream = acceptor
+    ///     .accept_with(tcp_stream, |session| {
+    ///         session.set_buffer_limit(Some(16 * 1024));
+    ///     })
+    ///     .await?;
+    /// # Ok(()) as std::io::Result<()>
+    /// # });
+    /// ```
+    ///
+    /// With `tls_alpn_01` or `admit`, `f` is called once the `ClientHello` has been read.
+    pub fn accept_with<IO, F>(&self, stream: IO, f: F) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+        F: FnOnce(&mut ServerConnection) + Send + 'static,
+    {
+        self.accept_with_config(stream, self.config(), f)
+    }
+
+    /// Accept a client connection like `accept`, with the config chosen for
+    /// `local_addr`, the address `stream` was accepted on.
+    ///
+    /// See `config_by_local_addr`.
+    pub fn accept_at<IO>(&self, local_addr: SocketAddr, stream: IO) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let config = (self.by_local_addr.as_ref())
+            .and_then(|select| select(local_addr))
+            .map(|config| self.configured(config))
+            .unwrap_or_else(|| self.config());
+        self.accept_with_config(stream, config, |_| ())
+    }
+
+    /// The config for connections, reloaded or not.
+    fn config(&self) -> Arc<ServerConfig> {
+        match self.reloader {
+            Some(ref reloader) => self.configured(reloader.config()),
+            None => self.inner.clone(),
+        }
+    }
+
+    fn accept_with_config<IO, F>(&self, stream: IO, config: Arc<ServerConfig>, f: F) -> Accept<IO>
     where
         IO: AsyncRead + AsyncWrite + Unpin,
-        F: FnOnce(&mut ServerConnection),
+        F: FnOnce(&mut ServerConnection) + Send + 'static,
     {
-        let mut conn = match ServerConnection::new(self.inner.clone()) {
-            Ok(conn) => conn,
-            Err(_) => {
-                return Accept(server::MidHandshake::End);
+        let observation = Observation::start(self.observer.clone());
+
+        #[allow(unused_mut)]
+        let mut read_hello = self.tls_alpn_01.is_some() || self.admission.is_some();
+        #[cfg(feature = "fingerprint")]
+        {
+            read_hello |= self.fingerprint_clients;
+        }
+        if read_hello {
+            return Accept(AcceptState::ReadingHello {
+                lazy: LazyConfigAcceptor::new(Acceptor::default(), stream),
+                config,
+                challenges: self.tls_alpn_01.clone(),
+                admission: self.admission.clone(),
+                #[cfg(feature = "fingerprint")]
+                fingerprint_clients: self.fingerprint_clients,
+                f: Some(Box::new(f)),
+                strict_close_notify: self.strict_close_notify,
+                send_close_notify: self.send_close_notify,
+                close_mode: self.close_mode,
+                handshake_flush: self.handshake_flush,
+                write_chunking: self.write_chunking,
+                coalesce_writes: self.coalesce_writes,
+                policy: self.policy,
+                observation: Some(observation),
+            });
+        }
+
+        let accept = match ServerConnection::new(config) {
+            Ok(mut conn) => {
+                f(&mut conn);
+                Accept::handshake(conn, stream)
             }
+            Err(err) => Accept::error(Error::from(err).into()),
         };
+        accept
+            .close_notify(self.strict_close_notify, self.send_close_notify)
+            .close_mode(self.close_mode)
+            .handshake_flush(self.handshake_flush)
+            .write_chunking(self.write_chunking)
+            .coalesce_writes(self.coalesce_writes)
+            .policy(self.policy)
+            .observe(observation)
+    }
+}
+
+/// Issues no tickets, as rustls's own `NeverProducesTickets` is private.
+struct NoTickets;
+
+impl ProducesTickets for NoTickets {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn lifetime(&self) -> u32 {
+        0
+    }
 
-        f(&mut conn);
+    fn encrypt(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
 
-        Accept(server::MidHandshake::Handshaking(server::TlsStream {
-            conn,
-            io: stream,
-            state: TlsState::Stream,
-        }))
+    fn decrypt(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
     }
 }
 
+type ConfigureConnection = Box<dyn FnOnce(&mut ServerConnection) + Send>;
+
+type ConfigByLocalAddr = dyn Fn(SocketAddr) -> Option<Arc<ServerConfig>> + Send + Sync;
+
+/// Configs with the options of an acceptor applied, by the config they were
+/// made from.
+type Configured = Vec<(Arc<ServerConfig>, Arc<ServerConfig>)>;
+
 /// Future returned from `TlsAcceptor::accept` which will resolve
 /// once the accept handshake has finished.
-pub struct Accept<IO>(server::MidHandshake<IO>);
+///
+/// It can be stored and polled by hand, e.g. by a scheduler of its own,
+/// which can tell how far the handshake got with `stage` to time out or
+/// prioritize handshakes, and whether the future resolved with
+/// `FusedFuture::is_terminated`.
+/// `into_mid_handshake` turns it into the `server::MidHandshake` it drives.
+pub struct Accept<IO>(AcceptState<IO>);
+
+#[allow(clippy::large_enum_variant)]
+enum AcceptState<IO> {
+    Error(Option<io::Error>),
+    ReadingHello {
+        lazy: LazyConfigAcceptor<IO>,
+        config: Arc<ServerConfig>,
+        challenges: Option<Arc<TlsAlpn01Responder>>,
+        admission: Option<Arc<AdmissionCallback>>,
+        #[cfg(feature = "fingerprint")]
+        fingerprint_clients: bool,
+        f: Option<ConfigureConnection>,
+        strict_close_notify: bool,
+        send_close_notify: bool,
+        close_mode: CloseMode,
+        handshake_flush: HandshakeFlush,
+        write_chunking: WriteChunking,
+        coalesce_writes: (usize, Duration),
+        policy: Policy,
+        observation: Option<Observation>,
+    },
+    /// Sending the alert of a rejected client.
+    Rejecting {
+        io: IO,
+        alert: [u8; 7],
+        written: usize,
+        error: Option<io::Error>,
+    },
+    Handshake(server::Handshake<IO>),
+}
+
+impl<IO> fmt::Debug for Accept<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.0 {
+            AcceptState::Error(_) => "Error",
+            AcceptState::ReadingHello { .. } => "ReadingHello",
+            AcceptState::Rejecting { .. } => "Rejecting",
+            AcceptState::Handshake(server::Handshake::Handshaking(_)) => "Handshaking",
+            AcceptState::Handshake(server::Handshake::End) => "Done",
+        };
+        f.debug_tuple("Accept").field(&state).finish()
+    }
+}
+
+impl<IO> Accept<IO> {
+    pub(crate) fn handshake(conn: ServerConnection, io: IO) -> Self {
+        Accept(AcceptState::Handshake(server::Handshake::Handshaking(
+            server::TlsStream {
+                conn,
+                io,
+                state: TlsState::Stream,
+                strict_close_notify: true,
+                send_close_notify: true,
+                close_mode: CloseMode::Send,
+                close_timer: CloseTimer::default(),
+                exact_reads: false,
+                handshake_flush: HandshakeFlush::Eager,
+                write_chunking: WriteChunking::Whole,
+                coalescer: Coalescer::default(),
+                hello: HelloSniffer::server(),
+                policy: Policy::default(),
+                probe: ClientProbe::default(),
+                observation: Observation::start(None),
+                peeked: Vec::new(),
+                io_state: None,
+                #[cfg(feature = "fingerprint")]
+                fingerprint: None,
+            },
+        )))
+    }
+
+    pub(crate) fn reject(
+        io: IO,
+        alert: AlertDescription,
+        server_name: Option<&str>,
+        client_hello: Option<ClientHelloSummary>,
+    ) -> Self {
+        let error = Error::Rejected(alert).into();
+        let error = HandshakeError::wrap_at(error, server_name, HandshakeStage::Hello);
+        Accept(AcceptState::Rejecting {
+            io,
+            alert: admission::alert_record(alert),
+            written: 0,
+            error: Some(HandshakeError::with_client_hello(error, client_hello)),
+        })
+    }
+
+    /// Keep what the client sent so far, to say why the handshake failed.
+    pub(crate) fn probe(mut self, probe: ClientProbe) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.probe = probe;
+        }
+        self
+    }
+
+    #[cfg(feature = "fingerprint")]
+    pub(crate) fn fingerprint(mut self, fingerprint: Option<ClientHelloFingerprint>) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.fingerprint = fingerprint;
+        }
+        self
+    }
+
+    fn close_notify(mut self, strict: bool, send: bool) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.strict_close_notify = strict;
+            stream.send_close_notify = send;
+        }
+        self
+    }
+
+    fn close_mode(mut self, mode: CloseMode) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.close_mode = mode;
+        }
+        self
+    }
+
+    fn handshake_flush(mut self, flush: HandshakeFlush) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.handshake_flush = flush;
+        }
+        self
+    }
+
+    fn write_chunking(mut self, chunking: WriteChunking) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.write_chunking = chunking;
+        }
+        self
+    }
+
+    fn coalesce_writes(mut self, (threshold, max_delay): (usize, Duration)) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.coalescer = Coalescer::new(threshold, max_delay);
+        }
+        self
+    }
+
+    fn policy(mut self, policy: Policy) -> Self {
+        if let AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) = self.0 {
+            stream.policy = policy;
+        }
+        self
+    }
+
+    /// Report the handshake, which started with `observation`, and the stream.
+    fn observe(mut self, mut observation: Observation) -> Self {
+        match self.0 {
+            AcceptState::Handshake(server::Handshake::Handshaking(ref mut stream)) => {
+                stream.observation = observation;
+            }
+            AcceptState::Error(Some(ref err))
+            | AcceptState::Rejecting {
+                error: Some(ref err),
+                ..
+            } => observation.handshake_error(err),
+            _ => (),
+        }
+        self
+    }
+
+    pub(crate) fn error(err: io::Error) -> Self {
+        Accept(AcceptState::Error(Some(err)))
+    }
+
+    /// Returns a reference to the underlying IO stream while the handshake
+    /// is in flight, e.g. to read the client's address.
+    ///
+    /// Returns `None` once the future resolved or the connection was taken,
+    /// or if accepting it failed before it was read from.
+    pub fn get_ref(&self) -> Option<&IO> {
+        match self.0 {
+            AcceptState::ReadingHello { ref lazy, .. } => lazy.get_ref(),
+            AcceptState::Rejecting { ref io, .. } => Some(io),
+            AcceptState::Handshake(ref handshake) => handshake.get_ref(),
+            AcceptState::Error(_) => None,
+        }
+    }
+
+    /// Returns a mutable reference to the underlying IO stream while the
+    /// handshake is in flight, e.g. to set socket options, see `get_ref`.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        match self.0 {
+            AcceptState::ReadingHello { ref mut lazy, .. } => lazy.get_mut(),
+            AcceptState::Rejecting { ref mut io, .. } => Some(io),
+            AcceptState::Handshake(ref mut handshake) => handshake.get_mut(),
+            AcceptState::Error(_) => None,
+        }
+    }
+
+    /// How far the handshake got, `None` once the future resolved, or if
+    /// accepting failed before the handshake started.
+    pub fn stage(&self) -> Option<HandshakeStage> {
+        match self.0 {
+            AcceptState::ReadingHello {
+                observation: Some(_),
+                ..
+            } => Some(HandshakeStage::Hello),
+            AcceptState::Rejecting { error: Some(_), .. } => Some(HandshakeStage::Hello),
+            AcceptState::Handshake(ref handshake) => handshake.stage(),
+            _ => None,
+        }
+    }
+
+    /// Turns the future into the handshake in flight, to be driven by hand
+    /// with `MidHandshake::poll`.
+    ///
+    /// Acceptors reading the `ClientHello` first, e.g. to `admit` clients,
+    /// only start the handshake once polled past it, and accepting may fail
+    /// before: the future is given back until then, or once it resolved.
+    #[allow(clippy::result_large_err)]
+    pub fn into_mid_handshake(self) -> Result<server::MidHandshake<IO>, Self> {
+        match self.0 {
+            AcceptState::Handshake(handshake) if !handshake.is_done() => {
+                Ok(server::MidHandshake(handshake))
+            }
+            state => Err(Accept(state)),
+        }
+    }
+
+    /// Takes back the connection, e.g. once accepting it failed with
+    /// `Error::PlaintextHttp` to answer the request with a redirect.
+    ///
+    /// Returns `None` if the connection was taken already, or accepting it
+    /// failed before it was read from. Polling the future afterwards panics.
+    pub fn take_io(&mut self) -> Option<IO> {
+        match mem::replace(&mut self.0, AcceptState::Error(None)) {
+            AcceptState::ReadingHello { mut lazy, .. } => lazy.take_io(),
+            AcceptState::Rejecting { io, .. } => Some(io),
+            AcceptState::Handshake(mut handshake) => handshake.take_io(),
+            state @ AcceptState::Error(_) => {
+                self.0 = state;
+                None
+            }
+        }
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Accept<IO> {
+    /// Cancels the handshake and returns the underlying IO, still open, e.g.
+    /// to shed load by closing it.
+    ///
+    /// With `notify`, the client is told with a `user_canceled` alert, or a
+    /// `close_notify` once a cipher suite was agreed on, which is all rustls
+    /// sends then. Failing to send it is ignored. Clients being rejected by
+    /// `admit` are sent nothing more.
+    ///
+    /// Returns `None` if the future resolved or the connection was taken,
+    /// or if accepting it failed before it was read from.
+    pub async fn abort(self, notify: bool) -> Option<IO> {
+        match self.0 {
+            AcceptState::ReadingHello { mut lazy, .. } => {
+                let mut io = lazy.take_io()?;
+                if notify {
+                    cancel::cancel(&mut io, None::<&mut ServerConnection>).await;
+                }
+                Some(io)
+            }
+            AcceptState::Rejecting { io, .. } => Some(io),
+            AcceptState::Handshake(handshake) => handshake.abort(notify).await,
+            AcceptState::Error(_) => None,
+        }
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FusedFuture for Accept<IO> {
+    fn is_terminated(&self) -> bool {
+        match self.0 {
+            AcceptState::Error(ref err) => err.is_none(),
+            AcceptState::ReadingHello {
+                ref observation, ..
+            } => observation.is_none(),
+            AcceptState::Rejecting { ref error, .. } => error.is_none(),
+            AcceptState::Handshake(ref handshake) => handshake.is_done(),
+        }
+    }
+}
 
 impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Accept<IO> {
     type Output = io::Result<server::TlsStream<IO>>;
 
-    #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.0).poll(cx)
+        loop {
+            match self.0 {
+                AcceptState::Error(ref mut err) => {
+                    return Poll::Ready(Err(err.take().expect("Polled twice after being Ready")))
+                }
+                AcceptState::Handshake(ref mut handshake) => return Pin::new(handshake).poll(cx),
+                AcceptState::Rejecting {
+                    ref mut io,
+                    ref alert,
+                    ref mut written,
+                    ref mut error,
+                } => {
+                    // the client may be gone already, which fails it all the same
+                    while *written < alert.len() {
+                        match ready!(Pin::new(&mut *io).poll_write(cx, &alert[*written..])) {
+                            Ok(n) if n > 0 => *written += n,
+                            _ => *written = alert.len(),
+                        }
+                    }
+                    let _ = ready!(Pin::new(&mut *io).poll_flush(cx));
+                    let err = error.take().expect("Polled twice after being Ready");
+                    return Poll::Ready(Err(err));
+                }
+                AcceptState::ReadingHello {
+                    ref mut lazy,
+                    ref config,
+                    ref challenges,
+                    ref admission,
+                    #[cfg(feature = "fingerprint")]
+                    fingerprint_clients,
+                    ref mut f,
+                    strict_close_notify,
+                    send_close_notify,
+                    close_mode,
+                    handshake_flush,
+                    write_chunking,
+                    coalesce_writes,
+                    policy,
+                    ref mut observation,
+                } => {
+                    let start = match ready!(Pin::new(lazy).poll(cx)) {
+                        Ok(start) => start,
+                        Err(err) => {
+                            let err = HandshakeError::wrap_at(err, None, HandshakeStage::Hello);
+                            // the IO stays, to be taken back
+                            if let Some(mut observation) = observation.take() {
+                                observation.handshake_error(&err);
+                            }
+                            return Poll::Ready(Err(err));
+                        }
+                    };
+                    let observation = observation.take().expect("Polled twice after being Ready");
+                    if let Some(ref admit) = admission {
+                        let client = ClientInfo::new(start.client_hello());
+                        #[cfg(feature = "fingerprint")]
+                        let client = client.with_fingerprint(start.fingerprint());
+                        if let Admission::Reject(alert) = admit(&client) {
+                            self.0 = start.reject(alert).observe(observation).0;
+                            continue;
+                        }
+                    }
+                    let config = challenges
+                        .as_ref()
+                        .and_then(|challenges| challenges.config_for(&start.client_hello()))
+                        .unwrap_or_else(|| config.clone());
+                    let f = f.take().expect("Polled twice after being Ready");
+                    let accept = start.into_stream_with(config, f);
+                    #[cfg(feature = "fingerprint")]
+                    let accept = match fingerprint_clients {
+                        true => accept,
+                        false => accept.fingerprint(None),
+                    };
+                    self.0 = accept
+                        .close_notify(strict_close_notify, send_close_notify)
+                        .close_mode(close_mode)
+                        .handshake_flush(handshake_flush)
+                        .write_chunking(write_chunking)
+                        .coalesce_writes(coalesce_writes)
+                        .policy(policy)
+                        .observe(observation)
+                        .0;
+                }
+            }
+        }
     }
 }
 
 impl From<Arc<ServerConfig>> for TlsAcceptor {
     fn from(inner: Arc<ServerConfig>) -> TlsAcceptor {
-        TlsAcceptor { inner }
+        TlsAcceptor {
+            base: inner.clone(),
+            inner,
+            reloader: None,
+            tls_alpn_01: None,
+            strict_close_notify: true,
+            send_close_notify: true,
+            close_mode: CloseMode::Send,
+            handshake_flush: HandshakeFlush::Eager,
+            write_chunking: WriteChunking::Whole,
+            coalesce_writes: (0, Duration::ZERO),
+            observer: None,
+            #[cfg(feature = "fingerprint")]
+            fingerprint_clients: false,
+            admission: None,
+            by_local_addr: None,
+            policy: Policy::default(),
+            session_tickets: true,
+            max_fragment_size: None,
+            #[cfg(feature = "session-tickets")]
+            ticketer: None,
+            expiry_monitor: None,
+            configured: Arc::default(),
+        }
     }
 }
 
 impl From<ServerConfig> for TlsAcceptor {
     fn from(inner: ServerConfig) -> TlsAcceptor {
-        TlsAcceptor {
-            inner: Arc::new(inner),
-        }
+        TlsAcceptor::from(Arc::new(inner))
+    }
+}
+
+impl From<CertStore> for TlsAcceptor {
+    /// An acceptor with safe defaults, selecting certificates from `store` by SNI.
+    fn from(store: CertStore) -> TlsAcceptor {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(store));
+        TlsAcceptor::from(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::server::ResolvesServerCertUsingSni;
+
+    #[test]
+    fn configures_each_config_once() {
+        let config = || {
+            Arc::new(
+                ServerConfig::builder()
+                    .with_safe_defaults()
+                    .with_no_client_auth()
+                    .with_cert_resolver(Arc::new(ResolvesServerCertUsingSni::new())),
+            )
+        };
+        let (a, b) = (config(), config());
+        let acceptor =
+            TlsAcceptor::from(config()).expiry_monitor(Arc::new(ExpiryMonitor::new(|_| ())));
+
+        let configured = acceptor.configured(a.clone());
+        assert!(!Arc::ptr_eq(&configured, &a));
+        assert!(Arc::ptr_eq(&acceptor.configured(a.clone()), &configured));
+        assert!(!Arc::ptr_eq(&acceptor.configured(b), &configured));
+
+        // copies with other options configure anew
+        let other = acceptor.clone().session_tickets(false);
+        let reconfigured = other.configured(a.clone());
+        assert!(!Arc::ptr_eq(&reconfigured, &configured));
+        assert_eq!(reconfigured.send_tls13_tickets, 0);
+        assert!(Arc::ptr_eq(&acceptor.configured(a), &configured));
     }
 }
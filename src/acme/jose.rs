@@ -0,0 +1,114 @@
+//! The JSON Web Signature subset ACME needs: ES256 with either an embedded
+//! JWK or a key ID.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde_json::{json, Value};
+use std::io;
+
+pub(crate) fn b64(data: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+/// The ACME account key, an ECDSA P-256 key pair.
+pub(crate) struct AccountKey {
+    pkcs8: Vec<u8>,
+    pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    pub(crate) fn generate() -> io::Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| io::Error::other("failed to generate ACME account key"))?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    pub(crate) fn from_pkcs8(pkcs8: &[u8]) -> io::Result<Self> {
+        let rng = SystemRandom::new();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid ACME account key"))?;
+        Ok(AccountKey {
+            pkcs8: pkcs8.to_vec(),
+            pair,
+            rng,
+        })
+    }
+
+    pub(crate) fn pkcs8(&self) -> &[u8] {
+        &self.pkcs8
+    }
+
+    /// The public key as a JWK, with members in the lexicographic order
+    /// RFC 7638 requires for thumbprints.
+    pub(crate) fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.pair.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": b64(&point[1..33]),
+            "y": b64(&point[33..65]),
+        })
+    }
+
+    /// The RFC 7638 thumbprint used in key authorizations.
+    pub(crate) fn thumbprint(&self) -> String {
+        let jwk = self.jwk().to_string();
+        b64(ring::digest::digest(&ring::digest::SHA256, jwk.as_bytes()))
+    }
+
+    /// Sign `payload` for `url`. Without a `kid` the JWK is embedded, as
+    /// required when creating the account.
+    pub(crate) fn sign(
+        &self,
+        url: &str,
+        nonce: &str,
+        kid: Option<&str>,
+        payload: Option<&Value>,
+    ) -> io::Result<Vec<u8>> {
+        let mut protected = json!({
+            "alg": "ES256",
+            "nonce": nonce,
+            "url": url,
+        });
+        match kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+
+        let protected = b64(protected.to_string());
+        // POST-as-GET requests carry an empty payload
+        let payload = payload.map(|p| b64(p.to_string())).unwrap_or_default();
+        let signature = self
+            .pair
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| io::Error::other("failed to sign ACME request"))?;
+
+        let body = json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": b64(signature),
+        });
+        Ok(body.to_string().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbprint_uses_canonical_jwk() {
+        let key = AccountKey::generate().unwrap();
+        let jwk = key.jwk().to_string();
+        assert!(jwk.starts_with("{\"crv\":\"P-256\",\"kty\":\"EC\",\"x\":"));
+        assert_eq!(key.thumbprint().len(), 43);
+
+        let reloaded = AccountKey::from_pkcs8(key.pkcs8()).unwrap();
+        assert_eq!(reloaded.thumbprint(), key.thumbprint());
+    }
+}
@@ -0,0 +1,806 @@
+//! Automatic certificate provisioning through ACME ([RFC 8555]), e.g. from Let's Encrypt.
+//!
+//! An `AcmeManager` creates (or reuses) an ACME account, orders a certificate for the
+//! configured domains, and installs it into the `TlsAcceptor` it hands out. Its `run`
+//! future keeps renewing the certificate before it expires, so a server only needs to
+//! spawn it next to its accept loop.
+//!
+//! Proving control over the domains is delegated to a `ChallengeSolver`. By default, the
+//! manager answers `tls-alpn-01` challenges on its own acceptor, so nothing but the TLS
+//! port has to be reachable. An `Http01Solver` is also provided, and DNS-based challenges
+//! can be implemented by the application.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::acme::AcmeConfig;
+//!
+//! # async_std::task::block_on(async {
+//! let manager = AcmeConfig::new(vec!["example.com".into()])
+//!     .contact("mailto:admin@example.com")
+//!     .cache_dir("/var/lib/my-server/acme")
+//!     .build();
+//!
+//! // Accept connections on port 443 with this acceptor, so that the
+//! // challenge handshakes can be answered while `run` orders the certificate.
+//! let acceptor = manager.acceptor();
+//! async_std::task::spawn(manager.run());
+//! # });
+//! ```
+//!
+//! [RFC 8555]: https://www.rfc-editor.org/rfc/rfc8555
+
+mod jose;
+
+use crate::common::{der, http, pem};
+use crate::{TlsAcceptor, TlsAlpn01Responder, TlsConnector};
+use jose::AccountKey;
+
+use async_std::fs;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{PrivateKey, ServerConfig};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// The Let's Encrypt production directory.
+pub const LETS_ENCRYPT_PRODUCTION: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// The Let's Encrypt staging directory, for testing without hitting rate limits.
+pub const LETS_ENCRYPT_STAGING: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+pub use crate::BoxFuture;
+
+type ErrorCallback = dyn Fn(&io::Error) + Send + Sync;
+
+/// The type of an ACME challenge.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChallengeKind {
+    /// `http-01`: serve the key authorization over plain HTTP.
+    Http01,
+    /// `dns-01`: publish a TXT record at `_acme-challenge.<domain>`.
+    Dns01,
+    /// `tls-alpn-01`: present a challenge certificate to handshakes offering `acme-tls/1`.
+    TlsAlpn01,
+}
+
+impl ChallengeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChallengeKind::Http01 => "http-01",
+            ChallengeKind::Dns01 => "dns-01",
+            ChallengeKind::TlsAlpn01 => "tls-alpn-01",
+        }
+    }
+}
+
+/// A challenge the ACME server wants answered for one domain.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    kind: ChallengeKind,
+    domain: String,
+    token: String,
+    key_authorization: String,
+}
+
+impl Challenge {
+    /// The type of this challenge.
+    pub fn kind(&self) -> ChallengeKind {
+        self.kind
+    }
+
+    /// The domain being validated.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The challenge token.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// The key authorization, served as-is for `http-01`.
+    pub fn key_authorization(&self) -> &str {
+        &self.key_authorization
+    }
+
+    /// The TXT record value for `dns-01`.
+    pub fn dns_value(&self) -> String {
+        jose::b64(ring::digest::digest(
+            &ring::digest::SHA256,
+            self.key_authorization.as_bytes(),
+        ))
+    }
+}
+
+/// Proves control over a domain to the ACME server.
+pub trait ChallengeSolver: Send + Sync {
+    /// The challenge type this solver answers.
+    fn kind(&self) -> ChallengeKind;
+
+    /// Make the challenge response available. The ACME server is asked to
+    /// validate once the returned future resolves.
+    fn present<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Remove the challenge response again, whether validation succeeded or not.
+    fn cleanup<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// An in-memory `http-01` solver.
+///
+/// Look up incoming requests for `/.well-known/acme-challenge/{token}` with
+/// `key_authorization` and answer with the returned body.
+#[derive(Debug, Default)]
+pub struct Http01Solver {
+    tokens: Mutex<HashMap<String, String>>,
+}
+
+impl Http01Solver {
+    /// Create a solver without pending challenges.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The response body for a challenge token, if that challenge is pending.
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.tokens.lock().unwrap().get(token).cloned()
+    }
+}
+
+impl ChallengeSolver for Http01Solver {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::Http01
+    }
+
+    fn present<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(challenge.token.clone(), challenge.key_authorization.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn cleanup<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>> {
+        self.tokens.lock().unwrap().remove(&challenge.token);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl ChallengeSolver for TlsAlpn01Responder {
+    fn kind(&self) -> ChallengeKind {
+        ChallengeKind::TlsAlpn01
+    }
+
+    fn present<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let digest = ring::digest::digest(
+                &ring::digest::SHA256,
+                challenge.key_authorization.as_bytes(),
+            );
+            let mut params = rcgen::CertificateParams::new(vec![challenge.domain.clone()]);
+            params.custom_extensions =
+                vec![rcgen::CustomExtension::new_acme_identifier(digest.as_ref())];
+            let cert = rcgen::Certificate::from_params(params).map_err(io::Error::other)?;
+
+            let chain = vec![rustls::Certificate(
+                cert.serialize_der().map_err(io::Error::other)?,
+            )];
+            let key = rustls::sign::any_ecdsa_type(&PrivateKey(cert.serialize_private_key_der()))
+                .map_err(io::Error::other)?;
+            self.insert(challenge.domain.clone(), CertifiedKey::new(chain, key));
+            Ok(())
+        })
+    }
+
+    fn cleanup<'a>(&'a self, challenge: &'a Challenge) -> BoxFuture<'a, io::Result<()>> {
+        self.remove(&challenge.domain);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Configuration for an `AcmeManager`.
+pub struct AcmeConfig {
+    directory: String,
+    domains: Vec<String>,
+    contacts: Vec<String>,
+    cache_dir: Option<PathBuf>,
+    renew_before: Duration,
+    check_interval: Duration,
+    retry_interval: Duration,
+    solver: Option<Arc<dyn ChallengeSolver>>,
+    connector: TlsConnector,
+    on_error: Option<Box<ErrorCallback>>,
+}
+
+impl AcmeConfig {
+    /// Order certificates covering `domains` from Let's Encrypt.
+    ///
+    /// The first domain becomes the certificate's common name.
+    pub fn new(domains: Vec<String>) -> Self {
+        AcmeConfig {
+            directory: LETS_ENCRYPT_PRODUCTION.to_string(),
+            domains,
+            contacts: Vec::new(),
+            cache_dir: None,
+            renew_before: Duration::from_secs(30 * 24 * 60 * 60),
+            check_interval: Duration::from_secs(12 * 60 * 60),
+            retry_interval: Duration::from_secs(60 * 60),
+            solver: None,
+            connector: TlsConnector::default(),
+            on_error: None,
+        }
+    }
+
+    /// Use a different ACME directory, e.g. `LETS_ENCRYPT_STAGING`.
+    pub fn directory(mut self, url: impl Into<String>) -> Self {
+        self.directory = url.into();
+        self
+    }
+
+    /// Add an account contact URL, such as `mailto:admin@example.com`.
+    pub fn contact(mut self, contact: impl Into<String>) -> Self {
+        self.contacts.push(contact.into());
+        self
+    }
+
+    /// Persist the account key and the issued certificate in this directory,
+    /// so restarts neither create new accounts nor order new certificates.
+    ///
+    /// A cached certificate is only used if it belongs to the cached key and
+    /// lists exactly the configured domains; otherwise a new one is ordered.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Renew certificates this long before they expire. Defaults to 30 days.
+    pub fn renew_before(mut self, duration: Duration) -> Self {
+        self.renew_before = duration;
+        self
+    }
+
+    /// How often `AcmeManager::run` checks whether renewal is due. Defaults to 12 hours.
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// How long `AcmeManager::run` waits after a failed renewal. Defaults to one hour.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Set the solver used to answer challenges.
+    ///
+    /// Defaults to answering `tls-alpn-01` challenges on `AcmeManager::acceptor`.
+    pub fn challenge_solver(mut self, solver: Arc<dyn ChallengeSolver>) -> Self {
+        self.solver = Some(solver);
+        self
+    }
+
+    /// Use this connector to talk to the ACME directory.
+    pub fn connector(mut self, connector: TlsConnector) -> Self {
+        self.connector = connector;
+        self
+    }
+
+    /// Called with every error `AcmeManager::run` encounters before it retries.
+    pub fn on_error<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&io::Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Create the manager. No network requests are made until it is driven.
+    pub fn build(mut self) -> AcmeManager {
+        let (solver, tls_alpn_01) = match self.solver.take() {
+            Some(solver) => (solver, None),
+            None => {
+                let responder = Arc::new(TlsAlpn01Responder::new());
+                (
+                    responder.clone() as Arc<dyn ChallengeSolver>,
+                    Some(responder),
+                )
+            }
+        };
+        AcmeManager {
+            config: self,
+            solver,
+            tls_alpn_01,
+            resolver: Arc::new(AcmeResolver::default()),
+            account: None,
+            not_after: None,
+        }
+    }
+}
+
+impl fmt::Debug for AcmeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcmeConfig")
+            .field("directory", &self.directory)
+            .field("domains", &self.domains)
+            .field("contacts", &self.contacts)
+            .field("cache_dir", &self.cache_dir)
+            .field("renew_before", &self.renew_before)
+            .finish()
+    }
+}
+
+/// Obtains and renews a certificate, and serves it through `acceptor`.
+pub struct AcmeManager {
+    config: AcmeConfig,
+    solver: Arc<dyn ChallengeSolver>,
+    tls_alpn_01: Option<Arc<TlsAlpn01Responder>>,
+    resolver: Arc<AcmeResolver>,
+    account: Option<AccountKey>,
+    not_after: Option<SystemTime>,
+}
+
+impl AcmeManager {
+    /// An acceptor that always serves the most recently issued certificate.
+    ///
+    /// Handshakes fail until the first certificate was obtained. Unless a
+    /// different solver was configured, it also answers `tls-alpn-01` challenges.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver());
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        match self.tls_alpn_01 {
+            Some(ref responder) => acceptor.tls_alpn_01(responder.clone()),
+            None => acceptor,
+        }
+    }
+
+    /// The built-in `tls-alpn-01` responder, for attaching it to custom acceptors.
+    ///
+    /// `None` if a different challenge solver was configured.
+    pub fn tls_alpn_01(&self) -> Option<Arc<TlsAlpn01Responder>> {
+        self.tls_alpn_01.clone()
+    }
+
+    /// The certificate resolver backing `acceptor`, for building a custom `ServerConfig`.
+    pub fn resolver(&self) -> Arc<dyn ResolvesServerCert> {
+        self.resolver.clone()
+    }
+
+    /// When the current certificate expires, if there is one.
+    pub fn not_after(&self) -> Option<SystemTime> {
+        self.not_after
+    }
+
+    /// Load a cached certificate or order a new one if it is missing or due for renewal.
+    ///
+    /// Returns whether a new certificate was ordered.
+    pub async fn renew_if_needed(&mut self) -> io::Result<bool> {
+        if self.not_after.is_none() {
+            self.load_cached_certificate().await?;
+        }
+        if let Some(not_after) = self.not_after {
+            if SystemTime::now() + self.config.renew_before < not_after {
+                return Ok(false);
+            }
+        }
+        self.order().await?;
+        Ok(true)
+    }
+
+    /// Keep the certificate renewed forever.
+    ///
+    /// Failures are reported to the `on_error` callback and retried after the retry interval.
+    pub async fn run(mut self) {
+        loop {
+            let wait = match self.renew_if_needed().await {
+                Ok(_) => self.config.check_interval,
+                Err(err) => {
+                    if let Some(ref on_error) = self.config.on_error {
+                        on_error(&err);
+                    }
+                    self.config.retry_interval
+                }
+            };
+            async_std::task::sleep(wait).await;
+        }
+    }
+
+    async fn load_cached_certificate(&mut self) -> io::Result<()> {
+        let dir = match self.config.cache_dir {
+            Some(ref dir) => dir,
+            None => return Ok(()),
+        };
+        let (chain, key) = match (
+            fs::read(dir.join("cert.pem")).await,
+            fs::read(dir.join("key.pem")).await,
+        ) {
+            (Ok(chain), Ok(key)) => (chain, key),
+            _ => return Ok(()),
+        };
+        let key = pem::private_key(&key)?;
+        // e.g. cached for different domains, or from a crash between
+        // replacing the key and the certificate
+        if !self.cache_matches(&chain, &key)? {
+            return Ok(());
+        }
+        self.install(&chain, key)
+    }
+
+    /// Whether a cached certificate belongs to `key` and lists exactly the configured domains.
+    fn cache_matches(&self, chain: &[u8], key: &PrivateKey) -> io::Result<bool> {
+        let certs = pem::certs(chain)?;
+        let tbs = certs
+            .first()
+            .and_then(|cert| der::tbs_certificate(&cert.0))
+            .ok_or_else(|| invalid("cached certificate is unreadable"))?;
+
+        let rng = ring::rand::SystemRandom::new();
+        // certificates are always ordered for P-256 keys
+        let pair = match EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &key.0, &rng) {
+            Ok(pair) => pair,
+            Err(_) => return Ok(false),
+        };
+        if Some(pair.public_key().as_ref()) != tbs.public_key() {
+            return Ok(false);
+        }
+
+        let mut names: Vec<_> = tbs.dns_names().iter().map(|n| n.to_lowercase()).collect();
+        let mut domains: Vec<_> = self
+            .config
+            .domains
+            .iter()
+            .map(|d| d.to_lowercase())
+            .collect();
+        names.sort();
+        names.dedup();
+        domains.sort();
+        domains.dedup();
+        Ok(names == domains)
+    }
+
+    fn install(&mut self, chain: &[u8], key: PrivateKey) -> io::Result<()> {
+        let certs = pem::certs(chain)?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| invalid("issued certificate chain is empty"))?;
+        let not_after = der::validity(&leaf.0)
+            .map(|(_, not_after)| not_after)
+            .ok_or_else(|| invalid("issued certificate has no readable validity"))?;
+        let key = rustls::sign::any_supported_type(&key)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        *self.resolver.cert.write().unwrap() = Some(Arc::new(CertifiedKey::new(certs, key)));
+        self.not_after = Some(not_after);
+        Ok(())
+    }
+
+    async fn load_account_key(&mut self) -> io::Result<()> {
+        if self.account.is_some() {
+            return Ok(());
+        }
+        let path = self
+            .config
+            .cache_dir
+            .as_ref()
+            .map(|d| d.join("account.pk8"));
+        let cached = match path {
+            Some(ref path) => fs::read(path).await.ok(),
+            None => None,
+        };
+        let key = match cached {
+            Some(pkcs8) => AccountKey::from_pkcs8(&pkcs8)?,
+            None => {
+                let key = AccountKey::generate()?;
+                if let Some(path) = path {
+                    fs::create_dir_all(path.parent().unwrap()).await?;
+                    write_atomic(path, key.pkcs8().to_vec(), true).await?;
+                }
+                key
+            }
+        };
+        self.account = Some(key);
+        Ok(())
+    }
+
+    async fn order(&mut self) -> io::Result<()> {
+        self.load_account_key().await?;
+        let key = self.account.as_ref().unwrap();
+        let (chain, cert) = order(&self.config, &*self.solver, key).await?;
+
+        if let Some(ref dir) = self.config.cache_dir {
+            fs::create_dir_all(dir).await?;
+            // the key first: a crash in between leaves a pair that does not
+            // match, which the next start orders again
+            let key = cert.serialize_private_key_pem().into_bytes();
+            write_atomic(dir.join("key.pem"), key, true).await?;
+            write_atomic(dir.join("cert.pem"), chain.clone(), false).await?;
+        }
+        self.install(&chain, PrivateKey(cert.serialize_private_key_der()))
+    }
+}
+
+/// Run a complete order, returning the PEM chain and the certificate holding its key.
+async fn order(
+    config: &AcmeConfig,
+    solver: &dyn ChallengeSolver,
+    key: &AccountKey,
+) -> io::Result<(Vec<u8>, rcgen::Certificate)> {
+    let mut session = Session::new(&config.connector, key, &config.directory).await?;
+    let new_account = session.directory.new_account.clone();
+    let response = session
+        .post(
+            &new_account,
+            Some(&json!({
+                "termsOfServiceAgreed": true,
+                "contact": config.contacts,
+            })),
+        )
+        .await?;
+    session.kid = Some(
+        response
+            .header("Location")
+            .ok_or_else(|| invalid("ACME account response without Location"))?
+            .to_string(),
+    );
+
+    let identifiers: Vec<_> = config
+        .domains
+        .iter()
+        .map(|domain| json!({ "type": "dns", "value": domain }))
+        .collect();
+    let new_order = session.directory.new_order.clone();
+    let response = session
+        .post(&new_order, Some(&json!({ "identifiers": identifiers })))
+        .await?;
+    let order_url = response
+        .header("Location")
+        .ok_or_else(|| invalid("ACME order response without Location"))?
+        .to_string();
+    let order = json_body(&response)?;
+
+    for authz in order["authorizations"].as_array().into_iter().flatten() {
+        let authz = authz
+            .as_str()
+            .ok_or_else(|| invalid("invalid authorization"))?;
+        session.authorize(authz, solver).await?;
+    }
+
+    let mut params = rcgen::CertificateParams::new(config.domains.clone());
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    if let Some(domain) = config.domains.first() {
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, domain.as_str());
+    }
+    let cert = rcgen::Certificate::from_params(params).map_err(io::Error::other)?;
+    let csr = cert.serialize_request_der().map_err(io::Error::other)?;
+
+    let finalize = order["finalize"]
+        .as_str()
+        .ok_or_else(|| invalid("ACME order without finalize URL"))?;
+    session
+        .post(finalize, Some(&json!({ "csr": jose::b64(csr) })))
+        .await?;
+    let order = session.poll(&order_url).await?;
+    let certificate = order["certificate"]
+        .as_str()
+        .ok_or_else(|| invalid("ACME order without certificate URL"))?;
+    let chain = session.post(certificate, None).await?.body;
+
+    Ok((chain, cert))
+}
+
+impl fmt::Debug for AcmeManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcmeManager")
+            .field("config", &self.config)
+            .field("not_after", &self.not_after)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct AcmeResolver {
+    cert: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for AcmeResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.cert.read().unwrap().clone()
+    }
+}
+
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// An authenticated conversation with the ACME server.
+struct Session<'a> {
+    connector: &'a TlsConnector,
+    key: &'a AccountKey,
+    directory: Directory,
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl<'a> Session<'a> {
+    async fn new(connector: &'a TlsConnector, key: &'a AccountKey, url: &str) -> io::Result<Self> {
+        let response = http::request(connector, "GET", url, None).await?;
+        let directory = json_body(&response)?;
+        let url = |name: &str| {
+            directory[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid("incomplete ACME directory"))
+        };
+        Ok(Session {
+            connector,
+            key,
+            directory: Directory {
+                new_nonce: url("newNonce")?,
+                new_account: url("newAccount")?,
+                new_order: url("newOrder")?,
+            },
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    async fn nonce(&mut self) -> io::Result<String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response =
+            http::request(self.connector, "HEAD", &self.directory.new_nonce, None).await?;
+        response
+            .header("Replay-Nonce")
+            .map(str::to_string)
+            .ok_or_else(|| invalid("ACME server sent no nonce"))
+    }
+
+    /// A signed POST, or a POST-as-GET if `payload` is `None`.
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> io::Result<http::Response> {
+        let mut retried = false;
+        loop {
+            let nonce = self.nonce().await?;
+            let body = self.key.sign(url, &nonce, self.kid.as_deref(), payload)?;
+            let response = http::request(
+                self.connector,
+                "POST",
+                url,
+                Some(("application/jose+json", &body)),
+            )
+            .await?;
+            self.nonce = response.header("Replay-Nonce").map(str::to_string);
+
+            if response.status < 400 {
+                return Ok(response);
+            }
+            let problem: Value = serde_json::from_slice(&response.body).unwrap_or(Value::Null);
+            if problem["type"] == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+            return Err(io::Error::other(format!(
+                "ACME request failed with status {}: {}",
+                response.status, problem
+            )));
+        }
+    }
+
+    /// Fetch `url` until its status is `valid`.
+    async fn poll(&mut self, url: &str) -> io::Result<Value> {
+        for _ in 0..30 {
+            let response = self.post(url, None).await?;
+            let body = json_body(&response)?;
+            match body["status"].as_str() {
+                Some("valid") => return Ok(body),
+                Some("pending") | Some("processing") | Some("ready") => (),
+                _ => {
+                    return Err(io::Error::other(format!(
+                        "ACME validation failed: {}",
+                        body
+                    )))
+                }
+            }
+            let retry_after = response
+                .header("Retry-After")
+                .and_then(|secs| secs.parse().ok())
+                .unwrap_or(2);
+            async_std::task::sleep(Duration::from_secs(retry_after)).await;
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "ACME server did not finish in time",
+        ))
+    }
+
+    async fn authorize(&mut self, url: &str, solver: &dyn ChallengeSolver) -> io::Result<()> {
+        let authz = json_body(&self.post(url, None).await?)?;
+        if authz["status"] == "valid" {
+            return Ok(());
+        }
+
+        let kind = solver.kind();
+        let offered = authz["challenges"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|c| c["type"] == kind.as_str())
+            .ok_or_else(|| {
+                io::Error::other(format!("ACME server does not offer {}", kind.as_str()))
+            })?;
+        let token = offered["token"]
+            .as_str()
+            .ok_or_else(|| invalid("ACME challenge without token"))?;
+        let challenge_url = offered["url"]
+            .as_str()
+            .ok_or_else(|| invalid("ACME challenge without URL"))?;
+        let challenge = Challenge {
+            kind,
+            domain: authz["identifier"]["value"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            token: token.to_string(),
+            key_authorization: format!("{}.{}", token, self.key.thumbprint()),
+        };
+
+        solver.present(&challenge).await?;
+        let result = async {
+            self.post(challenge_url, Some(&json!({}))).await?;
+            self.poll(url).await
+        }
+        .await;
+        solver.cleanup(&challenge).await?;
+        result.map(drop)
+    }
+}
+
+/// Replace `path` with `contents` through a temporary file, so that neither
+/// readers nor a crash ever see it half-written. With `private`, only the
+/// owner may read it.
+async fn write_atomic(path: PathBuf, contents: Vec<u8>, private: bool) -> io::Result<()> {
+    async_std::task::spawn_blocking(move || {
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if private {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        // the mode only applies to new files
+        #[cfg(unix)]
+        if private {
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        }
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)
+    })
+    .await
+}
+
+fn json_body(response: &http::Response) -> io::Result<Value> {
+    serde_json::from_slice(&response.body)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
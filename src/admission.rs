@@ -0,0 +1,115 @@
+//! Admitting clients by their `ClientHello`, before the handshake starts.
+
+#[cfg(feature = "fingerprint")]
+use crate::fingerprint::ClientHelloFingerprint;
+
+use rustls::server::ClientHello;
+use rustls::{AlertDescription, CipherSuite, SignatureScheme};
+use std::fmt;
+
+pub(crate) type AdmissionCallback = dyn Fn(&ClientInfo<'_>) -> Admission + Send + Sync;
+
+/// Whether a client may continue its handshake, see `TlsAcceptor::admit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Continue the handshake.
+    Accept,
+    /// Abort the handshake with a fatal alert, e.g. `ProtocolVersion` for
+    /// obsolete clients or `HandshakeFailure` otherwise.
+    Reject(AlertDescription),
+}
+
+/// What a client offered in its `ClientHello`, for `TlsAcceptor::admit`.
+pub struct ClientInfo<'a> {
+    client_hello: ClientHello<'a>,
+    #[cfg(feature = "fingerprint")]
+    fingerprint: Option<&'a ClientHelloFingerprint>,
+}
+
+impl fmt::Debug for ClientInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alpn: Vec<_> = (self.alpn().into_iter())
+            .map(String::from_utf8_lossy)
+            .collect();
+        f.debug_struct("ClientInfo")
+            .field("server_name", &self.server_name())
+            .field("alpn", &alpn)
+            .field("cipher_suites", &self.cipher_suites())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> ClientInfo<'a> {
+    pub(crate) fn new(client_hello: ClientHello<'a>) -> Self {
+        ClientInfo {
+            client_hello,
+            #[cfg(feature = "fingerprint")]
+            fingerprint: None,
+        }
+    }
+
+    #[cfg(feature = "fingerprint")]
+    pub(crate) fn with_fingerprint(
+        mut self,
+        fingerprint: Option<&'a ClientHelloFingerprint>,
+    ) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    /// The name the client asked for (SNI).
+    pub fn server_name(&self) -> Option<&str> {
+        self.client_hello.server_name()
+    }
+
+    /// The application protocols offered with ALPN, empty if none.
+    pub fn alpn(&self) -> Vec<&'a [u8]> {
+        match self.client_hello.alpn() {
+            Some(protocols) => protocols.collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The cipher suites, in the client's order of preference.
+    pub fn cipher_suites(&self) -> &[CipherSuite] {
+        self.client_hello.cipher_suites()
+    }
+
+    /// The signature schemes the client can verify.
+    pub fn signature_schemes(&self) -> &[SignatureScheme] {
+        self.client_hello.signature_schemes()
+    }
+
+    /// The `ClientHello` as parsed by rustls.
+    pub fn client_hello(&self) -> &ClientHello<'a> {
+        &self.client_hello
+    }
+
+    /// The fingerprint of the `ClientHello`, with the versions the client
+    /// offered, see `ClientHelloFingerprint::max_version`. `None` if it could
+    /// not be parsed.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(&self) -> Option<&'a ClientHelloFingerprint> {
+        self.fingerprint
+    }
+}
+
+/// The record of a fatal `alert`, sent in the clear before the handshake.
+pub(crate) fn alert_record(alert: AlertDescription) -> [u8; 7] {
+    const ALERT: u8 = 0x15;
+    const FATAL: u8 = 2;
+    [ALERT, 3, 3, 0, 2, FATAL, alert.get_u8()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_alert_records() {
+        assert_eq!(
+            alert_record(AlertDescription::ProtocolVersion),
+            [0x15, 3, 3, 0, 2, 2, 70]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Recording the TLS bytes of connections, to attach to bug reports.
+//!
+//! A `Recorder` wraps the IO passed to `TlsConnector::connect` or
+//! `TlsAcceptor::accept` and writes everything read from and written to it
+//! to a pcap file. The bytes are framed as one TCP connection between
+//! `10.0.0.1:49152`, the recorded end, and `10.0.0.2:443`, its peer, so that
+//! Wireshark dissects them as TLS. Timestamps are those of the reads and writes.
+//!
+//! With a `KeyLogWriter` set as the `key_log` of the `ClientConfig` or
+//! `ServerConfig`, Wireshark decrypts the capture as well: point its
+//! "(Pre)-Master-Secret log filename" TLS preference at the key log.
+//! Anyone with the key log can decrypt the traffic, so only use it for test
+//! connections.
+//!
+//! Recording writes to the file from the task driving the connection, and
+//! blocks it while doing so. If writing fails, recording stops while the
+//! connection goes on; `Recorder::is_recording` tells.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::capture::Recorder;
+//! use async_tls::TlsConnector;
+//!
+//! # async_std::task::block_on(async {
+//! let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await?;
+//! let recorded = Recorder::create(tcp_stream, "example.pcap")?;
+//! let stream = TlsConnector::new().connect("example.com", recorded).await?;
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::KeyLog;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Raw IPv4 packets, without a link layer header.
+const LINKTYPE_IPV4: u32 = 228;
+
+const LOCAL: ([u8; 4], u16) = ([10, 0, 0, 1], 49152);
+const PEER: ([u8; 4], u16) = ([10, 0, 0, 2], 443);
+
+/// The most payload that fits a packet after the IPv4 and TCP headers.
+const MAX_PAYLOAD: usize = 0xffff - 40;
+
+/// An IO wrapper recording the bytes read and written, see the module documentation.
+pub struct Recorder<IO> {
+    inner: IO,
+    sink: Option<Box<dyn Write + Send>>,
+    /// The next sequence numbers of the recorded end and its peer.
+    local_seq: u32,
+    peer_seq: u32,
+}
+
+impl<IO> Recorder<IO> {
+    /// Record `inner` to a new pcap file at `path`, replacing any file there.
+    pub fn create(inner: IO, path: impl AsRef<Path>) -> io::Result<Self> {
+        Recorder::new(inner, File::create(path)?)
+    }
+
+    /// Record `inner` in pcap format to `sink`.
+    pub fn new(inner: IO, sink: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut sink: Box<dyn Write + Send> = Box::new(sink);
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&0xffffu32.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_IPV4.to_le_bytes());
+        sink.write_all(&header)?;
+        Ok(Recorder {
+            inner,
+            sink: Some(sink),
+            local_seq: 1,
+            peer_seq: 1,
+        })
+    }
+
+    /// Whether the bytes are still being recorded, i.e. writing them never failed.
+    pub fn is_recording(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Get a reference to the wrapped IO.
+    pub fn get_ref(&self) -> &IO {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped IO.
+    ///
+    /// Bytes read from or written to it directly are not recorded.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.inner
+    }
+
+    /// Unwrap the IO, flushing the recording.
+    pub fn into_inner(mut self) -> IO {
+        if let Some(mut sink) = self.sink.take() {
+            let _ = sink.flush();
+        }
+        self.inner
+    }
+
+    /// Record `data` as sent by the recorded end if `sent`, or else by its peer.
+    fn record(&mut self, sent: bool, data: &[u8]) {
+        let Recorder {
+            sink,
+            local_seq,
+            peer_seq,
+            ..
+        } = self;
+        let out = match sink {
+            Some(out) => out,
+            None => return,
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let result = data.chunks(MAX_PAYLOAD).try_for_each(|payload| {
+            let (src, dst, seq, ack) = match sent {
+                true => (LOCAL, PEER, &mut *local_seq, *peer_seq),
+                false => (PEER, LOCAL, &mut *peer_seq, *local_seq),
+            };
+            let packet = packet(src, dst, *seq, ack, payload);
+            *seq = seq.wrapping_add(payload.len() as u32);
+            let mut record = Vec::with_capacity(16 + packet.len());
+            record.extend_from_slice(&(time.as_secs() as u32).to_le_bytes());
+            record.extend_from_slice(&time.subsec_micros().to_le_bytes());
+            record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            record.extend_from_slice(&packet);
+            out.write_all(&record)
+        });
+        if result.is_err() {
+            *sink = None;
+        }
+    }
+}
+
+/// An IPv4 packet with a TCP segment carrying `payload`.
+///
+/// The TCP checksum is left out, which Wireshark does not check by default.
+fn packet(
+    (src_ip, src_port): ([u8; 4], u16),
+    (dst_ip, dst_port): ([u8; 4], u16),
+    seq: u32,
+    ack: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let len = 40 + payload.len();
+    let mut packet = Vec::with_capacity(len);
+    // IPv4: version 4, 5 words of header, don't fragment, TTL 64, TCP
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&(len as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&src_ip);
+    packet.extend_from_slice(&dst_ip);
+    let checksum = checksum(&packet);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    // TCP: 5 words of header, PSH and ACK
+    packet.extend_from_slice(&src_port.to_be_bytes());
+    packet.extend_from_slice(&dst_port.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&ack.to_be_bytes());
+    packet.extend_from_slice(&[0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// The internet checksum of an IPv4 header.
+fn checksum(header: &[u8]) -> u16 {
+    let mut sum = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Recorder<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(len)) = result {
+            this.record(false, &buf[..len]);
+        }
+        result
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Recorder<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(len)) = result {
+            this.record(true, &buf[..len]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(ref mut sink) = self.sink {
+            if sink.flush().is_err() {
+                self.sink = None;
+            }
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(ref mut sink) = self.sink {
+            if sink.flush().is_err() {
+                self.sink = None;
+            }
+        }
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl<IO: fmt::Debug> fmt::Debug for Recorder<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("inner", &self.inner)
+            .field("recording", &self.is_recording())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes the secrets of connections in the NSS key log format, which
+/// Wireshark and other tools use to decrypt captured traffic.
+///
+/// Set it as the `key_log` of a `ClientConfig` or `ServerConfig`. Anyone
+/// with the file can decrypt the connections logged to it.
+pub struct KeyLogWriter {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl KeyLogWriter {
+    /// Append the secrets to the file at `path`, creating it if needed.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(KeyLogWriter::new(file))
+    }
+
+    /// Write the secrets to `sink`.
+    pub fn new(sink: impl Write + Send + 'static) -> Self {
+        KeyLogWriter {
+            sink: Mutex::new(Box::new(sink)),
+        }
+    }
+}
+
+impl KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+        let mut sink = self.sink.lock().unwrap();
+        // a failed write only makes the log incomplete
+        let _ = sink.write_all(line.as_bytes()).and_then(|_| sink.flush());
+    }
+}
+
+impl fmt::Debug for KeyLogWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyLogWriter").finish_non_exhaustive()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_packets() {
+        let packet = packet(LOCAL, PEER, 7, 1, b"hello");
+        assert_eq!(packet.len(), 45);
+        assert_eq!(&packet[2..4], &45u16.to_be_bytes());
+        // the checksum of a header with its checksum is 0
+        assert_eq!(checksum(&packet[..20]), 0);
+        assert_eq!(&packet[20..22], &49152u16.to_be_bytes());
+        assert_eq!(&packet[22..24], &443u16.to_be_bytes());
+        assert_eq!(&packet[24..28], &7u32.to_be_bytes());
+        assert_eq!(&packet[40..], b"hello");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::common::pem;
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+/// A set of certificates selected by the SNI hostname of each client.
+///
+/// Certificates can be added and removed at runtime, e.g. when customer domains
+/// are onboarded. `CertStore` is a handle: clones share the same certificates,
+/// so keep one around after turning another into a `TlsAcceptor`.
+///
+/// Besides exact hostnames, wildcard entries such as `*.example.com` can be
+/// registered. An exact entry always wins, otherwise the wildcard with the
+/// longest matching suffix is used.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::{CertStore, TlsAcceptor};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let store = CertStore::new();
+/// let acceptor = TlsAcceptor::from(store.clone());
+///
+/// // later, while serving connections:
+/// store.insert_pem(
+///     "customer.example",
+///     &std::fs::read("customer.crt")?,
+///     &std::fs::read("customer.key")?,
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct CertStore {
+    inner: Arc<RwLock<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    certs: HashMap<String, Arc<CertifiedKey>>,
+    fallback: Option<Arc<CertifiedKey>>,
+}
+
+impl CertStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Serve `cert` to clients asking for `hostname`, replacing any previous certificate.
+    ///
+    /// `hostname` may be a wildcard like `*.example.com`.
+    pub fn insert(&self, hostname: &str, cert: CertifiedKey) -> Option<Arc<CertifiedKey>> {
+        self.inner
+            .write()
+            .unwrap()
+            .certs
+            .insert(normalize(hostname), Arc::new(cert))
+    }
+
+    /// Like `insert`, parsing a PEM certificate chain and private key.
+    pub fn insert_pem(&self, hostname: &str, cert_pem: &[u8], key_pem: &[u8]) -> io::Result<()> {
+        let certs = pem::certs(cert_pem)?;
+        let key = rustls::sign::any_supported_type(&pem::private_key(key_pem)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.insert(hostname, CertifiedKey::new(certs, key));
+        Ok(())
+    }
+
+    /// Stop serving a certificate for `hostname`.
+    pub fn remove(&self, hostname: &str) -> Option<Arc<CertifiedKey>> {
+        self.inner
+            .write()
+            .unwrap()
+            .certs
+            .remove(&normalize(hostname))
+    }
+
+    /// The certificate that would be served for `hostname`, including wildcard matches.
+    pub fn get(&self, hostname: &str) -> Option<Arc<CertifiedKey>> {
+        self.inner
+            .read()
+            .unwrap()
+            .lookup(&normalize(hostname))
+            .cloned()
+    }
+
+    /// Whether a certificate is registered for exactly `hostname`.
+    pub fn contains(&self, hostname: &str) -> bool {
+        self.inner
+            .read()
+            .unwrap()
+            .certs
+            .contains_key(&normalize(hostname))
+    }
+
+    /// Serve `cert` to clients sending no SNI or an unknown hostname.
+    ///
+    /// Without a fallback, such handshakes fail.
+    pub fn set_fallback(&self, cert: Option<CertifiedKey>) {
+        self.inner.write().unwrap().fallback = cert.map(Arc::new);
+    }
+}
+
+impl Inner {
+    fn lookup(&self, hostname: &str) -> Option<&Arc<CertifiedKey>> {
+        if let Some(cert) = self.certs.get(hostname) {
+            return Some(cert);
+        }
+
+        // a.b.example.com tries *.b.example.com, then *.example.com, ...
+        let mut rest = hostname;
+        while let Some((_, parent)) = rest.split_once('.') {
+            if let Some(cert) = self.certs.get(&format!("*.{}", parent)) {
+                return Some(cert);
+            }
+            rest = parent;
+        }
+        None
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let inner = self.inner.read().unwrap();
+        client_hello
+            .server_name()
+            .and_then(|name| inner.lookup(&normalize(name)))
+            .or(inner.fallback.as_ref())
+            .cloned()
+    }
+}
+
+impl fmt::Debug for CertStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.read().unwrap();
+        f.debug_struct("CertStore")
+            .field("hostnames", &inner.certs.keys().collect::<Vec<_>>())
+            .field("fallback", &inner.fallback.is_some())
+            .finish()
+    }
+}
+
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
@@ -1,34 +1,187 @@
 //! The client end of a TLS connection.
 
+use crate::common::cancel;
+use crate::common::channel_binding;
+use crate::common::chunking::WriteChunking;
+use crate::common::close::{CloseMode, CloseTimer};
+use crate::common::coalesce::Coalescer;
+#[cfg(feature = "tokio")]
+use crate::common::compat;
+use crate::common::flush::HandshakeFlush;
+use crate::common::hello::HelloSniffer;
+use crate::common::io_state::IoState;
 use crate::common::tls_state::TlsState;
+use crate::common::traffic::TrafficCounters;
+#[cfg(feature = "ct")]
+use crate::ct::{CtLog, CtPolicy};
+use crate::error::{Error, HandshakeError, HandshakeStage};
+use crate::observer::{HandshakeParams, HandshakeTimings, Observation};
 use crate::rusttls::stream::Stream;
+#[cfg(feature = "tofu")]
+use crate::tofu::TofuVerifier;
+#[cfg(feature = "tofu")]
+use crate::BoxFuture;
+use crate::HandshakeKind;
+use futures_core::future::FusedFuture;
 use futures_core::ready;
 use futures_io::{AsyncRead, AsyncWrite};
-use rustls::ClientConnection;
-use std::future::Future;
+use rustls::{ClientConnection, NamedGroup};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io::{Read, Write};
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+#[cfg(feature = "async-std")]
+use std::time::Duration;
+use std::time::Instant;
+#[cfg(feature = "ct")]
+use std::time::SystemTime;
 use std::{io, mem};
 
 /// The client end of a TLS connection. Can be used like any other bidirectional IO stream.
 /// Wraps the underlying TCP stream.
-#[derive(Debug)]
+///
+/// Its `Debug` output shows the state of the connection and what was
+/// negotiated, never the data or keys it holds.
 pub struct TlsStream<IO> {
     pub(crate) io: IO,
     pub(crate) session: ClientConnection,
     pub(crate) state: TlsState,
+    /// The name connected to, as given to the connector.
+    pub(crate) server_name: String,
+    /// Whether an EOF without `close_notify` is an error.
+    pub(crate) strict_close_notify: bool,
+    /// Whether closing the stream sends `close_notify`.
+    pub(crate) send_close_notify: bool,
+    /// What closing the stream waits for.
+    pub(crate) close_mode: CloseMode,
+    pub(crate) close_timer: CloseTimer,
+    /// Whether reads stop at the end of each record, once shutting down.
+    pub(crate) exact_reads: bool,
+    /// When the handshake flushes the IO.
+    pub(crate) handshake_flush: HandshakeFlush,
+    /// How much of a caller's buffer a write takes.
+    pub(crate) write_chunking: WriteChunking,
+    /// Keeps small writes back, to encrypt them together.
+    pub(crate) coalescer: Coalescer,
+    /// Tells whether the session was resumed.
+    pub(crate) hello: HelloSniffer,
+    pub(crate) observation: Observation,
+    /// Plaintext read by `peek`, which the next reads return.
+    pub(crate) peeked: Vec<u8>,
+    /// The state of the buffers, as rustls told it to the stream last.
+    pub(crate) io_state: Option<IoState>,
 
-    #[cfg(feature = "early-data")]
+    /// The early data written so far, and how much of it was sent again.
     pub(crate) early_data: (usize, Vec<u8>),
+    /// How much early data may be kept to send again, and what happens beyond.
+    pub(crate) early_data_limit: (usize, EarlyDataOverflow),
+    /// What happens to early data the server rejected.
+    pub(crate) rejected_early_data: RejectedEarlyData,
+    /// Tells the `EarlyData` handle how the handshake ended.
+    pub(crate) notify_early_data: Option<EarlyDataNotifier>,
+
+    /// The CAs the server asked for a client certificate from, if recorded.
+    pub(crate) acceptable_issuers: Option<Arc<OnceLock<Vec<Vec<u8>>>>>,
+
+    #[cfg(feature = "ocsp")]
+    /// The OCSP response stapled by the server, and whether it was valid.
+    pub(crate) ocsp: Arc<OnceLock<(Vec<u8>, bool)>>,
+
+    #[cfg(feature = "ct")]
+    pub(crate) ct_policy: Option<Arc<CtPolicy>>,
+    #[cfg(feature = "ct")]
+    pub(crate) ct_logs: Vec<CtLog>,
+
+    /// The verifier the server is checked with, until the handshake is done.
+    #[cfg(feature = "tofu")]
+    pub(crate) tofu: Option<Arc<TofuVerifier>>,
 }
 
-pub(crate) enum MidHandshake<IO> {
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Handshake<IO> {
     Handshaking(TlsStream<IO>),
-    #[cfg(feature = "early-data")]
     EarlyData(TlsStream<IO>),
+    #[cfg(feature = "tofu")]
+    Trusting(TlsStream<IO>, BoxFuture<'static, io::Result<()>>),
     End,
 }
 
+impl<IO> Handshake<IO> {
+    pub(crate) fn get_ref(&self) -> Option<&IO> {
+        match self {
+            Handshake::Handshaking(stream) | Handshake::EarlyData(stream) => Some(stream.get_ref()),
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(stream, _) => Some(stream.get_ref()),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut IO> {
+        match self {
+            Handshake::Handshaking(stream) | Handshake::EarlyData(stream) => Some(stream.get_mut()),
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(stream, _) => Some(stream.get_mut()),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn stage(&self) -> Option<HandshakeStage> {
+        match self {
+            Handshake::Handshaking(stream) | Handshake::EarlyData(stream) => {
+                Some(HandshakeStage::of(&stream.session))
+            }
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(..) => Some(HandshakeStage::Verifying),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self, Handshake::End)
+    }
+
+    /// Cancel the handshake, see `Connect::abort`.
+    pub(crate) async fn abort(self, notify: bool) -> Option<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = match self {
+            Handshake::Handshaking(stream) | Handshake::EarlyData(stream) => stream,
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(stream, _) => stream,
+            Handshake::End => return None,
+        };
+        if notify {
+            cancel::cancel(&mut stream.io, Some(&mut stream.session)).await;
+        }
+        Some(stream.io)
+    }
+}
+
+impl<IO: fmt::Debug> fmt::Debug for TlsStream<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsStream")
+            .field("io", &self.io)
+            .field("server_name", &self.server_name)
+            .field("handshaking", &self.session.is_handshaking())
+            .field("state", &self.state)
+            .field("protocol_version", &self.session.protocol_version())
+            .field(
+                "cipher_suite",
+                &self.session.negotiated_cipher_suite().map(|s| s.suite()),
+            )
+            .field(
+                "alpn_protocol",
+                &self.session.alpn_protocol().map(String::from_utf8_lossy),
+            )
+            .field("early_data_buffered", &self.early_data.1.len())
+            .field("counters", &self.counters())
+            .finish_non_exhaustive()
+    }
+}
+
 impl<IO> TlsStream<IO> {
     /// Returns a reference to the underlying IO stream.
     pub fn get_ref(&self) -> &IO {
@@ -39,9 +192,309 @@ impl<IO> TlsStream<IO> {
     pub fn get_mut(&mut self) -> &mut IO {
         &mut self.io
     }
+
+    /// Reads plaintext that was already received and decrypted, without any IO.
+    ///
+    /// Once reading from the stream failed, or the peer sent `close_notify`,
+    /// data the peer sent before can still be buffered. Returns the number of
+    /// bytes read, `0` when nothing is buffered.
+    pub fn read_buffered(&mut self, buf: &mut [u8]) -> usize {
+        if !self.peeked.is_empty() {
+            return self.take_peeked(buf);
+        }
+        let len = self.session.reader().read(buf).unwrap_or(0);
+        if let Some(state) = self.io_state.as_mut() {
+            state.read(len);
+        }
+        len
+    }
+
+    /// Returns the state of the buffers of the connection, without any IO:
+    /// the plaintext received but not read yet, the TLS bytes waiting to be
+    /// sent, and whether the peer sent `close_notify`.
+    ///
+    /// Lets applications schedule by what is buffered, e.g. read plaintext
+    /// already received before polling other streams. rustls tells the state
+    /// as records are processed, and the stream keeps it up to date as
+    /// plaintext is read and records are sent. Writes kept back by
+    /// `coalesce_writes` are not encrypted yet, so not counted. `None` before
+    /// any records were processed, and once the connection failed.
+    pub fn io_state(&self) -> Option<&IoState> {
+        self.io_state.as_ref()
+    }
+
+    /// Returns how many bytes of plaintext were received but not read yet,
+    /// including those peeked at.
+    ///
+    /// See `io_state`, which does not count what was peeked at.
+    pub fn plaintext_bytes_to_read(&self) -> usize {
+        let buffered = self
+            .io_state()
+            .map_or(0, |state| state.plaintext_bytes_to_read());
+        self.peeked.len() + buffered
+    }
+
+    /// Move what was peeked at into `buf`, returning how much.
+    fn take_peeked(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.peeked.len());
+        buf[..len].copy_from_slice(&self.peeked[..len]);
+        self.peeked.drain(..len);
+        len
+    }
+
+    /// Returns how many bytes of TLS records wait to be sent.
+    ///
+    /// See `io_state`.
+    pub fn tls_bytes_to_write(&self) -> usize {
+        self.io_state()
+            .map_or(0, |state| state.tls_bytes_to_write())
+    }
+
+    /// Returns whether the peer sent `close_notify`, so nothing more is to be read.
+    ///
+    /// See `io_state`.
+    pub fn peer_has_closed(&self) -> bool {
+        self.io_state().is_some_and(|state| state.peer_has_closed())
+    }
+
+    /// Writes `buf` as early data, to be sent along with the `ClientHello`.
+    ///
+    /// Only possible on streams from `TlsConnector::connect_0rtt`, until the
+    /// handshake is done, and up to the amount the server allows. Returns the
+    /// number of bytes written, `0` once no more early data can be written.
+    /// Unlike writing to the stream, this does not wait for the handshake.
+    ///
+    /// Fails once the early data buffer is full if the connector was set up
+    /// with `EarlyDataOverflow::Fail`, see `TlsConnector::early_data_buffer`.
+    pub fn write_early_data(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !matches!(self.state, TlsState::EarlyData) {
+            return Ok(0);
+        }
+        let (limit, overflow) = self.early_data_limit;
+        let room = limit.saturating_sub(self.early_data.1.len());
+        let mut early_data = match self.session.early_data() {
+            Some(early_data) => early_data,
+            None => return Ok(0),
+        };
+        if room == 0 && !buf.is_empty() {
+            return match overflow {
+                EarlyDataOverflow::Wait => Ok(0),
+                EarlyDataOverflow::Fail => Err(io::Error::other("early data buffer is full")),
+            };
+        }
+        let len = early_data.write(&buf[..buf.len().min(room)])?;
+        self.early_data.1.extend_from_slice(&buf[..len]);
+        self.observation.written(len);
+        Ok(len)
+    }
+
+    /// Returns how the connection was set up: with a full handshake, or by
+    /// resuming a session from an earlier connection.
+    ///
+    /// `None` while a handshake with early data is still in progress, or if
+    /// the handshake could not be told apart.
+    pub fn handshake_kind(&self) -> Option<HandshakeKind> {
+        if self.session.is_handshaking() {
+            return None;
+        }
+        match self.hello.kind()? {
+            HandshakeKind::Resumed if self.session.is_early_data_accepted() => {
+                Some(HandshakeKind::ResumedWithEarlyData)
+            }
+            kind => Some(kind),
+        }
+    }
+
+    /// Returns whether a session from an earlier connection was resumed.
+    pub fn is_resumed(&self) -> bool {
+        self.handshake_kind().is_some_and(HandshakeKind::is_resumed)
+    }
+
+    /// Returns the group the keys were exchanged with, e.g. `X25519`.
+    ///
+    /// `None` while a handshake with early data is still in progress, for
+    /// resumed TLS 1.2 sessions, or if the group could not be told.
+    pub fn key_exchange_group(&self) -> Option<NamedGroup> {
+        if self.session.is_handshaking() {
+            return None;
+        }
+        self.hello.key_exchange_group()
+    }
+
+    /// Returns the `tls-exporter` channel binding of RFC 9266, for SASL
+    /// mechanisms such as SCRAM-SHA-256-PLUS.
+    ///
+    /// Fails with `Unsupported` for TLS 1.2 connections, where it is only safe
+    /// with the extended master secret, which rustls does not tell about.
+    /// `tls-unique` is not available either: rustls does not expose the
+    /// `Finished` messages it is made of.
+    pub fn tls_exporter_channel_binding(&self) -> io::Result<[u8; 32]> {
+        channel_binding::tls_exporter(&self.session)
+    }
+
+    /// Returns how long the handshake took.
+    ///
+    /// `None` while a handshake with early data is still in progress.
+    pub fn handshake_timings(&self) -> Option<HandshakeTimings> {
+        self.observation.timings()
+    }
+
+    /// Returns how much was read from and written to the stream so far.
+    ///
+    /// Counts plaintext as well as TLS bytes and records, so proxies can do
+    /// accounting without wrapping the underlying stream.
+    pub fn counters(&self) -> TrafficCounters {
+        self.observation.counters()
+    }
+
+    /// Returns the DER-encoded names of the CAs the server accepts client
+    /// certificates from, empty if it asked for one without naming any.
+    ///
+    /// `None` unless the server asked for a certificate and the connector
+    /// records requests (see `TlsConnector::record_certificate_requests`),
+    /// which servers do not on resumed sessions.
+    pub fn acceptable_issuers(&self) -> Option<&[Vec<u8>]> {
+        let issuers = self.acceptable_issuers.as_ref()?;
+        issuers.get().map(Vec::as_slice)
+    }
+
+    /// Returns the OCSP response stapled by the server.
+    ///
+    /// Only available if the connector checks staples (see `TlsConnector::ocsp`)
+    /// and the response was valid.
+    #[cfg(feature = "ocsp")]
+    pub fn ocsp_response(&self) -> Option<&[u8]> {
+        match self.ocsp.get() {
+            Some((staple, true)) => Some(staple),
+            _ => None,
+        }
+    }
+
+    /// Returns the OCSP response stapled by the server as it was sent, valid
+    /// or not, e.g. to log it or apply a revocation policy of one's own.
+    ///
+    /// Only recorded if the connector checks staples (see `TlsConnector::ocsp`),
+    /// which `OcspVerifier::wrap` adds to any certificate verifier. `None` if
+    /// the server stapled nothing or the session was resumed.
+    #[cfg(feature = "ocsp")]
+    pub fn stapled_ocsp_response(&self) -> Option<&[u8]> {
+        self.ocsp.get().map(|(staple, _)| staple.as_slice())
+    }
+
+    /// Returns the logs that satisfied the connector's CT policy (see `TlsConnector::ct_policy`).
+    #[cfg(feature = "ct")]
+    pub fn ct_logs(&self) -> &[CtLog] {
+        &self.ct_logs
+    }
+
+    /// Check the server's certificate against the CT policy, once the handshake is done.
+    #[cfg(feature = "ct")]
+    fn check_ct(&mut self) -> io::Result<()> {
+        if let Some(policy) = self.ct_policy.take() {
+            let chain = self.session.peer_certificates().unwrap_or_default();
+            self.ct_logs = policy.check(chain, SystemTime::now())?;
+        }
+        Ok(())
+    }
 }
 
-impl<IO> Future for MidHandshake<IO>
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Drive the handshake until it is done and its last messages are sent.
+    fn handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let polled_at = Instant::now();
+        let result = self.handshake_io(cx);
+        let wants_write = self.session.wants_write();
+        self.observation.handshake_polled(polled_at, wants_write);
+        result
+    }
+
+    fn handshake_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let eof = !self.state.readable();
+        let mut stream = Stream::new(&mut self.io, &mut self.session)
+            .set_io_state(&mut self.io_state)
+            .set_eof(eof)
+            .set_flush(self.handshake_flush)
+            .set_hello(&mut self.hello)
+            .set_traffic(&mut self.observation.traffic);
+
+        if stream.conn.is_handshaking() {
+            ready!(stream.complete_io(cx))?;
+        }
+
+        while stream.conn.wants_write() {
+            ready!(stream.complete_io(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Complete a handshake started with early data, sending the data again if
+    /// the server rejected it, or failing with `RejectedEarlyData::Fail`.
+    fn finish_early_data(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let polled_at = Instant::now();
+        let result = self.poll_early_data_handshake(cx);
+        let wants_write = self.session.wants_write();
+        self.observation.handshake_polled(polled_at, wants_write);
+        let result = ready!(result);
+        // dropping the notifier tells of a failure
+        let notify = self.notify_early_data.take();
+        let accepted = match result {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                self.observation.handshake_error(&err);
+                return Poll::Ready(Err(err));
+            }
+        };
+        self.handshake_complete();
+        let discarded = !accepted && self.early_data.0 < self.early_data.1.len();
+        self.state = TlsState::Stream;
+        self.early_data = (0, Vec::new());
+        if let Some(notify) = notify {
+            notify.finish(accepted);
+        }
+        match discarded {
+            true => Poll::Ready(Err(Error::EarlyDataRejected.into())),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_early_data_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        let mut stream = Stream::new(&mut self.io, &mut self.session)
+            .set_io_state(&mut self.io_state)
+            .set_eof(!self.state.readable())
+            .set_flush(self.handshake_flush)
+            .set_hello(&mut self.hello)
+            .set_traffic(&mut self.observation.traffic);
+        let (pos, data) = &mut self.early_data;
+
+        if stream.conn.is_handshaking() {
+            ready!(stream.complete_io(cx))?;
+        }
+
+        let accepted = stream.conn.is_early_data_accepted();
+        if !accepted && self.rejected_early_data == RejectedEarlyData::Resend {
+            while *pos < data.len() {
+                let len = ready!(stream.as_mut_pin().poll_write(cx, &data[*pos..]))?;
+                *pos += len;
+            }
+        }
+        Poll::Ready(Ok(accepted))
+    }
+
+    fn handshake_complete(&mut self) {
+        let (session, kind) = (&self.session, self.handshake_kind());
+        let group = self.key_exchange_group();
+        let server_name = Some(self.server_name.as_str());
+        self.observation.handshake_complete(|timings| {
+            HandshakeParams::of(session, kind, group, server_name, timings)
+        });
+    }
+}
+
+impl<IO> Future for Handshake<IO>
 where
     IO: AsyncRead + AsyncWrite + Unpin,
 {
@@ -50,28 +503,321 @@ where
     #[inline]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
+        let result = ready!(this.poll_handshake(cx));
 
-        if let MidHandshake::Handshaking(stream) = this {
-            let eof = !stream.state.readable();
-            let (io, session) = (&mut stream.io, &mut stream.session);
-            let mut stream = Stream::new(io, session).set_eof(eof);
+        let mut stream = match mem::replace(this, Handshake::End) {
+            Handshake::Handshaking(stream) => stream,
+            // the handshake is completed once the stream is used
+            Handshake::EarlyData(stream) => return Poll::Ready(Ok(stream)),
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(stream, _) => stream,
+            Handshake::End => panic!(),
+        };
+        match result {
+            Ok(()) => {
+                stream.handshake_complete();
+                Poll::Ready(Ok(stream))
+            }
+            Err(err) => {
+                stream.observation.handshake_error(&err);
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
 
-            if stream.conn.is_handshaking() {
-                ready!(stream.complete_io(cx))?;
+/// A client handshake in flight, from `Connect::into_mid_handshake`.
+///
+/// Lets schedulers of their own drive handshakes: poll it with `poll` until
+/// it resolves to the stream, and store it in between, e.g. ordered by
+/// `stage` to time out or prioritize handshakes. It is also a future doing
+/// the same. `into_inner` gives up on the handshake and returns the IO.
+pub struct MidHandshake<IO>(pub(crate) Handshake<IO>);
+
+impl<IO> MidHandshake<IO> {
+    /// Returns a reference to the underlying IO stream, `None` once the
+    /// handshake resolved.
+    pub fn get_ref(&self) -> Option<&IO> {
+        self.0.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying IO stream, see `get_ref`.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        self.0.get_mut()
+    }
+
+    /// How far the handshake got, `None` once it resolved.
+    ///
+    /// The handshake is at `HandshakeStage::Verifying` while checks after it,
+    /// e.g. TOFU, are pending.
+    pub fn stage(&self) -> Option<HandshakeStage> {
+        self.0.stage()
+    }
+
+    /// Gives up on the handshake and returns the underlying IO, as is,
+    /// without telling the server. `None` once the handshake resolved.
+    pub fn into_inner(self) -> Option<IO> {
+        match self.0 {
+            Handshake::Handshaking(stream) | Handshake::EarlyData(stream) => Some(stream.io),
+            #[cfg(feature = "tofu")]
+            Handshake::Trusting(stream, _) => Some(stream.io),
+            Handshake::End => None,
+        }
+    }
+}
+
+impl<IO> MidHandshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Drives the handshake and the checks after it, resolving to the stream
+    /// once they are done, or to the `HandshakeError` they failed with.
+    ///
+    /// Polling again once it resolved panics.
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TlsStream<IO>>> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<IO> Future for MidHandshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = io::Result<TlsStream<IO>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().poll(cx)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FusedFuture for MidHandshake<IO> {
+    fn is_terminated(&self) -> bool {
+        self.0.is_done()
+    }
+}
+
+impl<IO> fmt::Debug for MidHandshake<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MidHandshake").field(&self.stage()).finish()
+    }
+}
+
+impl<IO> Handshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Drive the handshake and the checks after it, leaving the stream in place.
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Handshake::Handshaking(stream) = self {
+            ready!(stream.handshake(cx)).map_err(|err| {
+                HandshakeError::wrap(err, Some(&stream.server_name), &stream.session)
+            })?;
+        }
+
+        #[cfg(feature = "tofu")]
+        {
+            if let Handshake::Handshaking(stream) = self {
+                if let Some(verifier) = stream.tofu.take() {
+                    let host = stream.server_name.clone();
+                    let check = verifier.check(host, stream.session.peer_certificates());
+                    if let Handshake::Handshaking(stream) = mem::replace(self, Handshake::End) {
+                        *self = Handshake::Trusting(stream, check);
+                    }
+                }
+            }
+            if let Handshake::Trusting(stream, check) = self {
+                ready!(check.as_mut().poll(cx)).map_err(|err| {
+                    HandshakeError::wrap_at(
+                        err,
+                        Some(&stream.server_name),
+                        HandshakeStage::Verifying,
+                    )
+                })?;
+            }
+        }
+
+        #[cfg(feature = "ct")]
+        {
+            let stream = match self {
+                Handshake::Handshaking(stream) => stream,
+                #[cfg(feature = "tofu")]
+                Handshake::Trusting(stream, _) => stream,
+                _ => return Poll::Ready(Ok(())),
+            };
+            stream.check_ct().map_err(|err| {
+                HandshakeError::wrap_at(err, Some(&stream.server_name), HandshakeStage::Verifying)
+            })?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Reads plaintext into `buf` without consuming it: the next reads return
+    /// it again. Returns the number of bytes peeked at, `0` at the end of the
+    /// stream.
+    ///
+    /// Waits for data like reading does, and returns what was received so
+    /// far, which may be less than `buf` holds. Lets protocol sniffers and
+    /// routers look at what comes first without a `BufReader` in between.
+    pub fn peek<'a>(&'a mut self, buf: &'a mut [u8]) -> Peek<'a, IO> {
+        Peek { stream: self, buf }
+    }
+
+    /// Ends TLS with an exchange of `close_notify` alerts, and returns the
+    /// underlying IO, still open.
+    ///
+    /// Lets protocols step down from TLS to plaintext, or hand the connection
+    /// over to another part of the application. Data the peer still sends
+    /// before its `close_notify` is discarded, what follows it is left to read
+    /// from the IO. Unless the peer waits for the `close_notify` of this end
+    /// before using the connection, what it sends may have been received by
+    /// an earlier read, and is lost.
+    pub async fn shutdown(mut self) -> io::Result<IO> {
+        if let TlsState::EarlyData = self.state {
+            poll_fn(|cx| self.finish_early_data(cx)).await?;
+        }
+        self.end_writing(true)?;
+        // what follows the peer's close_notify belongs to the IO
+        self.exact_reads = true;
+        poll_fn(|cx| self.poll_peer_close(cx)).await?;
+        self.observation.close();
+        Ok(self.io)
+    }
+
+    /// Closes the stream with an exchange of `close_notify` alerts, closing the
+    /// IO anyway after `timeout`. Returns whether the peer answered in time
+    /// with its `close_notify`.
+    ///
+    /// Bounds the time shutting down takes, e.g. when draining many
+    /// connections, whatever the `close_mode`. Data the peer still sends is
+    /// discarded.
+    #[cfg(feature = "async-std")]
+    pub async fn close_with_timeout(&mut self, timeout: Duration) -> io::Result<bool> {
+        let exchange = async {
+            if let TlsState::EarlyData = self.state {
+                poll_fn(|cx| self.finish_early_data(cx)).await?;
+            }
+            self.end_writing(true)?;
+            poll_fn(|cx| self.poll_peer_close(cx)).await
+        };
+        let clean = matches!(
+            async_std::future::timeout(timeout, exchange).await,
+            Ok(Ok(()))
+        );
+        poll_fn(|cx| Pin::new(&mut self.io).poll_close(cx)).await?;
+        self.observation.close();
+        Ok(clean)
+    }
+
+    /// Queue `close_notify`, if `send`, after what was kept back, and stop writing.
+    fn end_writing(&mut self, send: bool) -> io::Result<()> {
+        if self.state.writeable() {
+            Stream::new(&mut self.io, &mut self.session)
+                .set_io_state(&mut self.io_state)
+                .set_coalescer(&mut self.coalescer)
+                .uncork()?;
+            if send {
+                self.session.send_close_notify();
             }
+            self.state.shutdown_write();
+        }
+        Ok(())
+    }
 
-            if stream.conn.wants_write() {
-                ready!(stream.complete_io(cx))?;
+    /// Send what is queued, then read until the peer's `close_notify`,
+    /// discarding what it still sends.
+    fn poll_peer_close(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut stream = Stream::new(&mut self.io, &mut self.session)
+            .set_io_state(&mut self.io_state)
+            .set_eof(!self.state.readable())
+            .set_traffic(&mut self.observation.traffic);
+        ready!(stream.as_mut_pin().poll_flush(cx))?;
+        let mut buf = [0; 4096];
+        while self.state.readable() {
+            if self.close_timer.poll_elapsed(self.close_mode, cx) {
+                let err = "timed out waiting for the peer's close_notify";
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, err)));
             }
+            ready!(Pin::new(&mut *self).poll_read(cx, &mut buf))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Polls for plaintext to peek at, see `peek`.
+    pub fn poll_peek(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.peeked.is_empty() && !buf.is_empty() {
+            let mut peeked = mem::take(&mut self.peeked);
+            peeked.resize(buf.len(), 0);
+            let result = Pin::new(&mut *self).poll_read(cx, &mut peeked);
+            peeked.truncate(match result {
+                Poll::Ready(Ok(len)) => len,
+                _ => 0,
+            });
+            self.peeked = peeked;
+            ready!(result)?;
         }
+        let len = buf.len().min(self.peeked.len());
+        buf[..len].copy_from_slice(&self.peeked[..len]);
+        Poll::Ready(Ok(len))
+    }
 
-        match mem::replace(this, MidHandshake::End) {
-            MidHandshake::Handshaking(stream) => Poll::Ready(Ok(stream)),
-            #[cfg(feature = "early-data")]
-            MidHandshake::EarlyData(stream) => Poll::Ready(Ok(stream)),
-            MidHandshake::End => panic!(),
+    /// Drives the handshake until it is done, for poll loops of their own.
+    ///
+    /// Streams from `TlsConnector::connect` are done already. Those from
+    /// `connect_0rtt` are done once their first read or write beyond the
+    /// early data, or this, completed the handshake.
+    pub fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.state {
+            TlsState::EarlyData => self.finish_early_data(cx),
+            _ => Poll::Ready(Ok(())),
         }
     }
+
+    /// Sends the TLS records queued and receives those the peer sent, e.g.
+    /// session tickets, without reading or writing plaintext. Returns the
+    /// number of bytes received and sent.
+    ///
+    /// `(0, 0)` means nothing can be done without reading the plaintext
+    /// received: at the end of the stream, or once enough was buffered.
+    pub fn poll_complete_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(usize, usize)>> {
+        ready!(self.poll_handshake(cx))?;
+        Stream::new(&mut self.io, &mut self.session)
+            .set_io_state(&mut self.io_state)
+            .set_eof(!self.state.readable())
+            .set_traffic(&mut self.observation.traffic)
+            .complete_io(cx)
+    }
+}
+
+/// Future returned from `TlsStream::peek`.
+pub struct Peek<'a, IO> {
+    stream: &'a mut TlsStream<IO>,
+    buf: &'a mut [u8],
+}
+
+impl<IO> fmt::Debug for Peek<'_, IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the buffer holds plaintext
+        f.debug_struct("Peek")
+            .field("len", &self.buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> Future for Peek<'_, IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.stream.poll_peek(cx, this.buf)
+    }
 }
 
 impl<IO> AsyncRead for TlsStream<IO>
@@ -83,48 +829,42 @@ where
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
+        if !self.peeked.is_empty() {
+            return Poll::Ready(Ok(self.get_mut().take_peeked(buf)));
+        }
+
         match self.state {
-            #[cfg(feature = "early-data")]
             TlsState::EarlyData => {
                 let this = self.get_mut();
-
-                let is_handshaking = this.session.is_handshaking();
-                let is_early_data_accepted = this.session.is_early_data_accepted();
-
-                let mut stream =
-                    Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
-                let (pos, data) = &mut this.early_data;
-
-                // complete handshake
-                if is_handshaking {
-                    ready!(stream.complete_io(cx))?;
-                }
-
-                // write early data (fallback)
-                if !is_early_data_accepted {
-                    while *pos < data.len() {
-                        let len = ready!(stream.as_mut_pin().poll_write(cx, &data[*pos..]))?;
-                        *pos += len;
-                    }
-                }
-
-                // end
-                this.state = TlsState::Stream;
-                data.clear();
-
+                ready!(this.finish_early_data(cx))?;
                 Pin::new(this).poll_read(cx, buf)
             }
             TlsState::Stream | TlsState::WriteShutdown => {
                 let this = self.get_mut();
-                let mut stream =
-                    Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
+                let mut stream = Stream::new(&mut this.io, &mut this.session)
+                    .set_io_state(&mut this.io_state)
+                    .set_eof(!this.state.readable())
+                    .set_traffic(&mut this.observation.traffic)
+                    .set_exact_reads(this.exact_reads)
+                    .set_coalescer(&mut this.coalescer);
 
                 match stream.as_mut_pin().poll_read(cx, buf) {
                     Poll::Ready(Ok(0)) => {
                         this.state.shutdown_read();
                         Poll::Ready(Ok(0))
                     }
-                    Poll::Ready(Ok(n)) => Poll::Ready(Ok(n)),
+                    Poll::Ready(Ok(n)) => {
+                        this.observation.read(n);
+                        Poll::Ready(Ok(n))
+                    }
+                    Poll::Ready(Err(ref err))
+                        if !this.strict_close_notify
+                            && err.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        // the peer closed without close_notify, which is tolerated
+                        this.state.shutdown_read();
+                        Poll::Ready(Ok(0))
+                    }
                     Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::ConnectionAborted => {
                         this.state.shutdown_read();
                         if this.state.writeable() {
@@ -152,67 +892,215 @@ where
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
 
-        match this.state {
-            #[cfg(feature = "early-data")]
-            TlsState::EarlyData => {
-                use std::io::Write;
+        if let TlsState::EarlyData = this.state {
+            let len = this.write_early_data(buf)?;
+            if len > 0 || buf.is_empty() {
+                return Poll::Ready(Ok(len));
+            }
+            ready!(this.finish_early_data(cx))?;
+        }
 
-                let (pos, data) = &mut this.early_data;
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_chunking(this.write_chunking)
+            .set_coalescer(&mut this.coalescer);
+        let len = ready!(stream.as_mut_pin().poll_write(cx, buf))?;
+        this.observation.written(len);
+        Poll::Ready(Ok(len))
+    }
 
-                // write early data
-                if let Some(mut early_data) = stream.conn.client_early_data() {
-                    let len = match early_data.write(buf) {
-                        Ok(n) => n,
-                        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
-                            return Poll::Pending
-                        }
-                        Err(err) => return Poll::Ready(Err(err)),
-                    };
-                    data.extend_from_slice(&buf[..len]);
-                    return Poll::Ready(Ok(len));
-                }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_coalescer(&mut this.coalescer);
+        stream.as_mut_pin().poll_flush(cx)
+    }
 
-                // complete handshake
-                if stream.conn.is_handshaking() {
-                    ready!(stream.complete_io(cx))?;
-                }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
 
-                // write early data (fallback)
-                if !stream.conn.is_early_data_accepted() {
-                    while *pos < data.len() {
-                        let len = ready!(stream.as_mut_pin().poll_write(cx, &data[*pos..]))?;
-                        *pos += len;
-                    }
-                }
+        if let TlsState::EarlyData = this.state {
+            ready!(this.finish_early_data(cx))?;
+        }
 
-                // end
-                this.state = TlsState::Stream;
-                data.clear();
-                stream.as_mut_pin().poll_write(cx, buf)
-            }
-            _ => stream.as_mut_pin().poll_write(cx, buf),
+        this.end_writing(this.send_close_notify)?;
+
+        if this.close_mode != CloseMode::Send {
+            ready!(this.poll_peer_close(cx))?;
         }
+
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_coalescer(&mut this.coalescer);
+        ready!(stream.as_mut_pin().poll_close(cx))?;
+        this.observation.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// See the `AsyncRead` implementation of futures-io.
+#[cfg(feature = "tokio")]
+impl<IO> tokio::io::AsyncRead for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        compat::poll_read(self, cx, buf)
+    }
+}
+
+/// See the `AsyncWrite` implementation of futures-io, `poll_shutdown` is its `poll_close`.
+#[cfg(feature = "tokio")]
+impl<IO> tokio::io::AsyncWrite for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
-        stream.as_mut_pin().poll_flush(cx)
+        AsyncWrite::poll_flush(self, cx)
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        if self.state.writeable() {
-            self.session.send_close_notify();
-            self.state.shutdown_write();
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(self, cx)
+    }
+}
+
+/// What writing early data does once the buffer for it is full.
+///
+/// Early data is kept until the handshake is done, to send it again if the
+/// server rejects it. See `TlsConnector::early_data_buffer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyDataOverflow {
+    /// Wait for the handshake, and send the rest of the data normally.
+    Wait,
+    /// Fail the write.
+    Fail,
+}
+
+/// What happens to early data the server rejected.
+///
+/// See `TlsConnector::rejected_early_data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectedEarlyData {
+    /// Send the data again once the handshake is done, as if it was written then.
+    Resend,
+    /// Drop the data, and fail the read or write that completes the handshake
+    /// with `Error::EarlyDataRejected`. The stream can be used afterwards.
+    Fail,
+}
+
+/// How the early data of a connection from `TlsConnector::connect_0rtt` fared.
+///
+/// Handles can be cloned, e.g. to wait for the handshake in another task than
+/// the one using the stream.
+#[derive(Debug, Clone)]
+pub struct EarlyData {
+    outcome: Arc<Mutex<Outcome>>,
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Pending(Vec<Waker>),
+    /// Whether the early data was accepted, `None` if the handshake failed.
+    Done(Option<bool>),
+}
+
+impl EarlyData {
+    pub(crate) fn new() -> (EarlyData, EarlyDataNotifier) {
+        let outcome = Arc::new(Mutex::new(Outcome::Pending(Vec::new())));
+        (
+            EarlyData {
+                outcome: outcome.clone(),
+            },
+            EarlyDataNotifier(outcome),
+        )
+    }
+
+    /// Whether the server accepted the early data.
+    ///
+    /// `None` until the handshake is done, or if it failed. `Some(false)` if no
+    /// early data could be sent, e.g. because no session was resumed.
+    pub fn early_data_accepted(&self) -> Option<bool> {
+        match *self.outcome.lock().unwrap() {
+            Outcome::Pending(_) => None,
+            Outcome::Done(accepted) => accepted,
         }
+    }
 
-        let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
-        stream.as_mut_pin().poll_close(cx)
+    /// Wait for the handshake to be done, and tell whether the server accepted
+    /// the early data; `None` if the handshake failed or the stream was dropped.
+    ///
+    /// The handshake only progresses while the stream is read from or written to.
+    pub fn handshake_complete(&self) -> HandshakeComplete {
+        HandshakeComplete {
+            outcome: self.outcome.clone(),
+        }
+    }
+}
+
+/// Future returned from `EarlyData::handshake_complete`.
+#[derive(Debug)]
+pub struct HandshakeComplete {
+    outcome: Arc<Mutex<Outcome>>,
+}
+
+impl Future for HandshakeComplete {
+    type Output = Option<bool>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match *self.outcome.lock().unwrap() {
+            Outcome::Done(accepted) => Poll::Ready(accepted),
+            Outcome::Pending(ref mut wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The stream's side of `EarlyData`. Dropping it before `finish` tells of a failure.
+#[derive(Debug)]
+pub(crate) struct EarlyDataNotifier(Arc<Mutex<Outcome>>);
+
+impl EarlyDataNotifier {
+    pub(crate) fn finish(self, accepted: bool) {
+        self.set(Some(accepted));
+    }
+
+    fn set(&self, accepted: Option<bool>) {
+        let mut outcome = self.0.lock().unwrap();
+        if let Outcome::Pending(ref mut wakers) = *outcome {
+            let wakers = mem::take(wakers);
+            *outcome = Outcome::Done(accepted);
+            drop(outcome);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+impl Drop for EarlyDataNotifier {
+    fn drop(&mut self) {
+        self.set(None);
     }
 }
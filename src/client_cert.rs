@@ -0,0 +1,110 @@
+use crate::common::der;
+
+use rustls::client::ResolvesClientCert;
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, SignatureScheme};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+pub(crate) type ClientCertCallback =
+    dyn Fn(&CertificateRequest<'_>) -> Option<Arc<CertifiedKey>> + Send + Sync;
+
+/// What a server asked for when requesting a client certificate, see
+/// `TlsConnector::client_cert_resolver`.
+pub struct CertificateRequest<'a> {
+    acceptable_issuers: &'a [&'a [u8]],
+    signature_schemes: &'a [SignatureScheme],
+}
+
+impl fmt::Debug for CertificateRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateRequest")
+            .field("acceptable_issuers", &self.acceptable_issuers.len())
+            .field("signature_schemes", &self.signature_schemes)
+            .finish()
+    }
+}
+
+impl<'a> CertificateRequest<'a> {
+    /// The DER-encoded names of the CAs the server accepts client
+    /// certificates from, empty if it did not say.
+    pub fn acceptable_issuers(&self) -> &'a [&'a [u8]] {
+        self.acceptable_issuers
+    }
+
+    /// The signature schemes the server can verify.
+    pub fn signature_schemes(&self) -> &'a [SignatureScheme] {
+        self.signature_schemes
+    }
+
+    /// Whether `chain`, leaf first, was issued by one of the acceptable CAs:
+    /// if any of its certificates names one as its issuer, or the server
+    /// did not name any.
+    pub fn accepts(&self, chain: &[Certificate]) -> bool {
+        if self.acceptable_issuers.is_empty() {
+            return true;
+        }
+        chain
+            .iter()
+            .any(|cert| match der::tbs_certificate(&cert.0) {
+                Some(tbs) => self.acceptable_issuers.contains(&tbs.issuer),
+                None => false,
+            })
+    }
+}
+
+/// Chooses the client certificate with a callback.
+pub(crate) struct CallbackResolver(pub(crate) Arc<ClientCertCallback>);
+
+impl ResolvesClientCert for CallbackResolver {
+    fn resolve(
+        &self,
+        acceptable_issuers: &[&[u8]],
+        signature_schemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        (self.0)(&CertificateRequest {
+            acceptable_issuers,
+            signature_schemes,
+        })
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// A copy of `config` whose resolver records the CAs a server asks for in
+/// `issuers`, whether or not it finds a certificate.
+pub(crate) fn recording(
+    config: &ClientConfig,
+    issuers: Arc<OnceLock<Vec<Vec<u8>>>>,
+) -> Arc<ClientConfig> {
+    let mut config = config.clone();
+    config.client_auth_cert_resolver = Arc::new(Recording {
+        inner: config.client_auth_cert_resolver.clone(),
+        issuers,
+    });
+    Arc::new(config)
+}
+
+/// Resolves like `inner`, for a single connection.
+struct Recording {
+    inner: Arc<dyn ResolvesClientCert>,
+    issuers: Arc<OnceLock<Vec<Vec<u8>>>>,
+}
+
+impl ResolvesClientCert for Recording {
+    fn resolve(
+        &self,
+        acceptable_issuers: &[&[u8]],
+        signature_schemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        let issuers = acceptable_issuers.iter().map(|name| name.to_vec());
+        let _ = self.issuers.set(issuers.collect());
+        self.inner.resolve(acceptable_issuers, signature_schemes)
+    }
+
+    fn has_certs(&self) -> bool {
+        self.inner.has_certs()
+    }
+}
@@ -0,0 +1,44 @@
+//! Canceling a handshake in flight.
+
+use crate::rusttls::stream::Stream;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::{ConnectionCommon, SideData};
+use std::future::poll_fn;
+use std::ops::DerefMut;
+use std::pin::Pin;
+
+/// The record of a `user_canceled` warning alert, sent in the clear.
+const USER_CANCELED: [u8; 7] = [0x15, 3, 3, 0, 2, 1, 90];
+
+/// Tell the peer that the handshake was canceled: with `user_canceled` while
+/// nothing is encrypted, or else with a `close_notify` over `conn`, which is
+/// all rustls sends then.
+///
+/// Failures are ignored, the peer may be gone already.
+pub(crate) async fn cancel<IO, C, D>(io: &mut IO, conn: Option<&mut C>)
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
+    match conn {
+        // a cipher suite was agreed on, which rustls may encrypt with
+        Some(conn) if conn.negotiated_cipher_suite().is_some() => {
+            conn.send_close_notify();
+            let mut stream = Stream::new(io, conn);
+            let _ = poll_fn(|cx| stream.as_mut_pin().poll_flush(cx)).await;
+        }
+        _ => {
+            let mut written = 0;
+            while written < USER_CANCELED.len() {
+                let alert = &USER_CANCELED[written..];
+                match poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, alert)).await {
+                    Ok(n) if n > 0 => written += n,
+                    _ => return,
+                }
+            }
+            let _ = poll_fn(|cx| Pin::new(&mut *io).poll_flush(cx)).await;
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Channel bindings, tying an authentication exchange (e.g. SCRAM-PLUS) to
+//! the TLS connection it runs over.
+
+use crate::Error;
+
+use rustls::{ConnectionCommon, ProtocolVersion};
+use std::io;
+
+/// The exporter label of RFC 9266.
+const TLS_EXPORTER_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+
+/// The `tls-exporter` channel binding of RFC 9266: 32 bytes of keying
+/// material, exported with no context.
+///
+/// Only TLS 1.3 connections have one: with TLS 1.2 it is only safe with the
+/// extended master secret, which rustls does not tell about.
+pub(crate) fn tls_exporter<D>(conn: &ConnectionCommon<D>) -> io::Result<[u8; 32]> {
+    if conn.protocol_version() == Some(ProtocolVersion::TLSv1_2) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "tls-exporter channel binding needs TLS 1.3",
+        ));
+    }
+    conn.export_keying_material([0; 32], TLS_EXPORTER_LABEL, Some(&[]))
+        .map_err(|err| Error::from(err).into())
+}
@@ -0,0 +1,29 @@
+//! How much of a caller's buffer a write encrypts.
+
+/// How much of a caller's buffer one write to the stream takes.
+///
+/// rustls splits what a write takes into records of the maximum fragment
+/// size, see `TlsConnector::max_fragment_size`. Either way, the records of a
+/// write are sent before it returns, unless the IO is not ready for them.
+///
+/// See `TlsConnector::write_chunking` and `TlsAcceptor::write_chunking`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteChunking {
+    /// Take the whole buffer, as far as rustls has room for it, for the
+    /// fewest writes and the most data per system call.
+    Whole,
+    /// Take at most this many bytes per write, at least one. Up to the maximum
+    /// fragment size, each write is a single record, bounding the data a
+    /// write encrypts and the time it takes.
+    Chunk(usize),
+}
+
+impl WriteChunking {
+    /// The part of `buf` a write takes.
+    pub(crate) fn chunk<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        match *self {
+            WriteChunking::Whole => buf,
+            WriteChunking::Chunk(size) => &buf[..buf.len().min(size.max(1))],
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Reading the `ClientHello` from the records a client sent.
+
+use rustls::server::ClientHello;
+use rustls::ProtocolVersion;
+use std::fmt;
+
+pub(crate) const HANDSHAKE: u8 = 0x16;
+pub(crate) const CLIENT_HELLO: u8 = 1;
+
+pub(crate) const SERVER_NAME: u16 = 0;
+pub(crate) const ALPN: u16 = 16;
+pub(crate) const SUPPORTED_VERSIONS: u16 = 43;
+
+const HOST_NAME: u8 = 0;
+
+/// What a client offered in its `ClientHello`, enough to tell a scanner, an
+/// old client or one asking for the wrong host apart.
+///
+/// Carried by the `HandshakeError`s of `Accept` once the `ClientHello` was
+/// received, see `HandshakeError::client_hello`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientHelloSummary {
+    /// The name the client asked for (SNI).
+    pub server_name: Option<String>,
+    /// The application protocols offered with ALPN, empty if none.
+    pub alpn: Vec<Vec<u8>>,
+    /// The versions offered, from `supported_versions` without GREASE values
+    /// (RFC 8701), or else the `legacy_version`.
+    pub versions: Vec<ProtocolVersion>,
+}
+
+impl ClientHelloSummary {
+    /// Parse the records of a `ClientHello`.
+    pub(crate) fn parse(records: &[u8]) -> Option<Self> {
+        let body = message(records)?;
+        let mut reader = Reader(&body);
+        let version = reader.u16()?;
+        reader.take(32)?;
+        reader.u8_prefixed()?;
+        reader.u16_prefixed()?;
+        reader.u8_prefixed()?;
+
+        let mut summary = ClientHelloSummary {
+            server_name: None,
+            alpn: Vec::new(),
+            versions: Vec::new(),
+        };
+        let mut extensions = Reader(reader.u16_prefixed().unwrap_or_default());
+        while !extensions.0.is_empty() {
+            let kind = extensions.u16()?;
+            let mut data = Reader(extensions.u16_prefixed()?);
+            match kind {
+                SERVER_NAME => {
+                    let mut names = Reader(data.u16_prefixed()?);
+                    while !names.0.is_empty() {
+                        let (kind, name) = (names.u8()?, names.u16_prefixed()?);
+                        if kind == HOST_NAME {
+                            summary.server_name = Some(String::from_utf8_lossy(name).into());
+                        }
+                    }
+                }
+                ALPN => {
+                    let mut protocols = Reader(data.u16_prefixed()?);
+                    while !protocols.0.is_empty() {
+                        summary.alpn.push(protocols.u8_prefixed()?.to_vec());
+                    }
+                }
+                SUPPORTED_VERSIONS => {
+                    let versions = Reader(data.u8_prefixed()?).u16s()?;
+                    summary.versions = (versions.into_iter())
+                        .filter(|&version| !is_grease(version))
+                        .map(ProtocolVersion::from)
+                        .collect();
+                }
+                _ => (),
+            }
+        }
+        if summary.versions.is_empty() {
+            summary.versions.push(ProtocolVersion::from(version));
+        }
+        Some(summary)
+    }
+
+    /// What rustls parsed of a `ClientHello`, which leaves out the versions.
+    pub(crate) fn of(hello: &ClientHello<'_>) -> Self {
+        ClientHelloSummary {
+            server_name: hello.server_name().map(str::to_string),
+            alpn: (hello.alpn().into_iter().flatten())
+                .map(<[u8]>::to_vec)
+                .collect(),
+            versions: Vec::new(),
+        }
+    }
+}
+
+/// Shows e.g. `SNI example.com, ALPN h2,http/1.1, versions TLSv1_3,TLSv1_2`.
+impl fmt::Display for ClientHelloSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.server_name {
+            Some(ref name) => write!(f, "SNI {}", name)?,
+            None => f.write_str("no SNI")?,
+        }
+        if !self.alpn.is_empty() {
+            let alpn: Vec<_> = (self.alpn.iter())
+                .map(|protocol| String::from_utf8_lossy(protocol))
+                .collect();
+            write!(f, ", ALPN {}", alpn.join(","))?;
+        }
+        if !self.versions.is_empty() {
+            let versions: Vec<_> = (self.versions.iter())
+                .map(|version| format!("{:?}", version))
+                .collect();
+            write!(f, ", versions {}", versions.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// The body of the `ClientHello` in `records`, once received whole.
+pub(crate) fn message(mut records: &[u8]) -> Option<Vec<u8>> {
+    let mut message = Vec::new();
+    while records.len() >= 5 && records[0] == HANDSHAKE {
+        let len = usize::from(u16::from_be_bytes([records[3], records[4]]));
+        message.extend_from_slice(records.get(5..5 + len)?);
+        records = &records[5 + len..];
+
+        let mut reader = Reader(&message);
+        if reader.u8()? != CLIENT_HELLO {
+            return None;
+        }
+        if let Some(body) = reader.u24_prefixed() {
+            return Some(body.to_vec());
+        }
+    }
+    None
+}
+
+/// Whether `value` is reserved by RFC 8701, to keep peers from choking on unknown values.
+pub(crate) fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let taken = self.0.get(..len)?;
+        self.0 = &self.0[len..];
+        Some(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u8_prefixed(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.take(usize::from(len))
+    }
+
+    pub(crate) fn u16_prefixed(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(usize::from(len))
+    }
+
+    pub(crate) fn u24_prefixed(&mut self) -> Option<&'a [u8]> {
+        let len = self.take(3)?;
+        self.take(u32::from_be_bytes([0, len[0], len[1], len[2]]) as usize)
+    }
+
+    pub(crate) fn u16s(mut self) -> Option<Vec<u16>> {
+        let mut values = Vec::with_capacity(self.0.len() / 2);
+        while !self.0.is_empty() {
+            values.push(self.u16()?);
+        }
+        Some(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_client_hellos() {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[7; 32]);
+        body.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+        let mut extensions = Vec::new();
+        for (kind, data) in [
+            (SERVER_NAME, &b"\x00\x0e\x00\x00\x0bexample.com"[..]),
+            (ALPN, b"\x00\x0c\x02h2\x08http/1.1"),
+            (SUPPORTED_VERSIONS, b"\x06\x3a\x3a\x03\x04\x03\x03"),
+        ] {
+            extensions.extend_from_slice(&kind.to_be_bytes());
+            extensions.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(data);
+        }
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut records = vec![HANDSHAKE, 3, 1];
+        records.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+        records.push(CLIENT_HELLO);
+        records.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        records.extend_from_slice(&body);
+
+        let summary = ClientHelloSummary::parse(&records).unwrap();
+        assert_eq!(summary.server_name.as_deref(), Some("example.com"));
+        assert_eq!(summary.alpn, [b"h2".to_vec(), b"http/1.1".to_vec()]);
+        assert_eq!(
+            summary.versions,
+            [ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2]
+        );
+        assert_eq!(
+            summary.to_string(),
+            "SNI example.com, ALPN h2,http/1.1, versions TLSv1_3,TLSv1_2"
+        );
+        assert_eq!(ClientHelloSummary::parse(&records[..40]), None);
+    }
+}
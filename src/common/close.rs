@@ -0,0 +1,54 @@
+//! What closing a stream waits for.
+
+#[cfg(feature = "async-std")]
+use std::future::Future;
+#[cfg(feature = "async-std")]
+use std::pin::Pin;
+use std::task::Context;
+#[cfg(feature = "async-std")]
+use std::time::Duration;
+
+/// What closing a stream waits for.
+///
+/// See `TlsConnector::close_mode` and `TlsAcceptor::close_mode`. The modes
+/// available depend on the features enabled, so matching on one needs a
+/// wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CloseMode {
+    /// Send `close_notify` and close the IO right away.
+    Send,
+    /// Send `close_notify`, then wait for the peer's before closing the IO,
+    /// for protocols that need an acknowledged shutdown. What the peer still
+    /// sends in between is discarded.
+    AwaitPeer,
+    /// Like `AwaitPeer`, failing with `TimedOut` if the peer's `close_notify`
+    /// does not arrive in time. The IO is not closed then, dropping the
+    /// stream closes it.
+    #[cfg(feature = "async-std")]
+    AwaitPeerFor(Duration),
+}
+
+/// Waits out the timeout of `CloseMode::AwaitPeerFor`, started by the first poll.
+#[derive(Default)]
+pub(crate) struct CloseTimer {
+    #[cfg(feature = "async-std")]
+    sleep: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
+}
+
+impl CloseTimer {
+    /// Whether waiting with `mode` timed out, waking the task once it does.
+    #[cfg_attr(not(feature = "async-std"), allow(unused_variables))]
+    pub(crate) fn poll_elapsed(&mut self, mode: CloseMode, cx: &mut Context<'_>) -> bool {
+        match mode {
+            #[cfg(feature = "async-std")]
+            CloseMode::AwaitPeerFor(timeout) => self
+                .sleep
+                .get_or_insert_with(|| Box::pin(async_std::task::sleep(timeout)))
+                .as_mut()
+                .poll(cx)
+                .is_ready(),
+            _ => false,
+        }
+    }
+}
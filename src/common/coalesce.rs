@@ -0,0 +1,66 @@
+//! Coalescing small application writes into fewer, larger records.
+
+use std::time::{Duration, Instant};
+
+/// Keeps small writes back, to encrypt them together once enough is buffered,
+/// the stream is flushed, or the oldest byte waited for `max_delay`.
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    /// Writes at least this long go out on their own, `0` disables coalescing.
+    threshold: usize,
+    max_delay: Duration,
+    buf: Vec<u8>,
+    /// When the first byte buffered was written.
+    since: Option<Instant>,
+}
+
+impl Coalescer {
+    pub(crate) fn new(threshold: usize, max_delay: Duration) -> Self {
+        Coalescer {
+            threshold,
+            max_delay,
+            buf: Vec::new(),
+            since: None,
+        }
+    }
+
+    /// Whether `data` should be buffered rather than encrypted right away.
+    ///
+    /// Large writes are buffered as well while smaller ones wait, so that the
+    /// data keeps its order.
+    pub(crate) fn takes(&self, data: &[u8]) -> bool {
+        self.threshold > 0 && (!self.buf.is_empty() || data.len() < self.threshold)
+    }
+
+    /// Buffer as much of `data` as fits below the threshold, returning how much.
+    pub(crate) fn push(&mut self, data: &[u8]) -> usize {
+        let len = data
+            .len()
+            .min(self.threshold.saturating_sub(self.buf.len()));
+        if len > 0 && self.buf.is_empty() {
+            self.since = Some(Instant::now());
+        }
+        self.buf.extend_from_slice(&data[..len]);
+        len
+    }
+
+    /// Whether the buffer is full, or has waited long enough.
+    pub(crate) fn is_due(&self) -> bool {
+        match self.since {
+            Some(since) => self.buf.len() >= self.threshold || since.elapsed() >= self.max_delay,
+            None => false,
+        }
+    }
+
+    pub(crate) fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Drop the first `len` bytes buffered, once encrypted.
+    pub(crate) fn consume(&mut self, len: usize) {
+        self.buf.drain(..len);
+        if self.buf.is_empty() {
+            self.since = None;
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Between the IO traits of tokio and those of futures-io.
+
+use futures_core::ready;
+use futures_io::AsyncRead;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::ReadBuf;
+
+/// Read into the unfilled part of `buf`, for `tokio::io::AsyncRead`.
+pub(crate) fn poll_read<R: AsyncRead>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+) -> Poll<io::Result<()>> {
+    let n = ready!(reader.poll_read(cx, buf.initialize_unfilled()))?;
+    buf.advance(n);
+    Poll::Ready(Ok(()))
+}
+
+/// A tokio IO object, with the IO traits of futures-io.
+///
+/// See `TlsConnector::connect_tokio` and `TlsAcceptor::accept_tokio`.
+#[derive(Debug)]
+pub struct TokioIo<IO>(IO);
+
+impl<IO> TokioIo<IO> {
+    pub(crate) fn new(io: IO) -> Self {
+        TokioIo(io)
+    }
+
+    /// The IO object.
+    pub fn get_ref(&self) -> &IO {
+        &self.0
+    }
+
+    /// The IO object. Reading or writing it directly corrupts the TLS stream.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.0
+    }
+
+    /// Unwrap the IO object.
+    pub fn into_inner(self) -> IO {
+        self.0
+    }
+}
+
+impl<IO> AsyncRead for TokioIo<IO>
+where
+    IO: tokio::io::AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.0).poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<IO> futures_io::AsyncWrite for TokioIo<IO>
+where
+    IO: tokio::io::AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
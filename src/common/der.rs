@@ -0,0 +1,380 @@
+//! Just enough DER to pull individual fields out of certificates.
+
+// each feature using this needs a different subset
+#![cfg_attr(
+    not(all(feature = "acme", feature = "ocsp", feature = "ct")),
+    allow(dead_code)
+)]
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const ENUMERATED: u8 = 0x0a;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const UTC_TIME: u8 = 0x17;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+
+/// Reads consecutive TLV elements out of a DER buffer.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// Read the next element, returning its tag and contents.
+    pub(crate) fn read(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = if first & 0x80 == 0 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0usize, |len, &b| (len << 8) | b as usize);
+            (len, &rest[count..])
+        };
+        if rest.len() < len {
+            return None;
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.data = rest;
+        Some((tag, contents))
+    }
+
+    /// Read the next element, returning its complete encoding.
+    pub(crate) fn read_raw(&mut self) -> Option<&'a [u8]> {
+        let start = self.data;
+        self.read()?;
+        Some(&start[..start.len() - self.data.len()])
+    }
+
+    /// Read the next element, requiring it to have the given tag.
+    pub(crate) fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.read()? {
+            (t, contents) if t == tag => Some(contents),
+            _ => None,
+        }
+    }
+
+    /// Read the next element only if it has the given tag.
+    pub(crate) fn optional(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.data.first() {
+            Some(&t) if t == tag => self.expect(tag),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of an X.509 `TBSCertificate` needed elsewhere in the crate.
+pub(crate) struct TbsCertificate<'a> {
+    /// The contents of the serial number.
+    pub(crate) serial: &'a [u8],
+    /// The complete encoding of the issuer name.
+    pub(crate) issuer: &'a [u8],
+    validity: &'a [u8],
+    /// The complete encoding of the subject name.
+    pub(crate) subject: &'a [u8],
+    /// The complete encoding of the subject public key info.
+    pub(crate) spki: &'a [u8],
+    extensions: Option<&'a [u8]>,
+}
+
+/// Split a DER-encoded X.509 certificate into its `TBSCertificate` fields.
+pub(crate) fn tbs_certificate(cert: &[u8]) -> Option<TbsCertificate<'_>> {
+    let cert = Reader::new(cert).expect(SEQUENCE)?;
+    let mut tbs = Reader::new(Reader::new(cert).expect(SEQUENCE)?);
+    // version [0] EXPLICIT, defaults to v1
+    tbs.optional(0xa0);
+    let serial = tbs.expect(INTEGER)?;
+    // signature
+    tbs.expect(SEQUENCE)?;
+    let issuer = tbs.read_raw()?;
+    let validity = tbs.expect(SEQUENCE)?;
+    let subject = tbs.read_raw()?;
+    let spki = tbs.read_raw()?;
+    // issuerUniqueID [1], subjectUniqueID [2]
+    tbs.optional(0x81);
+    tbs.optional(0x82);
+    let extensions = match tbs.optional(0xa3) {
+        Some(explicit) => Some(Reader::new(explicit).expect(SEQUENCE)?),
+        None => None,
+    };
+
+    Some(TbsCertificate {
+        serial,
+        issuer,
+        validity,
+        subject,
+        spki,
+        extensions,
+    })
+}
+
+/// The complete encoding of the `TBSCertificate` of `cert`, minus the extension with the given OID.
+pub(crate) fn tbs_without_extension(cert: &[u8], oid: &[u8]) -> Option<Vec<u8>> {
+    let cert = Reader::new(cert).expect(SEQUENCE)?;
+    let mut tbs = Reader::new(Reader::new(cert).expect(SEQUENCE)?);
+    let mut fields = Vec::new();
+    while let Some(field) = tbs.read_raw() {
+        if field[0] != 0xa3 {
+            fields.push(field.to_vec());
+            continue;
+        }
+        let extensions = Reader::new(field).expect(0xa3)?;
+        let mut extensions = Reader::new(Reader::new(extensions).expect(SEQUENCE)?);
+        let mut kept = Vec::new();
+        while let Some(extension) = extensions.read_raw() {
+            let id = Reader::new(Reader::new(extension).expect(SEQUENCE)?).expect(OID)?;
+            if id != oid {
+                kept.push(extension);
+            }
+        }
+        if !kept.is_empty() {
+            fields.push(encode(0xa3, &[&encode(SEQUENCE, &kept)]));
+        }
+    }
+    let fields: Vec<&[u8]> = fields.iter().map(Vec::as_slice).collect();
+    Some(encode(SEQUENCE, &fields))
+}
+
+impl<'a> TbsCertificate<'a> {
+    /// The contents of the `extnValue` of the extension with the given OID.
+    pub(crate) fn extension(&self, oid: &[u8]) -> Option<&'a [u8]> {
+        let mut extensions = Reader::new(self.extensions?);
+        while let Some(extension) = extensions.expect(SEQUENCE) {
+            let mut extension = Reader::new(extension);
+            if extension.expect(OID)? != oid {
+                continue;
+            }
+            // critical BOOLEAN DEFAULT FALSE
+            extension.optional(0x01);
+            return extension.expect(OCTET_STRING);
+        }
+        None
+    }
+
+    /// The DNS names of the subject alternative name extension.
+    pub(crate) fn dns_names(&self) -> Vec<&'a str> {
+        // subjectAltName
+        let names = self
+            .extension(&[0x55, 0x1d, 0x11])
+            .and_then(|ext| Reader::new(ext).expect(SEQUENCE));
+        let mut names = Reader::new(names.unwrap_or_default());
+        let mut dns_names = Vec::new();
+        while let Some((tag, name)) = names.read() {
+            // dNSName [2] IMPLICIT IA5String
+            if tag == 0x82 {
+                dns_names.extend(std::str::from_utf8(name).ok());
+            }
+        }
+        dns_names
+    }
+
+    /// The contents of the subject public key `BIT STRING`, without the unused bits count.
+    pub(crate) fn public_key(&self) -> Option<&'a [u8]> {
+        Some(public_key(self.spki)?.key)
+    }
+
+    /// The OID of the public key algorithm, and the OID of its parameters if any (e.g. the curve).
+    pub(crate) fn public_key_algorithm(&self) -> Option<(&'a [u8], Option<&'a [u8]>)> {
+        let key = public_key(self.spki)?;
+        Some((key.algorithm, key.parameters))
+    }
+}
+
+/// The parts of a `SubjectPublicKeyInfo`.
+pub(crate) struct PublicKey<'a> {
+    /// The OID of the public key algorithm.
+    pub(crate) algorithm: &'a [u8],
+    /// The OID in the algorithm parameters, if any (e.g. the curve).
+    pub(crate) parameters: Option<&'a [u8]>,
+    /// The contents of the public key `BIT STRING`, without the unused bits count.
+    pub(crate) key: &'a [u8],
+}
+
+/// Split a DER-encoded `SubjectPublicKeyInfo`.
+pub(crate) fn public_key(spki: &[u8]) -> Option<PublicKey<'_>> {
+    let mut spki = Reader::new(Reader::new(spki).expect(SEQUENCE)?);
+    let mut algorithm_id = Reader::new(spki.expect(SEQUENCE)?);
+    let algorithm = algorithm_id.expect(OID)?;
+    let parameters = algorithm_id.optional(OID);
+    let key = spki.expect(BIT_STRING)?.get(1..)?;
+    Some(PublicKey {
+        algorithm,
+        parameters,
+        key,
+    })
+}
+
+/// A `SEQUENCE { data, signatureAlgorithm, signature }`, as in certificates and OCSP responses.
+pub(crate) struct Signed<'a> {
+    /// The complete encoding of the signed data.
+    pub(crate) data: &'a [u8],
+    /// The OID of the signature algorithm.
+    pub(crate) algorithm: &'a [u8],
+    /// The contents of the signature `BIT STRING`, without the unused bits count.
+    pub(crate) signature: &'a [u8],
+}
+
+/// Split a signed structure, also returning a reader over any elements after the signature.
+pub(crate) fn signed(der: &[u8]) -> Option<(Signed<'_>, Reader<'_>)> {
+    let mut signed = Reader::new(Reader::new(der).expect(SEQUENCE)?);
+    let data = signed.read_raw()?;
+    let algorithm = Reader::new(signed.expect(SEQUENCE)?).expect(OID)?;
+    let signature = match signed.expect(BIT_STRING)?.split_first()? {
+        (0, signature) => signature,
+        _ => return None,
+    };
+    Some((
+        Signed {
+            data,
+            algorithm,
+            signature,
+        },
+        signed,
+    ))
+}
+
+/// The `validity` period of a DER-encoded X.509 certificate.
+pub(crate) fn validity(cert: &[u8]) -> Option<(SystemTime, SystemTime)> {
+    let mut validity = Reader::new(tbs_certificate(cert)?.validity);
+    let not_before = time(validity.read()?)?;
+    let not_after = time(validity.read()?)?;
+    Some((not_before, not_after))
+}
+
+/// Encode an element from its tag and the concatenation of `parts`.
+pub(crate) fn encode(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    let mut out = vec![tag];
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+/// Decode an ASN.1 `UTCTime` or `GeneralizedTime` in the `Z` form DER requires.
+pub(crate) fn time((tag, value): (u8, &[u8])) -> Option<SystemTime> {
+    let value = std::str::from_utf8(value).ok()?;
+    let value = value.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        UTC_TIME if value.len() == 12 => {
+            let year: i64 = value[..2].parse().ok()?;
+            (
+                if year >= 50 { 1900 + year } else { 2000 + year },
+                &value[2..],
+            )
+        }
+        GENERALIZED_TIME if value.len() == 14 => (value[..4].parse().ok()?, &value[4..]),
+        _ => return None,
+    };
+    let field = |i: usize| -> Option<i64> { rest.get(i..i + 2)?.parse().ok() };
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+    }
+}
+
+/// Days since the unix epoch for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_times() {
+        assert_eq!(time((UTC_TIME, b"700101000000Z")), Some(UNIX_EPOCH));
+        assert_eq!(
+            time((GENERALIZED_TIME, b"20000301000000Z")),
+            Some(UNIX_EPOCH + Duration::from_secs(951868800))
+        );
+        assert_eq!(time((UTC_TIME, b"700101000000")), None);
+    }
+
+    #[test]
+    fn reads_certificate_validity() {
+        let pem = include_bytes!("../../tests/end.cert");
+        let cert = rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0);
+        let (not_before, not_after) = validity(&cert).unwrap();
+        assert!(not_before < not_after);
+    }
+
+    #[test]
+    fn splits_signed_certificates() {
+        let pem = include_bytes!("../../tests/end.cert");
+        let cert = rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0);
+        let (signed, mut rest) = signed(&cert).unwrap();
+        // sha256WithRSAEncryption
+        assert_eq!(signed.algorithm, b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0b");
+        assert_eq!(signed.data[0], SEQUENCE);
+        assert!(rest.read().is_none());
+    }
+
+    #[test]
+    fn removes_extensions() {
+        let pem = include_bytes!("../../tests/end.cert");
+        let cert = rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0);
+        let (signed, _) = signed(&cert).unwrap();
+        assert_eq!(tbs_without_extension(&cert, b"\x00").unwrap(), signed.data);
+
+        // subjectKeyIdentifier
+        let ski = [0x55, 0x1d, 0x0e];
+        let stripped = tbs_without_extension(&cert, &ski).unwrap();
+        assert!(stripped.len() < signed.data.len());
+        let stripped = encode(SEQUENCE, &[&stripped]);
+        let tbs = tbs_certificate(&stripped).unwrap();
+        assert!(tbs.extension(&ski).is_none());
+        assert!(tbs.extension(&[0x55, 0x1d, 0x11]).is_some());
+    }
+
+    #[test]
+    fn reads_dns_names() {
+        let pem = include_bytes!("../../tests/end.cert");
+        let cert = rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0);
+        let tbs = tbs_certificate(&cert).unwrap();
+        assert_eq!(
+            tbs.dns_names(),
+            ["testserver.com", "second.testserver.com", "localhost"]
+        );
+    }
+
+    #[test]
+    fn encodes_lengths() {
+        assert_eq!(encode(OCTET_STRING, &[b"ab", b"c"]), b"\x04\x03abc");
+        let long = encode(OCTET_STRING, &[&[0; 300]]);
+        assert_eq!(&long[..4], b"\x04\x82\x01\x2c");
+        assert_eq!(Reader::new(&long).expect(OCTET_STRING).unwrap().len(), 300);
+    }
+}
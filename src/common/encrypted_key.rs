@@ -0,0 +1,200 @@
+//! Decrypting passphrase-protected private keys.
+//!
+//! Two formats are supported: PKCS#8 `ENCRYPTED PRIVATE KEY` (PBES2), and the
+//! legacy OpenSSL format with `Proc-Type: 4,ENCRYPTED` and `DEK-Info` headers
+//! inside an `RSA PRIVATE KEY` or `EC PRIVATE KEY` block.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use md5::{Digest, Md5};
+use rustls::PrivateKey;
+use std::convert::TryFrom;
+use std::io;
+
+/// A PEM block with its RFC 1421 style headers.
+struct Block<'a> {
+    label: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    der: Vec<u8>,
+}
+
+/// Whether `pem` holds a private key that needs a passphrase.
+pub(crate) fn is_encrypted(pem: &[u8]) -> io::Result<bool> {
+    Ok(key_block(pem)?.is_some_and(|block| {
+        block.label == "ENCRYPTED PRIVATE KEY" || block.header("Proc-Type") == Some("4,ENCRYPTED")
+    }))
+}
+
+/// Parse the first private key out of a PEM file, decrypting it with `passphrase`.
+///
+/// Unencrypted keys are returned as is.
+pub(crate) fn private_key(pem: &[u8], passphrase: &[u8]) -> io::Result<PrivateKey> {
+    let block = key_block(pem)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+
+    if block.label == "ENCRYPTED PRIVATE KEY" {
+        let info = pkcs8::EncryptedPrivateKeyInfo::try_from(&block.der[..])
+            .map_err(|err| invalid(format!("invalid encrypted private key: {}", err)))?;
+        let key = info
+            .decrypt(passphrase)
+            .map_err(|_| invalid("incorrect passphrase or corrupt private key"))?;
+        return Ok(PrivateKey(key.as_bytes().to_vec()));
+    }
+
+    match block.header("Proc-Type") {
+        None => Ok(PrivateKey(block.der)),
+        Some("4,ENCRYPTED") => {
+            let dek_info = block
+                .header("DEK-Info")
+                .ok_or_else(|| invalid("missing DEK-Info header"))?;
+            decrypt_legacy(dek_info, &block.der, passphrase).map(PrivateKey)
+        }
+        Some(other) => Err(invalid(format!("unsupported Proc-Type: {}", other))),
+    }
+}
+
+/// Decrypt the body of a legacy OpenSSL encrypted key.
+fn decrypt_legacy(dek_info: &str, data: &[u8], passphrase: &[u8]) -> io::Result<Vec<u8>> {
+    let (cipher, iv) = dek_info
+        .split_once(',')
+        .ok_or_else(|| invalid("malformed DEK-Info header"))?;
+    let iv = hex(iv.trim()).ok_or_else(|| invalid("malformed DEK-Info IV"))?;
+    if iv.len() < 8 {
+        return Err(invalid("malformed DEK-Info IV"));
+    }
+
+    fn decrypt<C: KeyIvInit + BlockDecryptMut>(
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Option<Vec<u8>> {
+        C::new_from_slices(key, iv)
+            .ok()?
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .ok()
+    }
+
+    let key_len = match cipher {
+        "AES-128-CBC" => 16,
+        "AES-192-CBC" => 24,
+        "AES-256-CBC" => 32,
+        "DES-EDE3-CBC" => 24,
+        _ => return Err(invalid(format!("unsupported cipher: {}", cipher))),
+    };
+    let key = bytes_to_key(passphrase, &iv[..8], key_len);
+    let der = match cipher {
+        "AES-128-CBC" => decrypt::<cbc::Decryptor<aes::Aes128>>(&key, &iv, data),
+        "AES-192-CBC" => decrypt::<cbc::Decryptor<aes::Aes192>>(&key, &iv, data),
+        "AES-256-CBC" => decrypt::<cbc::Decryptor<aes::Aes256>>(&key, &iv, data),
+        _ => decrypt::<cbc::Decryptor<des::TdesEde3>>(&key, &iv, data),
+    };
+    der.ok_or_else(|| invalid("incorrect passphrase or corrupt private key"))
+}
+
+/// OpenSSL's `EVP_BytesToKey` with MD5 and a single iteration.
+fn bytes_to_key(passphrase: &[u8], salt: &[u8], len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(len);
+    let mut prev: Option<Vec<u8>> = None;
+    while key.len() < len {
+        let mut hasher = Md5::new();
+        if let Some(prev) = &prev {
+            hasher.update(prev);
+        }
+        hasher.update(passphrase);
+        hasher.update(salt);
+        let digest = hasher.finalize().to_vec();
+        key.extend_from_slice(&digest);
+        prev = Some(digest);
+    }
+    key.truncate(len);
+    key
+}
+
+/// Find the first private key block in `pem`.
+fn key_block(pem: &[u8]) -> io::Result<Option<Block<'_>>> {
+    let pem = std::str::from_utf8(pem).map_err(|_| invalid("PEM file is not valid UTF-8"))?;
+    let mut lines = pem.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let label = match line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|rest| rest.strip_suffix("-----"))
+        {
+            Some(label) if label.ends_with("PRIVATE KEY") => label,
+            _ => continue,
+        };
+
+        let mut headers = Vec::new();
+        let mut body = String::new();
+        let end = format!("-----END {}-----", label);
+        loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid(format!("missing {}", end)))?;
+            if line == end {
+                break;
+            }
+            match line.split_once(':') {
+                Some((name, value)) if body.is_empty() => headers.push((name, value.trim())),
+                _ => body.push_str(line),
+            }
+        }
+
+        let der = STANDARD
+            .decode(body)
+            .map_err(|_| invalid("invalid base64 in private key"))?;
+        return Ok(Some(Block {
+            label,
+            headers,
+            der,
+        }));
+    }
+    Ok(None)
+}
+
+impl Block<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+fn hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn invalid<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_openssl_key() {
+        // openssl enc -aes-128-cbc -P -md md5 -pass pass:secret -S 0001020304050607
+        let key = bytes_to_key(b"secret", &[0, 1, 2, 3, 4, 5, 6, 7], 16);
+        assert_eq!(key, hex("035fb8145b73cf111570dc936112be9c").unwrap());
+    }
+
+    #[test]
+    fn detects_encryption() {
+        assert!(!is_encrypted(include_bytes!("../../tests/end.rsa")).unwrap());
+        assert!(is_encrypted(include_bytes!("../../tests/end.enc.pk8")).unwrap());
+        assert!(is_encrypted(include_bytes!("../../tests/end.enc.rsa")).unwrap());
+    }
+}
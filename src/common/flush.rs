@@ -0,0 +1,19 @@
+//! When the handshake flushes the records it wrote.
+
+/// When the handshake flushes the underlying IO.
+///
+/// Sockets send what is written right away, so this only matters for IO
+/// that buffers writes until flushed, e.g. a `BufWriter`. Either way, what was
+/// written is flushed before the handshake waits for the peer, so `Eager`
+/// flushes once more than it wrote, which costs nothing once the IO's buffer
+/// is empty.
+///
+/// See `TlsConnector::handshake_flush` and `TlsAcceptor::handshake_flush`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeFlush {
+    /// Flush after every write of handshake records, for the lowest latency.
+    Eager,
+    /// Write all records ready to be sent, then flush once before waiting
+    /// for the peer, for the fewest system calls.
+    Batched,
+}
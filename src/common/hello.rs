@@ -0,0 +1,389 @@
+//! Tells full handshakes from resumed ones, and the key exchange group.
+//!
+//! rustls does not say whether a session was resumed, nor which group was
+//! used, so the plaintext records up to the ServerHello are watched instead:
+//! those the client receives, or those the server sends. For TLS 1.2, the
+//! group is only told by the ServerKeyExchange.
+
+use rustls::NamedGroup;
+
+const HANDSHAKE: u8 = 0x16;
+const CHANGE_CIPHER_SPEC: u8 = 0x14;
+
+const SERVER_HELLO: u8 = 2;
+const NEW_SESSION_TICKET: u8 = 4;
+const CERTIFICATE: u8 = 11;
+const SERVER_KEY_EXCHANGE: u8 = 12;
+const CERTIFICATE_STATUS: u8 = 22;
+
+const PRE_SHARED_KEY: u16 = 41;
+const SUPPORTED_VERSIONS: u16 = 43;
+const KEY_SHARE: u16 = 51;
+
+/// The `ECParameters` of a ServerKeyExchange naming its curve.
+const NAMED_CURVE: u8 = 3;
+
+/// The random of a TLS 1.3 HelloRetryRequest, which is sent as a ServerHello.
+const HELLO_RETRY_REQUEST: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// The largest record kept around: a full plaintext record and its header.
+const MAX_RECORD: usize = 5 + (1 << 14);
+
+/// How a connection was set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HandshakeKind {
+    /// A new session was negotiated, with the peer's certificate verified.
+    Full,
+    /// A session from an earlier connection was resumed.
+    Resumed,
+    /// A session was resumed and the server accepted the client's 0-RTT data.
+    ResumedWithEarlyData,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HandshakeKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (index, name) = match self {
+            HandshakeKind::Full => (0, "Full"),
+            HandshakeKind::Resumed => (1, "Resumed"),
+            HandshakeKind::ResumedWithEarlyData => (2, "ResumedWithEarlyData"),
+        };
+        serializer.serialize_unit_variant("HandshakeKind", index, name)
+    }
+}
+
+impl HandshakeKind {
+    /// Whether a session from an earlier connection was resumed.
+    pub fn is_resumed(self) -> bool {
+        self != HandshakeKind::Full
+    }
+}
+
+/// Watches the records up to the ServerHello, and what follows it up to the
+/// ServerKeyExchange for TLS 1.2.
+#[derive(Debug, Default)]
+pub(crate) struct HelloSniffer {
+    /// Whether the records watched are received, by a client, rather than
+    /// sent, by a server.
+    client: bool,
+    /// Bytes of the record being received.
+    record: Vec<u8>,
+    /// Bytes of the handshake messages being received.
+    messages: Vec<u8>,
+    /// Whether a TLS 1.2 ServerHello was seen: the next message tells.
+    tls12: bool,
+    /// Whether watching is over, successfully or not.
+    done: bool,
+    kind: Option<HandshakeKind>,
+    group: Option<NamedGroup>,
+}
+
+impl HelloSniffer {
+    /// Watch the records a client receives.
+    #[cfg(feature = "client")]
+    pub(crate) fn client() -> Self {
+        HelloSniffer {
+            client: true,
+            ..HelloSniffer::default()
+        }
+    }
+
+    /// Watch the records a server sends.
+    #[cfg(feature = "server")]
+    pub(crate) fn server() -> Self {
+        HelloSniffer::default()
+    }
+
+    /// Whether the records to watch are those received rather than sent.
+    pub(crate) fn watches_received(&self) -> bool {
+        self.client
+    }
+
+    /// The kind of handshake seen, if it could be told yet.
+    pub(crate) fn kind(&self) -> Option<HandshakeKind> {
+        self.kind
+    }
+
+    /// The group the keys were exchanged with, if it could be told.
+    pub(crate) fn key_exchange_group(&self) -> Option<NamedGroup> {
+        self.group
+    }
+
+    /// The server accepted early data, which is not told by the ServerHello.
+    #[cfg(feature = "server")]
+    pub(crate) fn set_early_data_accepted(&mut self) {
+        if self.kind == Some(HandshakeKind::Resumed) {
+            self.kind = Some(HandshakeKind::ResumedWithEarlyData);
+        }
+    }
+
+    /// Watch the next bytes of TLS records.
+    pub(crate) fn feed(&mut self, mut data: &[u8]) {
+        while !self.done && !data.is_empty() {
+            let wanted = match self.record.get(3..5) {
+                Some(len) => 5 + usize::from(u16::from_be_bytes([len[0], len[1]])),
+                None => 5,
+            };
+            if wanted > MAX_RECORD {
+                self.give_up();
+                return;
+            }
+            let n = (wanted - self.record.len()).min(data.len());
+            self.record.extend_from_slice(&data[..n]);
+            data = &data[n..];
+
+            // the header alone does not tell the length of the record yet
+            if self.record.len() == wanted && (wanted > 5 || n == 0) {
+                let record = std::mem::take(&mut self.record);
+                self.on_record(record[0], &record[5..]);
+            }
+        }
+    }
+
+    fn on_record(&mut self, content_type: u8, payload: &[u8]) {
+        match content_type {
+            HANDSHAKE => {
+                self.messages.extend_from_slice(payload);
+                self.on_messages();
+            }
+            // a resumed TLS 1.2 session skips right to the Finished messages,
+            // TLS 1.3 may send this after a HelloRetryRequest for middleboxes
+            CHANGE_CIPHER_SPEC if self.tls12 && self.kind.is_none() => {
+                self.decide(HandshakeKind::Resumed)
+            }
+            CHANGE_CIPHER_SPEC => (),
+            _ => self.give_up(),
+        }
+    }
+
+    fn on_messages(&mut self) {
+        while !self.done && self.messages.len() >= 4 {
+            let len = u32::from_be_bytes([0, self.messages[1], self.messages[2], self.messages[3]]);
+            let end = 4 + len as usize;
+            if self.messages.len() < end {
+                return;
+            }
+            let message = self.messages.drain(..end).collect::<Vec<_>>();
+            let (message_type, body) = (message[0], &message[4..]);
+
+            if self.tls12 {
+                match message_type {
+                    // a resumed session may send a ticket, a full handshake a
+                    // certificate, followed by the key exchange
+                    NEW_SESSION_TICKET if self.kind.is_none() => {
+                        self.decide(HandshakeKind::Resumed)
+                    }
+                    CERTIFICATE | CERTIFICATE_STATUS => self.kind = Some(HandshakeKind::Full),
+                    SERVER_KEY_EXCHANGE => {
+                        self.group = parse_server_key_exchange(body);
+                        self.decide(HandshakeKind::Full);
+                    }
+                    _ => self.decide(HandshakeKind::Full),
+                }
+            } else if message_type == SERVER_HELLO {
+                match parse_server_hello(body) {
+                    Some(ServerHello::RetryRequest) => (),
+                    Some(ServerHello::Tls12) => self.tls12 = true,
+                    Some(ServerHello::Tls13 { psk, group }) => {
+                        self.group = group;
+                        self.decide(match psk {
+                            true => HandshakeKind::Resumed,
+                            false => HandshakeKind::Full,
+                        });
+                    }
+                    None => self.give_up(),
+                }
+            } else {
+                self.give_up();
+            }
+        }
+        if self.messages.len() > MAX_RECORD {
+            self.give_up();
+        }
+    }
+
+    fn decide(&mut self, kind: HandshakeKind) {
+        self.kind = Some(kind);
+        self.give_up();
+    }
+
+    fn give_up(&mut self) {
+        self.done = true;
+        self.record = Vec::new();
+        self.messages = Vec::new();
+    }
+}
+
+enum ServerHello {
+    RetryRequest,
+    Tls12,
+    Tls13 {
+        psk: bool,
+        group: Option<NamedGroup>,
+    },
+}
+
+fn parse_server_hello(body: &[u8]) -> Option<ServerHello> {
+    let random = body.get(2..34)?;
+    if random == HELLO_RETRY_REQUEST {
+        return Some(ServerHello::RetryRequest);
+    }
+    let session_id = usize::from(*body.get(34)?);
+    // the cipher suite and the compression method follow the session id
+    let rest = body.get(35 + session_id + 3..)?;
+    let mut extensions = rest.get(2..).unwrap_or_default();
+
+    let (mut tls13, mut psk, mut group) = (false, false, None);
+    while extensions.len() >= 4 {
+        let kind = u16::from_be_bytes([extensions[0], extensions[1]]);
+        let len = usize::from(u16::from_be_bytes([extensions[2], extensions[3]]));
+        let data = extensions.get(4..4 + len)?;
+        match kind {
+            SUPPORTED_VERSIONS => tls13 = data == [3, 4],
+            PRE_SHARED_KEY => psk = true,
+            KEY_SHARE if data.len() >= 2 => {
+                group = Some(NamedGroup::from(u16::from_be_bytes([data[0], data[1]])))
+            }
+            _ => (),
+        }
+        extensions = &extensions[4 + len..];
+    }
+
+    Some(match tls13 {
+        true => ServerHello::Tls13 { psk, group },
+        false => ServerHello::Tls12,
+    })
+}
+
+/// The curve of an ECDHE ServerKeyExchange.
+fn parse_server_key_exchange(body: &[u8]) -> Option<NamedGroup> {
+    match body {
+        [NAMED_CURVE, high, low, ..] => Some(NamedGroup::from(u16::from_be_bytes([*high, *low]))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_hello(random: [u8; 32], extensions: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&random);
+        body.extend_from_slice(&[0, 0x13, 0x01, 0]);
+        let mut exts = Vec::new();
+        for (kind, data) in extensions {
+            exts.extend_from_slice(&kind.to_be_bytes());
+            exts.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            exts.extend_from_slice(data);
+        }
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        message(SERVER_HELLO, &body)
+    }
+
+    fn message(message_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![message_type];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(body);
+        message
+    }
+
+    fn record(content_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 3, 3];
+        record.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    /// Feed the records one byte at a time.
+    fn sniff(records: &[Vec<u8>]) -> Option<HandshakeKind> {
+        watch(records).kind()
+    }
+
+    fn watch(records: &[Vec<u8>]) -> HelloSniffer {
+        let mut sniffer = HelloSniffer::default();
+        for byte in records.concat() {
+            sniffer.feed(&[byte]);
+        }
+        sniffer
+    }
+
+    #[test]
+    fn tells_tls13_handshakes_apart() {
+        let versions: (u16, &[u8]) = (SUPPORTED_VERSIONS, &[3, 4]);
+        let full = server_hello([1; 32], &[versions]);
+        assert_eq!(
+            sniff(&[record(HANDSHAKE, &full)]),
+            Some(HandshakeKind::Full)
+        );
+
+        let resumed = server_hello([1; 32], &[versions, (PRE_SHARED_KEY, &[0, 0])]);
+        let retry = server_hello(HELLO_RETRY_REQUEST, &[versions]);
+        let records = [
+            record(HANDSHAKE, &retry),
+            record(CHANGE_CIPHER_SPEC, &[1]),
+            record(HANDSHAKE, &resumed),
+        ];
+        assert_eq!(sniff(&records), Some(HandshakeKind::Resumed));
+    }
+
+    #[test]
+    fn tells_tls12_handshakes_apart() {
+        let hello = server_hello([1; 32], &[]);
+        let mut flight = hello.clone();
+        flight.extend_from_slice(&message(11, &[0, 0, 0]));
+        assert_eq!(
+            sniff(&[record(HANDSHAKE, &flight)]),
+            Some(HandshakeKind::Full)
+        );
+
+        let records = [record(HANDSHAKE, &hello), record(CHANGE_CIPHER_SPEC, &[1])];
+        assert_eq!(sniff(&records), Some(HandshakeKind::Resumed));
+
+        let records = [
+            record(HANDSHAKE, &hello),
+            record(HANDSHAKE, &message(NEW_SESSION_TICKET, &[0; 6])),
+        ];
+        assert_eq!(sniff(&records), Some(HandshakeKind::Resumed));
+
+        // still waiting for the next message
+        assert_eq!(sniff(&[record(HANDSHAKE, &hello)]), None);
+    }
+
+    #[test]
+    fn tells_the_key_exchange_group() {
+        let versions: (u16, &[u8]) = (SUPPORTED_VERSIONS, &[3, 4]);
+        let key_share: (u16, &[u8]) = (KEY_SHARE, &[0x00, 0x1d, 0, 1, 0]);
+        let hello = server_hello([1; 32], &[versions, key_share]);
+        let sniffer = watch(&[record(HANDSHAKE, &hello)]);
+        assert_eq!(sniffer.key_exchange_group(), Some(NamedGroup::X25519));
+
+        // TLS 1.2 tells it after the certificate and its stapled status
+        let mut flight = server_hello([1; 32], &[]);
+        flight.extend_from_slice(&message(CERTIFICATE, &[0, 0, 0]));
+        flight.extend_from_slice(&message(CERTIFICATE_STATUS, &[1, 0, 0, 0]));
+        let params = [NAMED_CURVE, 0x00, 0x17, 1, 4];
+        flight.extend_from_slice(&message(SERVER_KEY_EXCHANGE, &params));
+        let sniffer = watch(&[record(HANDSHAKE, &flight)]);
+        assert_eq!(sniffer.kind(), Some(HandshakeKind::Full));
+        assert_eq!(sniffer.key_exchange_group(), Some(NamedGroup::secp256r1));
+
+        let hello = server_hello([1; 32], &[]);
+        let records = [record(HANDSHAKE, &hello), record(CHANGE_CIPHER_SPEC, &[1])];
+        assert_eq!(watch(&records).key_exchange_group(), None);
+    }
+
+    #[test]
+    fn gives_up_on_garbage() {
+        assert_eq!(sniff(&[record(0x17, &[0; 10])]), None);
+        assert_eq!(
+            sniff(&[record(HANDSHAKE, &message(SERVER_HELLO, &[3]))]),
+            None
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! A minimal HTTP/1.1 client, just enough to talk to ACME directories and OCSP responders.
+
+#[cfg(feature = "acme")]
+use crate::TlsConnector;
+
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::TcpStream;
+use futures_io::{AsyncRead, AsyncWrite};
+use std::io;
+
+/// The largest response read, ACME and OCSP responses are far smaller.
+const MAX_RESPONSE: u64 = 1 << 20;
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send a single request over a fresh TLS connection.
+#[cfg(feature = "acme")]
+pub(crate) async fn request(
+    connector: &TlsConnector,
+    method: &str,
+    url: &str,
+    body: Option<(&str, &[u8])>,
+) -> io::Result<Response> {
+    let (host, port, path) = parse_url(url, "https://", 443)?;
+
+    let stream = TcpStream::connect((host, port)).await?;
+    let stream = connector.connect(host, stream).await?;
+    send(stream, method, host, path, body).await
+}
+
+/// Send a single request over a fresh plain-text connection, as OCSP responders expect.
+#[cfg(all(feature = "ocsp", feature = "server"))]
+pub(crate) async fn request_plain(
+    method: &str,
+    url: &str,
+    body: Option<(&str, &[u8])>,
+) -> io::Result<Response> {
+    let (host, port, path) = parse_url(url, "http://", 80)?;
+
+    let stream = TcpStream::connect((host, port)).await?;
+    send(stream, method, host, path, body).await
+}
+
+async fn send<S>(
+    mut stream: S,
+    method: &str,
+    host: &str,
+    path: &str,
+    body: Option<(&str, &[u8])>,
+) -> io::Result<Response>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: async-tls\r\nAccept: */*\r\nConnection: close\r\n",
+        method, path, host
+    );
+    if let Some((content_type, body)) = body {
+        request.push_str(&format!("Content-Type: {}\r\n", content_type));
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await?;
+    if let Some((_, body)) = body {
+        stream.write_all(body).await?;
+    }
+    stream.flush().await?;
+
+    let mut raw = Vec::new();
+    match (&mut stream)
+        .take(MAX_RESPONSE + 1)
+        .read_to_end(&mut raw)
+        .await
+    {
+        Ok(_) => (),
+        // Plenty of servers close without a close_notify once the response is complete.
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => (),
+        Err(err) => return Err(err),
+    }
+    if raw.len() as u64 > MAX_RESPONSE {
+        return Err(invalid("HTTP response too large"));
+    }
+
+    parse_response(&raw)
+}
+
+fn parse_url<'a>(
+    url: &'a str,
+    scheme: &'static str,
+    default_port: u16,
+) -> io::Result<(&'a str, u16, &'a str)> {
+    let rest = url
+        .strip_prefix(scheme)
+        .ok_or_else(|| invalid("unsupported URL scheme"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        // an IPv6 address, e.g. `[::1]:8443`
+        Some(rest) => {
+            let (host, port) = rest
+                .split_once(']')
+                .ok_or_else(|| invalid("invalid IPv6 address in URL"))?;
+            match port {
+                "" => (host, None),
+                port => (host, Some(port.strip_prefix(':').unwrap_or(port))),
+            }
+        }
+        None => match authority.rsplit_once(':') {
+            // a bare IPv6 address has no room for a port
+            Some(_) if authority.matches(':').count() > 1 => (authority, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| invalid("invalid port in URL"))?,
+        None => default_port,
+    };
+    Ok((host, port, path))
+}
+
+fn parse_response(raw: &[u8]) -> io::Result<Response> {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("truncated HTTP response"))?;
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| invalid("invalid HTTP header"))?;
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("invalid HTTP status line"))?;
+    let headers: Vec<_> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let body = &raw[end + 4..];
+    response.body = match response.header("Transfer-Encoding") {
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => dechunk(body)?,
+        _ => match response.header("Content-Length") {
+            Some(len) => {
+                let len = len.parse().map_err(|_| invalid("invalid Content-Length"))?;
+                body.get(..len)
+                    .ok_or_else(|| invalid("truncated HTTP body"))?
+                    .to_vec()
+            }
+            None => body.to_vec(),
+        },
+    };
+    Ok(response)
+}
+
+fn dechunk(mut body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(|| invalid("invalid chunk size"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        let chunk = body.get(..size).ok_or_else(|| invalid("truncated chunk"))?;
+        out.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            parse_url("https://acme.example/dir", "https://", 443).unwrap(),
+            ("acme.example", 443, "/dir")
+        );
+        assert_eq!(
+            parse_url("https://localhost:14000", "https://", 443).unwrap(),
+            ("localhost", 14000, "/")
+        );
+        assert_eq!(
+            parse_url("http://ocsp.example", "http://", 80).unwrap(),
+            ("ocsp.example", 80, "/")
+        );
+        assert_eq!(
+            parse_url("https://[::1]:14000/dir", "https://", 443).unwrap(),
+            ("::1", 14000, "/dir")
+        );
+        assert_eq!(
+            parse_url("http://[2001:db8::1]", "http://", 80).unwrap(),
+            ("2001:db8::1", 80, "/")
+        );
+        assert_eq!(
+            parse_url("http://2001:db8::1/ocsp", "http://", 80).unwrap(),
+            ("2001:db8::1", 80, "/ocsp")
+        );
+        assert!(parse_url("http://[::1]x/", "http://", 80).is_err());
+        assert!(parse_url("http://acme.example/", "https://", 443).is_err());
+    }
+
+    #[test]
+    fn parses_chunked_response() {
+        let raw = b"HTTP/1.1 201 Created\r\nReplay-Nonce: abc\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.header("replay-nonce"), Some("abc"));
+        assert_eq!(response.body, b"{\"a\":1}");
+    }
+}
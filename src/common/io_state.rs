@@ -0,0 +1,53 @@
+//! The state of the buffers of a connection, kept as the stream does IO.
+
+/// The plaintext received but not read yet, the TLS bytes waiting to be
+/// sent, and whether the peer sent `close_notify`.
+///
+/// Taken from rustls whenever records are processed, and kept up to date
+/// from the plaintext read and the records sent in between, so reading and
+/// writing need not ask rustls again.
+///
+/// See `client::TlsStream::io_state` and `server::TlsStream::io_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoState {
+    plaintext_bytes_to_read: usize,
+    tls_bytes_to_write: usize,
+    peer_has_closed: bool,
+}
+
+impl IoState {
+    /// How many bytes of plaintext were received but not read yet.
+    pub fn plaintext_bytes_to_read(&self) -> usize {
+        self.plaintext_bytes_to_read
+    }
+
+    /// How many bytes of TLS records wait to be sent.
+    pub fn tls_bytes_to_write(&self) -> usize {
+        self.tls_bytes_to_write
+    }
+
+    /// Whether the peer sent `close_notify`, so nothing more is to be read.
+    pub fn peer_has_closed(&self) -> bool {
+        self.peer_has_closed
+    }
+
+    /// Account for `len` bytes of plaintext taken by a read.
+    pub(crate) fn read(&mut self, len: usize) {
+        self.plaintext_bytes_to_read = self.plaintext_bytes_to_read.saturating_sub(len);
+    }
+
+    /// Account for records sent, with `left` bytes still queued.
+    pub(crate) fn sent(&mut self, left: usize) {
+        self.tls_bytes_to_write = left;
+    }
+}
+
+impl From<rustls::IoState> for IoState {
+    fn from(state: rustls::IoState) -> Self {
+        IoState {
+            plaintext_bytes_to_read: state.plaintext_bytes_to_read(),
+            tls_bytes_to_write: state.tls_bytes_to_write(),
+            peer_has_closed: state.peer_has_closed(),
+        }
+    }
+}
@@ -1 +1,35 @@
+pub(crate) mod cancel;
+pub(crate) mod channel_binding;
+pub(crate) mod chunking;
+#[cfg(feature = "server")]
+pub(crate) mod client_hello;
+pub(crate) mod close;
+pub(crate) mod coalesce;
+#[cfg(feature = "tokio")]
+pub(crate) mod compat;
+#[cfg(any(
+    feature = "client",
+    feature = "server",
+    feature = "acme",
+    feature = "ocsp",
+    feature = "ct",
+    feature = "dane",
+    feature = "tofu"
+))]
+pub(crate) mod der;
+#[cfg(feature = "encrypted-keys")]
+pub(crate) mod encrypted_key;
+pub(crate) mod flush;
+pub(crate) mod hello;
+#[cfg(any(feature = "acme", all(feature = "ocsp", feature = "server")))]
+pub(crate) mod http;
+pub(crate) mod io_state;
+#[cfg(feature = "ocsp")]
+pub(crate) mod ocsp;
+pub(crate) mod pem;
+pub(crate) mod policy;
+#[cfg(feature = "server")]
+pub(crate) mod probe;
+pub(crate) mod records;
 pub(crate) mod tls_state;
+pub(crate) mod traffic;
@@ -0,0 +1,283 @@
+//! Building OCSP requests and reading OCSP responses ([RFC 6960]).
+//!
+//! Response signatures are not checked here, that is up to the relying party:
+//! `basic_response` hands out what is needed to do so.
+//!
+//! [RFC 6960]: https://www.rfc-editor.org/rfc/rfc6960
+
+use super::der::{self, Reader, ENUMERATED, INTEGER, OCTET_STRING, OID, SEQUENCE};
+
+use std::io;
+use std::time::SystemTime;
+
+/// id-pe-authorityInfoAccess
+#[cfg(feature = "server")]
+const AUTHORITY_INFO_ACCESS: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01];
+/// id-ad-ocsp
+#[cfg(feature = "server")]
+const AD_OCSP: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+/// id-pkix-ocsp-basic
+const OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// id-sha1
+#[cfg(feature = "server")]
+const SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+
+/// The revocation status of a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// The parts of an OCSP response concerning a single certificate.
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub(crate) status: CertStatus,
+    pub(crate) this_update: SystemTime,
+    pub(crate) next_update: Option<SystemTime>,
+}
+
+/// A `BasicOCSPResponse`, split for checking its signature.
+pub(crate) struct BasicResponse<'a> {
+    /// `tbsResponseData` and its signature.
+    pub(crate) signed: der::Signed<'a>,
+    /// The complete encodings of the certificates included to help verify the signature.
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    pub(crate) certs: Vec<&'a [u8]>,
+}
+
+/// The OCSP responder URL from a certificate's authority information access extension.
+#[cfg(feature = "server")]
+pub(crate) fn responder_url(cert: &[u8]) -> Option<String> {
+    let aia = der::tbs_certificate(cert)?.extension(AUTHORITY_INFO_ACCESS)?;
+    let mut descriptions = Reader::new(Reader::new(aia).expect(SEQUENCE)?);
+    while let Some(description) = descriptions.expect(SEQUENCE) {
+        let mut description = Reader::new(description);
+        if description.expect(OID)? != AD_OCSP {
+            continue;
+        }
+        // uniformResourceIdentifier [6] IMPLICIT IA5String
+        if let Some(uri) = description.optional(0x86) {
+            return String::from_utf8(uri.to_vec()).ok();
+        }
+    }
+    None
+}
+
+/// A DER-encoded OCSP request for `cert`, which was issued by `issuer`.
+#[cfg(feature = "server")]
+pub(crate) fn request(cert: &[u8], issuer: &[u8]) -> Option<Vec<u8>> {
+    use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+    let cert = der::tbs_certificate(cert)?;
+    let issuer = der::tbs_certificate(issuer)?;
+    let name_hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, issuer.subject);
+    let key_hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, issuer.public_key()?);
+
+    let algorithm = der::encode(
+        SEQUENCE,
+        &[&der::encode(OID, &[SHA1]), &der::encode(der::NULL, &[])],
+    );
+    let cert_id = der::encode(
+        SEQUENCE,
+        &[
+            &algorithm,
+            &der::encode(OCTET_STRING, &[name_hash.as_ref()]),
+            &der::encode(OCTET_STRING, &[key_hash.as_ref()]),
+            &der::encode(INTEGER, &[cert.serial]),
+        ],
+    );
+    let request = der::encode(SEQUENCE, &[&cert_id]);
+    let request_list = der::encode(SEQUENCE, &[&request]);
+    let tbs_request = der::encode(SEQUENCE, &[&request_list]);
+    Some(der::encode(SEQUENCE, &[&tbs_request]))
+}
+
+/// Unwrap the `BasicOCSPResponse` from an OCSP response.
+pub(crate) fn basic_response(response: &[u8]) -> io::Result<BasicResponse<'_>> {
+    let malformed = || invalid("malformed OCSP response");
+
+    let mut response = Reader::new(
+        Reader::new(response)
+            .expect(SEQUENCE)
+            .ok_or_else(malformed)?,
+    );
+    match response.expect(ENUMERATED).ok_or_else(malformed)? {
+        [0] => (),
+        [1] => return Err(invalid("OCSP responder: malformed request")),
+        [2] => return Err(invalid("OCSP responder: internal error")),
+        [3] => return Err(invalid("OCSP responder: try later")),
+        [5] => return Err(invalid("OCSP responder: signature required")),
+        [6] => return Err(invalid("OCSP responder: unauthorized")),
+        _ => return Err(malformed()),
+    }
+
+    // responseBytes [0] EXPLICIT
+    let bytes = response.expect(0xa0).ok_or_else(malformed)?;
+    let mut bytes = Reader::new(Reader::new(bytes).expect(SEQUENCE).ok_or_else(malformed)?);
+    if bytes.expect(OID).ok_or_else(malformed)? != OCSP_BASIC {
+        return Err(invalid("unsupported OCSP response type"));
+    }
+    let basic = bytes.expect(OCTET_STRING).ok_or_else(malformed)?;
+    let (signed, mut rest) = der::signed(basic).ok_or_else(malformed)?;
+
+    // certs [0] EXPLICIT SEQUENCE OF Certificate OPTIONAL
+    let mut certs = Vec::new();
+    if let Some(explicit) = rest.optional(0xa0) {
+        let mut list = Reader::new(
+            Reader::new(explicit)
+                .expect(SEQUENCE)
+                .ok_or_else(malformed)?,
+        );
+        while let Some(cert) = list.read_raw() {
+            certs.push(cert);
+        }
+    }
+    Ok(BasicResponse { signed, certs })
+}
+
+/// Read the status of the certificate with the given serial number out of an OCSP response.
+pub(crate) fn parse_response(response: &[u8], serial: &[u8]) -> io::Result<Response> {
+    let malformed = || invalid("malformed OCSP response");
+
+    let data = basic_response(response)?.signed.data;
+    let data = Reader::new(data).expect(SEQUENCE).ok_or_else(malformed)?;
+    let mut data = Reader::new(data);
+    // version [0] EXPLICIT, responderID, producedAt
+    data.optional(0xa0);
+    data.read().ok_or_else(malformed)?;
+    data.read().ok_or_else(malformed)?;
+
+    let mut responses = Reader::new(data.expect(SEQUENCE).ok_or_else(malformed)?);
+    while let Some(single) = responses.expect(SEQUENCE) {
+        let mut single = Reader::new(single);
+        let mut cert_id = Reader::new(single.expect(SEQUENCE).ok_or_else(malformed)?);
+        cert_id.expect(SEQUENCE).ok_or_else(malformed)?;
+        cert_id.expect(OCTET_STRING).ok_or_else(malformed)?;
+        cert_id.expect(OCTET_STRING).ok_or_else(malformed)?;
+        if cert_id.expect(INTEGER).ok_or_else(malformed)? != serial {
+            continue;
+        }
+
+        let status = match single.read().ok_or_else(malformed)?.0 {
+            0x80 => CertStatus::Good,
+            0xa1 => CertStatus::Revoked,
+            0x82 => CertStatus::Unknown,
+            _ => return Err(malformed()),
+        };
+        let this_update = der::time(single.read().ok_or_else(malformed)?).ok_or_else(malformed)?;
+        let next_update = match single.optional(0xa0) {
+            Some(explicit) => Some(
+                der::time(Reader::new(explicit).read().ok_or_else(malformed)?)
+                    .ok_or_else(malformed)?,
+            ),
+            None => None,
+        };
+        return Ok(Response {
+            status,
+            this_update,
+            next_update,
+        });
+    }
+    Err(invalid("OCSP response does not cover the certificate"))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::der::GENERALIZED_TIME;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn response(serial: &[u8], status: &[u8], next_update: bool) -> Vec<u8> {
+        let e = der::encode;
+        let cert_id = e(
+            SEQUENCE,
+            &[
+                &e(SEQUENCE, &[&e(OID, &[SHA1_OID]), &e(der::NULL, &[])]),
+                &e(OCTET_STRING, &[&[0; 20]]),
+                &e(OCTET_STRING, &[&[0; 20]]),
+                &e(INTEGER, &[serial]),
+            ],
+        );
+        let this_update = e(GENERALIZED_TIME, &[b"20000301000000Z"]);
+        let next_update = if next_update {
+            e(0xa0, &[&e(GENERALIZED_TIME, &[b"20000308000000Z"])])
+        } else {
+            Vec::new()
+        };
+        let single = e(SEQUENCE, &[&cert_id, status, &this_update, &next_update]);
+        let data = e(
+            SEQUENCE,
+            &[
+                &e(0xa2, &[&e(OCTET_STRING, &[&[0; 20]])]),
+                &this_update,
+                &e(SEQUENCE, &[&single]),
+            ],
+        );
+        let algorithm = e(SEQUENCE, &[&e(OID, &[ECDSA_WITH_SHA256])]);
+        let basic = e(SEQUENCE, &[&data, &algorithm, &e(0x03, &[&[0]])]);
+        let bytes = e(
+            SEQUENCE,
+            &[&e(OID, &[OCSP_BASIC]), &e(OCTET_STRING, &[&basic])],
+        );
+        e(SEQUENCE, &[&e(ENUMERATED, &[&[0]]), &e(0xa0, &[&bytes])])
+    }
+
+    const SHA1_OID: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+    const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+    #[test]
+    fn parses_responses() {
+        let good = parse_response(&response(&[42], &[0x80, 0], true), &[42]).unwrap();
+        assert_eq!(good.status, CertStatus::Good);
+        assert_eq!(
+            good.this_update,
+            UNIX_EPOCH + Duration::from_secs(951868800)
+        );
+        assert_eq!(
+            good.next_update,
+            Some(UNIX_EPOCH + Duration::from_secs(951868800 + 7 * 86400))
+        );
+
+        let revoked = der::encode(
+            0xa1,
+            &[&der::encode(GENERALIZED_TIME, &[b"20000301000000Z"])],
+        );
+        let revoked = parse_response(&response(&[42], &revoked, false), &[42]).unwrap();
+        assert_eq!(revoked.status, CertStatus::Revoked);
+        assert_eq!(revoked.next_update, None);
+
+        assert!(parse_response(&response(&[42], &[0x80, 0], true), &[43]).is_err());
+        assert!(parse_response(b"\x30\x03\x0a\x01\x03", &[42]).is_err());
+    }
+
+    #[test]
+    fn splits_basic_responses() {
+        let response = response(&[42], &[0x80, 0], true);
+        let basic = basic_response(&response).unwrap();
+        assert_eq!(basic.signed.data[0], SEQUENCE);
+        assert_eq!(basic.signed.algorithm, ECDSA_WITH_SHA256);
+        assert!(basic.signed.signature.is_empty());
+        assert!(basic.certs.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn builds_requests_like_openssl() {
+        let end = rustls_pemfile::certs(&mut &include_bytes!("../../tests/end.cert")[..]).unwrap();
+        let ca = rustls_pemfile::certs(&mut &include_bytes!("../../tests/ca.cert")[..]).unwrap();
+        // openssl ocsp -issuer tests/ca.cert -cert tests/end.cert -no_nonce -reqout -
+        let expected = "305530533051304f304d300906052b0e03021a05000414e1540b8eced562e7ac203c9d267d647e895e182904141d0de16d2a0c6f88924bc3591e7e35dc75d4fb6f021474c7faa3c6bb6d100d0c5427983b9882f37b11b1";
+        let request: String = request(&end[0], &ca[0])
+            .unwrap()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(request, expected);
+    }
+}
@@ -0,0 +1,49 @@
+use rustls::{Certificate, PrivateKey};
+use rustls_pemfile::Item;
+use std::io::{self, BufReader, Cursor};
+
+/// Parse every certificate out of a PEM bundle.
+pub(crate) fn certs(pem: &[u8]) -> io::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(Cursor::new(pem)))?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no certificates found",
+        ));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Parse every certificate revocation list out of a PEM bundle.
+#[cfg(feature = "crl")]
+pub(crate) fn crls(pem: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let crls = rustls_pemfile::crls(&mut BufReader::new(Cursor::new(pem)))?;
+    if crls.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no certificate revocation lists found",
+        ));
+    }
+    Ok(crls)
+}
+
+/// Parse the first private key out of a PEM file.
+///
+/// PKCS#8, PKCS#1 (RSA) and SEC1 (EC) keys are accepted.
+pub(crate) fn private_key(pem: &[u8]) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(Cursor::new(pem));
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => {
+                return Ok(PrivateKey(key))
+            }
+            Some(_) => (),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no private key found",
+                ))
+            }
+        }
+    }
+}
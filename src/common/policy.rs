@@ -0,0 +1,223 @@
+//! The TLS versions, cipher suites and key exchange groups a connector or
+//! acceptor allows, and the rustls configs offering only those.
+
+use crate::SecurityPreset;
+
+use rustls::{
+    ConfigBuilder, ConfigSide, ProtocolVersion, SupportedCipherSuite, SupportedProtocolVersion,
+    WantsCipherSuites, WantsVerifier,
+};
+use std::io;
+
+/// The versions, suites and groups allowed, those rustls enables by default
+/// unless restricted.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Policy {
+    /// Allows only the versions, suites and groups of the preset.
+    preset: Option<SecurityPreset>,
+    min: Option<u16>,
+    max: Option<u16>,
+    /// The allowed suites instead of those of the preset, as a mask over
+    /// `rustls::ALL_CIPHER_SUITES`.
+    suites: Option<u32>,
+}
+
+impl Policy {
+    /// Allow what `preset` allows.
+    pub(crate) fn preset(preset: SecurityPreset) -> Self {
+        Policy {
+            preset: Some(preset),
+            ..Policy::default()
+        }
+    }
+
+    pub(crate) fn set_min(&mut self, version: &'static SupportedProtocolVersion) {
+        self.min = Some(version.version.get_u16());
+    }
+
+    pub(crate) fn set_max(&mut self, version: &'static SupportedProtocolVersion) {
+        self.max = Some(version.version.get_u16());
+    }
+
+    pub(crate) fn allows(&self, version: ProtocolVersion) -> bool {
+        let number = version.get_u16();
+        self.min.is_none_or(|min| number >= min)
+            && self.max.is_none_or(|max| number <= max)
+            && self
+                .preset
+                .is_none_or(|preset| preset.versions().iter().any(|v| v.version == version))
+    }
+
+    /// Only allow `suites`, instead of those of the preset. At least one must
+    /// suit the versions allowed.
+    pub(crate) fn set_suites(&mut self, suites: &[SupportedCipherSuite]) -> io::Result<()> {
+        let mut mask = 0;
+        for suite in suites {
+            mask |= 1 << suite_index(suite.suite())?;
+        }
+        if mask == 0 {
+            return Err(invalid_suites("no cipher suites allowed"));
+        }
+        if !suites
+            .iter()
+            .any(|suite| self.allows(suite.version().version))
+        {
+            return Err(invalid_suites(
+                "no cipher suite allowed for the TLS versions allowed",
+            ));
+        }
+        self.suites = Some(mask);
+        Ok(())
+    }
+
+    /// Only allow the suites named `names`, e.g. `TLS13_AES_128_GCM_SHA256`,
+    /// ignoring case.
+    pub(crate) fn set_suite_names(&mut self, names: &[&str]) -> io::Result<()> {
+        let suites = names
+            .iter()
+            .map(|name| suite_named(name))
+            .collect::<io::Result<Vec<_>>>()?;
+        self.set_suites(&suites)
+    }
+
+    pub(crate) fn allows_suite(&self, suite: SupportedCipherSuite) -> bool {
+        match (self.suites, self.preset) {
+            (Some(mask), _) => suite_index(suite.suite()).is_ok_and(|i| mask & (1 << i) != 0),
+            (None, Some(preset)) => preset.cipher_suites().contains(&suite),
+            (None, None) => true,
+        }
+    }
+
+    /// Offer only the versions, suites and groups allowed.
+    ///
+    /// Fails with `InvalidInput` if no suite suits the versions allowed.
+    pub(crate) fn configure<S: ConfigSide>(
+        &self,
+        builder: ConfigBuilder<S, WantsCipherSuites>,
+    ) -> io::Result<ConfigBuilder<S, WantsVerifier>> {
+        let (versions, suites, groups) = match self.preset {
+            Some(preset) => (
+                preset.versions(),
+                preset.cipher_suites(),
+                preset.kx_groups(),
+            ),
+            None => (
+                rustls::DEFAULT_VERSIONS,
+                rustls::DEFAULT_CIPHER_SUITES,
+                &rustls::ALL_KX_GROUPS[..],
+            ),
+        };
+        let versions: Vec<_> = (versions.iter().copied())
+            .filter(|version| self.allows(version.version))
+            .collect();
+        let suites = match self.suites {
+            Some(_) => rustls::ALL_CIPHER_SUITES,
+            None => suites,
+        };
+        let suites: Vec<_> = (suites.iter().copied())
+            .filter(|suite| self.allows_suite(*suite))
+            .collect();
+        builder
+            .with_cipher_suites(&suites)
+            .with_kx_groups(groups)
+            .with_protocol_versions(&versions)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+}
+
+/// The suite named `name`, e.g. `TLS13_AES_128_GCM_SHA256`, ignoring case.
+pub(crate) fn suite_named(name: &str) -> io::Result<SupportedCipherSuite> {
+    rustls::ALL_CIPHER_SUITES
+        .iter()
+        .copied()
+        .find(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(name))
+        .ok_or_else(|| invalid_suites(&format!("unknown cipher suite {}", name)))
+}
+
+fn suite_index(suite: rustls::CipherSuite) -> io::Result<usize> {
+    rustls::ALL_CIPHER_SUITES
+        .iter()
+        .position(|known| known.suite() == suite)
+        .ok_or_else(|| invalid_suites(&format!("unsupported cipher suite {:?}", suite)))
+}
+
+fn invalid_suites(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::cipher_suite::{
+        TLS13_AES_128_GCM_SHA256, TLS13_CHACHA20_POLY1305_SHA256,
+        TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    };
+    use rustls::version::{TLS12, TLS13};
+    use rustls::ClientConfig;
+
+    #[test]
+    fn bounds_versions() {
+        let mut bounds = Policy::default();
+        assert!(bounds.allows(ProtocolVersion::TLSv1_0));
+        bounds.set_min(&TLS12);
+        assert!(!bounds.allows(ProtocolVersion::TLSv1_1));
+        assert!(bounds.allows(ProtocolVersion::TLSv1_3));
+        bounds.set_max(&TLS12);
+        assert!(bounds.allows(ProtocolVersion::TLSv1_2));
+        assert!(!bounds.allows(ProtocolVersion::TLSv1_3));
+        assert!(bounds.configure(ClientConfig::builder()).is_ok());
+        bounds.set_min(&TLS13);
+        assert!(!bounds.allows(ProtocolVersion::TLSv1_2));
+        assert!(!bounds.allows(ProtocolVersion::TLSv1_3));
+        assert_eq!(
+            bounds
+                .configure(ClientConfig::builder())
+                .err()
+                .map(|err| err.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn restricts_suites() {
+        let mut policy = Policy::default();
+        assert!(policy.allows_suite(TLS13_CHACHA20_POLY1305_SHA256));
+        policy
+            .set_suite_names(&["tls13_aes_128_gcm_sha256"])
+            .unwrap();
+        assert!(policy.allows_suite(TLS13_AES_128_GCM_SHA256));
+        assert!(!policy.allows_suite(TLS13_CHACHA20_POLY1305_SHA256));
+        assert!(policy.configure(ClientConfig::builder()).is_ok());
+
+        let invalid = |result: io::Result<()>| result.unwrap_err().kind();
+        assert_eq!(
+            invalid(policy.set_suite_names(&["TLS_NULL_WITH_NULL_NULL"])),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(invalid(policy.set_suites(&[])), io::ErrorKind::InvalidInput);
+        policy.set_min(&TLS13);
+        assert_eq!(
+            invalid(policy.set_suites(&[TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256])),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(policy.allows_suite(TLS13_AES_128_GCM_SHA256));
+    }
+
+    #[test]
+    fn follows_presets() {
+        let modern = Policy::preset(SecurityPreset::MozillaModern);
+        assert!(!modern.allows(ProtocolVersion::TLSv1_2));
+        assert!(!modern.allows_suite(TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256));
+        assert!(modern.allows_suite(TLS13_CHACHA20_POLY1305_SHA256));
+
+        // suites replace those of the preset
+        let mut intermediate = Policy::preset(SecurityPreset::MozillaIntermediate);
+        assert!(intermediate.allows(ProtocolVersion::TLSv1_2));
+        assert!(!intermediate.allows(ProtocolVersion::TLSv1_1));
+        intermediate
+            .set_suites(&[TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256])
+            .unwrap();
+        assert!(!intermediate.allows_suite(TLS13_AES_128_GCM_SHA256));
+        assert!(intermediate.configure(ClientConfig::builder()).is_ok());
+    }
+}
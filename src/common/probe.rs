@@ -0,0 +1,339 @@
+//! Tells what clients not speaking TLS sent to the TLS port: plaintext HTTP,
+//! ancient protocols or garbage.
+
+use crate::common::client_hello::{ClientHelloSummary, CLIENT_HELLO, HANDSHAKE};
+use crate::error::error_ref;
+use crate::Error;
+
+use rustls::{PeerIncompatible, ProtocolVersion};
+use std::fmt;
+use std::io::{self, Read};
+
+/// The most bytes of a request kept, enough for the headers of most.
+const MAX_PREFIX: usize = 8 * 1024;
+
+/// A record header, and the handshake header and version of a `ClientHello`.
+const HEAD_LEN: usize = 5 + 4 + 2;
+
+/// The most bytes of the `ClientHello` records kept, more than clients send.
+const MAX_HELLO: usize = 16 * 1024;
+
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// A plaintext HTTP request received instead of a `ClientHello`, see
+/// `Error::PlaintextHttp`.
+///
+/// Take the connection back with `Accept::take_io` or
+/// `LazyConfigAcceptor::take_io` to answer it, e.g. with a redirect to
+/// `https_url`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlaintextHttp {
+    /// The method, e.g. `GET`.
+    pub method: String,
+    /// The request target, e.g. `/index.html`, as far as it was received.
+    pub target: String,
+    /// The `Host` header, if it was received.
+    pub host: Option<String>,
+    /// The bytes received, at most 8 KiB of the request.
+    pub prefix: Vec<u8>,
+}
+
+impl PlaintextHttp {
+    /// The URL to redirect the client to, e.g. `https://example.com:8443/index.html`,
+    /// `None` without a `Host` header or with a target that is not a path.
+    pub fn https_url(&self) -> Option<String> {
+        let host = self.host.as_ref()?;
+        if !self.target.starts_with('/') {
+            return None;
+        }
+        Some(format!("https://{}{}", host, self.target))
+    }
+
+    fn parse(prefix: &[u8]) -> Option<Self> {
+        let space = prefix.iter().position(|&b| b == b' ')?;
+        let method = std::str::from_utf8(&prefix[..space]).ok()?;
+        if !METHODS.contains(&method) {
+            return None;
+        }
+
+        let line_end = prefix.windows(2).position(|w| w == b"\r\n");
+        let line = &prefix[space + 1..line_end.unwrap_or(prefix.len())];
+        let target = match line.iter().position(|&b| b == b' ') {
+            Some(end) => &line[..end],
+            None => line,
+        };
+
+        let host = line_end.and_then(|end| {
+            prefix[end + 2..]
+                .split(|&b| b == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                .take_while(|line| !line.is_empty())
+                .filter_map(|line| {
+                    let colon = line.iter().position(|&b| b == b':')?;
+                    let name = &line[..colon];
+                    match name.eq_ignore_ascii_case(b"host") {
+                        true => Some(
+                            String::from_utf8_lossy(&line[colon + 1..])
+                                .trim()
+                                .to_string(),
+                        ),
+                        false => None,
+                    }
+                })
+                .next()
+        });
+
+        Some(PlaintextHttp {
+            method: method.to_string(),
+            target: String::from_utf8_lossy(target).into_owned(),
+            host,
+            prefix: prefix.to_vec(),
+        })
+    }
+}
+
+/// The first five bytes a client sent, where a TLS record header was expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader(pub [u8; 5]);
+
+impl RecordHeader {
+    /// The content type, `0x16` for handshake records.
+    pub fn content_type(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// The record version, `0x0301` in the `ClientHello` of most clients.
+    pub fn version(&self) -> ProtocolVersion {
+        ProtocolVersion::from(u16::from_be_bytes([self.0[1], self.0[2]]))
+    }
+
+    /// The length of the record that follows.
+    pub fn length(&self) -> u16 {
+        u16::from_be_bytes([self.0[3], self.0[4]])
+    }
+
+    /// Whether this is the header of a TLS record: of a known content type,
+    /// with a version of SSLv3 or later.
+    fn is_tls(&self) -> bool {
+        (0x14..=0x18).contains(&self.0[0]) && self.0[1] == 3
+    }
+
+    /// Whether this is the header of an SSLv2 `CLIENT-HELLO`: a two byte
+    /// length with the high bit set, then the message type.
+    fn is_sslv2_hello(&self) -> bool {
+        self.0[0] & 0x80 != 0 && self.0[2] == CLIENT_HELLO
+    }
+}
+
+impl fmt::Display for RecordHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e] = self.0;
+        write!(f, "{:02x} {:02x} {:02x} {:02x} {:02x}", a, b, c, d, e)
+    }
+}
+
+/// Keeps the first bytes received: the `ClientHello`, or as much as may be
+/// an HTTP request.
+#[derive(Default)]
+pub(crate) struct ClientProbe {
+    head: [u8; HEAD_LEN],
+    head_len: usize,
+    prefix: Vec<u8>,
+    /// The records received, if they are handshake records.
+    hello: Vec<u8>,
+    done: bool,
+}
+
+/// Shows how much was kept, not what, which may be a plaintext request.
+impl fmt::Debug for ClientProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientProbe")
+            .field("head_len", &self.head_len)
+            .field("prefix_len", &self.prefix.len())
+            .field("hello_len", &self.hello.len())
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl ClientProbe {
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        let n = (HEAD_LEN - self.head_len).min(data.len());
+        self.head[self.head_len..self.head_len + n].copy_from_slice(&data[..n]);
+        self.head_len += n;
+
+        if self.head_len > 0 && self.head[0] == HANDSHAKE {
+            let n = (MAX_HELLO - self.hello.len()).min(data.len());
+            self.hello.extend_from_slice(&data[..n]);
+        }
+        if self.done || data.is_empty() {
+            return;
+        }
+        // TLS records start with their content type, which is no letter
+        if self.prefix.is_empty() && !data[0].is_ascii_uppercase() {
+            self.done = true;
+            return;
+        }
+        let n = (MAX_PREFIX - self.prefix.len()).min(data.len());
+        self.prefix.extend_from_slice(&data[..n]);
+        self.done = self.prefix.len() == MAX_PREFIX;
+    }
+
+    fn header(&self) -> Option<RecordHeader> {
+        if self.head_len < 5 {
+            return None;
+        }
+        let mut header = [0; 5];
+        header.copy_from_slice(&self.head[..5]);
+        Some(RecordHeader(header))
+    }
+
+    /// The `legacy_version` of the `ClientHello`, if one was received.
+    fn client_version(&self) -> Option<ProtocolVersion> {
+        match self.head[..self.head_len] {
+            [HANDSHAKE, _, _, _, _, CLIENT_HELLO, _, _, _, major, minor] => {
+                Some(ProtocolVersion::from(u16::from_be_bytes([major, minor])))
+            }
+            _ => None,
+        }
+    }
+
+    /// What the client offered, if its `ClientHello` was received.
+    pub(crate) fn client_hello(&self) -> Option<ClientHelloSummary> {
+        ClientHelloSummary::parse(&self.hello)
+    }
+
+    /// The client negotiated `version`, which is not allowed.
+    pub(crate) fn unsupported_version(&self, version: ProtocolVersion) -> io::Error {
+        let header = self.header().unwrap_or(RecordHeader([0; 5]));
+        Error::UnsupportedVersion(header, version).into()
+    }
+
+    /// Feed the bytes read from `inner` to this probe.
+    pub(crate) fn reader<R: Read>(&mut self, inner: R) -> Probe<'_, R> {
+        Probe { inner, probe: self }
+    }
+
+    /// Whether an HTTP request was received.
+    pub(crate) fn is_http(&self) -> bool {
+        PlaintextHttp::parse(&self.prefix).is_some()
+    }
+
+    /// `err`, or a clearer error if the client does not speak TLS, or none
+    /// of the versions enabled.
+    pub(crate) fn check(&self, err: io::Error) -> io::Error {
+        if let Some(request) = PlaintextHttp::parse(&self.prefix) {
+            return Error::PlaintextHttp(request).into();
+        }
+        let header = match self.header() {
+            Some(header) => header,
+            None => return err,
+        };
+        let error = match error_ref(&err) {
+            Some(Error::HandshakeFailed(rustls::Error::InvalidMessage(_))) if !header.is_tls() => {
+                match header.is_sslv2_hello() {
+                    true => Error::Sslv2Hello(header),
+                    false => Error::NotTls(header),
+                }
+            }
+            Some(Error::HandshakeFailed(rustls::Error::PeerIncompatible(
+                PeerIncompatible::Tls12NotOffered
+                | PeerIncompatible::Tls12NotOfferedOrEnabled
+                | PeerIncompatible::SupportedVersionsExtensionRequired,
+            ))) => match self.client_version() {
+                Some(version) => Error::UnsupportedVersion(header, version),
+                None => return err,
+            },
+            _ => return err,
+        };
+        error.into()
+    }
+}
+
+pub(crate) struct Probe<'a, R> {
+    inner: R,
+    probe: &'a mut ClientProbe,
+}
+
+impl<R: Read> Read for Probe<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.probe.feed(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests() {
+        let mut probe = ClientProbe::default();
+        probe.feed(b"GET /index.html?q=1 HTTP/1.1\r\nAccept: */*\r\n");
+        probe.feed(b"HOST: example.com:8443 \r\n\r\n");
+        let err = probe.check(io::ErrorKind::InvalidData.into());
+        let request = match Error::from(err) {
+            Error::PlaintextHttp(request) => request,
+            err => panic!("unexpected error: {:?}", err),
+        };
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/index.html?q=1");
+        assert_eq!(request.host.as_deref(), Some("example.com:8443"));
+        assert_eq!(
+            request.https_url().as_deref(),
+            Some("https://example.com:8443/index.html?q=1")
+        );
+
+        let request = PlaintextHttp::parse(b"POST /upl").unwrap();
+        assert_eq!((&*request.method, &*request.target), ("POST", "/upl"));
+        assert_eq!(request.https_url(), None);
+        assert!(PlaintextHttp::parse(b"GET").is_none());
+        assert!(PlaintextHttp::parse(b"BREW /pot HTTP/1.1\r\n").is_none());
+    }
+
+    fn check(data: &[u8], err: rustls::Error) -> Error {
+        let mut probe = ClientProbe::default();
+        probe.feed(data);
+        Error::from(probe.check(Error::from(err).into()))
+    }
+
+    #[test]
+    fn tells_ancient_clients() {
+        let garbage = rustls::Error::InvalidMessage(rustls::InvalidMessage::InvalidContentType);
+        match check(&[0x80, 0x2e, 1, 0, 2, 0, 0x15], garbage.clone()) {
+            Error::Sslv2Hello(header) => assert_eq!(header.to_string(), "80 2e 01 00 02"),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        match check(b"\x00\xffSSH-2.0", garbage.clone()) {
+            Error::NotTls(header) => assert_eq!(header.0, *b"\x00\xffSSH"),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        // a valid header fails for other reasons
+        let header = [HANDSHAKE, 3, 1, 0, 0];
+        assert!(matches!(check(&header, garbage), Error::HandshakeFailed(_)));
+
+        let sslv3 = [HANDSHAKE, 3, 0, 0, 0x2d, CLIENT_HELLO, 0, 0, 0x29, 3, 0];
+        let old = rustls::Error::PeerIncompatible(PeerIncompatible::Tls12NotOffered);
+        match check(&sslv3, old) {
+            Error::UnsupportedVersion(header, version) => {
+                assert_eq!(header.version(), ProtocolVersion::SSLv3);
+                assert_eq!(header.length(), 0x2d);
+                assert_eq!(version, ProtocolVersion::SSLv3);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn ignores_tls_records() {
+        let mut probe = ClientProbe::default();
+        probe.feed(&[0x16, 3, 1, 0, 5]);
+        probe.feed(b"GET / HTTP/1.1\r\n");
+        assert!(!probe.is_http());
+        assert_eq!(probe.prefix.len(), 0);
+    }
+}
@@ -0,0 +1,38 @@
+//! The size of the TLS records sent.
+
+use std::io;
+
+/// The smallest record rustls sends, including its 5-byte header.
+const MIN_RECORD: usize = 32;
+/// The largest record, a full 16 KiB fragment and its header.
+const MAX_RECORD: usize = (1 << 14) + 5;
+
+/// Check a maximum record size, as `ClientConfig::max_fragment_size` takes it.
+pub(crate) fn max_fragment_size(size: usize) -> io::Result<usize> {
+    if (MIN_RECORD..=MAX_RECORD).contains(&size) {
+        Ok(size)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "max fragment size {} is out of range {}..={}",
+                size, MIN_RECORD, MAX_RECORD
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_fragment_sizes() {
+        assert_eq!(max_fragment_size(32).unwrap(), 32);
+        assert_eq!(max_fragment_size(16389).unwrap(), 16389);
+        for size in [0, 31, 16390] {
+            let err = max_fragment_size(size).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Counts the traffic of a connection.
+
+/// How much a stream read and wrote, see `TlsStream::counters`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TrafficCounters {
+    /// Plaintext bytes read from the stream.
+    pub bytes_read: u64,
+    /// Plaintext bytes written to the stream.
+    pub bytes_written: u64,
+    /// Bytes of TLS records received from the underlying stream, including
+    /// those of the handshake.
+    pub tls_bytes_received: u64,
+    /// Bytes of TLS records sent to the underlying stream.
+    pub tls_bytes_sent: u64,
+    /// TLS records received.
+    pub records_received: u64,
+    /// TLS records sent.
+    pub records_sent: u64,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TrafficCounters {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut counters = serializer.serialize_struct("TrafficCounters", 6)?;
+        counters.serialize_field("bytes_read", &self.bytes_read)?;
+        counters.serialize_field("bytes_written", &self.bytes_written)?;
+        counters.serialize_field("tls_bytes_received", &self.tls_bytes_received)?;
+        counters.serialize_field("tls_bytes_sent", &self.tls_bytes_sent)?;
+        counters.serialize_field("records_received", &self.records_received)?;
+        counters.serialize_field("records_sent", &self.records_sent)?;
+        counters.end()
+    }
+}
+
+/// The TLS records a connection received and sent.
+#[derive(Debug, Default)]
+pub(crate) struct Traffic {
+    pub(crate) bytes_in: u64,
+    pub(crate) bytes_out: u64,
+    records_in: Records,
+    records_out: Records,
+}
+
+impl Traffic {
+    pub(crate) fn received(&mut self, data: &[u8]) {
+        self.bytes_in += data.len() as u64;
+        self.records_in.feed(data);
+    }
+
+    pub(crate) fn sent(&mut self, data: &[u8]) {
+        self.bytes_out += data.len() as u64;
+        self.records_out.feed(data);
+    }
+
+    /// The bytes to receive up to the end of the current record, or of the
+    /// header of the next one.
+    pub(crate) fn received_record_left(&self) -> usize {
+        self.records_in.left()
+    }
+
+    pub(crate) fn counters(&self, bytes_read: u64, bytes_written: u64) -> TrafficCounters {
+        TrafficCounters {
+            bytes_read,
+            bytes_written,
+            tls_bytes_received: self.bytes_in,
+            tls_bytes_sent: self.bytes_out,
+            records_received: self.records_in.count,
+            records_sent: self.records_out.count,
+        }
+    }
+}
+
+/// Counts the records in a stream of bytes, by their headers.
+#[derive(Debug, Default)]
+struct Records {
+    count: u64,
+    header: [u8; 5],
+    header_len: usize,
+    /// The bytes left of the current record, after its header.
+    remaining: usize,
+}
+
+impl Records {
+    fn left(&self) -> usize {
+        match self.remaining {
+            0 => self.header.len() - self.header_len,
+            remaining => remaining,
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len());
+                self.remaining -= n;
+                data = &data[n..];
+                continue;
+            }
+
+            let n = (self.header.len() - self.header_len).min(data.len());
+            self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+            self.header_len += n;
+            data = &data[n..];
+            if self.header_len == self.header.len() {
+                self.count += 1;
+                self.remaining = usize::from(u16::from_be_bytes([self.header[3], self.header[4]]));
+                self.header_len = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_split_records() {
+        let mut records = Records::default();
+        let stream = [
+            &[0x16, 3, 3, 0, 2, 1, 2][..],
+            &[0x17, 3, 3, 0, 0],
+            &[0x17, 3, 3, 1, 0],
+            &[0; 256],
+        ]
+        .concat();
+        for byte in &stream[..stream.len() - 1] {
+            records.feed(&[*byte]);
+        }
+        assert_eq!(records.count, 3);
+        assert_eq!((records.remaining, records.left()), (1, 1));
+
+        let mut records = Records::default();
+        records.feed(&stream);
+        records.feed(&[0x15, 3]);
+        assert_eq!((records.count, records.left()), (3, 3));
+        records.feed(&[3, 0, 2, 1, 0]);
+        assert_eq!(records.count, 4);
+        assert_eq!((records.header_len, records.remaining), (0, 0));
+    }
+}
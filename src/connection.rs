@@ -0,0 +1,170 @@
+//! Driving any rustls connection over an async IO.
+//!
+//! `client::TlsStream` and `server::TlsStream` drive the connections of a
+//! `TlsConnector` and a `TlsAcceptor`. `TlsStream` here drives anything that
+//! dereferences to a rustls `ConnectionCommon`, e.g. a `ServerConnection`
+//! built from the `Accepted` of rustls's `Acceptor`, or a wrapper of one's
+//! own, with the same IO handling but none of the options of the connector
+//! and acceptor.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_std::net::TcpStream;
+//! use async_tls::connection::TlsStream;
+//! use futures_util::io::AsyncWriteExt;
+//! use rustls::{ClientConfig, ClientConnection, RootCertStore};
+//! use std::convert::TryInto;
+//! use std::sync::Arc;
+//!
+//! # async_std::task::block_on(async {
+//! let config = ClientConfig::builder()
+//!     .with_safe_defaults()
+//!     .with_root_certificates(RootCertStore::empty())
+//!     .with_no_client_auth();
+//! let conn = ClientConnection::new(Arc::new(config), "example.com".try_into()?)?;
+//! let io = TcpStream::connect("example.com:443").await?;
+//!
+//! let mut stream = TlsStream::new(io, conn);
+//! stream.handshake().await?;
+//! stream.write_all(b"GET / HTTP/1.0\r\n\r\n").await?;
+//! # Ok(()) as Result<(), Box<dyn std::error::Error>>
+//! # });
+//! ```
+
+use crate::rusttls::stream::Stream;
+
+use futures_core::ready;
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::{ConnectionCommon, SideData};
+use std::future::poll_fn;
+use std::io;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A TLS stream over `io`, driving the rustls connection `conn`.
+///
+/// Reads and writes complete the handshake first, if it is not done yet.
+#[derive(Debug)]
+pub struct TlsStream<IO, C> {
+    io: IO,
+    conn: C,
+    eof: bool,
+    /// Whether `close_notify` was queued.
+    closing: bool,
+}
+
+impl<IO, C> TlsStream<IO, C> {
+    /// Drive `conn` over `io`.
+    pub fn new(io: IO, conn: C) -> Self {
+        TlsStream {
+            io,
+            conn,
+            eof: false,
+            closing: false,
+        }
+    }
+
+    /// The IO and the connection.
+    pub fn get_ref(&self) -> (&IO, &C) {
+        (&self.io, &self.conn)
+    }
+
+    /// The IO and the connection.
+    pub fn get_mut(&mut self) -> (&mut IO, &mut C) {
+        (&mut self.io, &mut self.conn)
+    }
+
+    /// Unwrap the IO and the connection.
+    pub fn into_inner(self) -> (IO, C) {
+        (self.io, self.conn)
+    }
+}
+
+impl<IO, C, D> TlsStream<IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
+    /// Sends the TLS records queued and receives those the peer sent, without
+    /// reading or writing plaintext. Returns the number of bytes received
+    /// and sent.
+    ///
+    /// During the handshake, this returns once a flight was exchanged. `(0, 0)`
+    /// means nothing can be done without reading the plaintext received.
+    pub fn poll_complete_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(usize, usize)>> {
+        let mut stream = Stream::new(&mut self.io, &mut self.conn).set_eof(self.eof);
+        let result = stream.complete_io(cx);
+        self.eof = stream.eof;
+        result
+    }
+
+    /// Complete the handshake, if it is not done yet.
+    pub fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.conn.is_handshaking() {
+            ready!(self.poll_complete_io(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Complete the handshake, if it is not done yet.
+    pub async fn handshake(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_handshake(cx)).await
+    }
+}
+
+impl<IO, C, D> AsyncRead for TlsStream<IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_handshake(cx))?;
+        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(this.eof);
+        let result = stream.as_mut_pin().poll_read(cx, buf);
+        this.eof = stream.eof;
+        result
+    }
+}
+
+impl<IO, C, D> AsyncWrite for TlsStream<IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_handshake(cx))?;
+        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(this.eof);
+        stream.as_mut_pin().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(this.eof);
+        stream.as_mut_pin().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closing {
+            this.conn.send_close_notify();
+            this.closing = true;
+        }
+        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(this.eof);
+        stream.as_mut_pin().poll_close(cx)
+    }
+}
@@ -1,15 +1,60 @@
+use crate::common::chunking::WriteChunking;
+use crate::common::close::{CloseMode, CloseTimer};
+use crate::common::coalesce::Coalescer;
+#[cfg(feature = "tokio")]
+use crate::common::compat::TokioIo;
+use crate::common::flush::HandshakeFlush;
+use crate::common::hello::HelloSniffer;
+use crate::common::pem;
+use crate::common::policy::Policy;
+use crate::common::records;
 use crate::common::tls_state::TlsState;
 
-use crate::client;
+use crate::client::{self, EarlyDataOverflow, RejectedEarlyData};
+use crate::client_cert::{self, CallbackResolver, CertificateRequest};
+#[cfg(feature = "ct")]
+use crate::ct::CtPolicy;
+#[cfg(feature = "dane")]
+use crate::dane::{self, DaneVerifier};
+#[cfg(feature = "dangerous")]
+use crate::dangerous::Danger;
+use crate::error::{HandshakeError, HandshakeStage};
+use crate::observer::{Observation, Observer};
+#[cfg(feature = "ocsp")]
+use crate::ocsp::{self, OcspVerifier};
+use crate::preset::SecurityPreset;
+#[cfg(feature = "async-std")]
+use crate::retry::RetryPolicy;
+#[cfg(feature = "async-std")]
+use crate::tcp;
+#[cfg(feature = "tofu")]
+use crate::tofu::{self, TofuVerifier};
+use crate::Error;
 
+use futures_core::future::FusedFuture;
+use futures_core::ready;
 use futures_io::{AsyncRead, AsyncWrite};
-use rustls::{ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, ServerName};
+use rustls::client::{ClientSessionStore, Resumption};
+use rustls::sign::CertifiedKey;
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, ServerName,
+    SupportedCipherSuite, SupportedProtocolVersion,
+};
 use std::convert::TryFrom;
+use std::fmt;
 use std::future::Future;
 use std::io;
+use std::net::IpAddr;
+#[cfg(feature = "async-std")]
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// How much early data is kept to send again, unless set otherwise.
+const DEFAULT_EARLY_DATA_BUFFER: usize = 64 * 1024;
 
 /// The TLS connecting part. The acceptor drives
 /// the client side of the TLS handshake process. It works
@@ -38,16 +83,57 @@ use std::task::{Context, Poll};
 #[derive(Clone)]
 pub struct TlsConnector {
     inner: Arc<ClientConfig>,
-    #[cfg(feature = "early-data")]
-    early_data: bool,
+    /// The roots the config verifies servers against, if the connector built
+    /// the config itself and its verifier was not replaced since.
+    roots: Option<Arc<RootCertStore>>,
+    strict_close_notify: bool,
+    send_close_notify: bool,
+    close_mode: CloseMode,
+    handshake_flush: HandshakeFlush,
+    write_chunking: WriteChunking,
+    coalesce_writes: (usize, Duration),
+    early_data_limit: (usize, EarlyDataOverflow),
+    rejected_early_data: RejectedEarlyData,
+    observer: Option<Arc<dyn Observer>>,
+    policy: Policy,
+    record_certificate_requests: bool,
+    /// The resumption `session_resumption(false)` replaced, to restore.
+    paused_resumption: Option<Resumption>,
+    #[cfg(feature = "ocsp")]
+    ocsp: Option<Arc<OcspVerifier>>,
+    #[cfg(feature = "ct")]
+    ct_policy: Option<Arc<CtPolicy>>,
+    #[cfg(feature = "tofu")]
+    tofu: Option<Arc<TofuVerifier>>,
+    #[cfg(feature = "dangerous")]
+    danger: Danger,
 }
 
 impl From<Arc<ClientConfig>> for TlsConnector {
     fn from(inner: Arc<ClientConfig>) -> TlsConnector {
         TlsConnector {
             inner,
-            #[cfg(feature = "early-data")]
-            early_data: false,
+            roots: None,
+            strict_close_notify: true,
+            send_close_notify: true,
+            close_mode: CloseMode::Send,
+            handshake_flush: HandshakeFlush::Eager,
+            write_chunking: WriteChunking::Whole,
+            coalesce_writes: (0, Duration::ZERO),
+            early_data_limit: (DEFAULT_EARLY_DATA_BUFFER, EarlyDataOverflow::Wait),
+            rejected_early_data: RejectedEarlyData::Resend,
+            observer: None,
+            policy: Policy::default(),
+            record_certificate_requests: false,
+            paused_resumption: None,
+            #[cfg(feature = "ocsp")]
+            ocsp: None,
+            #[cfg(feature = "ct")]
+            ct_policy: None,
+            #[cfg(feature = "tofu")]
+            tofu: None,
+            #[cfg(feature = "dangerous")]
+            danger: Danger::default(),
         }
     }
 }
@@ -56,44 +142,578 @@ impl From<ClientConfig> for TlsConnector {
     fn from(inner: ClientConfig) -> TlsConnector {
         TlsConnector {
             inner: Arc::new(inner),
-            #[cfg(feature = "early-data")]
-            early_data: false,
+            roots: None,
+            strict_close_notify: true,
+            send_close_notify: true,
+            close_mode: CloseMode::Send,
+            handshake_flush: HandshakeFlush::Eager,
+            write_chunking: WriteChunking::Whole,
+            coalesce_writes: (0, Duration::ZERO),
+            early_data_limit: (DEFAULT_EARLY_DATA_BUFFER, EarlyDataOverflow::Wait),
+            rejected_early_data: RejectedEarlyData::Resend,
+            observer: None,
+            policy: Policy::default(),
+            record_certificate_requests: false,
+            paused_resumption: None,
+            #[cfg(feature = "ocsp")]
+            ocsp: None,
+            #[cfg(feature = "ct")]
+            ct_policy: None,
+            #[cfg(feature = "tofu")]
+            tofu: None,
+            #[cfg(feature = "dangerous")]
+            danger: Danger::default(),
         }
     }
 }
 
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alpn: Vec<_> = (self.inner.alpn_protocols.iter())
+            .map(|protocol| String::from_utf8_lossy(protocol))
+            .collect();
+        f.debug_struct("TlsConnector")
+            .field("roots", &self.roots.as_ref().map(|roots| roots.len()))
+            .field("alpn_protocols", &alpn)
+            .field("enable_sni", &self.inner.enable_sni)
+            .field("enable_early_data", &self.inner.enable_early_data)
+            .field("max_fragment_size", &self.inner.max_fragment_size)
+            .field("strict_close_notify", &self.strict_close_notify)
+            .field("send_close_notify", &self.send_close_notify)
+            .field("close_mode", &self.close_mode)
+            .field("handshake_flush", &self.handshake_flush)
+            .field("write_chunking", &self.write_chunking)
+            .field("coalesce_writes", &self.coalesce_writes)
+            .field("observed", &self.observer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for TlsConnector {
+    /// The WebPKI roots are parsed into a config once, which all default
+    /// connectors share, along with its in-memory session cache.
     fn default() -> Self {
-        let mut root_certs = RootCertStore::empty();
-        root_certs.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
-            OwnedTrustAnchor::from_subject_spki_name_constraints(
-                ta.subject,
-                ta.spki,
-                ta.name_constraints,
-            )
-        }));
-        let config = ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_certs)
-            .with_no_client_auth();
-        Arc::new(config).into()
+        static DEFAULT: OnceLock<TlsConnector> = OnceLock::new();
+        DEFAULT
+            .get_or_init(|| TlsConnector::trusting(webpki_roots().clone()))
+            .clone()
     }
 }
 
 impl TlsConnector {
     /// Create a new TlsConnector with default configuration.
     ///
-    /// This is the same as calling `TlsConnector::default()`.
+    /// This is the same as calling `TlsConnector::default()`. It is cheap: the
+    /// default config is only built once.
     pub fn new() -> Self {
         Default::default()
     }
 
-    /// Enable 0-RTT.
+    /// Create a new TlsConnector trusting `roots` instead of the WebPKI roots.
+    pub fn with_root_certificates(roots: RootCertStore) -> Self {
+        TlsConnector::trusting(Arc::new(roots))
+    }
+
+    fn trusting(roots: Arc<RootCertStore>) -> Self {
+        let mut connector = TlsConnector::from(verifying(&roots));
+        connector.roots = Some(roots.clone());
+        #[cfg(feature = "dangerous")]
+        {
+            connector.danger.roots = Some(roots);
+        }
+        connector
+    }
+
+    /// Create a new TlsConnector trusting the WebPKI roots and `certs`, e.g.
+    /// the CA of an internal network.
+    ///
+    /// Fails with `InvalidData` if one of the DER-encoded certificates cannot
+    /// be used as a trust anchor.
+    pub fn with_extra_roots(certs: &[Certificate]) -> io::Result<Self> {
+        let mut roots = RootCertStore::clone(webpki_roots());
+        for cert in certs {
+            add_root(&mut roots, cert)?;
+        }
+        Ok(TlsConnector::with_root_certificates(roots))
+    }
+
+    /// Trust `cert`, a DER-encoded certificate, in addition to the roots the
+    /// connector was created with by `new`, `with_root_certificates`,
+    /// `with_extra_roots` or `with_ca_pem`.
+    ///
+    /// The other settings of the connector are kept. Fails with `InvalidInput`
+    /// for connectors created from a `ClientConfig`, whose roots are unknown,
+    /// and for those whose verifier was replaced, e.g. by `tofu`: add roots
+    /// first. Fails with `InvalidData` if `cert` cannot be a trust anchor.
+    pub fn add_root_certificate(mut self, cert: &Certificate) -> io::Result<TlsConnector> {
+        let mut roots = RootCertStore::clone(self.known_roots()?);
+        add_root(&mut roots, cert)?;
+        self.rebuild(Arc::new(roots), self.policy)?;
+        Ok(self)
+    }
+
+    /// Like `add_root_certificate`, trusting the certificates of a PEM bundle.
+    ///
+    /// Fails with `InvalidData` if the bundle has no certificates, or one that
+    /// cannot be used as a trust anchor; no root is added then.
+    pub fn add_root_pem(mut self, pem: &[u8]) -> io::Result<TlsConnector> {
+        let mut roots = RootCertStore::clone(self.known_roots()?);
+        for cert in pem::certs(pem)? {
+            add_root(&mut roots, &cert)?;
+        }
+        self.rebuild(Arc::new(roots), self.policy)?;
+        Ok(self)
+    }
+
+    fn known_roots(&self) -> io::Result<&Arc<RootCertStore>> {
+        self.roots.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the roots of the connector's config are unknown",
+            )
+        })
+    }
+
+    /// Build the config anew from `roots` and `policy`, keeping the other settings.
+    ///
+    /// rustls keeps the versions, cipher suites, groups and verifier private,
+    /// so the config cannot be cloned and changed: every public field is copied
+    /// over instead. `enable_secret_extraction` only exists, and is only kept,
+    /// with the `secret-extraction` feature.
+    fn rebuild(&mut self, roots: Arc<RootCertStore>, policy: Policy) -> io::Result<()> {
+        let mut config = policy
+            .configure(ClientConfig::builder())?
+            .with_root_certificates(RootCertStore::clone(&roots))
+            .with_no_client_auth();
+        let current = &self.inner;
+        config.alpn_protocols = current.alpn_protocols.clone();
+        config.resumption = current.resumption.clone();
+        config.max_fragment_size = current.max_fragment_size;
+        config.client_auth_cert_resolver = current.client_auth_cert_resolver.clone();
+        config.enable_sni = current.enable_sni;
+        config.key_log = current.key_log.clone();
+        config.enable_early_data = current.enable_early_data;
+        #[cfg(feature = "secret-extraction")]
+        {
+            config.enable_secret_extraction = current.enable_secret_extraction;
+        }
+        self.inner = Arc::new(config);
+        self.roots = Some(roots.clone());
+        self.policy = policy;
+        #[cfg(feature = "dangerous")]
+        {
+            self.danger.roots = Some(roots);
+            self.inner = self.danger.rebuilt(self.inner.clone(), &mut self.roots);
+        }
+        Ok(())
+    }
+
+    /// Create a new TlsConnector trusting the certificates of a PEM bundle
+    /// instead of the WebPKI roots.
+    ///
+    /// Fails with `InvalidData` if the bundle has no certificates, or one that
+    /// cannot be used as a trust anchor.
+    pub fn with_ca_pem(pem: &[u8]) -> io::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in pem::certs(pem)? {
+            add_root(&mut roots, &cert)?;
+        }
+        Ok(TlsConnector::with_root_certificates(roots))
+    }
+
+    /// Like `with_ca_pem`, reading the bundle from `path` without blocking.
+    #[cfg(feature = "async-std")]
+    pub async fn with_ca_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let pem = async_std::fs::read(path.as_ref()).await?;
+        TlsConnector::with_ca_pem(&pem)
+    }
+
+    /// The config connections are made with.
+    #[cfg(feature = "pool")]
+    pub(crate) fn config(&self) -> &Arc<ClientConfig> {
+        &self.inner
+    }
+
+    /// Send the name connected to in the `ClientHello` (SNI). Enabled by default.
+    ///
+    /// Disabling SNI keeps the name from eavesdroppers and from servers that
+    /// choke on it. The server's certificate is still verified against the name.
+    /// Servers hosting several names then have to pick the certificate without it.
+    pub fn enable_sni(mut self, flag: bool) -> TlsConnector {
+        let update = |config: &mut ClientConfig| config.enable_sni = flag;
+        update(Arc::make_mut(&mut self.inner));
+        #[cfg(feature = "dangerous")]
+        self.danger.update(update);
+        self
+    }
+
+    /// Keep the sessions to resume in `store`.
     ///
-    /// You must also set `enable_early_data` to `true` in `ClientConfig`.
-    #[cfg(feature = "early-data")]
-    pub fn early_data(mut self, flag: bool) -> TlsConnector {
-        self.early_data = flag;
+    /// By default, each `ClientConfig` keeps up to 256 sessions in memory.
+    /// Connectors sharing a store resume each other's sessions. TLS 1.2
+    /// sessions are resumed by session id or ticket; set
+    /// `ClientConfig::resumption` to change that.
+    pub fn session_store(mut self, store: Arc<dyn ClientSessionStore>) -> TlsConnector {
+        let update = |config: &mut ClientConfig| {
+            config.resumption = Resumption::store(store.clone());
+        };
+        self.paused_resumption = None;
+        update(Arc::make_mut(&mut self.inner));
+        #[cfg(feature = "dangerous")]
+        self.danger.update(update);
+        self
+    }
+
+    /// Resume sessions from earlier connections. Enabled by default.
+    ///
+    /// Disabling this keeps no sessions or tickets, so connections to the same
+    /// server cannot be linked by them, at the cost of a full handshake every
+    /// time. Enabling it again goes back to the store in use before, such as
+    /// one set by `session_store`; otherwise it changes nothing.
+    pub fn session_resumption(mut self, flag: bool) -> TlsConnector {
+        let resumption = match flag {
+            true => match self.paused_resumption.take() {
+                Some(resumption) => resumption,
+                None => return self,
+            },
+            false => {
+                if self.paused_resumption.is_none() {
+                    self.paused_resumption = Some(self.inner.resumption.clone());
+                }
+                Resumption::disabled()
+            }
+        };
+        let update = |config: &mut ClientConfig| {
+            config.resumption = resumption.clone();
+        };
+        update(Arc::make_mut(&mut self.inner));
+        #[cfg(feature = "dangerous")]
+        self.danger.update(update);
+        self
+    }
+
+    /// Choose the certificate to authenticate with when a server asks for
+    /// one, e.g. by the CAs it accepts, so one connector can present
+    /// different identities to different backends.
+    ///
+    /// Returning `None` continues without a certificate, which the server
+    /// may refuse.
+    ///
+    /// ```rust,no_run
+    /// use async_tls::{Identity, TlsConnector};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let identities = [
+    ///     Identity::from_pem_files("billing.crt", "billing.key")?,
+    ///     Identity::from_pem_files("reports.crt", "reports.key")?,
+    /// ];
+    /// let keys = (identities.iter())
+    ///     .map(|identity| identity.certified_key())
+    ///     .collect::<std::io::Result<Vec<_>>>()?;
+    /// let connector = TlsConnector::new().client_cert_resolver(move |request| {
+    ///     let mut keys = keys.iter();
+    ///     keys.find(|key| request.accepts(&key.cert)).cloned()
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn client_cert_resolver<F>(mut self, resolve: F) -> TlsConnector
+    where
+        F: Fn(&CertificateRequest<'_>) -> Option<Arc<CertifiedKey>> + Send + Sync + 'static,
+    {
+        let resolver = Arc::new(CallbackResolver(Arc::new(resolve)));
+        let update = |config: &mut ClientConfig| {
+            config.client_auth_cert_resolver = resolver.clone();
+        };
+        update(Arc::make_mut(&mut self.inner));
+        #[cfg(feature = "dangerous")]
+        self.danger.update(update);
+        self
+    }
+
+    /// Record the CAs a server names when asking for a client certificate,
+    /// available from `TlsStream::acceptable_issuers` even if no certificate
+    /// was sent, e.g. to tell users which identity to provision. Disabled by
+    /// default, as each connection then gets its own copy of the config.
+    pub fn record_certificate_requests(mut self, flag: bool) -> TlsConnector {
+        self.record_certificate_requests = flag;
+        self
+    }
+
+    /// Send TLS records of at most `size` bytes, including their 5-byte header.
+    ///
+    /// Smaller records let constrained peers decrypt with smaller buffers and
+    /// deliver data sooner, e.g. when set to the TCP MSS. rustls does not
+    /// negotiate the size with the `max_fragment_length` or `record_size_limit`
+    /// extensions, so the peer still sends full records. Fails with
+    /// `InvalidInput` unless `size` is between 32 and 16389.
+    pub fn max_fragment_size(mut self, size: usize) -> io::Result<TlsConnector> {
+        let size = records::max_fragment_size(size)?;
+        let update = |config: &mut ClientConfig| config.max_fragment_size = Some(size);
+        update(Arc::make_mut(&mut self.inner));
+        #[cfg(feature = "dangerous")]
+        self.danger.update(update);
+        Ok(self)
+    }
+
+    /// Fail reads with `Error::Truncated` when the server closes the connection
+    /// without a `close_notify` alert. Enabled by default.
+    ///
+    /// Without the alert, a reader cannot tell whether the server is done or an
+    /// attacker cut the connection. Disable this only for servers that do not
+    /// send the alert, and where the application protocol detects truncation
+    /// by itself; such EOFs then read as the normal end of the stream.
+    pub fn strict_close_notify(mut self, flag: bool) -> TlsConnector {
+        self.strict_close_notify = flag;
+        self
+    }
+
+    /// Send a `close_notify` alert when the stream is closed. Enabled by default.
+    ///
+    /// Disabling this saves a record and a flush when closing, e.g. for
+    /// fire-and-forget connections, or for peers that choke on the alert. Data
+    /// written before is still flushed. Peers with a strict policy then see
+    /// the connection as truncated.
+    pub fn send_close_notify(mut self, flag: bool) -> TlsConnector {
+        self.send_close_notify = flag;
+        self
+    }
+
+    /// What closing a stream waits for. Defaults to `CloseMode::Send`.
+    ///
+    /// With `CloseMode::AwaitPeer`, closing waits for the server's
+    /// `close_notify`, so both ends know the other got everything. Only useful
+    /// along with `send_close_notify`, which servers answer.
+    pub fn close_mode(mut self, mode: CloseMode) -> TlsConnector {
+        self.close_mode = mode;
+        self
+    }
+
+    /// When the handshake flushes the IO. Defaults to `HandshakeFlush::Eager`.
+    ///
+    /// Only matters for IO buffering writes until flushed. Eager flushing
+    /// sends each record as soon as it is written, batching flushes once for
+    /// all records ready to be sent, saving system calls when the IO does not
+    /// take them in one vectored write.
+    pub fn handshake_flush(mut self, flush: HandshakeFlush) -> TlsConnector {
+        self.handshake_flush = flush;
+        self
+    }
+
+    /// How much of a caller's buffer a write to the stream takes. Defaults to
+    /// `WriteChunking::Whole`.
+    ///
+    /// Taking the whole buffer makes the fewest writes and system calls, for
+    /// throughput. Taking a chunk per write, e.g. of the maximum fragment size,
+    /// bounds the data a write encrypts and sends before returning, so other
+    /// work can run in between. See `max_fragment_size` for the record size.
+    pub fn write_chunking(mut self, chunking: WriteChunking) -> TlsConnector {
+        self.write_chunking = chunking;
+        self
+    }
+
+    /// Coalesce writes shorter than `threshold` bytes into fewer, larger
+    /// records. Disabled by default.
+    ///
+    /// Small writes are kept back until `threshold` bytes are buffered, the
+    /// stream is flushed or closed, or the first of them waited `max_delay`.
+    /// The delay is only checked when the stream is used, it does not wake the
+    /// task: flush once done writing, e.g. before waiting for a response.
+    /// Saves the overhead of a record per write for chatty protocols.
+    pub fn coalesce_writes(mut self, threshold: usize, max_delay: Duration) -> TlsConnector {
+        self.coalesce_writes = (threshold, max_delay);
+        self
+    }
+
+    /// Keep up to `limit` bytes of early data, see `connect_0rtt`. Defaults to
+    /// 64 KiB, waiting for the handshake beyond.
+    ///
+    /// Early data is kept until the handshake is done, to send it again if the
+    /// server rejects it. The server caps early data as well, but may allow up
+    /// to 4 GiB. Once the buffer is full, writes either wait for the handshake,
+    /// or fail with `EarlyDataOverflow::Fail`.
+    pub fn early_data_buffer(mut self, limit: usize, overflow: EarlyDataOverflow) -> TlsConnector {
+        self.early_data_limit = (limit, overflow);
+        self
+    }
+
+    /// What to do with early data the server rejected, see `connect_0rtt`.
+    /// Defaults to `RejectedEarlyData::Resend`.
+    ///
+    /// Sending the data again is transparent, but not always right: a request
+    /// may depend on the time it is sent, or the application may rather pick
+    /// what to send again. With `RejectedEarlyData::Fail`, the rejection
+    /// surfaces as an error of the stream instead.
+    pub fn rejected_early_data(mut self, policy: RejectedEarlyData) -> TlsConnector {
+        self.rejected_early_data = policy;
+        self
+    }
+
+    /// Report the handshakes and connections of this connector to `observer`.
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> TlsConnector {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Only offer TLS versions from `version` on, e.g. `rustls::version::TLS13`.
+    ///
+    /// The config is built anew, keeping the other settings, so this fails
+    /// with `InvalidInput` like `add_root_certificate` when its roots are
+    /// unknown: build the `ClientConfig` `with_protocol_versions` instead. Also
+    /// fails with `InvalidInput` if no cipher suite allowed suits the versions.
+    pub fn min_tls_version(
+        mut self,
+        version: &'static SupportedProtocolVersion,
+    ) -> io::Result<TlsConnector> {
+        let mut policy = self.policy;
+        policy.set_min(version);
+        self.rebuild(self.known_roots()?.clone(), policy)?;
+        Ok(self)
+    }
+
+    /// Only offer TLS versions up to `version`.
+    ///
+    /// See `min_tls_version`.
+    pub fn max_tls_version(
+        mut self,
+        version: &'static SupportedProtocolVersion,
+    ) -> io::Result<TlsConnector> {
+        let mut policy = self.policy;
+        policy.set_max(version);
+        self.rebuild(self.known_roots()?.clone(), policy)?;
+        Ok(self)
+    }
+
+    /// Only speak TLS 1.3, the same as `min_tls_version(&rustls::version::TLS13)`.
+    pub fn tls13_only(self) -> io::Result<TlsConnector> {
+        self.min_tls_version(&rustls::version::TLS13)
+    }
+
+    /// Only offer the cipher suites `suites`, e.g.
+    /// `rustls::cipher_suite::TLS13_AES_256_GCM_SHA384`.
+    ///
+    /// Fails with `InvalidInput` if `suites` is empty or none of them is for a
+    /// TLS version allowed by `min_tls_version` and `max_tls_version`, so set
+    /// those first. Like those, this builds the config anew, and fails with
+    /// `InvalidInput` when its roots are unknown: build the `ClientConfig`
+    /// `with_cipher_suites` instead.
+    pub fn cipher_suites(mut self, suites: &[SupportedCipherSuite]) -> io::Result<TlsConnector> {
+        let mut policy = self.policy;
+        policy.set_suites(suites)?;
+        self.rebuild(self.known_roots()?.clone(), policy)?;
+        Ok(self)
+    }
+
+    /// Like `cipher_suites`, with the suites named as in the IANA registry,
+    /// e.g. `TLS13_AES_256_GCM_SHA384`, ignoring case.
+    ///
+    /// Fails with `InvalidInput` on names unknown to rustls.
+    pub fn cipher_suite_names(mut self, names: &[&str]) -> io::Result<TlsConnector> {
+        let mut policy = self.policy;
+        policy.set_suite_names(names)?;
+        self.rebuild(self.known_roots()?.clone(), policy)?;
+        Ok(self)
+    }
+
+    /// Only offer the versions, cipher suites and key exchange groups of
+    /// `preset`, replacing earlier bounds and suites.
+    ///
+    /// Like `min_tls_version`, this builds the config anew, and fails with
+    /// `InvalidInput` when its roots are unknown: build the `ClientConfig`
+    /// with `SecurityPreset::configure` instead.
+    pub fn security_preset(mut self, preset: SecurityPreset) -> io::Result<TlsConnector> {
+        self.rebuild(self.known_roots()?.clone(), Policy::preset(preset))?;
+        Ok(self)
+    }
+
+    /// Check OCSP responses stapled by the server with `verifier`.
+    ///
+    /// The verifier replaces the one of the `ClientConfig`. Valid responses are
+    /// available from `TlsStream::ocsp_response`.
+    #[cfg(feature = "ocsp")]
+    pub fn ocsp(mut self, verifier: OcspVerifier) -> TlsConnector {
+        self.ocsp = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Require the server's certificate to satisfy a Certificate Transparency policy.
+    ///
+    /// The check happens once the handshake completes, so 0-RTT is not used
+    /// with a policy. The matched logs are available from `TlsStream::ct_logs`.
+    #[cfg(feature = "ct")]
+    pub fn ct_policy(mut self, policy: CtPolicy) -> TlsConnector {
+        self.ct_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Verify the server with TLSA records instead of the roots of the `ClientConfig`.
+    ///
+    /// The records are those of one service, so use a connector per service.
+    #[cfg(feature = "dane")]
+    pub fn dane(mut self, verifier: DaneVerifier) -> TlsConnector {
+        self.inner = dane::verifying(&self.inner, verifier);
+        self.roots = None;
+        self
+    }
+
+    /// Trust servers' certificates on first use, see the `tofu` module.
+    ///
+    /// This replaces the certificate verifier of the `ClientConfig`. The check
+    /// happens once the handshake completes, so 0-RTT is not used either.
+    #[cfg(feature = "tofu")]
+    pub fn tofu(mut self, verifier: TofuVerifier) -> TlsConnector {
+        self.inner = tofu::accept_any(&self.inner);
+        self.roots = None;
+        self.tofu = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Accept any certificate the server presents, valid or not.
+    ///
+    /// This is meant for development endpoints with self-signed certificates,
+    /// and makes the connection as insecure as unencrypted traffic against an
+    /// active attacker. The server still has to prove it holds the key of the
+    /// certificate it presents.
+    #[cfg(feature = "dangerous")]
+    pub fn danger_accept_invalid_certs(mut self, flag: bool) -> TlsConnector {
+        self.danger.invalid_certs = flag;
+        self.inner = self.danger.apply(&self.inner, &mut self.roots);
+        self
+    }
+
+    /// Accept certificates that are not valid for the name connected to.
+    ///
+    /// The chain is still verified, against the roots the connector was
+    /// created with by `new` or `with_root_certificates`. Fails with
+    /// `InvalidInput` for connectors created from a `ClientConfig`, which do
+    /// not know its roots.
+    #[cfg(feature = "dangerous")]
+    pub fn danger_accept_invalid_hostnames(mut self, flag: bool) -> io::Result<TlsConnector> {
+        if flag && self.danger.roots.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the roots of the connector's config are unknown",
+            ));
+        }
+        self.danger.invalid_hostnames = flag;
+        self.inner = self.danger.apply(&self.inner, &mut self.roots);
+        Ok(self)
+    }
+
+    /// Replace the check that the server's certificate is valid for the name
+    /// connected to with `callback`, e.g. to accept an internal naming scheme.
+    ///
+    /// The callback gets the name and the server's DER-encoded certificate.
+    /// The chain is verified as with `danger_accept_invalid_hostnames`, which
+    /// takes precedence over the callback. As the verifier is replaced, methods
+    /// that need the roots, like `add_root_certificate`, fail afterwards.
+    #[cfg(feature = "dangerous")]
+    pub fn verify_hostname_with<F>(mut self, callback: F) -> TlsConnector
+    where
+        F: Fn(&ServerName, &Certificate) -> bool + Send + Sync + 'static,
+    {
+        self.danger.hostname = Some(Arc::new(callback));
+        self.inner = self.danger.apply(&self.inner, &mut self.roots);
         self
     }
 
@@ -102,82 +722,360 @@ impl TlsConnector {
     ///
     /// The function will return a `Connect` Future, representing the connecting part of a Tls
     /// handshake. It will resolve when the handshake is over.
+    ///
+    /// `domain` is either a DNS name or an IP address, which may be enclosed in
+    /// brackets as in URLs. The server's certificate has to be valid for it,
+    /// i.e. list it among its DNS names or IP addresses, respectively.
+    ///
+    /// With the `idna` feature, unicode names such as `bücher.example` are
+    /// converted to their ASCII form, `xn--bcher-kva.example`, which is what
+    /// certificates list.
     #[inline]
-    pub fn connect<'a, IO>(&self, domain: impl AsRef<str>, stream: IO) -> Connect<IO>
+    pub fn connect<IO>(&self, domain: impl AsRef<str>, stream: IO) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        match server_name(domain.as_ref()) {
+            Some(domain) => self.connect_with(domain, stream, |_| ()),
+            None => Connect(ConnectInner::invalid_dns_name(domain.as_ref())),
+        }
+    }
+
+    /// Resolve `addr`, e.g. `example.com:443` or `[::1]:443`, open a TCP
+    /// connection to it and connect to the server like `connect`.
+    ///
+    /// The host is also the server name. Resolving does not block the
+    /// executor. The addresses are raced as in Happy Eyeballs (RFC 8305):
+    /// IPv6 and IPv4 alternate, and each attempt gets 250ms before the next
+    /// one starts alongside it, so a broken IPv6 network does not stall.
+    #[cfg(feature = "async-std")]
+    pub async fn connect_tcp(
+        &self,
+        addr: &str,
+    ) -> io::Result<client::TlsStream<async_std::net::TcpStream>> {
+        let (host, port) = tcp::split_host_port(addr)?;
+        let stream = tcp::connect(host, port).await?;
+        self.connect(host, stream).await
+    }
+
+    /// Connect to `addr` like `connect_tcp`, trying again after transient
+    /// failures such as reset connections, as `policy` says.
+    ///
+    /// Failures that would only repeat, such as an invalid certificate, are
+    /// returned right away, see `RetryPolicy::is_transient`.
+    #[cfg(feature = "async-std")]
+    pub async fn connect_with_retry(
+        &self,
+        addr: &str,
+        policy: &RetryPolicy,
+    ) -> io::Result<client::TlsStream<async_std::net::TcpStream>> {
+        let mut retry = 0;
+        loop {
+            let attempt = self.connect_tcp(addr);
+            let result = match policy.timeout() {
+                Some(timeout) => async_std::future::timeout(timeout, attempt)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "connection attempt timed out",
+                        ))
+                    }),
+                None => attempt.await,
+            };
+            match result {
+                Err(err) if RetryPolicy::is_transient(&err) => match policy.backoff(retry) {
+                    Some(backoff) => {
+                        async_std::task::sleep(backoff).await;
+                        retry += 1;
+                    }
+                    None => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Connect to a server like `connect`, over a tokio IO object such as
+    /// `tokio::net::TcpStream`.
+    ///
+    /// The stream implements the IO traits of both tokio and futures-io.
+    #[cfg(feature = "tokio")]
+    pub fn connect_tokio<IO>(&self, domain: impl AsRef<str>, stream: IO) -> Connect<TokioIo<IO>>
+    where
+        IO: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        self.connect(domain, TokioIo::new(stream))
+    }
+
+    /// Connect to a server like `connect`, sending the data written before the
+    /// handshake is done along with the `ClientHello` (0-RTT).
+    ///
+    /// The stream is returned right away, together with an `EarlyData` handle
+    /// telling whether the server accepted the data. Rejected data is sent again
+    /// once the handshake is done, so reads and writes work either way; see
+    /// `early_data_buffer` for how much is kept, and `rejected_early_data` to
+    /// handle rejections yourself. See `TlsStream::write_early_data` to only
+    /// write what can be sent early.
+    ///
+    /// Early data is only sent when resuming a session of a server that allows
+    /// it, and when `enable_early_data` is set in the `ClientConfig`. With a CT
+    /// policy or TOFU, the server is checked first: the stream is returned once
+    /// the handshake is done, without sending early data.
+    ///
+    /// An attacker can replay early data: only send requests that are safe to repeat.
+    pub fn connect_0rtt<IO>(&self, domain: impl AsRef<str>, stream: IO) -> Connect0Rtt<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (early_data, notify) = client::EarlyData::new();
+        let connect = match server_name(domain.as_ref()) {
+            Some(domain) => self.connect_inner(domain, stream, |_| (), Some(notify)),
+            None => Connect(ConnectInner::invalid_dns_name(domain.as_ref())),
+        };
+        Connect0Rtt {
+            connect,
+            early_data: Some(early_data),
+        }
+    }
+
+    /// Connect to a server, like `connect`, with a name that has already been parsed.
+    ///
+    /// This saves parsing the name again when connecting to the same server repeatedly.
+    #[inline]
+    pub fn connect_with_name<IO>(&self, domain: ServerName, stream: IO) -> Connect<IO>
     where
         IO: AsyncRead + AsyncWrite + Unpin,
     {
         self.connect_with(domain, stream, |_| ())
     }
 
-    // NOTE: Currently private, exposing ClientConnection exposes rusttls
-    // Early data should be exposed differently
-    fn connect_with<'a, IO, F>(&self, domain: impl AsRef<str>, stream: IO, f: F) -> Connect<IO>
+    /// Connect to a server, like `connect_with_name`, calling `f` on the rustls
+    /// session before the handshake starts.
+    ///
+    /// This is the place for per-connection options, such as the buffer limit:
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::TryFrom;
+    /// # async_std::task::block_on(async {
+    /// let connector = async_tls::TlsConnector::new();
+    /// let name = rustls::ServerName::try_from("example.com").unwrap();
+    /// let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await?;
+    /// let stream = connector
+    ///     .connect_with(name, tcp_stream, |session| {
+    ///         session.set_buffer_limit(Some(16 * 1024));
+    ///     })
+    ///     .await?;
+    /// # Ok(()) as std::io::Result<()>
+    /// # });
+    /// ```
+    pub fn connect_with<IO, F>(&self, domain: ServerName, stream: IO, f: F) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+        F: FnOnce(&mut ClientConnection),
+    {
+        self.connect_inner(domain, stream, f, None)
+    }
+
+    fn connect_inner<IO, F>(
+        &self,
+        domain: ServerName,
+        stream: IO,
+        f: F,
+        notify_early_data: Option<client::EarlyDataNotifier>,
+    ) -> Connect<IO>
     where
         IO: AsyncRead + AsyncWrite + Unpin,
         F: FnOnce(&mut ClientConnection),
     {
-        let domain = match ServerName::try_from(domain.as_ref()) {
-            Ok(domain) => domain,
-            Err(_) => {
-                return Connect(ConnectInner::Error(Some(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "invalid domain",
-                ))))
+        let server_name = match domain {
+            ServerName::DnsName(ref name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => format!("{:?}", domain),
+        };
+
+        #[cfg(feature = "ocsp")]
+        let ocsp = Arc::new(OnceLock::new());
+        #[cfg(feature = "ocsp")]
+        let config = match self.ocsp {
+            Some(ref verifier) => ocsp::capturing(&self.inner, verifier.clone(), ocsp.clone()),
+            None => self.inner.clone(),
+        };
+        #[cfg(not(feature = "ocsp"))]
+        let config = self.inner.clone();
+
+        let (config, acceptable_issuers) = match self.record_certificate_requests {
+            true => {
+                let issuers = Arc::new(OnceLock::new());
+                (
+                    client_cert::recording(&config, issuers.clone()),
+                    Some(issuers),
+                )
             }
+            false => (config, None),
         };
 
-        let mut session = match ClientConnection::new(self.inner.clone(), domain) {
+        let mut session = match ClientConnection::new(config, domain) {
             Ok(session) => session,
-            Err(_) => {
-                return Connect(ConnectInner::Error(Some(io::Error::new(
-                    io::ErrorKind::Other,
-                    "invalid connection",
-                ))))
+            Err(err) => {
+                let err = HandshakeError::wrap_at(
+                    Error::from(err).into(),
+                    Some(&server_name),
+                    HandshakeStage::Hello,
+                );
+                return Connect(ConnectInner::Error(Some(err)));
             }
         };
 
         f(&mut session);
 
-        #[cfg(not(feature = "early-data"))]
+        let notify_early_data = match notify_early_data {
+            // these checks only happen once the handshake is done
+            Some(notify) if self.checks_after_handshake() => {
+                notify.finish(false);
+                None
+            }
+            notify => notify,
+        };
+
+        let stream = client::TlsStream {
+            session,
+            io: stream,
+            state: match notify_early_data {
+                Some(_) => TlsState::EarlyData,
+                None => TlsState::Stream,
+            },
+            server_name,
+            strict_close_notify: self.strict_close_notify,
+            send_close_notify: self.send_close_notify,
+            close_mode: self.close_mode,
+            close_timer: CloseTimer::default(),
+            exact_reads: false,
+            handshake_flush: self.handshake_flush,
+            write_chunking: self.write_chunking,
+            coalescer: Coalescer::new(self.coalesce_writes.0, self.coalesce_writes.1),
+            early_data_limit: self.early_data_limit,
+            rejected_early_data: self.rejected_early_data,
+            hello: HelloSniffer::client(),
+            observation: Observation::start(self.observer.clone()),
+            peeked: Vec::new(),
+            io_state: None,
+            early_data: (0, Vec::new()),
+            notify_early_data,
+            acceptable_issuers,
+            #[cfg(feature = "ocsp")]
+            ocsp,
+            #[cfg(feature = "ct")]
+            ct_policy: self.ct_policy.clone(),
+            #[cfg(feature = "ct")]
+            ct_logs: Vec::new(),
+            #[cfg(feature = "tofu")]
+            tofu: self.tofu.clone(),
+        };
+        Connect(ConnectInner::Handshake(match stream.state {
+            TlsState::EarlyData => client::Handshake::EarlyData(stream),
+            _ => client::Handshake::Handshaking(stream),
+        }))
+    }
+
+    /// Whether the server is checked once the handshake is done, before the
+    /// stream is used, which rules out early data.
+    fn checks_after_handshake(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut checks = false;
+        #[cfg(feature = "ct")]
         {
-            Connect(ConnectInner::Handshake(client::MidHandshake::Handshaking(
-                client::TlsStream {
-                    session,
-                    io: stream,
-                    state: TlsState::Stream,
-                },
-            )))
+            checks |= self.ct_policy.is_some();
         }
-
-        #[cfg(feature = "early-data")]
+        #[cfg(feature = "tofu")]
         {
-            Connect(ConnectInner::Handshake(if self.early_data {
-                client::MidHandshake::EarlyData(client::TlsStream {
-                    session,
-                    io: stream,
-                    state: TlsState::EarlyData,
-                    early_data: (0, Vec::new()),
-                })
-            } else {
-                client::MidHandshake::Handshaking(client::TlsStream {
-                    session,
-                    io: stream,
-                    state: TlsState::Stream,
-                    early_data: (0, Vec::new()),
-                })
-            }))
+            checks |= self.tofu.is_some();
+        }
+        checks
+    }
+}
+
+/// Parse `domain` as an IP address, possibly in brackets, or else as a DNS name.
+///
+/// With the `idna` feature, unicode names are converted to their ASCII form.
+fn server_name(domain: &str) -> Option<ServerName> {
+    let ip = domain
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(domain);
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => Some(ServerName::IpAddress(ip)),
+        #[cfg(feature = "idna")]
+        Err(_) if !domain.is_ascii() => {
+            let domain = idna::domain_to_ascii(domain).ok()?;
+            ServerName::try_from(domain.as_str()).ok()
         }
+        Err(_) => ServerName::try_from(domain).ok(),
     }
 }
 
+/// The WebPKI roots, which connectors trust by default.
+///
+/// rustls only verifies against owned anchors, so they are converted on first
+/// use, by the first connector needing them, and shared from then on.
+pub(crate) fn webpki_roots() -> &'static Arc<RootCertStore> {
+    static ROOTS: OnceLock<Arc<RootCertStore>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        Arc::new(RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS
+                .0
+                .iter()
+                .map(|ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                })
+                .collect(),
+        })
+    })
+}
+
+fn add_root(roots: &mut RootCertStore, cert: &Certificate) -> io::Result<()> {
+    roots
+        .add(cert)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// A config with safe defaults, verifying servers against `roots`.
+fn verifying(roots: &RootCertStore) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots.clone())
+        .with_no_client_auth()
+}
+
 /// Future returned from `TlsConnector::connect` which will resolve
 /// once the connection handshake has finished.
+///
+/// It can be stored and polled by hand, e.g. by a scheduler of its own,
+/// which can tell how far the handshake got with `stage` to time out or
+/// prioritize handshakes, and whether the future resolved with
+/// `FusedFuture::is_terminated`.
+/// `into_mid_handshake` turns it into the `client::MidHandshake` it drives.
 pub struct Connect<IO>(ConnectInner<IO>);
 
+#[allow(clippy::large_enum_variant)]
 enum ConnectInner<IO> {
     Error(Option<io::Error>),
-    Handshake(client::MidHandshake<IO>),
+    Handshake(client::Handshake<IO>),
+}
+
+impl<IO> ConnectInner<IO> {
+    fn invalid_dns_name(domain: &str) -> Self {
+        let err = Error::InvalidDnsName(domain.to_string()).into();
+        ConnectInner::Error(Some(HandshakeError::wrap_at(
+            err,
+            Some(domain),
+            HandshakeStage::Hello,
+        )))
+    }
 }
 
 impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Connect<IO> {
@@ -193,3 +1091,146 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Connect<IO> {
         }
     }
 }
+
+impl<IO> Connect<IO> {
+    /// Returns a reference to the underlying IO stream while the handshake
+    /// is in flight, e.g. to read the server's address.
+    ///
+    /// Returns `None` once the future resolved, or if connecting failed
+    /// before the handshake started, e.g. for an invalid domain.
+    pub fn get_ref(&self) -> Option<&IO> {
+        match self.0 {
+            ConnectInner::Error(_) => None,
+            ConnectInner::Handshake(ref handshake) => handshake.get_ref(),
+        }
+    }
+
+    /// Returns a mutable reference to the underlying IO stream while the
+    /// handshake is in flight, e.g. to set socket options, see `get_ref`.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        match self.0 {
+            ConnectInner::Error(_) => None,
+            ConnectInner::Handshake(ref mut handshake) => handshake.get_mut(),
+        }
+    }
+
+    /// How far the handshake got, `None` once the future resolved, or if
+    /// connecting failed before the handshake started.
+    ///
+    /// The handshake is at `HandshakeStage::Verifying` while checks after it,
+    /// e.g. TOFU, are pending.
+    pub fn stage(&self) -> Option<HandshakeStage> {
+        match self.0 {
+            ConnectInner::Error(_) => None,
+            ConnectInner::Handshake(ref handshake) => handshake.stage(),
+        }
+    }
+
+    /// Turns the future into the handshake in flight, to be driven by hand
+    /// with `MidHandshake::poll`.
+    ///
+    /// Fails with the error the future would resolve to if connecting failed
+    /// before the handshake started, e.g. for an invalid domain.
+    pub fn into_mid_handshake(self) -> io::Result<client::MidHandshake<IO>> {
+        match self.0 {
+            ConnectInner::Error(err) => Err(err.expect("Connect already resolved")),
+            ConnectInner::Handshake(handshake) => Ok(client::MidHandshake(handshake)),
+        }
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Connect<IO> {
+    /// Cancels the handshake and returns the underlying IO, still open, e.g.
+    /// to close it when giving up on a slow server.
+    ///
+    /// With `notify`, the server is told with a `user_canceled` alert, or a
+    /// `close_notify` once a cipher suite was agreed on, which is all rustls
+    /// sends then. Failing to send it is ignored.
+    ///
+    /// Returns `None` if the future resolved, or failed before the handshake
+    /// started, e.g. for an invalid domain.
+    pub async fn abort(self, notify: bool) -> Option<IO> {
+        match self.0 {
+            ConnectInner::Error(_) => None,
+            ConnectInner::Handshake(handshake) => handshake.abort(notify).await,
+        }
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FusedFuture for Connect<IO> {
+    fn is_terminated(&self) -> bool {
+        match self.0 {
+            ConnectInner::Error(ref err) => err.is_none(),
+            ConnectInner::Handshake(ref handshake) => handshake.is_done(),
+        }
+    }
+}
+
+impl<IO> fmt::Debug for Connect<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.0 {
+            ConnectInner::Error(_) => "Error",
+            ConnectInner::Handshake(client::Handshake::Handshaking(_)) => "Handshaking",
+            ConnectInner::Handshake(client::Handshake::EarlyData(_)) => "EarlyData",
+            #[cfg(feature = "tofu")]
+            ConnectInner::Handshake(client::Handshake::Trusting(..)) => "Trusting",
+            ConnectInner::Handshake(client::Handshake::End) => "Done",
+        };
+        f.debug_tuple("Connect").field(&state).finish()
+    }
+}
+
+/// Future returned from `TlsConnector::connect_0rtt` which will resolve
+/// once early data can be written.
+pub struct Connect0Rtt<IO> {
+    connect: Connect<IO>,
+    early_data: Option<client::EarlyData>,
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Connect0Rtt<IO> {
+    type Output = io::Result<(client::TlsStream<IO>, client::EarlyData)>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let stream = ready!(Pin::new(&mut self.connect).poll(cx))?;
+        let early_data = self.early_data.take();
+        Poll::Ready(Ok((
+            stream,
+            early_data.expect("Polled twice after being Ready"),
+        )))
+    }
+}
+
+impl<IO> fmt::Debug for Connect0Rtt<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connect0Rtt")
+            .field("connect", &self.connect)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_the_default_config() {
+        let (a, b) = (TlsConnector::new(), TlsConnector::default());
+        assert!(Arc::ptr_eq(&a.inner, &b.inner));
+        // options still apply to a copy
+        let c = TlsConnector::new().enable_sni(false);
+        assert!(!Arc::ptr_eq(&a.inner, &c.inner));
+        assert!(a.inner.enable_sni);
+    }
+
+    #[test]
+    fn converts_the_webpki_roots_once() {
+        assert!(Arc::ptr_eq(webpki_roots(), webpki_roots()));
+        let connector = TlsConnector::new();
+        assert!(Arc::ptr_eq(
+            connector.roots.as_ref().unwrap(),
+            webpki_roots()
+        ));
+        assert_eq!(webpki_roots().len(), webpki_roots::TLS_SERVER_ROOTS.0.len());
+    }
+}
@@ -0,0 +1,310 @@
+//! Certificate revocation lists (CRLs), checked when verifying the peer's certificate.
+//!
+//! `ClientCertVerifierBuilder` builds the verifier for a server authenticating
+//! its clients, `ServerCertVerifierBuilder` the one for a client verifying the
+//! server. Both take the trusted roots and any number of DER or PEM encoded
+//! CRLs; a certificate listed by the CRL of its issuer fails the handshake.
+//!
+//! Use `is_revoked` to tell a revoked peer certificate apart from other
+//! verification failures. Problems with the CRLs themselves, e.g. a bad
+//! signature, are reported as `rustls::Error::InvalidCertRevocationList`.
+
+use crate::common::pem;
+
+#[cfg(feature = "client")]
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+#[cfg(feature = "server")]
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientCertVerifier,
+    UnparsedCertRevocationList,
+};
+#[cfg(feature = "client")]
+use rustls::{CertRevocationListError, Error, ServerName};
+use rustls::{Certificate, CertificateError, RootCertStore};
+#[cfg(feature = "client")]
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+#[cfg(feature = "client")]
+use std::time::SystemTime;
+
+/// Builds a verifier for client certificates that also checks CRLs.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::crl::ClientCertVerifierBuilder;
+/// use async_tls::{Identity, TlsAcceptor};
+/// use rustls::{Certificate, ServerConfig};
+///
+/// # fn main() -> std::io::Result<()> {
+/// # let (client_ca, identity): (Certificate, Identity) = todo!();
+/// let verifier = ClientCertVerifierBuilder::new(vec![client_ca])
+///     .crls_pem(&std::fs::read("clients.crl.pem")?)?
+///     .build()?;
+/// let (chain, key) = identity.into_parts();
+/// let config = ServerConfig::builder()
+///     .with_safe_defaults()
+///     .with_client_cert_verifier(verifier)
+///     .with_single_cert(chain, key)
+///     .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+/// let acceptor = TlsAcceptor::from(config);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "server")]
+pub struct ClientCertVerifierBuilder {
+    roots: Vec<Certificate>,
+    crls: Vec<Vec<u8>>,
+    anonymous: bool,
+}
+
+#[cfg(feature = "server")]
+impl ClientCertVerifierBuilder {
+    /// Accept client certificates issued by `roots`.
+    pub fn new(roots: Vec<Certificate>) -> Self {
+        ClientCertVerifierBuilder {
+            roots,
+            crls: Vec::new(),
+            anonymous: false,
+        }
+    }
+
+    /// Add a DER-encoded CRL.
+    pub fn crl(mut self, der: Vec<u8>) -> Self {
+        self.crls.push(der);
+        self
+    }
+
+    /// Add every CRL in a PEM bundle.
+    pub fn crls_pem(mut self, pem: &[u8]) -> io::Result<Self> {
+        self.crls.extend(pem::crls(pem)?);
+        Ok(self)
+    }
+
+    /// Also accept clients that do not present a certificate.
+    pub fn allow_anonymous(mut self, flag: bool) -> Self {
+        self.anonymous = flag;
+        self
+    }
+
+    /// Parse the roots and CRLs into a verifier for `ServerConfig`.
+    pub fn build(self) -> io::Result<Arc<dyn ClientCertVerifier>> {
+        let roots = root_store(&self.roots)?;
+        let crls = self.crls.into_iter().map(UnparsedCertRevocationList);
+        Ok(if self.anonymous {
+            AllowAnyAnonymousOrAuthenticatedClient::new(roots)
+                .with_crls(crls)
+                .map_err(invalid_crl)?
+                .boxed()
+        } else {
+            AllowAnyAuthenticatedClient::new(roots)
+                .with_crls(crls)
+                .map_err(invalid_crl)?
+                .boxed()
+        })
+    }
+}
+
+#[cfg(feature = "server")]
+impl fmt::Debug for ClientCertVerifierBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCertVerifierBuilder")
+            .field("roots", &self.roots.len())
+            .field("crls", &self.crls.len())
+            .field("anonymous", &self.anonymous)
+            .finish()
+    }
+}
+
+/// Builds a verifier for server certificates that also checks CRLs.
+///
+/// Apart from revocation, certificates are verified as rustls does by default.
+#[cfg(feature = "client")]
+pub struct ServerCertVerifierBuilder {
+    roots: Vec<Certificate>,
+    crls: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "client")]
+impl ServerCertVerifierBuilder {
+    /// Accept server certificates issued by `roots`.
+    pub fn new(roots: Vec<Certificate>) -> Self {
+        ServerCertVerifierBuilder {
+            roots,
+            crls: Vec::new(),
+        }
+    }
+
+    /// Add a DER-encoded CRL.
+    pub fn crl(mut self, der: Vec<u8>) -> Self {
+        self.crls.push(der);
+        self
+    }
+
+    /// Add every CRL in a PEM bundle.
+    pub fn crls_pem(mut self, pem: &[u8]) -> io::Result<Self> {
+        self.crls.extend(pem::crls(pem)?);
+        Ok(self)
+    }
+
+    /// Parse the roots and CRLs into a verifier for `ClientConfig`.
+    pub fn build(self) -> io::Result<Arc<dyn ServerCertVerifier>> {
+        let crls = self
+            .crls
+            .iter()
+            .map(|der| {
+                webpki::BorrowedCertRevocationList::from_der(der)
+                    .and_then(|crl| crl.to_owned())
+                    .map_err(|err| invalid_crl(err.into()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        // parse the anchors once, so that handshakes cannot fail on them
+        for root in &self.roots {
+            webpki::TrustAnchor::try_from_cert_der(&root.0)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+
+        Ok(Arc::new(CrlServerVerifier {
+            inner: WebPkiVerifier::new(root_store(&self.roots)?, None),
+            roots: self.roots,
+            crls,
+        }))
+    }
+}
+
+#[cfg(feature = "client")]
+impl fmt::Debug for ServerCertVerifierBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerCertVerifierBuilder")
+            .field("roots", &self.roots.len())
+            .field("crls", &self.crls.len())
+            .finish()
+    }
+}
+
+/// rustls' `WebPkiVerifier`, plus a revocation check of the verified chain.
+#[cfg(feature = "client")]
+struct CrlServerVerifier {
+    inner: WebPkiVerifier,
+    roots: Vec<Certificate>,
+    crls: Vec<webpki::OwnedCertRevocationList>,
+}
+
+#[cfg(feature = "client")]
+impl ServerCertVerifier for CrlServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        if self.crls.is_empty() {
+            return Ok(verified);
+        }
+
+        let cert = webpki::EndEntityCert::try_from(end_entity.0.as_slice())
+            .map_err(|_| CertificateError::BadEncoding)?;
+        let anchors = self
+            .roots
+            .iter()
+            .filter_map(|root| webpki::TrustAnchor::try_from_cert_der(&root.0).ok())
+            .collect::<Vec<_>>();
+        let intermediates = intermediates
+            .iter()
+            .map(|cert| cert.0.as_slice())
+            .collect::<Vec<_>>();
+        let crls = self
+            .crls
+            .iter()
+            .map(|crl| crl as &dyn webpki::CertRevocationList)
+            .collect::<Vec<_>>();
+        let now = webpki::Time::try_from(now).map_err(|_| Error::FailedToGetCurrentTime)?;
+
+        cert.verify_for_usage(
+            SUPPORTED_SIG_ALGS,
+            &anchors,
+            &intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            &crls,
+        )
+        .map_err(|err| match err {
+            webpki::Error::CertRevoked => CertificateError::Revoked.into(),
+            webpki::Error::InvalidCrlSignatureForPublicKey
+            | webpki::Error::UnsupportedCrlSignatureAlgorithm
+            | webpki::Error::UnsupportedCrlSignatureAlgorithmForPublicKey
+            | webpki::Error::IssuerNotCrlSigner => {
+                Error::InvalidCertRevocationList(CertRevocationListError::from(err))
+            }
+            err => CertificateError::Other(Arc::new(err)).into(),
+        })?;
+        Ok(verified)
+    }
+}
+
+/// The algorithms rustls accepts for certificate signatures.
+#[cfg(feature = "client")]
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Whether a handshake failed because the peer's certificate has been revoked.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # async fn connect(connector: async_tls::TlsConnector, tcp: async_std::net::TcpStream) {
+/// match connector.connect("example.com", tcp).await {
+///     Ok(stream) => (),
+///     Err(err) if async_tls::crl::is_revoked(&err) => eprintln!("server certificate revoked"),
+///     Err(err) => eprintln!("handshake failed: {}", err),
+/// }
+/// # }
+/// ```
+pub fn is_revoked(err: &io::Error) -> bool {
+    matches!(
+        crate::error::error_ref(err),
+        Some(crate::Error::Certificate(CertificateError::Revoked))
+    )
+}
+
+fn root_store(roots: &[Certificate]) -> io::Result<RootCertStore> {
+    let mut store = RootCertStore::empty();
+    for root in roots {
+        store
+            .add(root)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    }
+    Ok(store)
+}
+
+fn invalid_crl(err: rustls::CertRevocationListError) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        rustls::Error::InvalidCertRevocationList(err),
+    )
+}
@@ -0,0 +1,296 @@
+//! Certificate Transparency ([RFC 6962]): requiring servers' certificates to be publicly logged.
+//!
+//! A `CtPolicy` lists the logs the client trusts. Connectors with a policy
+//! check the signed certificate timestamps (SCTs) embedded in the server's
+//! certificate once the handshake completes, and fail the connection unless
+//! enough of them carry a valid signature by distinct trusted logs. The logs
+//! that were matched are available from the stream.
+//!
+//! SCTs delivered in the TLS extension or in an OCSP response are not
+//! considered, and the issuer of the certificate has to be the first
+//! certificate of the chain sent by the server.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::ct::{CtLog, CtPolicy};
+//! use async_tls::TlsConnector;
+//! # fn log_keys() -> Vec<(String, Vec<u8>)> { todo!() }
+//!
+//! # async_std::task::block_on(async {
+//! let logs = log_keys()
+//!     .into_iter()
+//!     .map(|(name, key)| CtLog::new(name, key))
+//!     .collect::<std::io::Result<_>>()?;
+//! let connector = TlsConnector::new().ct_policy(CtPolicy::new(logs));
+//!
+//! let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await?;
+//! let stream = connector.connect("example.com", tcp_stream).await?;
+//! for log in stream.ct_logs() {
+//!     println!("logged in {}", log.name());
+//! }
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+//!
+//! [RFC 6962]: https://www.rfc-editor.org/rfc/rfc6962
+
+use crate::common::der;
+
+use ring::digest::{digest, SHA256};
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use rustls::Certificate;
+use std::fmt;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The embedded SCT list extension.
+const SCT_LIST: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xd6, 0x79, 0x02, 0x04, 0x02];
+
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// A Certificate Transparency log.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CtLog {
+    name: String,
+    id: [u8; 32],
+    key: Vec<u8>,
+}
+
+impl CtLog {
+    /// A log with the given DER-encoded `SubjectPublicKeyInfo`, as published in log lists.
+    ///
+    /// Logs sign with either ECDSA P-256 or RSA keys.
+    pub fn new(name: impl Into<String>, key: Vec<u8>) -> io::Result<Self> {
+        let supported = match der::public_key(&key) {
+            Some(spki) => matches!(
+                (spki.algorithm, spki.parameters),
+                (EC_PUBLIC_KEY, Some(P256)) | (RSA_ENCRYPTION, _)
+            ),
+            None => false,
+        };
+        if !supported {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unsupported CT log key",
+            ));
+        }
+
+        let mut id = [0; 32];
+        id.copy_from_slice(digest(&SHA256, &key).as_ref());
+        Ok(CtLog {
+            name: name.into(),
+            id,
+            key,
+        })
+    }
+
+    /// The name the log was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The log ID, the SHA-256 hash of its key.
+    pub fn id(&self) -> &[u8; 32] {
+        &self.id
+    }
+
+    fn verify(&self, sct: &Sct<'_>, issuer_key_hash: &[u8], tbs: &[u8]) -> bool {
+        let spki = match der::public_key(&self.key) {
+            Some(spki) => spki,
+            None => return false,
+        };
+        // only SHA-256 is allowed by RFC 6962
+        let algorithm: &'static dyn VerificationAlgorithm = match (sct.hash, sct.algorithm) {
+            (4, 3) if spki.algorithm == EC_PUBLIC_KEY => &signature::ECDSA_P256_SHA256_ASN1,
+            (4, 1) if spki.algorithm == RSA_ENCRYPTION => &signature::RSA_PKCS1_2048_8192_SHA256,
+            _ => return false,
+        };
+
+        // digitally-signed struct of a precert_entry
+        let mut signed = vec![0, 0];
+        signed.extend_from_slice(&sct.timestamp.to_be_bytes());
+        signed.extend_from_slice(&[0, 1]);
+        signed.extend_from_slice(issuer_key_hash);
+        signed.extend_from_slice(&(tbs.len() as u32).to_be_bytes()[1..]);
+        signed.extend_from_slice(tbs);
+        signed.extend_from_slice(&(sct.extensions.len() as u16).to_be_bytes());
+        signed.extend_from_slice(sct.extensions);
+
+        UnparsedPublicKey::new(algorithm, spki.key)
+            .verify(&signed, sct.signature)
+            .is_ok()
+    }
+}
+
+impl fmt::Debug for CtLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtLog").field("name", &self.name).finish()
+    }
+}
+
+/// The logs a client trusts, and how many of them need to have logged a certificate.
+#[derive(Debug, Clone)]
+pub struct CtPolicy {
+    logs: Vec<CtLog>,
+    min_scts: usize,
+}
+
+impl CtPolicy {
+    /// Trust `logs`, requiring SCTs from at least two of them.
+    pub fn new(logs: Vec<CtLog>) -> Self {
+        CtPolicy { logs, min_scts: 2 }
+    }
+
+    /// Require SCTs from at least `count` distinct logs.
+    pub fn min_scts(mut self, count: usize) -> Self {
+        self.min_scts = count;
+        self
+    }
+
+    /// The trusted logs with a valid SCT for the leaf of `chain`.
+    pub(crate) fn check(&self, chain: &[Certificate], now: SystemTime) -> io::Result<Vec<CtLog>> {
+        let (leaf, issuer) = match chain {
+            [leaf, issuer, ..] => (&leaf.0, &issuer.0),
+            _ => return Err(unsatisfied("server did not send the issuer certificate")),
+        };
+        let issuer = der::tbs_certificate(issuer)
+            .ok_or_else(|| unsatisfied("failed to parse issuer certificate"))?;
+        let issuer_key_hash = digest(&SHA256, issuer.spki);
+        let scts = der::tbs_certificate(leaf)
+            .ok_or_else(|| unsatisfied("failed to parse certificate"))?
+            .extension(SCT_LIST)
+            .and_then(|list| scts(list))
+            .unwrap_or_default();
+        let tbs = der::tbs_without_extension(leaf, SCT_LIST)
+            .ok_or_else(|| unsatisfied("failed to parse certificate"))?;
+
+        let mut matched: Vec<CtLog> = Vec::new();
+        for sct in &scts {
+            let log = match self.logs.iter().find(|log| log.id[..] == *sct.log_id) {
+                Some(log) => log,
+                None => continue,
+            };
+            let timestamp = UNIX_EPOCH + Duration::from_millis(sct.timestamp);
+            if timestamp <= now
+                && !matched.contains(log)
+                && log.verify(sct, issuer_key_hash.as_ref(), &tbs)
+            {
+                matched.push(log.clone());
+            }
+        }
+
+        if matched.len() < self.min_scts {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "certificate transparency policy not satisfied: valid SCTs from {} of {} required logs",
+                    matched.len(),
+                    self.min_scts
+                ),
+            ));
+        }
+        Ok(matched)
+    }
+}
+
+/// A v1 signed certificate timestamp.
+struct Sct<'a> {
+    log_id: &'a [u8],
+    /// Milliseconds since the unix epoch.
+    timestamp: u64,
+    extensions: &'a [u8],
+    hash: u8,
+    algorithm: u8,
+    signature: &'a [u8],
+}
+
+/// Parse the SCTs out of the contents of the SCT list extension.
+///
+/// SCTs of unknown versions are skipped.
+fn scts(extension: &[u8]) -> Option<Vec<Sct<'_>>> {
+    let mut list = der::Reader::new(extension).expect(der::OCTET_STRING)?;
+    let mut list = prefixed(&mut list, 2)?;
+    let mut scts = Vec::new();
+    while !list.is_empty() {
+        let mut sct = prefixed(&mut list, 2)?;
+        if take(&mut sct, 1)? != [0] {
+            continue;
+        }
+        let log_id = take(&mut sct, 32)?;
+        let timestamp = take(&mut sct, 8)?;
+        let extensions = prefixed(&mut sct, 2)?;
+        let hash = take(&mut sct, 1)?[0];
+        let algorithm = take(&mut sct, 1)?[0];
+        let signature = prefixed(&mut sct, 2)?;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(timestamp);
+        scts.push(Sct {
+            log_id,
+            timestamp: u64::from_be_bytes(bytes),
+            extensions,
+            hash,
+            algorithm,
+            signature,
+        });
+    }
+    Some(scts)
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (head, rest) = data.split_at(len);
+    *data = rest;
+    Some(head)
+}
+
+/// Take a TLS vector with a big-endian length of `size` bytes.
+fn prefixed<'a>(data: &mut &'a [u8], size: usize) -> Option<&'a [u8]> {
+    let len = take(data, size)?
+        .iter()
+        .fold(0usize, |len, &b| (len << 8) | b as usize);
+    take(data, len)
+}
+
+fn unsatisfied(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("certificate transparency policy not satisfied: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sct_lists() {
+        let mut sct = vec![0];
+        sct.extend_from_slice(&[7; 32]);
+        sct.extend_from_slice(&1_600_000_000_000u64.to_be_bytes());
+        sct.extend_from_slice(&[0, 0, 4, 3, 0, 2, 0xaa, 0xbb]);
+        let mut unknown = sct.clone();
+        unknown[0] = 1;
+
+        let mut list = Vec::new();
+        for sct in [&sct, &unknown] {
+            list.extend_from_slice(&(sct.len() as u16).to_be_bytes());
+            list.extend_from_slice(sct);
+        }
+        let list = [&(list.len() as u16).to_be_bytes()[..], &list].concat();
+        let extension = der::encode(der::OCTET_STRING, &[&list]);
+
+        let parsed = scts(&extension).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].log_id, &[7; 32]);
+        assert_eq!(parsed[0].timestamp, 1_600_000_000_000);
+        assert_eq!((parsed[0].hash, parsed[0].algorithm), (4, 3));
+        assert_eq!(parsed[0].signature, &[0xaa, 0xbb]);
+
+        assert!(scts(&der::encode(der::OCTET_STRING, &[&[0, 5, 0]])).is_none());
+    }
+}
@@ -0,0 +1,366 @@
+//! DANE ([RFC 6698], [RFC 7671]): authenticating servers with TLSA records.
+//!
+//! async-tls does not resolve DNS. Look up the TLSA records of the service,
+//! e.g. `_443._tcp.example.com`, with a DNSSEC-validating resolver, and hand
+//! them to a `DaneVerifier`. Only pass records that were validated as secure;
+//! DANE offers no protection on top of an insecure lookup.
+//!
+//! A server is accepted if any usable record matches it:
+//!
+//! * `DANE-EE(3)`: the server's certificate matches. Its names and validity
+//!   period are not checked.
+//! * `DANE-TA(2)`: a certificate sent by the server matches, and the chain
+//!   verifies up to it as the trust anchor, including the server name.
+//! * `PKIX-EE(1)` and `PKIX-TA(0)`: the chain verifies against the WebPKI
+//!   roots passed to `DaneVerifier::pkix`, and the server's certificate or one
+//!   of the certificates sent by the server, respectively, matches.
+//!
+//! Records with unknown parameters, and PKIX records without roots, are
+//! unusable. Without usable records, the verifier falls back to WebPKI if it
+//! has roots and fails the handshake otherwise.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::dane::{DaneVerifier, TlsaRecord};
+//! use async_tls::TlsConnector;
+//! # fn resolve_tlsa(name: &str) -> Vec<String> { todo!() }
+//!
+//! # async_std::task::block_on(async {
+//! // e.g. "3 1 1 0c72ac70b745ac19998811b131d662c9ac69dbdbe7cb23e5b514b56664c5d3d6"
+//! let records = resolve_tlsa("_443._tcp.example.com")
+//!     .iter()
+//!     .map(|record| record.parse())
+//!     .collect::<std::io::Result<_>>()?;
+//! let connector = TlsConnector::new().dane(DaneVerifier::new(records));
+//!
+//! let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await?;
+//! let stream = connector.connect("example.com", tcp_stream).await?;
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+//!
+//! [RFC 6698]: https://www.rfc-editor.org/rfc/rfc6698
+//! [RFC 7671]: https://www.rfc-editor.org/rfc/rfc7671
+
+use crate::common::der;
+
+use ring::digest::{digest, SHA256, SHA512};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, ClientConfig, Error, RootCertStore, ServerName};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Certificate usage: a CA certificate, on top of WebPKI validation.
+pub const PKIX_TA: u8 = 0;
+/// Certificate usage: the server's certificate, on top of WebPKI validation.
+pub const PKIX_EE: u8 = 1;
+/// Certificate usage: a trust anchor, replacing the WebPKI roots.
+pub const DANE_TA: u8 = 2;
+/// Certificate usage: the server's certificate, replacing WebPKI validation.
+pub const DANE_EE: u8 = 3;
+
+/// Selector: the whole certificate.
+pub const CERT: u8 = 0;
+/// Selector: the certificate's `SubjectPublicKeyInfo`.
+pub const SPKI: u8 = 1;
+
+/// Matching type: the selected data itself.
+pub const FULL: u8 = 0;
+/// Matching type: the SHA-256 hash of the selected data.
+pub const SHA2_256: u8 = 1;
+/// Matching type: the SHA-512 hash of the selected data.
+pub const SHA2_512: u8 = 2;
+
+/// A TLSA resource record.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TlsaRecord {
+    usage: u8,
+    selector: u8,
+    matching_type: u8,
+    data: Vec<u8>,
+}
+
+impl TlsaRecord {
+    /// A record with the given fields, as returned by a resolver.
+    pub fn new(usage: u8, selector: u8, matching_type: u8, data: Vec<u8>) -> Self {
+        TlsaRecord {
+            usage,
+            selector,
+            matching_type,
+            data,
+        }
+    }
+
+    /// The certificate usage field.
+    pub fn usage(&self) -> u8 {
+        self.usage
+    }
+
+    /// The selector field.
+    pub fn selector(&self) -> u8 {
+        self.selector
+    }
+
+    /// The matching type field.
+    pub fn matching_type(&self) -> u8 {
+        self.matching_type
+    }
+
+    /// The certificate association data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the verifier understands this record.
+    fn usable(&self, pkix: bool) -> bool {
+        let usage = match self.usage {
+            PKIX_TA | PKIX_EE => pkix,
+            DANE_TA | DANE_EE => true,
+            _ => false,
+        };
+        usage && self.selector <= SPKI && self.matching_type <= SHA2_512
+    }
+
+    fn matches(&self, cert: &Certificate) -> bool {
+        let selected = match self.selector {
+            CERT => &cert.0[..],
+            SPKI => match der::tbs_certificate(&cert.0) {
+                Some(tbs) => tbs.spki,
+                None => return false,
+            },
+            _ => return false,
+        };
+        match self.matching_type {
+            FULL => selected == &self.data[..],
+            SHA2_256 => digest(&SHA256, selected).as_ref() == &self.data[..],
+            SHA2_512 => digest(&SHA512, selected).as_ref() == &self.data[..],
+            _ => false,
+        }
+    }
+}
+
+/// Parses the presentation format, e.g. `3 1 1 0c72ac70…`.
+///
+/// The association data may be split into several hex chunks.
+impl FromStr for TlsaRecord {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed TLSA record");
+        let mut fields = s.split_whitespace();
+        let mut field = || -> io::Result<u8> {
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let (usage, selector, matching_type) = (field()?, field()?, field()?);
+
+        let hex = fields.collect::<String>();
+        if hex.is_empty() || hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+            .collect::<io::Result<_>>()?;
+        Ok(TlsaRecord::new(usage, selector, matching_type, data))
+    }
+}
+
+impl fmt::Debug for TlsaRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TlsaRecord({} {} {} ",
+            self.usage, self.selector, self.matching_type
+        )?;
+        self.data.iter().try_for_each(|b| write!(f, "{:02x}", b))?;
+        write!(f, ")")
+    }
+}
+
+/// Verifies server certificates against TLSA records.
+///
+/// See the [module documentation](index.html) for the semantics of the records.
+pub struct DaneVerifier {
+    records: Vec<TlsaRecord>,
+    pkix: Option<WebPkiVerifier>,
+}
+
+impl DaneVerifier {
+    /// Verify servers with `records`, the TLSA records of the service connected to.
+    pub fn new(records: Vec<TlsaRecord>) -> Self {
+        DaneVerifier {
+            records,
+            pkix: None,
+        }
+    }
+
+    /// Validate against the WebPKI `roots` for PKIX records, and when no record is usable.
+    pub fn pkix(mut self, roots: RootCertStore) -> Self {
+        self.pkix = Some(WebPkiVerifier::new(roots, None));
+        self
+    }
+
+    /// Whether `record` authenticates the server.
+    fn verify_record(
+        &self,
+        record: &TlsaRecord,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        now: SystemTime,
+    ) -> bool {
+        let pkix = || match self.pkix {
+            Some(ref pkix) => pkix
+                .verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    server_name,
+                    &mut std::iter::empty(),
+                    &[],
+                    now,
+                )
+                .is_ok(),
+            None => false,
+        };
+        match record.usage {
+            DANE_EE => record.matches(end_entity),
+            PKIX_EE => record.matches(end_entity) && pkix(),
+            PKIX_TA => intermediates.iter().any(|cert| record.matches(cert)) && pkix(),
+            DANE_TA => intermediates
+                .iter()
+                .enumerate()
+                .filter(|(_, cert)| record.matches(cert))
+                .any(|(i, anchor)| {
+                    let mut roots = RootCertStore::empty();
+                    roots.add(anchor).is_ok()
+                        && WebPkiVerifier::new(roots, None)
+                            .verify_server_cert(
+                                end_entity,
+                                &intermediates[..i],
+                                server_name,
+                                &mut std::iter::empty(),
+                                &[],
+                                now,
+                            )
+                            .is_ok()
+                }),
+            _ => false,
+        }
+    }
+}
+
+impl ServerCertVerifier for DaneVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let usable = self
+            .records
+            .iter()
+            .filter(|record| record.usable(self.pkix.is_some()))
+            .collect::<Vec<_>>();
+        if usable.is_empty() {
+            return match self.pkix {
+                Some(ref pkix) => pkix.verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    server_name,
+                    scts,
+                    ocsp_response,
+                    now,
+                ),
+                None => Err(mismatch("no usable TLSA records")),
+            };
+        }
+
+        if usable
+            .iter()
+            .any(|record| self.verify_record(record, end_entity, intermediates, server_name, now))
+        {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(mismatch("no TLSA record matches the server's certificate"))
+        }
+    }
+}
+
+impl fmt::Debug for DaneVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DaneVerifier")
+            .field("records", &self.records)
+            .field("pkix", &self.pkix.is_some())
+            .finish()
+    }
+}
+
+/// A copy of `config` verifying with `verifier`.
+pub(crate) fn verifying(config: &ClientConfig, verifier: DaneVerifier) -> Arc<ClientConfig> {
+    let mut config = config.clone();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(verifier));
+    Arc::new(config)
+}
+
+fn mismatch(msg: &str) -> Error {
+    Error::InvalidCertificate(CertificateError::Other(Arc::new(io::Error::new(
+        io::ErrorKind::InvalidData,
+        msg,
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presentation_format() {
+        let record: TlsaRecord = "3 1 1 0C72AC70 b745".parse().unwrap();
+        assert_eq!(
+            record,
+            TlsaRecord::new(
+                DANE_EE,
+                SPKI,
+                SHA2_256,
+                vec![0x0c, 0x72, 0xac, 0x70, 0xb7, 0x45]
+            )
+        );
+        assert_eq!(format!("{:?}", record), "TlsaRecord(3 1 1 0c72ac70b745)");
+
+        for malformed in [
+            "",
+            "3 1 1",
+            "3 1 1 abc",
+            "3 1 x ab",
+            "256 1 1 ab",
+            "3 1 1 +a",
+        ] {
+            assert!(malformed.parse::<TlsaRecord>().is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn ignores_unknown_parameters() {
+        let record = |usage, selector, matching_type| {
+            TlsaRecord::new(usage, selector, matching_type, Vec::new())
+        };
+        assert!(record(DANE_EE, SPKI, SHA2_512).usable(false));
+        assert!(!record(PKIX_EE, SPKI, SHA2_256).usable(false));
+        assert!(record(PKIX_EE, SPKI, SHA2_256).usable(true));
+        assert!(!record(4, CERT, FULL).usable(true));
+        assert!(!record(DANE_EE, 2, FULL).usable(true));
+        assert!(!record(DANE_EE, CERT, 3).usable(true));
+    }
+}
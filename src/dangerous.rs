@@ -0,0 +1,125 @@
+//! Verifiers that skip or replace certificate checks, for `TlsConnector::danger_*`.
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, ClientConfig, Error, RootCertStore, ServerName};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Decides whether a certificate is valid for the name connected to.
+pub(crate) type HostnameCallback = dyn Fn(&ServerName, &Certificate) -> bool + Send + Sync;
+
+/// Accepts any certificate; handshake signatures are still checked.
+struct AcceptAny;
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Verifies the chain, leaving the name to a callback, or not checking it at all.
+struct Hostname {
+    inner: WebPkiVerifier,
+    callback: Option<Arc<HostnameCallback>>,
+}
+
+impl ServerCertVerifier for Hostname {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        // the name is only checked once the chain has been verified
+        match self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        ) {
+            Ok(_) | Err(Error::InvalidCertificate(CertificateError::NotValidForName)) => {}
+            Err(err) => return Err(err),
+        }
+        match self.callback {
+            Some(ref callback) if !callback(server_name, end_entity) => {
+                Err(Error::InvalidCertificate(CertificateError::NotValidForName))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// The state behind the `danger_*` toggles of a connector.
+#[derive(Clone, Default)]
+pub(crate) struct Danger {
+    /// The roots of the connector's config, if it built the config itself.
+    pub(crate) roots: Option<Arc<RootCertStore>>,
+    /// The config before any toggle was applied, and its roots if known.
+    verified: Option<(Arc<ClientConfig>, Option<Arc<RootCertStore>>)>,
+    pub(crate) invalid_certs: bool,
+    pub(crate) invalid_hostnames: bool,
+    pub(crate) hostname: Option<Arc<HostnameCallback>>,
+}
+
+impl Danger {
+    /// Make the same change to the config from before the toggles, if any were applied.
+    pub(crate) fn update(&mut self, f: impl Fn(&mut ClientConfig)) {
+        if let Some((ref mut verified, _)) = self.verified {
+            f(Arc::make_mut(verified));
+        }
+    }
+
+    /// The config to use instead of `config`, built anew, with the toggles as
+    /// they are now.
+    pub(crate) fn rebuilt(
+        &mut self,
+        config: Arc<ClientConfig>,
+        roots: &mut Option<Arc<RootCertStore>>,
+    ) -> Arc<ClientConfig> {
+        self.verified = None;
+        self.apply(&config, roots)
+    }
+
+    /// The config to use instead of `current` with the toggles as they are now.
+    ///
+    /// `roots` are those of `current`. They are cleared while a toggle replaces
+    /// the verifier, and restored once none does.
+    pub(crate) fn apply(
+        &mut self,
+        current: &Arc<ClientConfig>,
+        roots: &mut Option<Arc<RootCertStore>>,
+    ) -> Arc<ClientConfig> {
+        let (verified, verified_roots) = (self.verified)
+            .get_or_insert_with(|| (current.clone(), roots.clone()))
+            .clone();
+        let verifier: Arc<dyn ServerCertVerifier> = if self.invalid_certs {
+            Arc::new(AcceptAny)
+        } else if self.invalid_hostnames || self.hostname.is_some() {
+            let roots = self.roots.as_deref().cloned();
+            Arc::new(Hostname {
+                inner: WebPkiVerifier::new(roots.unwrap_or_else(RootCertStore::empty), None),
+                callback: self.hostname.clone().filter(|_| !self.invalid_hostnames),
+            })
+        } else {
+            *roots = verified_roots;
+            return verified;
+        };
+        *roots = None;
+        let mut config = ClientConfig::clone(&verified);
+        config.dangerous().set_certificate_verifier(verifier);
+        Arc::new(config)
+    }
+}
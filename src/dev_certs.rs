@@ -0,0 +1,153 @@
+//! Throwaway certificates for local development and tests.
+//!
+//! The first use generates a CA that lives for the rest of the process.
+//! `TlsAcceptor::self_signed` issues leaf certificates from it, and
+//! `TlsConnector::trust_self_signed` trusts it, so a client and server in the
+//! same process can talk TLS without any files. For a client in another
+//! process, write out `ca_pem` and add it to its roots.
+//!
+//! Never use these in production: the CA key is kept in memory only, and
+//! anything holding the CA certificate trusts every name it issues.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa};
+#[cfg(feature = "server")]
+use rustls::ServerConfig;
+use rustls::{Certificate, PrivateKey};
+#[cfg(feature = "client")]
+use rustls::{ClientConfig, RootCertStore};
+use std::io;
+use std::sync::OnceLock;
+
+/// A CA issuing leaf certificates, with its key in memory.
+pub(crate) struct Ca {
+    cert: rcgen::Certificate,
+    der: Vec<u8>,
+}
+
+impl Ca {
+    pub(crate) fn generate(name: &str) -> io::Result<Self> {
+        let mut params = CertificateParams::new(Vec::<String>::new());
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let cert = rcgen::Certificate::from_params(params).map_err(invalid)?;
+        let der = cert.serialize_der().map_err(invalid)?;
+        Ok(Ca { cert, der })
+    }
+
+    pub(crate) fn certificate(&self) -> Certificate {
+        Certificate(self.der.clone())
+    }
+
+    pub(crate) fn issue(&self, names: &[&str]) -> io::Result<(Vec<Certificate>, PrivateKey)> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let mut params = CertificateParams::new(names.clone());
+        if let Some(name) = names.first() {
+            params.distinguished_name.push(DnType::CommonName, name);
+        }
+        let leaf = rcgen::Certificate::from_params(params).map_err(invalid)?;
+        let der = leaf
+            .serialize_der_with_signer(&self.cert)
+            .map_err(invalid)?;
+
+        Ok((
+            vec![Certificate(der), self.certificate()],
+            PrivateKey(leaf.serialize_private_key_der()),
+        ))
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn server_config(&self, names: &[&str]) -> io::Result<ServerConfig> {
+        let (chain, key) = self.issue(names)?;
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .map_err(invalid)
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(&self.certificate()).map_err(invalid)?;
+        Ok(ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth())
+    }
+}
+
+fn ca() -> io::Result<&'static Ca> {
+    static CA: OnceLock<Ca> = OnceLock::new();
+
+    if let Some(ca) = CA.get() {
+        return Ok(ca);
+    }
+    let ca = Ca::generate("async-tls development CA")?;
+    // another thread may have won the race, all users must see the same CA
+    let _ = CA.set(ca);
+    Ok(CA.get().unwrap())
+}
+
+/// The development CA certificate.
+pub fn ca_certificate() -> io::Result<Certificate> {
+    Ok(ca()?.certificate())
+}
+
+/// The development CA certificate in PEM format.
+pub fn ca_pem() -> io::Result<String> {
+    let b64 = STANDARD.encode(&ca()?.der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in b64.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    Ok(pem)
+}
+
+/// Issue a certificate for `names` from the development CA.
+///
+/// Returns the chain, leaf first, and the PKCS#8 private key.
+pub fn issue(names: &[&str]) -> io::Result<(Vec<Certificate>, PrivateKey)> {
+    ca()?.issue(names)
+}
+
+#[cfg(feature = "server")]
+impl crate::TlsAcceptor {
+    /// An acceptor serving a fresh certificate for `names`, issued by the development CA.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use async_tls::{TlsAcceptor, TlsConnector};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let acceptor = TlsAcceptor::self_signed(&["localhost"])?;
+    /// let connector = TlsConnector::trust_self_signed()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn self_signed(names: &[&str]) -> io::Result<Self> {
+        Ok(ca()?.server_config(names)?.into())
+    }
+}
+
+#[cfg(feature = "client")]
+impl crate::TlsConnector {
+    /// A connector that trusts only the development CA.
+    ///
+    /// It accepts the certificates of any `TlsAcceptor::self_signed` acceptor in
+    /// this process.
+    pub fn trust_self_signed() -> io::Result<Self> {
+        Ok(ca()?.client_config()?.into())
+    }
+}
+
+fn invalid<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,373 @@
+#[cfg(feature = "server")]
+use crate::common::client_hello::ClientHelloSummary;
+#[cfg(feature = "server")]
+use crate::common::probe::{PlaintextHttp, RecordHeader};
+
+#[cfg(feature = "server")]
+use rustls::ProtocolVersion;
+use rustls::{AlertDescription, CertificateError, CommonState};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// The ways a TLS connection can fail.
+///
+/// Streams and futures of this crate fail with `io::Error`s, to fit the
+/// `AsyncRead` and `AsyncWrite` traits. Convert them with `Error::from` to
+/// tell the failures apart:
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "client")]
+/// # async_std::task::block_on(async {
+/// # let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await.unwrap();
+/// let connector = async_tls::TlsConnector::new();
+/// match connector.connect("example.com", tcp_stream).await {
+///     Ok(stream) => { /* ... */ }
+///     Err(err) => match async_tls::Error::from(err) {
+///         async_tls::Error::Certificate(err) => eprintln!("untrusted server: {:?}", err),
+///         err => eprintln!("connection failed: {}", err),
+///     },
+/// }
+/// # });
+/// ```
+///
+/// The `io::Error`s carry the `Error` itself, with a kind matching its
+/// variant, so `io::Error::get_ref` downcasts to it as well.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The name passed to `TlsConnector::connect` is neither a DNS name nor an IP address.
+    InvalidDnsName(String),
+    /// The TLS protocol failed, e.g. because no common cipher suite was found.
+    HandshakeFailed(rustls::Error),
+    /// The peer aborted the connection with an alert, e.g. `UnknownCA` when it
+    /// did not trust our certificate.
+    AlertReceived(AlertDescription),
+    /// The peer's certificate was rejected.
+    Certificate(CertificateError),
+    /// The peer closed the connection during the handshake.
+    PeerClosed,
+    /// The peer closed the connection without a `close_notify` alert, so the
+    /// data read may have been cut short by an attacker.
+    ///
+    /// Many peers do not bother sending the alert. Where the application
+    /// protocol detects truncation by itself, e.g. with a `Content-Length`,
+    /// disable `strict_close_notify` on the connector or acceptor to read
+    /// this as a normal end of the stream.
+    Truncated,
+    /// The server rejected the early data of a connection from
+    /// `TlsConnector::connect_0rtt`, which was not sent again, see
+    /// `TlsConnector::rejected_early_data`.
+    EarlyDataRejected,
+    /// The acceptor's admission callback turned the client away with this
+    /// alert, see `TlsAcceptor::admit`.
+    Rejected(AlertDescription),
+    /// The client sent a plaintext HTTP request instead of a `ClientHello`,
+    /// e.g. for `http://` rather than `https://`.
+    #[cfg(feature = "server")]
+    PlaintextHttp(PlaintextHttp),
+    /// The client sent bytes that are no TLS record, e.g. a scanner or another
+    /// protocol, starting with this header.
+    #[cfg(feature = "server")]
+    NotTls(RecordHeader),
+    /// The client sent an SSLv2 `ClientHello`, whose header this is.
+    #[cfg(feature = "server")]
+    Sslv2Hello(RecordHeader),
+    /// The client offered none of the versions enabled, e.g. SSLv3 or TLS 1.0.
+    /// The version is the `legacy_version` of its `ClientHello`, which is
+    /// TLS 1.2 for clients offering later versions; or the version negotiated,
+    /// which the bounds of the `TlsAcceptor` did not allow.
+    #[cfg(feature = "server")]
+    UnsupportedVersion(RecordHeader, ProtocolVersion),
+    /// The underlying stream failed, or another check, e.g. OCSP, failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidDnsName(name) => write!(f, "invalid domain: {}", name),
+            Error::HandshakeFailed(err) => err.fmt(f),
+            Error::AlertReceived(alert) => write!(f, "received fatal alert: {:?}", alert),
+            Error::Certificate(err) => rustls::Error::InvalidCertificate(err.clone()).fmt(f),
+            Error::PeerClosed => write!(f, "peer closed the connection"),
+            Error::Truncated => write!(f, "peer closed the connection without close_notify"),
+            Error::EarlyDataRejected => write!(f, "server rejected the early data"),
+            Error::Rejected(alert) => write!(f, "rejected the client with alert: {:?}", alert),
+            #[cfg(feature = "server")]
+            Error::PlaintextHttp(request) => {
+                write!(f, "received a plaintext HTTP {} request", request.method)
+            }
+            #[cfg(feature = "server")]
+            Error::NotTls(header) => write!(f, "received no TLS record, but: {}", header),
+            #[cfg(feature = "server")]
+            Error::Sslv2Hello(header) => write!(f, "received an SSLv2 ClientHello: {}", header),
+            #[cfg(feature = "server")]
+            Error::UnsupportedVersion(header, version) => write!(
+                f,
+                "client offered no enabled version, asked for {:?}: {}",
+                version, header
+            ),
+            Error::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::HandshakeFailed(err) => Some(err),
+            Error::Certificate(CertificateError::Other(err)) => Some(&**err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Self {
+        match err {
+            rustls::Error::InvalidCertificate(err) => Error::Certificate(err),
+            rustls::Error::AlertReceived(alert) => Error::AlertReceived(alert),
+            err => Error::HandshakeFailed(err),
+        }
+    }
+}
+
+/// Recovers the `Error` an `io::Error` of this crate carries, also from a `HandshakeError`.
+///
+/// Other `io::Error`s become `Io`, or `PeerClosed` if of kind `UnexpectedEof`.
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<HandshakeError>())
+        {
+            let inner = err.into_inner().unwrap();
+            return inner.downcast::<HandshakeError>().unwrap().error;
+        }
+        if let Some(tls) = err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        {
+            return Error::from(tls.clone());
+        }
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::PeerClosed,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::InvalidDnsName(_) => io::ErrorKind::InvalidInput,
+            Error::HandshakeFailed(_) | Error::AlertReceived(_) | Error::Certificate(_) => {
+                io::ErrorKind::InvalidData
+            }
+            Error::PeerClosed | Error::Truncated => io::ErrorKind::UnexpectedEof,
+            Error::EarlyDataRejected => io::ErrorKind::Other,
+            Error::Rejected(_) => io::ErrorKind::PermissionDenied,
+            #[cfg(feature = "server")]
+            Error::PlaintextHttp(_) => io::ErrorKind::InvalidData,
+            #[cfg(feature = "server")]
+            Error::NotTls(_) | Error::Sslv2Hello(_) | Error::UnsupportedVersion(..) => {
+                io::ErrorKind::InvalidData
+            }
+            Error::Io(err) => return err,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// The `Error` an `io::Error` of this crate carries, if any.
+#[cfg(any(feature = "crl", feature = "server"))]
+pub(crate) fn error_ref(err: &io::Error) -> Option<&Error> {
+    let inner = err.get_ref()?;
+    match inner.downcast_ref::<HandshakeError>() {
+        Some(handshake) => Some(&handshake.error),
+        None => inner.downcast_ref(),
+    }
+}
+
+/// How far a failed handshake got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HandshakeStage {
+    /// No version and cipher suite were agreed on: the peer closed the
+    /// connection right away, does not speak TLS, or rejected our hello.
+    Hello,
+    /// The version and cipher suite were agreed on, the peer's certificate was
+    /// not verified yet.
+    Negotiated,
+    /// The peer's certificate was rejected: by the verifier of the config, or
+    /// by a check after the handshake, e.g. CT or TOFU.
+    Verifying,
+    /// The peer's certificate was verified, the handshake was not finished.
+    /// Servers only get here with client authentication.
+    Authenticated,
+}
+
+impl HandshakeStage {
+    pub(crate) fn of(conn: &CommonState) -> Self {
+        if conn.peer_certificates().is_some() {
+            HandshakeStage::Authenticated
+        } else if conn.negotiated_cipher_suite().is_some() {
+            HandshakeStage::Negotiated
+        } else {
+            HandshakeStage::Hello
+        }
+    }
+}
+
+impl fmt::Display for HandshakeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HandshakeStage::Hello => "before negotiating a cipher suite",
+            HandshakeStage::Negotiated => "after negotiating a cipher suite",
+            HandshakeStage::Verifying => "while verifying the peer's certificate",
+            HandshakeStage::Authenticated => "after verifying the peer's certificate",
+        })
+    }
+}
+
+/// The context of a failed handshake.
+///
+/// The `io::Error`s `Connect` and `Accept` fail with carry one, with the kind
+/// of the underlying error. Downcast `io::Error::get_ref` to get at it;
+/// `Error::from` unwraps it to the underlying error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct HandshakeError {
+    /// The name connected to, for clients, or the name the client asked for
+    /// (SNI), for servers.
+    pub server_name: Option<String>,
+    /// How far the handshake got.
+    pub stage: HandshakeStage,
+    /// Why the handshake failed.
+    pub error: Error,
+    /// What the client offered, for servers once its `ClientHello` was received.
+    #[cfg(feature = "server")]
+    pub client_hello: Option<ClientHelloSummary>,
+}
+
+impl HandshakeError {
+    /// `err`, with the context of the handshake on `conn`.
+    pub(crate) fn wrap(err: io::Error, server_name: Option<&str>, conn: &CommonState) -> io::Error {
+        HandshakeError::wrap_at(err, server_name, HandshakeStage::of(conn))
+    }
+
+    pub(crate) fn wrap_at(
+        err: io::Error,
+        server_name: Option<&str>,
+        stage: HandshakeStage,
+    ) -> io::Error {
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<HandshakeError>())
+        {
+            return err;
+        }
+        let kind = err.kind();
+        let error = Error::from(err);
+        let stage = match (stage, &error) {
+            (HandshakeStage::Negotiated, Error::Certificate(_)) => HandshakeStage::Verifying,
+            (stage, _) => stage,
+        };
+        let err = HandshakeError {
+            server_name: server_name.map(str::to_string),
+            stage,
+            error,
+            #[cfg(feature = "server")]
+            client_hello: None,
+        };
+        io::Error::new(kind, err)
+    }
+
+    /// `err`, with what the client offered if it carries a `HandshakeError`.
+    #[cfg(feature = "server")]
+    pub(crate) fn with_client_hello(
+        mut err: io::Error,
+        client_hello: Option<ClientHelloSummary>,
+    ) -> io::Error {
+        let context = (err.get_mut()).and_then(|inner| inner.downcast_mut::<HandshakeError>());
+        if let Some(context) = context {
+            context.client_hello = context.client_hello.take().or(client_hello);
+        }
+        err
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tls handshake")?;
+        if let Some(ref name) = self.server_name {
+            write!(f, " with {}", name)?;
+        }
+        write!(f, " failed {}: {}", self.stage, self.error)?;
+        #[cfg(feature = "server")]
+        if let Some(ref client_hello) = self.client_hello {
+            write!(f, " (client offered {})", client_hello)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for HandshakeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_io_errors() {
+        let err = io::Error::from(Error::from(rustls::Error::InvalidCertificate(
+            CertificateError::Expired,
+        )));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.get_ref().unwrap().is::<Error>());
+        match Error::from(err) {
+            Error::Certificate(CertificateError::Expired) => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let err = io::Error::from(Error::InvalidDnsName("a..b".to_string()));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(matches!(Error::from(err), Error::InvalidDnsName(name) if name == "a..b"));
+    }
+
+    #[test]
+    fn classifies_other_io_errors() {
+        let tls = io::Error::new(io::ErrorKind::InvalidData, rustls::Error::DecryptError);
+        assert!(matches!(
+            Error::from(tls),
+            Error::HandshakeFailed(rustls::Error::DecryptError)
+        ));
+
+        let alert = rustls::Error::AlertReceived(AlertDescription::UnknownCA);
+        let alert = io::Error::new(io::ErrorKind::InvalidData, alert);
+        assert!(matches!(
+            Error::from(alert),
+            Error::AlertReceived(AlertDescription::UnknownCA)
+        ));
+
+        let eof = io::Error::from(io::ErrorKind::UnexpectedEof);
+        assert!(matches!(Error::from(eof), Error::PeerClosed));
+
+        let err = Error::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        match err {
+            Error::Io(ref io) => assert_eq!(io.kind(), io::ErrorKind::ConnectionReset),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::ConnectionReset);
+    }
+}
@@ -0,0 +1,244 @@
+//! Warnings about served certificates that are about to expire.
+
+use crate::common::der;
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::Certificate;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+type ExpiryCallback = dyn Fn(&ExpiryWarning) + Send + Sync;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Warns before the certificates an acceptor serves expire, so the process
+/// serving them notices, rather than its clients.
+///
+/// Install it with `TlsAcceptor::expiry_monitor`. The monitor learns about
+/// certificates as they are served, and forgets them once they are replaced,
+/// e.g. by a `PollingReloader` or in a `CertStore`. The callback is called
+/// once for each threshold a certificate gets closer to expiring than, 30, 7
+/// and 1 days by default, and once more when it expired.
+///
+/// Certificates are checked when a new one is served, and at most every
+/// `interval` on later handshakes: no task is spawned. Call `check`
+/// periodically, or spawn `run`, to be warned while no clients connect.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::{ExpiryMonitor, TlsAcceptor};
+/// use std::sync::Arc;
+/// # fn config() -> rustls::ServerConfig { todo!() }
+///
+/// let monitor = Arc::new(ExpiryMonitor::new(|warning| {
+///     eprintln!(
+///         "certificate for {:?} expires in {:?}",
+///         warning.server_name, warning.remaining
+///     );
+/// }));
+/// let acceptor = TlsAcceptor::from(config()).expiry_monitor(monitor.clone());
+/// ```
+pub struct ExpiryMonitor {
+    /// Sorted from the longest to the shortest.
+    thresholds: Vec<Duration>,
+    interval: Duration,
+    callback: Box<ExpiryCallback>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The certificates served, by the address of their `CertifiedKey`.
+    served: HashMap<usize, Served>,
+    /// How many warnings were given for each end-entity certificate.
+    warned: HashMap<Vec<u8>, usize>,
+    checked: Option<Instant>,
+}
+
+struct Served {
+    key: Weak<CertifiedKey>,
+    server_name: Option<String>,
+}
+
+/// A served certificate passed an `ExpiryMonitor` threshold, or expired.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ExpiryWarning {
+    /// The end-entity certificate.
+    pub certificate: Certificate,
+    /// The name a client asked for (SNI) when the certificate was first
+    /// served, if any.
+    pub server_name: Option<String>,
+    /// When the certificate expires.
+    pub not_after: SystemTime,
+    /// How long until it expires, zero once it did.
+    pub remaining: Duration,
+    /// The threshold passed, `None` once the certificate expired.
+    pub threshold: Option<Duration>,
+}
+
+impl ExpiryWarning {
+    /// Whether the certificate expired already.
+    pub fn is_expired(&self) -> bool {
+        self.threshold.is_none()
+    }
+}
+
+impl ExpiryMonitor {
+    /// A monitor calling `callback` with each warning.
+    ///
+    /// Certificates are checked at most every hour by default.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&ExpiryWarning) + Send + Sync + 'static,
+    {
+        ExpiryMonitor {
+            thresholds: vec![30 * DAY, 7 * DAY, DAY],
+            interval: Duration::from_secs(60 * 60),
+            callback: Box::new(callback),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Set how long before a certificate expires warnings are given.
+    pub fn thresholds(mut self, thresholds: &[Duration]) -> Self {
+        self.thresholds = thresholds.to_vec();
+        self.thresholds.sort_unstable_by(|a, b| b.cmp(a));
+        self.thresholds.dedup();
+        self
+    }
+
+    /// Set how often certificates are checked on handshakes.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Check the certificates served so far right away.
+    pub fn check(&self) {
+        let warnings = self.check_locked(&mut self.state.lock().unwrap(), SystemTime::now());
+        for warning in &warnings {
+            (self.callback)(warning);
+        }
+    }
+
+    /// Check the certificates every `interval`, forever.
+    #[cfg(feature = "async-std")]
+    pub async fn run(&self) {
+        loop {
+            self.check();
+            async_std::task::sleep(self.interval).await;
+        }
+    }
+
+    /// `key` was served to a client asking for `server_name`.
+    fn served(&self, key: &Arc<CertifiedKey>, server_name: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        let addr = Arc::as_ptr(key) as usize;
+        let known = (state.served.get(&addr))
+            .is_some_and(|served| served.key.upgrade().is_some_and(|k| Arc::ptr_eq(&k, key)));
+        if !known {
+            let server_name = server_name.map(str::to_string);
+            let key = Arc::downgrade(key);
+            state.served.insert(addr, Served { key, server_name });
+            state.checked = None;
+        }
+
+        if state
+            .checked
+            .is_some_and(|checked| checked.elapsed() < self.interval)
+        {
+            return;
+        }
+        let warnings = self.check_locked(&mut state, SystemTime::now());
+        drop(state);
+        for warning in &warnings {
+            (self.callback)(warning);
+        }
+    }
+
+    fn check_locked(&self, state: &mut State, now: SystemTime) -> Vec<ExpiryWarning> {
+        state.checked = Some(Instant::now());
+        state
+            .served
+            .retain(|_, served| served.key.strong_count() > 0);
+
+        // the same certificate may be served from several keys, e.g. with
+        // different OCSP responses
+        let mut live = HashMap::new();
+        for served in state.served.values() {
+            let key = match served.key.upgrade() {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Some(cert) = key.cert.first() {
+                live.entry(cert.0.clone())
+                    .or_insert_with(|| (cert.clone(), served.server_name.clone()));
+            }
+        }
+        state.warned.retain(|der, _| live.contains_key(der));
+
+        let mut warnings = Vec::new();
+        for (der, (certificate, server_name)) in live {
+            let not_after = match der::validity(&der) {
+                Some((_, not_after)) => not_after,
+                None => continue,
+            };
+            let remaining = not_after.duration_since(now).ok();
+            let passed = match remaining {
+                Some(remaining) => (self.thresholds.iter())
+                    .filter(|threshold| remaining <= **threshold)
+                    .count(),
+                None => self.thresholds.len() + 1,
+            };
+
+            let warned = state.warned.entry(der).or_insert(0);
+            if passed > *warned {
+                *warned = passed;
+                warnings.push(ExpiryWarning {
+                    certificate,
+                    server_name,
+                    not_after,
+                    remaining: remaining.unwrap_or_default(),
+                    threshold: self.thresholds.get(passed - 1).copied(),
+                });
+            }
+        }
+        warnings
+    }
+}
+
+impl fmt::Debug for ExpiryMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpiryMonitor")
+            .field("thresholds", &self.thresholds)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Resolves like `inner`, telling `monitor` about the certificates served.
+pub(crate) fn watching(
+    inner: Arc<dyn ResolvesServerCert>,
+    monitor: Arc<ExpiryMonitor>,
+) -> Arc<dyn ResolvesServerCert> {
+    Arc::new(Watching { inner, monitor })
+}
+
+struct Watching {
+    inner: Arc<dyn ResolvesServerCert>,
+    monitor: Arc<ExpiryMonitor>,
+}
+
+impl ResolvesServerCert for Watching {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name().map(str::to_string);
+        let key = self.inner.resolve(client_hello)?;
+        self.monitor.served(&key, server_name.as_deref());
+        Some(key)
+    }
+}
@@ -0,0 +1,354 @@
+//! JA3 and JA4 fingerprints of the `ClientHello`, to classify clients.
+
+use crate::common::client_hello::{self, is_grease, Reader, ALPN, SERVER_NAME, SUPPORTED_VERSIONS};
+
+use md5::{Digest, Md5};
+use ring::digest::{digest, SHA256};
+use rustls::ProtocolVersion;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+
+const SUPPORTED_GROUPS: u16 = 10;
+const EC_POINT_FORMATS: u16 = 11;
+const SIGNATURE_ALGORITHMS: u16 = 13;
+
+/// The most bytes kept until the `ClientHello` was received.
+const MAX_CAPTURE: usize = 64 * 1024;
+
+/// What a client offered in its `ClientHello`, and its JA3 and JA4 fingerprints.
+///
+/// Clients built on the same TLS library and settings send the same offers,
+/// whatever they claim to be, which helps to tell browsers from bots. The lists
+/// are in the order the client sent them, including GREASE values (RFC 8701),
+/// which the fingerprints leave out.
+///
+/// Available from `server::TlsStream::client_hello_fingerprint` and
+/// `StartHandshake::fingerprint`, see `TlsAcceptor::fingerprint_clients`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClientHelloFingerprint {
+    /// The `legacy_version` of the `ClientHello`, `0x0303` for TLS 1.2 and 1.3.
+    pub version: u16,
+    /// The cipher suites.
+    pub cipher_suites: Vec<u16>,
+    /// The types of the extensions.
+    pub extensions: Vec<u16>,
+    /// The `supported_groups` extension, also known as elliptic curves.
+    pub supported_groups: Vec<u16>,
+    /// The `ec_point_formats` extension.
+    pub ec_point_formats: Vec<u8>,
+    /// The `signature_algorithms` extension.
+    pub signature_algorithms: Vec<u16>,
+    /// The `supported_versions` extension.
+    pub supported_versions: Vec<u16>,
+    /// The `application_layer_protocol_negotiation` extension.
+    pub alpn_protocols: Vec<Vec<u8>>,
+}
+
+impl ClientHelloFingerprint {
+    /// The JA3 string: the version, cipher suites, extensions, groups and point
+    /// formats, in decimal.
+    pub fn ja3(&self) -> String {
+        fn join<T: Copy + Into<u16>>(values: &[T]) -> String {
+            let values = values.iter().map(|&value| value.into());
+            let values = values.filter(|&value| !is_grease(value));
+            values
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join("-")
+        }
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join(&self.cipher_suites),
+            join(&self.extensions),
+            join(&self.supported_groups),
+            join(&self.ec_point_formats),
+        )
+    }
+
+    /// The JA3 fingerprint: the MD5 hash of `ja3`, in hex.
+    pub fn ja3_hash(&self) -> String {
+        hex(&Md5::digest(self.ja3().as_bytes()))
+    }
+
+    /// The highest TLS version the client offered, from `supported_versions`
+    /// or else the `legacy_version`.
+    pub fn max_version(&self) -> ProtocolVersion {
+        ProtocolVersion::from(self.highest_version())
+    }
+
+    fn highest_version(&self) -> u16 {
+        without_grease(&self.supported_versions)
+            .into_iter()
+            .max()
+            .unwrap_or(self.version)
+    }
+
+    /// The JA4 fingerprint, e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`, of a
+    /// connection over TCP.
+    pub fn ja4(&self) -> String {
+        let ciphers = without_grease(&self.cipher_suites);
+        let extensions = without_grease(&self.extensions);
+
+        let version = match self.highest_version() {
+            0x0304 => "13",
+            0x0303 => "12",
+            0x0302 => "11",
+            0x0301 => "10",
+            0x0300 => "s3",
+            _ => "00",
+        };
+        let sni = match extensions.contains(&SERVER_NAME) {
+            true => 'd',
+            false => 'i',
+        };
+        let alpn = match self.alpn_protocols.first() {
+            Some(protocol) if !protocol.is_empty() => {
+                let (first, last) = (protocol[0], protocol[protocol.len() - 1]);
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                    format!("{}{}", first as char, last as char)
+                } else {
+                    let (first, last) = (hex(&[first]), hex(&[last]));
+                    format!("{}{}", &first[..1], &last[1..])
+                }
+            }
+            _ => "00".to_string(),
+        };
+        let a = format!(
+            "t{}{}{:02}{:02}{}",
+            version,
+            sni,
+            ciphers.len().min(99),
+            extensions.len().min(99),
+            alpn
+        );
+
+        let mut sorted = ciphers;
+        sorted.sort_unstable();
+        let b = truncated_hash(&hex_list(&sorted), sorted.is_empty());
+
+        let mut sorted = extensions;
+        sorted.retain(|&extension| extension != SERVER_NAME && extension != ALPN);
+        sorted.sort_unstable();
+        let mut c = hex_list(&sorted);
+        if !self.signature_algorithms.is_empty() {
+            c.push('_');
+            c.push_str(&hex_list(&self.signature_algorithms));
+        }
+        let c = truncated_hash(&c, sorted.is_empty());
+
+        format!("{}_{}_{}", a, b, c)
+    }
+
+    /// Parse the records of a `ClientHello`.
+    pub(crate) fn parse(records: &[u8]) -> Option<Self> {
+        Self::parse_body(&client_hello::message(records)?)
+    }
+
+    fn parse_body(body: &[u8]) -> Option<Self> {
+        let mut reader = Reader(body);
+        let version = reader.u16()?;
+        reader.take(32)?;
+        reader.u8_prefixed()?;
+        let cipher_suites = Reader(reader.u16_prefixed()?).u16s()?;
+        reader.u8_prefixed()?;
+
+        let mut fingerprint = ClientHelloFingerprint {
+            version,
+            cipher_suites,
+            extensions: Vec::new(),
+            supported_groups: Vec::new(),
+            ec_point_formats: Vec::new(),
+            signature_algorithms: Vec::new(),
+            supported_versions: Vec::new(),
+            alpn_protocols: Vec::new(),
+        };
+        let mut extensions = Reader(reader.u16_prefixed().unwrap_or_default());
+        while !extensions.0.is_empty() {
+            let kind = extensions.u16()?;
+            let mut data = Reader(extensions.u16_prefixed()?);
+            fingerprint.extensions.push(kind);
+            match kind {
+                SUPPORTED_GROUPS => {
+                    fingerprint.supported_groups = Reader(data.u16_prefixed()?).u16s()?;
+                }
+                EC_POINT_FORMATS => fingerprint.ec_point_formats = data.u8_prefixed()?.to_vec(),
+                SIGNATURE_ALGORITHMS => {
+                    fingerprint.signature_algorithms = Reader(data.u16_prefixed()?).u16s()?;
+                }
+                SUPPORTED_VERSIONS => {
+                    fingerprint.supported_versions = Reader(data.u8_prefixed()?).u16s()?;
+                }
+                ALPN => {
+                    let mut protocols = Reader(data.u16_prefixed()?);
+                    while !protocols.0.is_empty() {
+                        let protocol = protocols.u8_prefixed()?;
+                        fingerprint.alpn_protocols.push(protocol.to_vec());
+                    }
+                }
+                _ => (),
+            }
+        }
+        Some(fingerprint)
+    }
+}
+
+/// Keeps the bytes read until the `ClientHello` was received.
+#[derive(Debug, Default)]
+pub(crate) struct ClientHelloCapture {
+    records: Vec<u8>,
+}
+
+impl ClientHelloCapture {
+    /// Keep the bytes read from `inner`.
+    pub(crate) fn reader<R: Read>(&mut self, inner: R) -> Capture<'_, R> {
+        Capture {
+            inner,
+            records: &mut self.records,
+        }
+    }
+
+    /// The fingerprint of the `ClientHello` read, if it could be parsed.
+    pub(crate) fn finish(self) -> Option<ClientHelloFingerprint> {
+        ClientHelloFingerprint::parse(&self.records)
+    }
+}
+
+pub(crate) struct Capture<'a, R> {
+    inner: R,
+    records: &'a mut Vec<u8>,
+}
+
+impl<R: Read> Read for Capture<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let room = MAX_CAPTURE.saturating_sub(self.records.len());
+        self.records.extend_from_slice(&buf[..n.min(room)]);
+        Ok(n)
+    }
+}
+
+fn without_grease(values: &[u16]) -> Vec<u16> {
+    let values = values.iter().copied();
+    values.filter(|&value| !is_grease(value)).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+fn hex_list(values: &[u16]) -> String {
+    let values = values.iter().map(|value| format!("{:04x}", value));
+    values.collect::<Vec<_>>().join(",")
+}
+
+fn truncated_hash(list: &str, empty: bool) -> String {
+    match empty {
+        true => "000000000000".to_string(),
+        false => hex(&digest(&SHA256, list.as_bytes()).as_ref()[..6]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::client_hello::{CLIENT_HELLO, HANDSHAKE};
+
+    /// The `ClientHello` of the JA4 reference pcap of Chrome, shortened.
+    fn fingerprint() -> ClientHelloFingerprint {
+        ClientHelloFingerprint {
+            version: 0x0303,
+            cipher_suites: vec![0x0a0a, 0x1301, 0x1302, 0x1303, 0xc02b],
+            extensions: vec![0x1a1a, 0, 23, 65281, 10, 11, 35, 16, 5, 13, 18, 51, 45, 43],
+            supported_groups: vec![0x2a2a, 29, 23, 24],
+            ec_point_formats: vec![0],
+            signature_algorithms: vec![0x0403, 0x0804, 0x0401],
+            supported_versions: vec![0x3a3a, 0x0304, 0x0303],
+            alpn_protocols: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+
+    #[test]
+    fn computes_fingerprints() {
+        let fingerprint = fingerprint();
+        assert_eq!(
+            fingerprint.ja3(),
+            "771,4865-4866-4867-49195,0-23-65281-10-11-35-16-5-13-18-51-45-43,29-23-24,0"
+        );
+        assert_eq!(fingerprint.ja3_hash().len(), 32);
+
+        let ja4 = fingerprint.ja4();
+        assert!(ja4.starts_with("t13d0413h2_"), "{}", ja4);
+        let b = hex(&digest(&SHA256, b"1301,1302,1303,c02b").as_ref()[..6]);
+        let c = "0005,000a,000b,000d,0012,0017,0023,002b,002d,0033,ff01_0403,0804,0401";
+        let c = hex(&digest(&SHA256, c.as_bytes()).as_ref()[..6]);
+        assert_eq!(ja4, format!("t13d0413h2_{}_{}", b, c));
+
+        assert_eq!(fingerprint.max_version(), ProtocolVersion::TLSv1_3);
+        let legacy = ClientHelloFingerprint {
+            supported_versions: vec![],
+            ..fingerprint
+        };
+        assert_eq!(legacy.max_version(), ProtocolVersion::TLSv1_2);
+    }
+
+    #[test]
+    fn parses_client_hellos() {
+        let expected = fingerprint();
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[7; 32]);
+        body.push(0);
+        body.extend_from_slice(&10u16.to_be_bytes());
+        for suite in &expected.cipher_suites {
+            body.extend_from_slice(&suite.to_be_bytes());
+        }
+        body.extend_from_slice(&[1, 0]);
+
+        let list = |values: &[u16]| {
+            let mut list = ((values.len() * 2) as u16).to_be_bytes().to_vec();
+            values
+                .iter()
+                .for_each(|v| list.extend_from_slice(&v.to_be_bytes()));
+            list
+        };
+        let mut extensions = Vec::new();
+        for &kind in &expected.extensions {
+            let data = match kind {
+                SUPPORTED_GROUPS => list(&expected.supported_groups),
+                EC_POINT_FORMATS => vec![1, 0],
+                SIGNATURE_ALGORITHMS => list(&expected.signature_algorithms),
+                SUPPORTED_VERSIONS => list(&expected.supported_versions)[1..].to_vec(),
+                ALPN => b"\x00\x0c\x02h2\x08http/1.1".to_vec(),
+                _ => Vec::new(),
+            };
+            extensions.extend_from_slice(&kind.to_be_bytes());
+            extensions.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(&data);
+        }
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut message = vec![CLIENT_HELLO];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(&body);
+        // split across two records
+        let (first, second) = message.split_at(20);
+        let mut records = Vec::new();
+        for fragment in [first, second] {
+            records.extend_from_slice(&[HANDSHAKE, 3, 1]);
+            records.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            records.extend_from_slice(fragment);
+        }
+
+        let mut capture = ClientHelloCapture::default();
+        let mut reader = capture.reader(&records[..]);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(capture.finish(), Some(expected));
+        assert_eq!(ClientHelloFingerprint::parse(&records[..40]), None);
+    }
+}
@@ -0,0 +1,88 @@
+//! Sending requests with async-h1, for http-client.
+
+use crate::tcp;
+use crate::TlsConnector;
+
+use async_std::future;
+use http_client::http_types::{Error, Request, Response, StatusCode};
+use http_client::{async_trait, Config, HttpClient};
+use std::io;
+
+/// An `HttpClient` for `https` and `http` URLs, securing the former with a
+/// `TlsConnector`.
+///
+/// Each request opens a connection of its own, so the `http_keep_alive` and
+/// `max_connections_per_host` options of the `Config` have no effect; session
+/// resumption keeps the handshakes short. The `timeout` bounds connecting,
+/// sending the request and receiving the head of the response.
+#[derive(Debug, Clone)]
+pub struct H1Client {
+    connector: TlsConnector,
+    config: Config,
+}
+
+impl H1Client {
+    /// Send requests with `connector`.
+    pub fn new(connector: TlsConnector) -> Self {
+        H1Client {
+            connector,
+            config: Config::new(),
+        }
+    }
+
+    async fn connect(&self, req: Request) -> Result<Response, Error> {
+        let url = req.url();
+        let host = url.host_str().ok_or_else(|| {
+            Error::from_str(StatusCode::BadRequest, "the URL has no host to connect to")
+        })?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let https = match url.scheme() {
+            "https" => true,
+            "http" => false,
+            scheme => {
+                return Err(Error::from_str(
+                    StatusCode::BadRequest,
+                    format!("unsupported URL scheme {:?}", scheme),
+                ))
+            }
+        };
+
+        let host = host.to_string();
+        let stream = tcp::connect(&host, port).await?;
+        stream.set_nodelay(self.config.tcp_no_delay)?;
+        if https {
+            let stream = self.connector.connect(&host, stream).await?;
+            async_h1::connect(stream, req).await
+        } else {
+            async_h1::connect(stream, req).await
+        }
+    }
+}
+
+impl From<TlsConnector> for H1Client {
+    fn from(connector: TlsConnector) -> Self {
+        H1Client::new(connector)
+    }
+}
+
+#[async_trait]
+impl HttpClient for H1Client {
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        match self.config.timeout {
+            Some(timeout) => match future::timeout(timeout, self.connect(req)).await {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out").into()),
+            },
+            None => self.connect(req).await,
+        }
+    }
+
+    fn set_config(&mut self, config: Config) -> http_client::http_types::Result<()> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+}
@@ -0,0 +1,57 @@
+//! HTTP/1.1 over async-tls with async-h1, for clients and servers on async-std.
+//!
+//! On the client, `H1Client` is an `http_client::HttpClient` sending requests
+//! with async-h1 through a `TlsConnector`, so surf and other users of
+//! http-client can do without native-tls.
+//!
+//! On the server, `serve` accepts connections from a listener, does the
+//! handshake with a `TlsAcceptor` and answers the requests with an endpoint
+//! function, while `accept` does the same for a single connection.
+//!
+//! ## Examples
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "http-client")]
+//! # async_std::task::block_on(async {
+//! use async_tls::h1::H1Client;
+//! use async_tls::TlsConnector;
+//! use http_client::http_types::{Method, Request};
+//! use http_client::HttpClient;
+//!
+//! let client = H1Client::new(TlsConnector::new());
+//! let request = Request::new(Method::Get, "https://example.com/");
+//! let mut response = client.send(request).await?;
+//! println!("{}", response.body_string().await?);
+//! # Ok(()) as http_client::http_types::Result<()>
+//! # });
+//! ```
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "h1-server")]
+//! # async_std::task::block_on(async {
+//! use async_std::net::TcpListener;
+//! use async_tls::{h1, TlsAcceptor};
+//! use http_types::{Request, Response, StatusCode};
+//!
+//! # let config: rustls::ServerConfig = unimplemented!();
+//! let acceptor = TlsAcceptor::from(config);
+//! let listener = TcpListener::bind("0.0.0.0:8443").await?;
+//! h1::serve(listener, acceptor, |request: Request| async move {
+//!     let mut response = Response::new(StatusCode::Ok);
+//!     response.set_body(format!("hello {}", request.url().path()));
+//!     Ok(response)
+//! })
+//! .await?;
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+
+#[cfg(feature = "http-client")]
+mod client;
+#[cfg(feature = "h1-server")]
+mod server;
+
+#[cfg(feature = "http-client")]
+pub use client::H1Client;
+#[cfg(feature = "h1-server")]
+pub use server::{accept, serve};
@@ -0,0 +1,92 @@
+//! Answering requests with async-h1 on accepted TLS streams.
+
+use crate::server::TlsStream;
+use crate::TlsAcceptor;
+
+use async_std::net::TcpListener;
+use async_std::task;
+use futures_io::{AsyncRead, AsyncWrite};
+use http_types::{Request, Response};
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Accept connections from `listener` and answer their requests with
+/// `endpoint`, each connection on a task of its own.
+///
+/// Connections whose handshake or requests fail are closed without affecting
+/// the others. Returns only when accepting from the listener fails.
+pub async fn serve<F, Fut>(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    endpoint: F,
+) -> io::Result<()>
+where
+    F: Fn(Request) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = http_types::Result<Response>> + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let endpoint = endpoint.clone();
+        task::spawn(async move {
+            if let Ok(stream) = acceptor.accept(stream).await {
+                let _ = accept(stream, endpoint).await;
+            }
+        });
+    }
+}
+
+/// Answer the requests of a client on `stream` with `endpoint`, until either
+/// side ends the connection, and close it.
+pub async fn accept<IO, F, Fut>(stream: TlsStream<IO>, endpoint: F) -> http_types::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = http_types::Result<Response>>,
+{
+    let mut stream = Shared(Arc::new(Mutex::new(stream)));
+    async_h1::accept(stream.clone(), endpoint).await?;
+    poll_fn(|cx| Pin::new(&mut stream).poll_close(cx)).await?;
+    Ok(())
+}
+
+/// A TLS stream async-h1 can clone, to read the body of a request while
+/// writing the response.
+struct Shared<IO>(Arc<Mutex<TlsStream<IO>>>);
+
+impl<IO> Clone for Shared<IO> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> AsyncRead for Shared<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Shared<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_close(cx)
+    }
+}
@@ -0,0 +1,180 @@
+//! Using async-tls as the TLS layer of hyper 1.0 clients and servers.
+//!
+//! `HttpsConnector` is a connector for hyper-util's `Client`: it resolves the
+//! host of each `https` URI, opens the TCP connection like
+//! `TlsConnector::connect_tcp` and does the handshake. `HyperIo` gives any
+//! stream of this crate the IO traits of hyper, e.g. to serve the streams of a
+//! `TlsAcceptor` with `hyper::server::conn`. `AsyncStdExecutor` runs the
+//! background tasks of a client on async-std.
+//!
+//! A `Client` speaks HTTP/2 on connections that negotiated it with ALPN, which
+//! takes `h2` among the `alpn_protocols` of the `ClientConfig` and the `http2`
+//! feature of hyper-util.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::hyper::{AsyncStdExecutor, HttpsConnector};
+//! use async_tls::TlsConnector;
+//! use hyper_util::client::legacy::Client;
+//!
+//! # async_std::task::block_on(async {
+//! let connector = HttpsConnector::new(TlsConnector::new());
+//! let client: Client<_, String> = Client::builder(AsyncStdExecutor).build(connector);
+//!
+//! let response = client.get("https://example.com/".parse()?).await?;
+//! println!("{}", response.status());
+//! # Ok(()) as Result<(), Box<dyn std::error::Error>>
+//! # });
+//! ```
+
+use crate::client::TlsStream;
+use crate::remote_sign::BoxFuture;
+use crate::TlsConnector;
+
+use async_std::net::TcpStream;
+use futures_core::ready;
+use futures_io::{AsyncRead, AsyncWrite};
+use hyper::rt::{Executor, Read, ReadBufCursor, Write};
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// The most read into `HyperIo`'s buffer at once.
+const READ_CHUNK: usize = 8192;
+
+/// A connector for hyper-util's `Client`, connecting with a `TlsConnector`.
+///
+/// URIs are connected to like with the `Service` implementation of
+/// `TlsConnector`: only `https` ones are, with the host as the server name.
+#[derive(Clone, Debug)]
+pub struct HttpsConnector {
+    connector: TlsConnector,
+}
+
+impl HttpsConnector {
+    /// Connect with `connector`.
+    pub fn new(connector: TlsConnector) -> Self {
+        HttpsConnector { connector }
+    }
+}
+
+impl From<TlsConnector> for HttpsConnector {
+    fn from(connector: TlsConnector) -> Self {
+        HttpsConnector::new(connector)
+    }
+}
+
+impl Service<Uri> for HttpsConnector {
+    type Response = HyperIo<TlsStream<TcpStream>>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.connector.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connect = self.connector.call(uri);
+        Box::pin(async move { connect.await.map(HyperIo) })
+    }
+}
+
+/// A stream with the IO traits of futures-io, with those of hyper.
+#[derive(Debug)]
+pub struct HyperIo<IO>(IO);
+
+impl<IO> HyperIo<IO> {
+    /// Wrap `io`, e.g. a stream of a `TlsAcceptor`.
+    pub fn new(io: IO) -> Self {
+        HyperIo(io)
+    }
+
+    /// The stream.
+    pub fn get_ref(&self) -> &IO {
+        &self.0
+    }
+
+    /// The stream.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.0
+    }
+
+    /// Unwrap the stream.
+    pub fn into_inner(self) -> IO {
+        self.0
+    }
+}
+
+impl<IO: AsyncRead + Unpin> Read for HyperIo<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        // the cursor only takes initialized bytes without unsafe code, so the
+        // plaintext is read into a buffer of our own and copied over
+        let mut chunk = [0; READ_CHUNK];
+        let len = buf.remaining().min(READ_CHUNK);
+        let n = ready!(Pin::new(&mut self.0).poll_read(cx, &mut chunk[..len]))?;
+        buf.put_slice(&chunk[..n]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> Write for HyperIo<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+impl<IO> Connection for HyperIo<TlsStream<IO>> {
+    fn connected(&self) -> Connected {
+        match self.0.session.alpn_protocol() {
+            Some(b"h2") => Connected::new().negotiated_h2(),
+            _ => Connected::new(),
+        }
+    }
+}
+
+/// Runs the tasks of hyper clients and servers with `async_std::task::spawn`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdExecutor;
+
+impl<F> Executor<F> for AsyncStdExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        async_std::task::spawn(future);
+    }
+}
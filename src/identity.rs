@@ -0,0 +1,130 @@
+use crate::common::pem;
+
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, PrivateKey};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A certificate chain together with its private key.
+///
+/// This is what a server presents to clients, or a client presents for
+/// client authentication.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::Identity;
+/// use rustls::ServerConfig;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let (chain, key) = Identity::from_pem_files("server.crt", "server.key")?.into_parts();
+/// let config = ServerConfig::builder()
+///     .with_safe_defaults()
+///     .with_no_client_auth()
+///     .with_single_cert(chain, key)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Identity {
+    chain: Vec<Certificate>,
+    key: PrivateKey,
+}
+
+impl Identity {
+    /// Create an identity from an already parsed chain and key.
+    pub fn new(chain: Vec<Certificate>, key: PrivateKey) -> Self {
+        Identity { chain, key }
+    }
+
+    /// Parse a PEM certificate chain and an unencrypted PEM private key.
+    ///
+    /// PKCS#8, PKCS#1 (RSA) and SEC1 (EC) keys are accepted.
+    pub fn from_pem(cert_pem: &[u8], key_pem: &[u8]) -> io::Result<Self> {
+        Ok(Identity::new(
+            pem::certs(cert_pem)?,
+            pem::private_key(key_pem)?,
+        ))
+    }
+
+    /// Like `from_pem`, reading both files from disk.
+    pub fn from_pem_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        Identity::from_pem(&std::fs::read(cert_path)?, &std::fs::read(key_path)?)
+    }
+
+    /// Parse a PEM certificate chain and a passphrase-protected PEM private key.
+    ///
+    /// Both PKCS#8 `ENCRYPTED PRIVATE KEY` files and legacy OpenSSL keys with a
+    /// `DEK-Info` header are accepted. Unencrypted keys are loaded as by `from_pem`.
+    #[cfg(feature = "encrypted-keys")]
+    pub fn from_encrypted_pem(
+        cert_pem: &[u8],
+        key_pem: &[u8],
+        passphrase: impl AsRef<[u8]>,
+    ) -> io::Result<Self> {
+        Identity::from_encrypted_pem_with(cert_pem, key_pem, || Ok(passphrase))
+    }
+
+    /// Like `from_encrypted_pem`, asking `passphrase` for the passphrase.
+    ///
+    /// The callback is only invoked if the key is actually encrypted, so it may
+    /// prompt a user or fetch the passphrase from a secret store.
+    #[cfg(feature = "encrypted-keys")]
+    pub fn from_encrypted_pem_with<F, P>(
+        cert_pem: &[u8],
+        key_pem: &[u8],
+        passphrase: F,
+    ) -> io::Result<Self>
+    where
+        F: FnOnce() -> io::Result<P>,
+        P: AsRef<[u8]>,
+    {
+        use crate::common::encrypted_key;
+
+        let chain = pem::certs(cert_pem)?;
+        let key = if encrypted_key::is_encrypted(key_pem)? {
+            encrypted_key::private_key(key_pem, passphrase()?.as_ref())?
+        } else {
+            pem::private_key(key_pem)?
+        };
+        Ok(Identity::new(chain, key))
+    }
+
+    /// The certificate chain, leaf first.
+    pub fn cert_chain(&self) -> &[Certificate] {
+        &self.chain
+    }
+
+    /// The private key of the leaf certificate.
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.key
+    }
+
+    /// The chain with a signing key, as returned by certificate resolvers
+    /// such as `TlsConnector::client_cert_resolver`.
+    ///
+    /// Fails with `InvalidData` if the key type is not supported.
+    pub fn certified_key(&self) -> io::Result<Arc<CertifiedKey>> {
+        let key = rustls::sign::any_supported_type(&self.key)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Arc::new(CertifiedKey::new(self.chain.clone(), key)))
+    }
+
+    /// Split into the chain and key, as expected by `with_single_cert` and friends.
+    pub fn into_parts(self) -> (Vec<Certificate>, PrivateKey) {
+        (self.chain, self.key)
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("chain", &self.chain.len())
+            .finish_non_exhaustive()
+    }
+}
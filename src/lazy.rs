@@ -0,0 +1,242 @@
+use crate::acceptor::Accept;
+use crate::common::probe::ClientProbe;
+use crate::error::{HandshakeError, HandshakeStage};
+#[cfg(feature = "fingerprint")]
+use crate::fingerprint::{ClientHelloCapture, ClientHelloFingerprint};
+use crate::rusttls::stream::SyncReader;
+use crate::Error;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::server::{Accepted, Acceptor, ClientHello};
+use rustls::{AlertDescription, ServerConfig, ServerConnection};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Reads a client's `ClientHello` before a `ServerConfig` is chosen.
+///
+/// This allows picking the config (certificates, ALPN protocols, client authentication)
+/// based on what the client offered, e.g. its SNI. The future resolves to a
+/// `StartHandshake` once the `ClientHello` was received.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::LazyConfigAcceptor;
+/// use rustls::server::Acceptor;
+/// # use std::sync::Arc;
+/// # fn choose(hello: rustls::server::ClientHello) -> Arc<rustls::ServerConfig> { todo!() }
+///
+/// # async_std::task::block_on(async {
+/// # let tcp_stream = async_std::net::TcpStream::connect("127.0.0.1:8443").await?;
+/// let start = LazyConfigAcceptor::new(Acceptor::default(), tcp_stream).await?;
+/// let config = choose(start.client_hello());
+/// let stream = start.into_stream(config).await?;
+/// # Ok(()) as std::io::Result<()>
+/// # });
+/// ```
+pub struct LazyConfigAcceptor<IO> {
+    acceptor: Acceptor,
+    io: Option<IO>,
+    probe: ClientProbe,
+    #[cfg(feature = "fingerprint")]
+    capture: ClientHelloCapture,
+}
+
+impl<IO> fmt::Debug for LazyConfigAcceptor<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyConfigAcceptor")
+            .field("done", &self.io.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> LazyConfigAcceptor<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Start reading the `ClientHello` from `io`.
+    #[inline]
+    pub fn new(acceptor: Acceptor, io: IO) -> Self {
+        LazyConfigAcceptor {
+            acceptor,
+            io: Some(io),
+            probe: ClientProbe::default(),
+            #[cfg(feature = "fingerprint")]
+            capture: ClientHelloCapture::default(),
+        }
+    }
+}
+
+impl<IO> LazyConfigAcceptor<IO> {
+    /// Takes back the connection, e.g. once reading the `ClientHello` failed
+    /// with `Error::PlaintextHttp` to answer the request with a redirect.
+    ///
+    /// Returns `None` if the connection was taken already, or the `ClientHello`
+    /// was received. Polling the future afterwards panics.
+    pub fn take_io(&mut self) -> Option<IO> {
+        self.io.take()
+    }
+
+    /// Returns a reference to the underlying IO stream, `None` once the
+    /// `ClientHello` was received.
+    pub fn get_ref(&self) -> Option<&IO> {
+        self.io.as_ref()
+    }
+
+    /// Returns a mutable reference to the underlying IO stream, `None` once
+    /// the `ClientHello` was received.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        self.io.as_mut()
+    }
+}
+
+impl<IO> Future for LazyConfigAcceptor<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = io::Result<StartHandshake<IO>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        loop {
+            let io = this
+                .io
+                .as_mut()
+                .expect("Polled LazyConfigAcceptor after completion");
+
+            let reader = SyncReader { io, cx };
+            #[cfg(feature = "fingerprint")]
+            let reader = this.capture.reader(reader);
+            let mut reader = this.probe.reader(reader);
+            match this.acceptor.read_tls(&mut reader) {
+                Ok(0) => return Poll::Ready(Err(this.probe.check(Error::PeerClosed.into()))),
+                Ok(_) => (),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+
+            match this.acceptor.accept() {
+                Ok(Some(accepted)) => {
+                    let io = this.io.take().unwrap();
+                    return Poll::Ready(Ok(StartHandshake {
+                        accepted,
+                        io,
+                        probe: std::mem::take(&mut this.probe),
+                        #[cfg(feature = "fingerprint")]
+                        fingerprint: std::mem::take(&mut this.capture).finish(),
+                    }));
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    let err = Error::from(err).into();
+                    return Poll::Ready(Err(this.probe.check(err)));
+                }
+            }
+        }
+    }
+}
+
+/// A received `ClientHello`, waiting for a `ServerConfig` to continue the handshake.
+pub struct StartHandshake<IO> {
+    accepted: Accepted,
+    io: IO,
+    /// Tells clients offering none of the versions of the config.
+    probe: ClientProbe,
+    #[cfg(feature = "fingerprint")]
+    fingerprint: Option<ClientHelloFingerprint>,
+}
+
+impl<IO> fmt::Debug for StartHandshake<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StartHandshake")
+            .field("server_name", &self.accepted.client_hello().server_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> StartHandshake<IO> {
+    /// Continue after a `ClientHello` read elsewhere, from its `records`.
+    pub(crate) fn from_parts(
+        accepted: Accepted,
+        io: IO,
+        probe: ClientProbe,
+        #[cfg_attr(not(feature = "fingerprint"), allow(unused_variables))] records: &[u8],
+    ) -> Self {
+        StartHandshake {
+            accepted,
+            io,
+            probe,
+            #[cfg(feature = "fingerprint")]
+            fingerprint: ClientHelloFingerprint::parse(records),
+        }
+    }
+}
+
+impl<IO> StartHandshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// The `ClientHello` sent by the client.
+    pub fn client_hello(&self) -> ClientHello<'_> {
+        self.accepted.client_hello()
+    }
+
+    /// The fingerprint of the `ClientHello`, `None` if it could not be parsed.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(&self) -> Option<&ClientHelloFingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    /// Continue the handshake with the chosen config.
+    pub fn into_stream(self, config: Arc<ServerConfig>) -> Accept<IO> {
+        self.into_stream_with(config, |_| ())
+    }
+
+    /// Continue the handshake with the chosen config, customizing the
+    /// `ServerConnection` before any more handshake data is processed.
+    pub fn into_stream_with<F>(self, config: Arc<ServerConfig>, f: F) -> Accept<IO>
+    where
+        F: FnOnce(&mut ServerConnection),
+    {
+        match self.accepted.into_connection(config) {
+            Ok(mut conn) => {
+                f(&mut conn);
+                let accept = Accept::handshake(conn, self.io).probe(self.probe);
+                #[cfg(feature = "fingerprint")]
+                let accept = accept.fingerprint(self.fingerprint);
+                accept
+            }
+            Err(err) => {
+                let err = self.probe.check(Error::from(err).into());
+                let client_hello = self.probe.client_hello();
+                let server_name = client_hello
+                    .as_ref()
+                    .and_then(|hello| hello.server_name.as_deref());
+                let err = HandshakeError::wrap_at(err, server_name, HandshakeStage::Hello);
+                Accept::error(HandshakeError::with_client_hello(err, client_hello))
+            }
+        }
+    }
+
+    /// Abort the handshake, sending the client a fatal `alert`.
+    pub(crate) fn reject(self, alert: AlertDescription) -> Accept<IO> {
+        let server_name = self.client_hello().server_name().map(str::to_string);
+        let client_hello = self.probe.client_hello();
+        Accept::reject(self.io, alert, server_name.as_deref(), client_hello)
+    }
+
+    /// Returns a reference to the underlying IO stream.
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the underlying IO stream.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+}
@@ -1,22 +1,133 @@
 //! Asynchronous TLS/SSL streams for async-std and AsyncRead/AsyncWrite sockets using [rustls](https://github.com/ctz/rustls).
 
 #![deny(unsafe_code)]
+// without a side, the stream machinery shared by both goes unused
+#![cfg_attr(not(any(feature = "client", feature = "server")), allow(dead_code))]
 
 #[cfg(feature = "server")]
 mod acceptor;
+#[cfg(feature = "acme")]
+pub mod acme;
+#[cfg(feature = "server")]
+mod admission;
+#[cfg(feature = "capture")]
+pub mod capture;
+#[cfg(feature = "server")]
+mod cert_store;
 #[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "client")]
+mod client_cert;
 mod common;
+pub mod connection;
 #[cfg(feature = "client")]
 mod connector;
+#[cfg(feature = "crl")]
+pub mod crl;
+#[cfg(feature = "ct")]
+pub mod ct;
+#[cfg(feature = "dane")]
+pub mod dane;
+#[cfg(feature = "dangerous")]
+mod dangerous;
+#[cfg(feature = "dev-certs")]
+pub mod dev_certs;
+mod error;
+#[cfg(feature = "server")]
+mod expiry;
+#[cfg(feature = "fingerprint")]
+mod fingerprint;
+#[cfg(any(feature = "http-client", feature = "h1-server"))]
+pub mod h1;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+mod identity;
+#[cfg(feature = "server")]
+mod lazy;
+mod observer;
+#[cfg(feature = "ocsp")]
+pub mod ocsp;
+#[cfg(feature = "server")]
+mod passthrough;
+#[cfg(feature = "pool")]
+pub mod pool;
+mod preset;
+#[cfg(feature = "server")]
+mod reload;
+mod remote_sign;
+#[cfg(all(feature = "client", feature = "async-std"))]
+mod retry;
+#[cfg(feature = "server")]
+mod router;
 mod rusttls;
 #[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(all(feature = "settings", any(feature = "client", feature = "server")))]
+pub mod settings;
+#[cfg(all(feature = "client", feature = "async-std"))]
+mod tcp;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+#[cfg(feature = "session-tickets")]
+mod ticketer;
+#[cfg(feature = "server")]
+mod tls_alpn;
+#[cfg(feature = "tofu")]
+pub mod tofu;
+#[cfg(feature = "tpm")]
+mod tpm;
+#[cfg(feature = "tungstenite")]
+pub mod ws;
 
 #[cfg(feature = "server")]
 pub use acceptor::{Accept, TlsAcceptor};
+#[cfg(feature = "server")]
+pub use admission::{Admission, ClientInfo};
+#[cfg(feature = "server")]
+pub use cert_store::CertStore;
 #[cfg(feature = "client")]
-pub use connector::{Connect, TlsConnector};
+pub use client_cert::CertificateRequest;
+pub use common::chunking::WriteChunking;
+#[cfg(feature = "server")]
+pub use common::client_hello::ClientHelloSummary;
+pub use common::close::CloseMode;
+#[cfg(feature = "tokio")]
+pub use common::compat::TokioIo;
+pub use common::flush::HandshakeFlush;
+pub use common::hello::HandshakeKind;
+pub use common::io_state::IoState;
+#[cfg(feature = "server")]
+pub use common::probe::{PlaintextHttp, RecordHeader};
+pub use common::traffic::TrafficCounters;
+#[cfg(feature = "client")]
+pub use connector::{Connect, Connect0Rtt, TlsConnector};
+pub use error::{Error, HandshakeError, HandshakeStage};
+#[cfg(feature = "server")]
+pub use expiry::{ExpiryMonitor, ExpiryWarning};
+#[cfg(feature = "fingerprint")]
+pub use fingerprint::ClientHelloFingerprint;
+pub use identity::Identity;
+#[cfg(feature = "server")]
+pub use lazy::{LazyConfigAcceptor, StartHandshake};
+pub use observer::{HandshakeParams, HandshakeTimings, Observer};
+#[cfg(feature = "server")]
+pub use passthrough::{SniffClientHello, SniffedHello};
+pub use preset::SecurityPreset;
+#[cfg(feature = "server")]
+pub use reload::PollingReloader;
+pub use remote_sign::{BoxFuture, RemoteSigner, RemoteSigningKey};
+#[cfg(all(feature = "client", feature = "async-std"))]
+pub use retry::RetryPolicy;
+#[cfg(feature = "server")]
+pub use router::{Route, Routed, Router};
+#[cfg(feature = "session-tickets")]
+pub use ticketer::RotatingTicketer;
+#[cfg(feature = "server")]
+pub use tls_alpn::{TlsAlpn01Responder, ACME_TLS_ALPN_NAME};
+#[cfg(feature = "tpm")]
+pub use tpm::{TpmKey, TpmKeyType};
 
-#[cfg(all(test, feature = "client", feature = "early-data"))]
+#[cfg(all(test, feature = "client"))]
 mod test_0rtt;
@@ -0,0 +1,286 @@
+use crate::common::traffic::{Traffic, TrafficCounters};
+use crate::HandshakeKind;
+
+use rustls::{CommonState, NamedGroup, ProtocolVersion, SupportedCipherSuite};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Hooks to instrument connections, e.g. to feed metrics to Prometheus or StatsD.
+///
+/// Set it with `TlsConnector::observer` or `TlsAcceptor::observer`. All methods
+/// do nothing by default. They are called from the task driving the connection,
+/// so they should return quickly.
+///
+/// ## Example
+///
+/// ```rust
+/// use async_tls::{HandshakeParams, Observer};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct Handshakes {
+///     completed: AtomicU64,
+///     failed: AtomicU64,
+/// }
+///
+/// impl Observer for Handshakes {
+///     fn on_handshake_complete(&self, _: Duration, _: &HandshakeParams) {
+///         self.completed.fetch_add(1, Ordering::Relaxed);
+///     }
+///
+///     fn on_handshake_error(&self, _: &std::io::Error) {
+///         self.failed.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+/// ```
+pub trait Observer: Send + Sync {
+    /// A handshake started.
+    fn on_handshake_start(&self) {}
+
+    /// A handshake started `duration` ago was completed.
+    ///
+    /// Connections from `TlsConnector::connect_0rtt` complete their handshake
+    /// once they are first read from or written to beyond the early data.
+    fn on_handshake_complete(&self, duration: Duration, params: &HandshakeParams) {
+        let _ = (duration, params);
+    }
+
+    /// A handshake failed. Convert the error with `Error::from` to tell why.
+    fn on_handshake_error(&self, error: &io::Error) {
+        let _ = error;
+    }
+
+    /// A connection was closed, or dropped, after `bytes_in` bytes of plaintext
+    /// were read from it and `bytes_out` bytes written to it.
+    fn on_close(&self, bytes_in: u64, bytes_out: u64) {
+        let _ = (bytes_in, bytes_out);
+    }
+}
+
+/// What a completed handshake agreed on.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HandshakeParams {
+    /// The TLS version.
+    pub protocol_version: Option<ProtocolVersion>,
+    /// The cipher suite.
+    pub cipher_suite: Option<SupportedCipherSuite>,
+    /// The group the keys were exchanged with, e.g. `X25519`. `None` for
+    /// resumed TLS 1.2 sessions, which exchange no keys.
+    pub key_exchange_group: Option<NamedGroup>,
+    /// The application protocol negotiated with ALPN.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// Whether a session was resumed, see `HandshakeKind`.
+    pub kind: Option<HandshakeKind>,
+    /// The name connected to, for clients, or the name the client asked for
+    /// (SNI), for servers.
+    pub server_name: Option<String>,
+    /// How long the handshake took.
+    pub timings: HandshakeTimings,
+}
+
+/// The version, suite and group are serialized by name, e.g. `TLSv1_3`,
+/// `TLS13_AES_128_GCM_SHA256` and `X25519`, and the ALPN protocol as a string.
+#[cfg(feature = "serde")]
+impl serde::Serialize for HandshakeParams {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut params = serializer.serialize_struct("HandshakeParams", 7)?;
+        let version = self
+            .protocol_version
+            .map(|version| format!("{:?}", version));
+        params.serialize_field("protocol_version", &version)?;
+        let suite = self
+            .cipher_suite
+            .map(|suite| format!("{:?}", suite.suite()));
+        params.serialize_field("cipher_suite", &suite)?;
+        let group = self.key_exchange_group.map(|group| format!("{:?}", group));
+        params.serialize_field("key_exchange_group", &group)?;
+        let alpn = self.alpn_protocol.as_deref().map(String::from_utf8_lossy);
+        params.serialize_field("alpn_protocol", &alpn)?;
+        params.serialize_field("kind", &self.kind)?;
+        params.serialize_field("server_name", &self.server_name)?;
+        params.serialize_field("timings", &self.timings)?;
+        params.end()
+    }
+}
+
+impl HandshakeParams {
+    pub(crate) fn of(
+        conn: &CommonState,
+        kind: Option<HandshakeKind>,
+        key_exchange_group: Option<NamedGroup>,
+        server_name: Option<&str>,
+        timings: HandshakeTimings,
+    ) -> Self {
+        HandshakeParams {
+            protocol_version: conn.protocol_version(),
+            cipher_suite: conn.negotiated_cipher_suite(),
+            key_exchange_group,
+            alpn_protocol: conn.alpn_protocol().map(<[u8]>::to_vec),
+            kind,
+            server_name: server_name.map(str::to_string),
+            timings,
+        }
+    }
+}
+
+/// How long a handshake took, to tell network latency from the cost of the
+/// handshake itself.
+///
+/// The time the handshake was not being worked on is counted as waiting: for
+/// the peer, but also for the executor to poll the connection again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HandshakeTimings {
+    /// From the start until the first flight of messages was written: the
+    /// `ClientHello` for clients, the `ServerHello` and what follows it for
+    /// servers. `None` if nothing was written.
+    pub first_flight: Option<Duration>,
+    /// The time spent waiting, mostly for the peer's messages.
+    pub waiting: Duration,
+    /// From the start until the handshake was completed.
+    pub total: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HandshakeTimings {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut timings = serializer.serialize_struct("HandshakeTimings", 3)?;
+        timings.serialize_field("first_flight", &self.first_flight)?;
+        timings.serialize_field("waiting", &self.waiting)?;
+        timings.serialize_field("total", &self.total)?;
+        timings.end()
+    }
+}
+
+impl HandshakeTimings {
+    /// The time spent working on the handshake: processing messages, signing,
+    /// verifying certificates, and reading and writing.
+    pub fn busy(&self) -> Duration {
+        self.total.saturating_sub(self.waiting)
+    }
+}
+
+/// Reports a connection to an `Observer`, if there is one.
+pub(crate) struct Observation {
+    observer: Option<Arc<dyn Observer>>,
+    /// When the handshake started, until it is done.
+    start: Option<Instant>,
+    /// The time spent driving the handshake so far.
+    busy: Duration,
+    first_flight: Option<Duration>,
+    timings: Option<HandshakeTimings>,
+    pub(crate) traffic: Traffic,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Observation {
+    /// Observe a connection whose handshake starts now.
+    pub(crate) fn start(observer: Option<Arc<dyn Observer>>) -> Self {
+        if let Some(ref observer) = observer {
+            observer.on_handshake_start();
+        }
+        Observation {
+            observer,
+            start: Some(Instant::now()),
+            busy: Duration::ZERO,
+            first_flight: None,
+            timings: None,
+            traffic: Traffic::default(),
+            bytes_in: 0,
+            bytes_out: 0,
+        }
+    }
+
+    /// The handshake was driven since `polled_at`, and still `wants_write`.
+    pub(crate) fn handshake_polled(&mut self, polled_at: Instant, wants_write: bool) {
+        let start = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+        let now = Instant::now();
+        self.busy += now - polled_at;
+        if self.first_flight.is_none() && self.traffic.bytes_out > 0 && !wants_write {
+            self.first_flight = Some(now - start);
+        }
+    }
+
+    /// The handshake was completed, with the parameters made by `params`.
+    pub(crate) fn handshake_complete(
+        &mut self,
+        params: impl FnOnce(HandshakeTimings) -> HandshakeParams,
+    ) {
+        let start = match self.start.take() {
+            Some(start) => start,
+            None => return,
+        };
+        let total = start.elapsed();
+        let timings = HandshakeTimings {
+            first_flight: self.first_flight,
+            waiting: total.saturating_sub(self.busy),
+            total,
+        };
+        self.timings = Some(timings);
+        if let Some(ref observer) = self.observer {
+            observer.on_handshake_complete(total, &params(timings));
+        }
+    }
+
+    pub(crate) fn counters(&self) -> TrafficCounters {
+        self.traffic.counters(self.bytes_in, self.bytes_out)
+    }
+
+    /// How long the handshake took, once it was completed.
+    pub(crate) fn timings(&self) -> Option<HandshakeTimings> {
+        self.timings
+    }
+
+    /// The handshake failed. The connection is not reported as closed then.
+    pub(crate) fn handshake_error(&mut self, err: &io::Error) {
+        if self.start.take().is_some() {
+            if let Some(observer) = self.observer.take() {
+                observer.on_handshake_error(err);
+            }
+        }
+    }
+
+    pub(crate) fn read(&mut self, len: usize) {
+        self.bytes_in += len as u64;
+    }
+
+    pub(crate) fn written(&mut self, len: usize) {
+        self.bytes_out += len as u64;
+    }
+
+    /// The connection was closed, which is only reported once, and only after
+    /// the handshake was completed.
+    pub(crate) fn close(&mut self) {
+        if let Some(observer) = self.observer.take() {
+            if self.start.is_none() {
+                observer.on_close(self.bytes_in, self.bytes_out);
+            }
+        }
+    }
+}
+
+impl Drop for Observation {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl fmt::Debug for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observation")
+            .field("observed", &self.observer.is_some())
+            .field("bytes_in", &self.bytes_in)
+            .field("bytes_out", &self.bytes_out)
+            .finish()
+    }
+}
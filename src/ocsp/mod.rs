@@ -0,0 +1,24 @@
+//! OCSP stapling: the certificate's revocation status, served in the handshake.
+//!
+//! On the server, an `OcspStapler` fetches the OCSP response for a certificate
+//! from the responder named in the certificate, staples it to the certificate
+//! served by its acceptor, and its `run` future keeps it fresh: each response is
+//! replaced about halfway through its validity, with some jitter so that a fleet
+//! of servers does not hit the responder at once. Failed fetches are retried
+//! with exponential backoff, and a response that expires is no longer stapled.
+//!
+//! On the client, an `OcspVerifier` checks the stapled response: a certificate
+//! reported as revoked is rejected, and certificates carrying the must-staple
+//! extension can be required to come with a valid response.
+
+#[cfg(feature = "server")]
+mod stapler;
+#[cfg(feature = "client")]
+mod verifier;
+
+#[cfg(feature = "server")]
+pub use stapler::{OcspConfig, OcspStapler};
+#[cfg(feature = "client")]
+pub(crate) use verifier::capturing;
+#[cfg(feature = "client")]
+pub use verifier::OcspVerifier;
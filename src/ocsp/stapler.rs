@@ -0,0 +1,308 @@
+//! Keeping an OCSP response stapled to the server's certificate.
+
+use crate::common::{http, ocsp};
+use crate::TlsAcceptor;
+
+use ocsp::CertStatus;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+type ErrorCallback = dyn Fn(&io::Error) + Send + Sync;
+
+/// Configuration for an `OcspStapler`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::ocsp::OcspConfig;
+/// # fn cert() -> rustls::sign::CertifiedKey { todo!() }
+///
+/// # async_std::task::block_on(async {
+/// let mut stapler = OcspConfig::new(cert()).build()?;
+/// // staple before the first connection, then keep refreshing in the background
+/// stapler.refresh().await?;
+/// let acceptor = stapler.acceptor();
+/// async_std::task::spawn(stapler.run());
+/// # Ok(()) as std::io::Result<()>
+/// # });
+/// ```
+pub struct OcspConfig {
+    cert: CertifiedKey,
+    responder: Option<String>,
+    retry_interval: Duration,
+    max_retry_interval: Duration,
+    on_error: Option<Box<ErrorCallback>>,
+}
+
+impl OcspConfig {
+    /// Staple OCSP responses to `cert`.
+    ///
+    /// The chain must include the issuer of the leaf certificate.
+    pub fn new(cert: CertifiedKey) -> Self {
+        OcspConfig {
+            cert,
+            responder: None,
+            retry_interval: Duration::from_secs(60),
+            max_retry_interval: Duration::from_secs(60 * 60),
+            on_error: None,
+        }
+    }
+
+    /// Query this responder instead of the one named in the certificate.
+    pub fn responder(mut self, url: impl Into<String>) -> Self {
+        self.responder = Some(url.into());
+        self
+    }
+
+    /// How long to wait after the first failed fetch, doubling on each further failure.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// The longest time to wait between failed fetches.
+    pub fn max_retry_interval(mut self, interval: Duration) -> Self {
+        self.max_retry_interval = interval;
+        self
+    }
+
+    /// Report errors from `run`, which otherwise only retries silently.
+    pub fn on_error<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&io::Error) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Prepare the OCSP request, without fetching a response yet.
+    pub fn build(self) -> io::Result<OcspStapler> {
+        let (leaf, issuer) = match self.cert.cert.as_slice() {
+            [leaf, issuer, ..] => (leaf, issuer),
+            _ => return Err(invalid("certificate chain does not include the issuer")),
+        };
+        let url = match self.responder {
+            Some(ref url) => url.clone(),
+            None => ocsp::responder_url(&leaf.0)
+                .ok_or_else(|| invalid("certificate does not name an OCSP responder"))?,
+        };
+        let request = ocsp::request(&leaf.0, &issuer.0)
+            .ok_or_else(|| invalid("failed to parse certificate"))?;
+        let serial = crate::common::der::tbs_certificate(&leaf.0)
+            .map(|tbs| tbs.serial.to_vec())
+            .ok_or_else(|| invalid("failed to parse certificate"))?;
+
+        Ok(OcspStapler {
+            resolver: Arc::new(StaplingResolver {
+                cert: RwLock::new(Arc::new(self.cert.clone())),
+            }),
+            config: self,
+            url,
+            request,
+            serial,
+            next_update: None,
+            failures: 0,
+        })
+    }
+}
+
+impl fmt::Debug for OcspConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OcspConfig")
+            .field("responder", &self.responder)
+            .field("retry_interval", &self.retry_interval)
+            .field("max_retry_interval", &self.max_retry_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps an OCSP response stapled to a certificate.
+pub struct OcspStapler {
+    config: OcspConfig,
+    resolver: Arc<StaplingResolver>,
+    url: String,
+    request: Vec<u8>,
+    serial: Vec<u8>,
+    next_update: Option<SystemTime>,
+    failures: u32,
+}
+
+impl OcspStapler {
+    /// An acceptor serving the certificate with the most recent OCSP response.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver());
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// The certificate resolver backing `acceptor`, for building a custom `ServerConfig`.
+    pub fn resolver(&self) -> Arc<dyn ResolvesServerCert> {
+        self.resolver.clone()
+    }
+
+    /// The DER-encoded OCSP response currently stapled, if any.
+    pub fn response(&self) -> Option<Vec<u8>> {
+        self.resolver.cert.read().unwrap().ocsp.clone()
+    }
+
+    /// When the stapled response expires, if it has an expiry.
+    pub fn next_update(&self) -> Option<SystemTime> {
+        self.next_update
+    }
+
+    /// Fetch a fresh response and staple it.
+    ///
+    /// Returns when the response should be refreshed. A response reporting the
+    /// certificate as revoked or unknown is not stapled, and reported as an error.
+    pub async fn refresh(&mut self) -> io::Result<SystemTime> {
+        let response = http::request_plain(
+            "POST",
+            &self.url,
+            Some(("application/ocsp-request", &self.request)),
+        )
+        .await?;
+        if response.status != 200 {
+            return Err(io::Error::other(format!(
+                "OCSP responder returned HTTP {}",
+                response.status
+            )));
+        }
+
+        let parsed = ocsp::parse_response(&response.body, &self.serial)?;
+        match parsed.status {
+            CertStatus::Good => (),
+            CertStatus::Revoked => {
+                self.clear();
+                return Err(invalid("certificate has been revoked"));
+            }
+            CertStatus::Unknown => {
+                return Err(invalid("OCSP responder does not know the certificate"))
+            }
+        }
+        if parsed
+            .next_update
+            .is_some_and(|next| next <= SystemTime::now())
+        {
+            return Err(invalid("OCSP response is already expired"));
+        }
+
+        let mut cert = self.config.cert.clone();
+        cert.ocsp = Some(response.body);
+        *self.resolver.cert.write().unwrap() = Arc::new(cert);
+        self.next_update = parsed.next_update;
+        Ok(refresh_at(parsed.this_update, parsed.next_update))
+    }
+
+    /// Keep the stapled response fresh forever.
+    ///
+    /// Failures are reported to the `on_error` callback and retried with backoff.
+    pub async fn run(mut self) {
+        loop {
+            let wait = match self.refresh().await {
+                Ok(at) => {
+                    self.failures = 0;
+                    at.duration_since(SystemTime::now()).unwrap_or_default()
+                }
+                Err(err) => {
+                    if let Some(ref on_error) = self.config.on_error {
+                        on_error(&err);
+                    }
+                    if self
+                        .next_update
+                        .is_some_and(|next| next <= SystemTime::now())
+                    {
+                        self.clear();
+                    }
+                    self.failures += 1;
+                    self.backoff()
+                }
+            };
+            async_std::task::sleep(wait).await;
+        }
+    }
+
+    fn clear(&mut self) {
+        *self.resolver.cert.write().unwrap() = Arc::new(self.config.cert.clone());
+        self.next_update = None;
+    }
+
+    fn backoff(&self) -> Duration {
+        let factor = 1u32 << self.failures.saturating_sub(1).min(16);
+        let wait = self
+            .config
+            .retry_interval
+            .saturating_mul(factor)
+            .min(self.config.max_retry_interval);
+        wait + jitter(wait / 4)
+    }
+}
+
+impl fmt::Debug for OcspStapler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OcspStapler")
+            .field("url", &self.url)
+            .field("next_update", &self.next_update)
+            .field("failures", &self.failures)
+            .finish()
+    }
+}
+
+struct StaplingResolver {
+    cert: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for StaplingResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.cert.read().unwrap().clone())
+    }
+}
+
+/// Halfway through the validity of a response, minus up to an eighth of it.
+///
+/// Responses without `nextUpdate` are refreshed hourly.
+fn refresh_at(this_update: SystemTime, next_update: Option<SystemTime>) -> SystemTime {
+    let next_update = match next_update {
+        Some(next_update) => next_update,
+        None => return SystemTime::now() + Duration::from_secs(60 * 60),
+    };
+    let validity = next_update.duration_since(this_update).unwrap_or_default();
+    this_update + validity / 2 - jitter(validity / 8)
+}
+
+/// A random duration up to `max`.
+fn jitter(max: Duration) -> Duration {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut bytes = [0; 4];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return Duration::ZERO;
+    }
+    max.mul_f64(f64::from(u32::from_be_bytes(bytes)) / f64::from(u32::MAX))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refreshes_before_halfway() {
+        let this_update = SystemTime::now();
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+        for _ in 0..100 {
+            let at = refresh_at(this_update, Some(this_update + week));
+            assert!(at <= this_update + week / 2);
+            assert!(at >= this_update + week / 2 - week / 8);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! Checking the OCSP response stapled by the server.
+
+use crate::common::der::{self, Reader, INTEGER, OID, SEQUENCE};
+use crate::common::ocsp::{self, CertStatus};
+
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use rustls::client::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier, WebPkiVerifier,
+};
+use rustls::{
+    Certificate, CertificateError, ClientConfig, DigitallySignedStruct, Error, RootCertStore,
+    ServerName, SignatureScheme,
+};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// id-pe-tlsfeature
+const TLS_FEATURE: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x18];
+/// The `status_request` TLS extension, as listed in the TLS feature extension.
+const STATUS_REQUEST: &[u8] = &[5];
+/// id-ce-extKeyUsage
+const EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+/// id-kp-OCSPSigning
+const OCSP_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+/// How far in the future a response's `thisUpdate` may lie, for clock skew.
+const MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+/// How long a response without `nextUpdate` is considered fresh.
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Verifies server certificates and the OCSP responses stapled to them.
+///
+/// A stapled response is valid if it is signed by the issuer of the
+/// certificate, or by a responder the issuer delegated to, and is current. The
+/// issuer has to be the first certificate of the chain sent by the server.
+///
+/// A certificate reported as revoked by a valid response is always rejected.
+/// A missing or invalid response is ignored, unless the certificate carries
+/// the must-staple extension and `enforce_must_staple` is set. The response
+/// is not checked on resumed sessions, which skip certificate verification.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::ocsp::OcspVerifier;
+/// use async_tls::TlsConnector;
+/// # fn roots() -> rustls::RootCertStore { todo!() }
+///
+/// # async_std::task::block_on(async {
+/// let verifier = OcspVerifier::new(roots()).enforce_must_staple(true);
+/// let connector = TlsConnector::new().ocsp(verifier);
+///
+/// let tcp_stream = async_std::net::TcpStream::connect("example.com:443").await?;
+/// let stream = connector.connect("example.com", tcp_stream).await?;
+/// println!("stapled: {:?}", stream.ocsp_response());
+/// # Ok(()) as std::io::Result<()>
+/// # });
+/// ```
+pub struct OcspVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    must_staple: bool,
+}
+
+impl OcspVerifier {
+    /// Verify certificates against `roots`, as rustls does by default.
+    pub fn new(roots: RootCertStore) -> Self {
+        Self::wrap(Arc::new(WebPkiVerifier::new(roots, None)))
+    }
+
+    /// Check stapled responses on top of another certificate verifier.
+    pub fn wrap(inner: Arc<dyn ServerCertVerifier>) -> Self {
+        OcspVerifier {
+            inner,
+            must_staple: false,
+        }
+    }
+
+    /// Fail the handshake when a must-staple certificate comes without a valid response.
+    pub fn enforce_must_staple(mut self, flag: bool) -> Self {
+        self.must_staple = flag;
+        self
+    }
+
+    /// Check the stapled response, returning it if it is valid.
+    fn check<'a>(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        staple: &'a [u8],
+        now: SystemTime,
+    ) -> Result<Option<&'a [u8]>, Error> {
+        let status = if staple.is_empty() {
+            Err(invalid("no OCSP response was stapled"))
+        } else {
+            status(end_entity, intermediates, staple, now)
+        };
+
+        let err = match status {
+            Ok(CertStatus::Good) => return Ok(Some(staple)),
+            Ok(CertStatus::Revoked) => {
+                return Err(Error::InvalidCertificate(CertificateError::Revoked))
+            }
+            Ok(CertStatus::Unknown) => invalid("OCSP responder does not know the certificate"),
+            Err(err) => err,
+        };
+        if self.must_staple && must_staple(&end_entity.0) {
+            return Err(Error::InvalidCertificate(CertificateError::Other(
+                Arc::new(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("must-staple certificate: {}", err),
+                )),
+            )));
+        }
+        Ok(None)
+    }
+}
+
+impl ServerCertVerifier for OcspVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        self.check(end_entity, intermediates, ocsp_response, now)?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &Certificate,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &Certificate,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+
+    fn request_scts(&self) -> bool {
+        self.inner.request_scts()
+    }
+}
+
+impl fmt::Debug for OcspVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OcspVerifier")
+            .field("must_staple", &self.must_staple)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A copy of `config` verifying with `verifier`, which records the staple in
+/// `staple` along with whether it is valid.
+pub(crate) fn capturing(
+    config: &ClientConfig,
+    verifier: Arc<OcspVerifier>,
+    staple: Arc<OnceLock<(Vec<u8>, bool)>>,
+) -> Arc<ClientConfig> {
+    let mut config = config.clone();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(Capture { verifier, staple }));
+    Arc::new(config)
+}
+
+/// Verifies like `verifier`, for a single connection.
+struct Capture {
+    verifier: Arc<OcspVerifier>,
+    staple: Arc<OnceLock<(Vec<u8>, bool)>>,
+}
+
+impl ServerCertVerifier for Capture {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let verified = self.verifier.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let valid = (self.verifier)
+            .check(end_entity, intermediates, ocsp_response, now)?
+            .is_some();
+        if !ocsp_response.is_empty() {
+            let _ = self.staple.set((ocsp_response.to_vec(), valid));
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &Certificate,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &Certificate,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+
+    fn request_scts(&self) -> bool {
+        self.verifier.request_scts()
+    }
+}
+
+/// The status of `end_entity` according to a validly signed, current `staple`.
+fn status(
+    end_entity: &Certificate,
+    intermediates: &[Certificate],
+    staple: &[u8],
+    now: SystemTime,
+) -> io::Result<CertStatus> {
+    let issuer = intermediates
+        .first()
+        .ok_or_else(|| invalid("certificate chain does not include the issuer"))?;
+    let issuer = der::tbs_certificate(&issuer.0)
+        .ok_or_else(|| invalid("failed to parse issuer certificate"))?;
+    let serial = der::tbs_certificate(&end_entity.0)
+        .ok_or_else(|| invalid("failed to parse certificate"))?
+        .serial;
+
+    let basic = ocsp::basic_response(staple)?;
+    let signed_by_issuer = verify(&issuer, &basic.signed);
+    let signed_by_delegate = || {
+        basic.certs.iter().any(|cert| {
+            delegate(cert, &issuer, now).is_some_and(|delegate| verify(&delegate, &basic.signed))
+        })
+    };
+    if !signed_by_issuer && !signed_by_delegate() {
+        return Err(invalid(
+            "OCSP response is not signed by the issuer or its responder",
+        ));
+    }
+
+    let response = ocsp::parse_response(staple, serial)?;
+    if response.this_update > now + MAX_SKEW {
+        return Err(invalid("OCSP response is not valid yet"));
+    }
+    let expiry = response
+        .next_update
+        .unwrap_or(response.this_update + MAX_AGE);
+    if expiry <= now {
+        return Err(invalid("OCSP response has expired"));
+    }
+    Ok(response.status)
+}
+
+/// A current certificate signed by `issuer` and authorizing its subject to sign OCSP responses.
+fn delegate<'a>(
+    cert: &'a [u8],
+    issuer: &der::TbsCertificate<'_>,
+    now: SystemTime,
+) -> Option<der::TbsCertificate<'a>> {
+    let (signed, _) = der::signed(cert)?;
+    let tbs = der::tbs_certificate(cert)?;
+    let (not_before, not_after) = der::validity(cert)?;
+    if now < not_before || now > not_after {
+        return None;
+    }
+
+    let mut purposes =
+        Reader::new(Reader::new(tbs.extension(EXTENDED_KEY_USAGE)?).expect(SEQUENCE)?);
+    while let Some(purpose) = purposes.expect(OID) {
+        if purpose == OCSP_SIGNING {
+            return Some(tbs).filter(|_| verify(issuer, &signed));
+        }
+    }
+    None
+}
+
+/// Whether `signed` carries a valid signature by the key of `signer`.
+fn verify(signer: &der::TbsCertificate<'_>, signed: &der::Signed<'_>) -> bool {
+    let (key_algorithm, parameters) = match signer.public_key_algorithm() {
+        Some(algorithm) => algorithm,
+        None => return false,
+    };
+    let algorithm: &'static dyn VerificationAlgorithm =
+        match (key_algorithm, parameters, signed.algorithm) {
+            (RSA_ENCRYPTION, _, SHA256_WITH_RSA) => &signature::RSA_PKCS1_2048_8192_SHA256,
+            (RSA_ENCRYPTION, _, SHA384_WITH_RSA) => &signature::RSA_PKCS1_2048_8192_SHA384,
+            (RSA_ENCRYPTION, _, SHA512_WITH_RSA) => &signature::RSA_PKCS1_2048_8192_SHA512,
+            (EC_PUBLIC_KEY, Some(P256), ECDSA_WITH_SHA256) => &signature::ECDSA_P256_SHA256_ASN1,
+            (EC_PUBLIC_KEY, Some(P256), ECDSA_WITH_SHA384) => &signature::ECDSA_P256_SHA384_ASN1,
+            (EC_PUBLIC_KEY, Some(P384), ECDSA_WITH_SHA256) => &signature::ECDSA_P384_SHA256_ASN1,
+            (EC_PUBLIC_KEY, Some(P384), ECDSA_WITH_SHA384) => &signature::ECDSA_P384_SHA384_ASN1,
+            (ED25519, None, ED25519) => &signature::ED25519,
+            _ => return false,
+        };
+    match signer.public_key() {
+        Some(key) => UnparsedPublicKey::new(algorithm, key)
+            .verify(signed.data, signed.signature)
+            .is_ok(),
+        None => false,
+    }
+}
+
+/// Whether the certificate asks for a stapled OCSP response in its TLS feature extension.
+fn must_staple(cert: &[u8]) -> bool {
+    let features = match der::tbs_certificate(cert).and_then(|tbs| tbs.extension(TLS_FEATURE)) {
+        Some(features) => features,
+        None => return false,
+    };
+    let mut features = match Reader::new(features).expect(SEQUENCE) {
+        Some(features) => Reader::new(features),
+        None => return false,
+    };
+    while let Some(feature) = features.expect(INTEGER) {
+        if feature == STATUS_REQUEST {
+            return true;
+        }
+    }
+    false
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
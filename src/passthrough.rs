@@ -0,0 +1,243 @@
+use crate::acceptor::Accept;
+use crate::common::client_hello::ClientHelloSummary;
+use crate::common::probe::ClientProbe;
+use crate::lazy::StartHandshake;
+use crate::rusttls::stream::SyncReader;
+use crate::Error;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::server::{Accepted, Acceptor};
+use rustls::{AlertDescription, ServerConfig};
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Reads a client's `ClientHello` without terminating TLS, for proxies that
+/// route connections by SNI and forward the raw TLS bytes to a backend.
+///
+/// The future resolves to a `SniffedHello` holding what the client offered,
+/// the bytes read so far and the connection, which nothing was written to.
+/// Send the bytes on to the backend before copying the rest of the connection,
+/// or terminate TLS after all with `SniffedHello::into_stream`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::SniffClientHello;
+/// use futures_util::io::AsyncWriteExt;
+///
+/// # async_std::task::block_on(async {
+/// # let tcp_stream = async_std::net::TcpStream::connect("127.0.0.1:8443").await?;
+/// let sniffed = SniffClientHello::new(tcp_stream).await?;
+/// let backend = match sniffed.server_name() {
+///     Some("example.com") => "10.0.0.1:443",
+///     _ => "10.0.0.2:443",
+/// };
+/// let (consumed, client) = sniffed.into_parts();
+/// let mut backend = async_std::net::TcpStream::connect(backend).await?;
+/// backend.write_all(&consumed).await?;
+/// // copy between `client` and `backend` from here on
+/// # Ok(()) as std::io::Result<()>
+/// # });
+/// ```
+pub struct SniffClientHello<IO> {
+    acceptor: Acceptor,
+    io: Option<IO>,
+    consumed: Vec<u8>,
+    probe: ClientProbe,
+}
+
+impl<IO> fmt::Debug for SniffClientHello<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniffClientHello")
+            .field("consumed", &self.consumed.len())
+            .field("done", &self.io.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> SniffClientHello<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    /// Start reading the `ClientHello` from `io`.
+    #[inline]
+    pub fn new(io: IO) -> Self {
+        SniffClientHello {
+            acceptor: Acceptor::default(),
+            io: Some(io),
+            consumed: Vec::new(),
+            probe: ClientProbe::default(),
+        }
+    }
+}
+
+impl<IO> SniffClientHello<IO> {
+    /// Takes back the connection and the bytes read from it, e.g. once
+    /// reading the `ClientHello` failed with `Error::PlaintextHttp` to answer
+    /// the request with a redirect.
+    ///
+    /// Returns `None` if the connection was taken already, or the `ClientHello`
+    /// was received. Polling the future afterwards panics.
+    pub fn take_io(&mut self) -> Option<(Vec<u8>, IO)> {
+        let io = self.io.take()?;
+        Some((std::mem::take(&mut self.consumed), io))
+    }
+
+    /// Returns a reference to the underlying IO stream, `None` once the
+    /// `ClientHello` was received.
+    pub fn get_ref(&self) -> Option<&IO> {
+        self.io.as_ref()
+    }
+
+    /// Returns a mutable reference to the underlying IO stream, `None` once
+    /// the `ClientHello` was received.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        self.io.as_mut()
+    }
+}
+
+impl<IO> Future for SniffClientHello<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    type Output = io::Result<SniffedHello<IO>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        loop {
+            let io = this
+                .io
+                .as_mut()
+                .expect("Polled SniffClientHello after completion");
+
+            let reader = Keep {
+                inner: SyncReader { io, cx },
+                consumed: &mut this.consumed,
+            };
+            let mut reader = this.probe.reader(reader);
+            match this.acceptor.read_tls(&mut reader) {
+                Ok(0) => return Poll::Ready(Err(this.probe.check(Error::PeerClosed.into()))),
+                Ok(_) => (),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+
+            match this.acceptor.accept() {
+                Ok(Some(accepted)) => {
+                    let client_hello = match ClientHelloSummary::parse(&this.consumed) {
+                        Some(client_hello) => client_hello,
+                        None => ClientHelloSummary::of(&accepted.client_hello()),
+                    };
+                    return Poll::Ready(Ok(SniffedHello {
+                        client_hello,
+                        consumed: std::mem::take(&mut this.consumed),
+                        io: this.io.take().unwrap(),
+                        accepted,
+                        probe: std::mem::take(&mut this.probe),
+                    }));
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    let err = Error::from(err).into();
+                    return Poll::Ready(Err(this.probe.check(err)));
+                }
+            }
+        }
+    }
+}
+
+/// A `ClientHello` read by `SniffClientHello`, with the connection it came from.
+pub struct SniffedHello<IO> {
+    client_hello: ClientHelloSummary,
+    consumed: Vec<u8>,
+    io: IO,
+    /// What rustls read, to terminate TLS after all.
+    accepted: Accepted,
+    probe: ClientProbe,
+}
+
+impl<IO> fmt::Debug for SniffedHello<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniffedHello")
+            .field("client_hello", &self.client_hello)
+            .field("consumed", &self.consumed.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> SniffedHello<IO> {
+    /// What the client offered.
+    pub fn client_hello(&self) -> &ClientHelloSummary {
+        &self.client_hello
+    }
+
+    /// The name the client asked for (SNI), if any.
+    pub fn server_name(&self) -> Option<&str> {
+        self.client_hello.server_name.as_deref()
+    }
+
+    /// The application protocols offered with ALPN, empty if none.
+    pub fn alpn(&self) -> &[Vec<u8>] {
+        &self.client_hello.alpn
+    }
+
+    /// The bytes read from the connection: the records of the `ClientHello`,
+    /// and whatever followed them in the same reads.
+    pub fn consumed(&self) -> &[u8] {
+        &self.consumed
+    }
+
+    /// Returns a reference to the underlying IO stream.
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the underlying IO stream.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    /// The bytes read, to be sent on first, and the connection.
+    pub fn into_parts(self) -> (Vec<u8>, IO) {
+        (self.consumed, self.io)
+    }
+}
+
+impl<IO> SniffedHello<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Terminate TLS after all, continuing the handshake with `config` as
+    /// `StartHandshake::into_stream` does.
+    pub fn into_stream(self, config: Arc<ServerConfig>) -> Accept<IO> {
+        self.into_start().into_stream(config)
+    }
+
+    /// Abort the handshake, sending the client a fatal `alert`.
+    pub(crate) fn reject(self, alert: AlertDescription) -> Accept<IO> {
+        self.into_start().reject(alert)
+    }
+
+    fn into_start(self) -> StartHandshake<IO> {
+        StartHandshake::from_parts(self.accepted, self.io, self.probe, &self.consumed)
+    }
+}
+
+/// Keeps all bytes read from `inner`, to be forwarded.
+struct Keep<'a, R> {
+    inner: R,
+    consumed: &'a mut Vec<u8>,
+}
+
+impl<R: Read> Read for Keep<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,206 @@
+//! Reusing idle client connections, to save the handshakes of request-oriented clients.
+//!
+//! A `Pool` keeps the streams given back with `Pool::put`, keyed by host, port
+//! and the `ClientConfig` of the connector that made them. `Pool::get` hands
+//! out the most recently used stream for the same key, so clones of a connector
+//! share their connections, while connectors with other configs never get them.
+//!
+//! Idle streams are dropped after `idle_timeout`, and at most `max_idle` are
+//! kept per key. A pooled stream may still have been closed by the server in
+//! the meantime: be prepared to retry a request on a fresh connection.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::pool::Pool;
+//! use async_tls::TlsConnector;
+//!
+//! # async_std::task::block_on(async {
+//! let pool = Pool::new();
+//! let connector = TlsConnector::new();
+//!
+//! let stream = pool.connect(&connector, "example.com:443").await?;
+//! // ... send a request and read the whole response ...
+//! pool.put(&connector, "example.com:443", stream)?;
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+
+use crate::client::TlsStream;
+use crate::tcp;
+use crate::TlsConnector;
+
+use async_std::net::TcpStream;
+use rustls::ClientConfig;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A pool of idle TLS connections, see the module documentation.
+pub struct Pool<IO> {
+    idle: Mutex<HashMap<Key, VecDeque<Idle<IO>>>>,
+    max_idle: usize,
+    idle_timeout: Duration,
+}
+
+/// The host, port and config of pooled streams.
+struct Key {
+    host: String,
+    port: u16,
+    config: Arc<ClientConfig>,
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.host == other.host
+            && self.port == other.port
+            && Arc::ptr_eq(&self.config, &other.config)
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.host.hash(state);
+        self.port.hash(state);
+        Arc::as_ptr(&self.config).hash(state);
+    }
+}
+
+struct Idle<IO> {
+    stream: TlsStream<IO>,
+    since: Instant,
+}
+
+impl<IO> Default for Pool<IO> {
+    fn default() -> Self {
+        Pool {
+            idle: Mutex::new(HashMap::new()),
+            max_idle: 8,
+            idle_timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl<IO> fmt::Debug for Pool<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("idle", &self.idle_count())
+            .field("max_idle", &self.max_idle)
+            .field("idle_timeout", &self.idle_timeout)
+            .finish()
+    }
+}
+
+impl<IO> Pool<IO> {
+    /// An empty pool, keeping up to 8 streams per key for up to 90 seconds.
+    pub fn new() -> Self {
+        Pool::default()
+    }
+
+    /// Keep at most `max` idle streams per host, port and config. Defaults to 8.
+    pub fn max_idle(mut self, max: usize) -> Self {
+        self.max_idle = max;
+        self
+    }
+
+    /// Drop streams idle for longer than `timeout`. Defaults to 90 seconds.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Take the most recently returned stream to `addr`, e.g.
+    /// `example.com:443`, made by `connector` or a clone of it.
+    pub fn get(&self, connector: &TlsConnector, addr: &str) -> io::Result<Option<TlsStream<IO>>> {
+        let key = key(connector, addr)?;
+        let mut idle = self.idle.lock().unwrap();
+        let streams = match idle.get_mut(&key) {
+            Some(streams) => streams,
+            None => return Ok(None),
+        };
+        let now = Instant::now();
+        let stream = loop {
+            match streams.pop_back() {
+                Some(entry) if now.duration_since(entry.since) < self.idle_timeout => {
+                    break Some(entry.stream)
+                }
+                Some(_) => (),
+                None => break None,
+            }
+        };
+        if streams.is_empty() {
+            idle.remove(&key);
+        }
+        Ok(stream)
+    }
+
+    /// Give back a stream to `addr`, made by `connector`, for reuse.
+    ///
+    /// Only give back streams that are done with their last exchange, i.e.
+    /// with nothing left to read. The oldest stream of the key is dropped if
+    /// it already has `max_idle` streams.
+    pub fn put(
+        &self,
+        connector: &TlsConnector,
+        addr: &str,
+        stream: TlsStream<IO>,
+    ) -> io::Result<()> {
+        let key = key(connector, addr)?;
+        let now = Instant::now();
+        let mut idle = self.idle.lock().unwrap();
+        let streams = idle.entry(key).or_default();
+        streams.retain(|entry| now.duration_since(entry.since) < self.idle_timeout);
+        streams.push_back(Idle { stream, since: now });
+        while streams.len() > self.max_idle {
+            streams.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Drop the streams idle for longer than `idle_timeout`.
+    ///
+    /// `get` and `put` only evict the streams of the key they use; call this
+    /// now and then to close connections to hosts no longer used.
+    pub fn evict_expired(&self) {
+        let now = Instant::now();
+        let mut idle = self.idle.lock().unwrap();
+        idle.retain(|_, streams| {
+            streams.retain(|entry| now.duration_since(entry.since) < self.idle_timeout);
+            !streams.is_empty()
+        });
+    }
+
+    /// The number of idle streams in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().values().map(VecDeque::len).sum()
+    }
+}
+
+impl Pool<TcpStream> {
+    /// Take an idle stream to `addr`, or connect to it with
+    /// `TlsConnector::connect_tcp`.
+    pub async fn connect(
+        &self,
+        connector: &TlsConnector,
+        addr: &str,
+    ) -> io::Result<TlsStream<TcpStream>> {
+        match self.get(connector, addr)? {
+            Some(stream) => Ok(stream),
+            None => connector.connect_tcp(addr).await,
+        }
+    }
+}
+
+fn key(connector: &TlsConnector, addr: &str) -> io::Result<Key> {
+    let (host, port) = tcp::split_host_port(addr)?;
+    Ok(Key {
+        host: host.to_ascii_lowercase(),
+        port,
+        config: connector.config().clone(),
+    })
+}
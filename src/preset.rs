@@ -0,0 +1,108 @@
+//! Security profiles configuring versions, cipher suites and key exchange groups at once.
+
+use crate::common::policy::Policy;
+
+use rustls::cipher_suite::{
+    TLS13_AES_128_GCM_SHA256, TLS13_AES_256_GCM_SHA384, TLS13_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256, TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384, TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+};
+use rustls::kx_group::{SECP256R1, SECP384R1, X25519};
+use rustls::version::{TLS12, TLS13};
+use rustls::{
+    ConfigBuilder, ConfigSide, SupportedCipherSuite, SupportedKxGroup, SupportedProtocolVersion,
+    WantsCipherSuites, WantsVerifier,
+};
+
+/// A server-side TLS recommendation of Mozilla, see
+/// <https://wiki.mozilla.org/Security/Server_Side_TLS>.
+///
+/// Apply one to a connector or acceptor with `security_preset`, or build a
+/// config with `configure`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityPreset {
+    /// TLS 1.3 only, for clients that all support it.
+    MozillaModern,
+    /// TLS 1.2 with forward secret AEAD suites, and TLS 1.3.
+    MozillaIntermediate,
+}
+
+static MODERN_SUITES: [SupportedCipherSuite; 3] = [
+    TLS13_AES_128_GCM_SHA256,
+    TLS13_AES_256_GCM_SHA384,
+    TLS13_CHACHA20_POLY1305_SHA256,
+];
+
+static INTERMEDIATE_SUITES: [SupportedCipherSuite; 9] = [
+    TLS13_AES_128_GCM_SHA256,
+    TLS13_AES_256_GCM_SHA384,
+    TLS13_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+];
+
+static MODERN_VERSIONS: [&SupportedProtocolVersion; 1] = [&TLS13];
+
+static INTERMEDIATE_VERSIONS: [&SupportedProtocolVersion; 2] = [&TLS13, &TLS12];
+
+static GROUPS: [&SupportedKxGroup; 3] = [&X25519, &SECP256R1, &SECP384R1];
+
+impl SecurityPreset {
+    /// The TLS versions of the preset.
+    pub fn versions(&self) -> &'static [&'static SupportedProtocolVersion] {
+        match self {
+            SecurityPreset::MozillaModern => &MODERN_VERSIONS,
+            SecurityPreset::MozillaIntermediate => &INTERMEDIATE_VERSIONS,
+        }
+    }
+
+    /// The cipher suites of the preset, in order of preference.
+    pub fn cipher_suites(&self) -> &'static [SupportedCipherSuite] {
+        match self {
+            SecurityPreset::MozillaModern => &MODERN_SUITES,
+            SecurityPreset::MozillaIntermediate => &INTERMEDIATE_SUITES,
+        }
+    }
+
+    /// The key exchange groups of the preset, in order of preference.
+    ///
+    /// Both presets allow X25519, P-256 and P-384, as Mozilla recommends.
+    pub fn kx_groups(&self) -> &'static [&'static SupportedKxGroup] {
+        &GROUPS
+    }
+
+    /// Offer only the versions, suites and groups of the preset, e.g.
+    /// `SecurityPreset::MozillaModern.configure(ClientConfig::builder())`.
+    pub fn configure<S: ConfigSide>(
+        &self,
+        builder: ConfigBuilder<S, WantsCipherSuites>,
+    ) -> ConfigBuilder<S, WantsVerifier> {
+        Policy::preset(*self)
+            .configure(builder)
+            .expect("the suites of a preset cover its versions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_consistent() {
+        for preset in [
+            SecurityPreset::MozillaModern,
+            SecurityPreset::MozillaIntermediate,
+        ] {
+            for suite in preset.cipher_suites() {
+                assert!(preset.versions().contains(&suite.version()));
+            }
+            preset.configure(rustls::ClientConfig::builder());
+            preset.configure(rustls::ServerConfig::builder());
+        }
+    }
+}
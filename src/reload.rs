@@ -0,0 +1,201 @@
+//! Polling reload of certificate and key files.
+//!
+//! Kubernetes (and similar systems) rotate secrets by writing a fresh
+//! timestamped directory and atomically swapping a `..data` symlink. File
+//! watchers are unreliable under that scheme, so the `PollingReloader` simply
+//! re-reads the files on an interval and swaps the config when their contents
+//! changed. The files are read on a background thread, so accepting a
+//! connection never waits on the filesystem.
+
+use crate::acceptor::TlsAcceptor;
+use crate::common::pem;
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type BuildConfig =
+    dyn Fn(Vec<Certificate>, PrivateKey) -> Result<ServerConfig, rustls::Error> + Send + Sync;
+
+/// Builds a `TlsAcceptor` whose certificate and key are re-read from disk.
+///
+/// The files are checked lazily: once `interval` has elapsed, the next call to
+/// `TlsAcceptor::accept` starts re-reading both files on a background thread
+/// and, if they changed and parse correctly, later connections get a new
+/// `ServerConfig`. The accept itself goes on with the current config, and
+/// connections already in flight keep the config they started with. No
+/// runtime is needed, so this works with any of them.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::PollingReloader;
+/// use std::time::Duration;
+///
+/// let acceptor = PollingReloader::new("/etc/tls/tls.crt", "/etc/tls/tls.key")
+///     .interval(Duration::from_secs(30))
+///     .build()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct PollingReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    interval: Duration,
+    build: Box<BuildConfig>,
+}
+
+impl PollingReloader {
+    /// Reload from a PEM certificate chain and a PEM private key.
+    ///
+    /// By default the files are checked every 60 seconds and turned into a
+    /// `ServerConfig` with safe defaults and no client authentication.
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        PollingReloader {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            interval: Duration::from_secs(60),
+            build: Box::new(|certs, key| {
+                ServerConfig::builder()
+                    .with_safe_defaults()
+                    .with_no_client_auth()
+                    .with_single_cert(certs, key)
+            }),
+        }
+    }
+
+    /// Set how often the files are re-read.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Customize how a `ServerConfig` is built from the loaded certificate chain and key.
+    ///
+    /// Use this to set ALPN protocols, client authentication and similar options.
+    pub fn config<F>(mut self, build: F) -> Self
+    where
+        F: Fn(Vec<Certificate>, PrivateKey) -> Result<ServerConfig, rustls::Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.build = Box::new(build);
+        self
+    }
+
+    /// Load the files once and create the acceptor.
+    ///
+    /// Fails if the initial load fails; later failures keep the previous config.
+    pub fn build(self) -> io::Result<TlsAcceptor> {
+        let contents = read_consistent(&self.cert_path, &self.key_path)?;
+        let config = build_config(&contents, &*self.build)?;
+        let reloader = Reloader {
+            cert_path: self.cert_path,
+            key_path: self.key_path,
+            interval: self.interval,
+            build: self.build,
+            config: RwLock::new(Arc::new(config)),
+            contents: Mutex::new(contents),
+            started: Instant::now(),
+            next_check: AtomicU64::new(millis(self.interval)),
+            reloading: AtomicBool::new(false),
+        };
+        Ok(TlsAcceptor::from_reloader(Arc::new(reloader)))
+    }
+}
+
+impl fmt::Debug for PollingReloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollingReloader")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+pub(crate) struct Reloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    interval: Duration,
+    build: Box<BuildConfig>,
+    /// The published config, only locked to clone or swap the `Arc`.
+    config: RwLock<Arc<ServerConfig>>,
+    /// The file contents `config` was built from, held while reloading.
+    contents: Mutex<(Vec<u8>, Vec<u8>)>,
+    started: Instant,
+    /// When the files are due to be checked, in milliseconds since `started`.
+    next_check: AtomicU64,
+    reloading: AtomicBool,
+}
+
+impl Reloader {
+    /// The current config, starting a reload in the background if the interval elapsed.
+    pub(crate) fn config(self: &Arc<Self>) -> Arc<ServerConfig> {
+        let due = millis(self.started.elapsed()) >= self.next_check.load(Ordering::Relaxed);
+        if due && !self.reloading.swap(true, Ordering::Acquire) {
+            let reloader = self.clone();
+            let spawned = thread::Builder::new()
+                .name("async-tls-reload".into())
+                .spawn(move || {
+                    // A failed reload keeps serving the previous config; the
+                    // next interval will try again.
+                    let _ = reloader.reload();
+                    reloader.reloading.store(false, Ordering::Release);
+                });
+            if spawned.is_err() {
+                self.reloading.store(false, Ordering::Release);
+            }
+        }
+        self.config.read().unwrap().clone()
+    }
+
+    /// Re-read the files now, returning whether the config was swapped.
+    pub(crate) fn reload(&self) -> io::Result<bool> {
+        let mut contents = self.contents.lock().unwrap();
+        let next_check = self.started.elapsed() + self.interval;
+        self.next_check.store(millis(next_check), Ordering::Relaxed);
+
+        let new_contents = read_consistent(&self.cert_path, &self.key_path)?;
+        if new_contents == *contents {
+            return Ok(false);
+        }
+
+        let config = build_config(&new_contents, &*self.build)?;
+        *self.config.write().unwrap() = Arc::new(config);
+        *contents = new_contents;
+        Ok(true)
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Read both files, making sure the certificate did not change while the key
+/// was read. Otherwise a symlink swap between the two reads could pair the new
+/// certificate with the old key.
+fn read_consistent(cert_path: &Path, key_path: &Path) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let cert = fs::read(cert_path)?;
+    let key = fs::read(key_path)?;
+    if fs::read(cert_path)? != cert {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "certificate changed while reloading",
+        ));
+    }
+    Ok((cert, key))
+}
+
+fn build_config((cert, key): &(Vec<u8>, Vec<u8>), build: &BuildConfig) -> io::Result<ServerConfig> {
+    let certs = pem::certs(cert)?;
+    let key = pem::private_key(key)?;
+    build(certs, key).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -0,0 +1,174 @@
+//! Handshake signatures produced by a remote key service.
+
+use rustls::sign::{CertifiedKey, Signer, SigningKey};
+use rustls::{Certificate, SignatureAlgorithm, SignatureScheme};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// A boxed future, as returned by `RemoteSigner`.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A private key held by a remote service, such as AWS KMS or GCP Cloud KMS.
+///
+/// Implement this on top of the service's client and wrap it in a
+/// `RemoteSigningKey` to use it wherever rustls expects a `SigningKey`.
+///
+/// The same goes for hardware-bound keys, e.g. in an HSM. Their blocking
+/// calls fit here: the returned future is driven on its own thread, so do the
+/// work inside it rather than in `sign` itself. Keys in a TPM 2.0 are supported
+/// by `TpmKey`, with the `tpm` feature.
+pub trait RemoteSigner: Send + Sync + 'static {
+    /// The algorithm of the key.
+    fn algorithm(&self) -> SignatureAlgorithm;
+
+    /// The signature schemes the key can produce, most preferred first.
+    fn schemes(&self) -> &[SignatureScheme];
+
+    /// Sign `message` using `scheme`.
+    ///
+    /// The message is the raw data to be signed; hashing it is part of the scheme.
+    fn sign(
+        &self,
+        scheme: SignatureScheme,
+        message: Vec<u8>,
+    ) -> BoxFuture<'static, io::Result<Vec<u8>>>;
+}
+
+/// Adapts a `RemoteSigner` to rustls' synchronous `SigningKey`.
+///
+/// rustls asks for the signature in the middle of processing handshake data, so
+/// the handshake cannot be suspended while the remote call is in flight. The
+/// signing future is driven on a dedicated thread and the task performing the
+/// handshake waits for it, at most `timeout`. This keeps futures that depend on
+/// the caller's executor from deadlocking it, but the executor thread is blocked
+/// for the round-trip, so prefer a multi-threaded executor.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "server")]
+/// # fn main() {
+/// use async_tls::{CertStore, RemoteSigner, RemoteSigningKey};
+/// use std::sync::Arc;
+/// # fn kms_signer() -> Arc<dyn RemoteSigner> { todo!() }
+/// # let chain = vec![];
+///
+/// let key = RemoteSigningKey::new(kms_signer());
+/// let store = CertStore::new();
+/// store.insert("example.com", key.certified(chain));
+/// # }
+/// # #[cfg(not(feature = "server"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct RemoteSigningKey {
+    signer: Arc<dyn RemoteSigner>,
+    timeout: Duration,
+}
+
+impl RemoteSigningKey {
+    /// Wrap `signer`, with a signing timeout of 10 seconds.
+    pub fn new(signer: Arc<dyn RemoteSigner>) -> Self {
+        RemoteSigningKey {
+            signer,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Fail the handshake if a signature takes longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pair the key with its certificate chain.
+    pub fn certified(self, chain: Vec<Certificate>) -> CertifiedKey {
+        CertifiedKey::new(chain, Arc::new(self))
+    }
+}
+
+impl SigningKey for RemoteSigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        let scheme = self
+            .signer
+            .schemes()
+            .iter()
+            .find(|scheme| offered.contains(scheme))?;
+        Some(Box::new(RemoteSchemeSigner {
+            key: self.clone(),
+            scheme: *scheme,
+        }))
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.signer.algorithm()
+    }
+}
+
+impl fmt::Debug for RemoteSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteSigningKey")
+            .field("algorithm", &self.signer.algorithm())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+struct RemoteSchemeSigner {
+    key: RemoteSigningKey,
+    scheme: SignatureScheme,
+}
+
+impl Signer for RemoteSchemeSigner {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        let future = self.key.signer.sign(self.scheme, message.to_vec());
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::Builder::new()
+            .name("async-tls-remote-sign".into())
+            .spawn(move || {
+                let _ = tx.send(block_on(future));
+            })
+            .map_err(|err| rustls::Error::General(format!("remote signing: {}", err)))?;
+
+        match rx.recv_timeout(self.key.timeout) {
+            Ok(Ok(signature)) => Ok(signature),
+            Ok(Err(err)) => Err(rustls::Error::General(format!("remote signing: {}", err))),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                Err(rustls::Error::General("remote signing timed out".into()))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(rustls::Error::General("remote signer panicked".into()))
+            }
+        }
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+}
+
+/// Drive `future` to completion on the current thread.
+fn block_on<T>(mut future: BoxFuture<'static, T>) -> T {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
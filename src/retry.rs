@@ -0,0 +1,135 @@
+//! Retrying connections that failed for transient reasons.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+/// When and how often `TlsConnector::connect_with_retry` tries again.
+///
+/// Only transient failures are retried, see `is_transient`. The backoff
+/// doubles with every attempt, up to `max_backoff`, and each wait is drawn at
+/// random below it ("full jitter"), so clients failing together do not retry
+/// together.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    attempt_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retry up to three times, backing off from 100ms up to 5s.
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    /// Retry up to `retries` times after the first attempt. Defaults to 3.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Back off up to `initial` before the first retry. Defaults to 100ms.
+    pub fn initial_backoff(mut self, initial: Duration) -> Self {
+        self.initial_backoff = initial;
+        self
+    }
+
+    /// Never back off for more than `max`. Defaults to 5s.
+    pub fn max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = max;
+        self
+    }
+
+    /// Fail attempts taking longer than `timeout` to connect and handshake
+    /// with `TimedOut`, which is retried. No timeout by default.
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = Some(timeout);
+        self
+    }
+
+    /// Whether `err` is worth another attempt.
+    ///
+    /// Connections refused, reset or aborted, timeouts and peers hanging up
+    /// during the handshake are transient. Invalid certificates, alerts and
+    /// other TLS failures are not, nor are invalid names or addresses.
+    pub fn is_transient(err: &io::Error) -> bool {
+        matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::UnexpectedEof
+        )
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.attempt_timeout
+    }
+
+    /// How long to wait before retry number `retry`, counting from 0, or
+    /// `None` if no retries are left.
+    pub(crate) fn backoff(&self, retry: u32) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << retry.min(31))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        Some(backoff.mul_f64(jitter()))
+    }
+}
+
+/// A random factor in `[0, 1)`, from the random keys of the standard library.
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy::new()
+            .max_retries(8)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1));
+        for retry in 0..8 {
+            let cap = Duration::from_millis(100 << retry).min(Duration::from_secs(1));
+            assert!(policy.backoff(retry).unwrap() <= cap);
+        }
+        assert_eq!(policy.backoff(8), None);
+        assert_eq!(RetryPolicy::new().max_retries(0).backoff(0), None);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let transient = |kind| RetryPolicy::is_transient(&io::Error::from(kind));
+        assert!(transient(io::ErrorKind::ConnectionReset));
+        assert!(transient(io::ErrorKind::TimedOut));
+        assert!(transient(io::ErrorKind::UnexpectedEof));
+        assert!(!transient(io::ErrorKind::InvalidData));
+        assert!(!transient(io::ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::passthrough::SniffClientHello;
+use crate::server;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::{AlertDescription, ServerConfig};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// What to do with the connections asking for a hostname, see `Router`.
+#[derive(Clone)]
+pub enum Route<T> {
+    /// Terminate TLS with this config.
+    Terminate(Arc<ServerConfig>),
+    /// Forward the raw TLS bytes to a target, e.g. the address of a backend.
+    Passthrough(T),
+}
+
+impl<T: fmt::Debug> fmt::Debug for Route<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Route::Terminate(_) => f.write_str("Terminate"),
+            Route::Passthrough(target) => f.debug_tuple("Passthrough").field(target).finish(),
+        }
+    }
+}
+
+/// A connection routed by a `Router`.
+#[allow(clippy::large_enum_variant)]
+pub enum Routed<IO, T> {
+    /// TLS was terminated, the handshake is done.
+    Terminated(server::TlsStream<IO>),
+    /// The connection is to be forwarded to `target`, with `replay` sent first:
+    /// the bytes read from `io` for its `ClientHello`.
+    Passthrough { target: T, replay: Vec<u8>, io: IO },
+}
+
+impl<IO, T: fmt::Debug> fmt::Debug for Routed<IO, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Routed::Terminated(_) => f.write_str("Terminated"),
+            Routed::Passthrough { target, replay, .. } => f
+                .debug_struct("Passthrough")
+                .field("target", target)
+                .field("replay", &replay.len())
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Routes connections by the SNI hostname of their `ClientHello`: TLS is
+/// either terminated with a `ServerConfig`, or passed through to a target.
+///
+/// Besides exact hostnames, wildcard patterns such as `*.example.com` can be
+/// registered. An exact pattern always wins, otherwise the wildcard with the
+/// longest matching suffix is used. Clients sending no SNI or an unknown
+/// hostname take the fallback route; without one, their handshake is aborted
+/// with an `unrecognized_name` alert.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use async_tls::{Route, Routed, Router};
+/// use std::net::SocketAddr;
+/// # use std::sync::Arc;
+/// # fn main() -> std::io::Result<()> {
+/// # let config: Arc<rustls::ServerConfig> = todo!();
+///
+/// let router = Router::new()
+///     .terminate("example.com", config.clone())
+///     .passthrough("*.internal.example.com", SocketAddr::from(([10, 0, 0, 1], 443)))
+///     .fallback(Route::Terminate(config));
+///
+/// # async_std::task::block_on(async {
+/// # let tcp_stream = async_std::net::TcpStream::connect("127.0.0.1:8443").await?;
+/// match router.accept(tcp_stream).await? {
+///     Routed::Terminated(stream) => { /* serve the client */ }
+///     Routed::Passthrough { target, replay, io } => {
+///         /* connect to `target`, send `replay`, then copy between both */
+///     }
+/// }
+/// # Ok(()) as std::io::Result<()>
+/// # })
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Router<T> {
+    routes: HashMap<String, Route<T>>,
+    fallback: Option<Route<T>>,
+}
+
+impl<T> Router<T> {
+    /// Create a router without any routes.
+    pub fn new() -> Self {
+        Router {
+            routes: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Terminate TLS with `config` for clients asking for `hostname`, which
+    /// may be a wildcard like `*.example.com`.
+    pub fn terminate(mut self, hostname: &str, config: impl Into<Arc<ServerConfig>>) -> Self {
+        let route = Route::Terminate(config.into());
+        self.routes.insert(normalize(hostname), route);
+        self
+    }
+
+    /// Pass the connections of clients asking for `hostname` through to
+    /// `target`. `hostname` may be a wildcard like `*.example.com`.
+    pub fn passthrough(mut self, hostname: &str, target: T) -> Self {
+        self.routes
+            .insert(normalize(hostname), Route::Passthrough(target));
+        self
+    }
+
+    /// Route clients sending no SNI or an unknown hostname with `route`.
+    pub fn fallback(mut self, route: Route<T>) -> Self {
+        self.fallback = Some(route);
+        self
+    }
+
+    /// The route taken by clients asking for `server_name`.
+    pub fn route(&self, server_name: Option<&str>) -> Option<&Route<T>> {
+        server_name
+            .and_then(|name| self.lookup(&normalize(name)))
+            .or(self.fallback.as_ref())
+    }
+
+    fn lookup(&self, hostname: &str) -> Option<&Route<T>> {
+        if let Some(route) = self.routes.get(hostname) {
+            return Some(route);
+        }
+
+        // a.b.example.com tries *.b.example.com, then *.example.com, ...
+        let mut rest = hostname;
+        while let Some((_, parent)) = rest.split_once('.') {
+            if let Some(route) = self.routes.get(&format!("*.{}", parent)) {
+                return Some(route);
+            }
+            rest = parent;
+        }
+        None
+    }
+}
+
+impl<T: Clone> Router<T> {
+    /// Read the `ClientHello` from `io` and route it, completing the handshake
+    /// of terminated connections.
+    ///
+    /// Fails like `TlsAcceptor::accept` does, and with `Error::Rejected` for
+    /// a client without a route.
+    pub async fn accept<IO>(&self, io: IO) -> io::Result<Routed<IO, T>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let hello = SniffClientHello::new(io).await?;
+        match self.route(hello.server_name()) {
+            Some(Route::Terminate(config)) => {
+                let stream = hello.into_stream(config.clone()).await?;
+                Ok(Routed::Terminated(stream))
+            }
+            Some(Route::Passthrough(target)) => {
+                let target = target.clone();
+                let (replay, io) = hello.into_parts();
+                Ok(Routed::Passthrough { target, replay, io })
+            }
+            None => {
+                hello.reject(AlertDescription::UnrecognisedName).await?;
+                unreachable!("a rejected handshake fails")
+            }
+        }
+    }
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Router<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.routes)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+fn normalize(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
@@ -1,114 +1,137 @@
+#[cfg(feature = "server")]
+use crate::admission;
+use crate::common::chunking::WriteChunking;
+use crate::common::coalesce::Coalescer;
+use crate::common::flush::HandshakeFlush;
+use crate::common::hello::HelloSniffer;
+use crate::common::io_state::IoState;
+#[cfg(feature = "server")]
+use crate::common::policy::Policy;
+#[cfg(feature = "server")]
+use crate::common::probe::ClientProbe;
+use crate::common::traffic::Traffic;
+use crate::Error;
+
 use futures_core::ready;
 use futures_io::{AsyncRead, AsyncWrite};
-#[cfg(feature = "early-data")]
-use rustls::client::WriteEarlyData;
-use rustls::{ClientConnection, IoState, Reader, ServerConnection, Writer};
-use std::io::{self, Read, Write};
+#[cfg(feature = "server")]
+use rustls::{AlertDescription, PeerIncompatible};
+use rustls::{ConnectionCommon, SideData};
+use std::io::{self, IoSlice, Read, Write};
 use std::marker::Unpin;
+use std::ops::DerefMut;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-pub struct Stream<'a, IO> {
+/// Drives a rustls connection over an async IO: a `ClientConnection`, a
+/// `ServerConnection` or any other `ConnectionCommon`.
+pub struct Stream<'a, IO, C> {
     pub io: &'a mut IO,
-    pub conn: Conn<'a>,
+    pub conn: &'a mut C,
     pub eof: bool,
+    /// When the handshake flushes the IO.
+    pub flush: HandshakeFlush,
+    /// Watches the handshake, to tell whether a session was resumed.
+    pub hello: Option<&'a mut HelloSniffer>,
+    /// Counts the bytes received and sent.
+    pub traffic: Option<&'a mut Traffic>,
+    /// Whether reads stop at the end of each record, as counted by `traffic`,
+    /// leaving what follows `close_notify` in the IO.
+    pub exact_reads: bool,
+    /// How much of a caller's buffer a write takes.
+    pub chunking: WriteChunking,
+    /// Keeps small writes back, to encrypt them together.
+    pub coalescer: Option<&'a mut Coalescer>,
+    /// Keeps what servers receive first, in case it is no TLS.
+    #[cfg(feature = "server")]
+    pub probe: Option<&'a mut ClientProbe>,
+    /// The versions and suites a server allows, checked before it answers.
+    #[cfg(feature = "server")]
+    pub policy: Option<&'a Policy>,
+    /// Where to keep the state of the buffers, as rustls told it last and
+    /// updated by the plaintext read and records sent since.
+    pub io_state: Option<&'a mut Option<IoState>>,
 }
 
-pub(crate) enum Conn<'a> {
-    Client(&'a mut ClientConnection),
-    Server(&'a mut ServerConnection),
+/// Adapts an `AsyncRead` to `std::io::Read`, turning `Pending` into `WouldBlock`.
+pub(crate) struct SyncReader<'a, 'b, T> {
+    pub(crate) io: &'a mut T,
+    pub(crate) cx: &'a mut Context<'b>,
 }
 
-impl Conn<'_> {
-    pub(crate) fn is_handshaking(&self) -> bool {
-        match self {
-            Conn::Client(c) => c.is_handshaking(),
-            Conn::Server(c) => c.is_handshaking(),
-        }
-    }
-
-    pub(crate) fn wants_write(&self) -> bool {
-        match self {
-            Conn::Client(c) => c.wants_write(),
-            Conn::Server(c) => c.wants_write(),
-        }
-    }
-
-    pub(crate) fn wants_read(&self) -> bool {
-        match self {
-            Conn::Client(c) => c.wants_read(),
-            Conn::Server(c) => c.wants_read(),
+impl<'a, 'b, T: AsyncRead + Unpin> Read for SyncReader<'a, 'b, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match Pin::new(&mut self.io).poll_read(self.cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
         }
     }
+}
 
-    pub(crate) fn write_tls(&mut self, wr: &mut dyn io::Write) -> Result<usize, io::Error> {
-        match self {
-            Conn::Client(c) => c.write_tls(wr),
-            Conn::Server(c) => c.write_tls(wr),
-        }
-    }
+/// Passes the bytes read or written on to a `HelloSniffer`, `Traffic` and
+/// `ClientProbe`, if any.
+struct Sniff<'a, T> {
+    inner: T,
+    sniffer: Option<&'a mut HelloSniffer>,
+    traffic: Option<&'a mut Traffic>,
+    /// The most bytes a read takes.
+    limit: usize,
+    #[cfg(feature = "server")]
+    probe: Option<&'a mut ClientProbe>,
+}
 
-    pub(crate) fn reader(&mut self) -> Reader {
-        match self {
-            Conn::Client(c) => c.reader(),
-            Conn::Server(c) => c.reader(),
+impl<T: Read> Read for Sniff<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.limit);
+        let n = self.inner.read(&mut buf[..len])?;
+        if let Some(sniffer) = self.sniffer.as_mut() {
+            sniffer.feed(&buf[..n]);
         }
-    }
-
-    pub(crate) fn writer(&mut self) -> Writer {
-        match self {
-            Conn::Client(c) => c.writer(),
-            Conn::Server(c) => c.writer(),
+        if let Some(traffic) = self.traffic.as_mut() {
+            traffic.received(&buf[..n]);
         }
-    }
-
-    pub(crate) fn send_close_notify(&mut self) {
-        match self {
-            Conn::Client(c) => c.send_close_notify(),
-            Conn::Server(c) => c.send_close_notify(),
+        #[cfg(feature = "server")]
+        if let Some(probe) = self.probe.as_mut() {
+            probe.feed(&buf[..n]);
         }
+        Ok(n)
     }
+}
 
-    pub(crate) fn read_tls(&mut self, rd: &mut dyn io::Read) -> Result<usize, io::Error> {
-        match self {
-            Conn::Client(c) => c.read_tls(rd),
-            Conn::Server(c) => c.read_tls(rd),
+impl<T> Sniff<'_, T> {
+    fn sent(&mut self, data: &[u8]) {
+        if let Some(sniffer) = self.sniffer.as_mut() {
+            sniffer.feed(data);
         }
-    }
-
-    pub(crate) fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
-        match self {
-            Conn::Client(c) => c.process_new_packets(),
-            Conn::Server(c) => c.process_new_packets(),
+        if let Some(traffic) = self.traffic.as_mut() {
+            traffic.sent(data);
         }
     }
+}
 
-    #[cfg(feature = "early-data")]
-    pub(crate) fn is_early_data_accepted(&self) -> bool {
-        match self {
-            Conn::Client(c) => c.is_early_data_accepted(),
-            Conn::Server(_) => false,
-        }
+impl<T: Write> Write for Sniff<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.sent(&buf[..n]);
+        Ok(n)
     }
 
-    #[cfg(feature = "early-data")]
-    pub(crate) fn client_early_data(&mut self) -> Option<WriteEarlyData<'_>> {
-        match self {
-            Conn::Client(c) => c.early_data(),
-            Conn::Server(_) => None,
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        let mut left = n;
+        for buf in bufs {
+            let len = buf.len().min(left);
+            self.sent(&buf[..len]);
+            left -= len;
+            if left == 0 {
+                break;
+            }
         }
+        Ok(n)
     }
-}
-
-impl<'a> From<&'a mut ClientConnection> for Conn<'a> {
-    fn from(conn: &'a mut ClientConnection) -> Self {
-        Conn::Client(conn)
-    }
-}
 
-impl<'a> From<&'a mut ServerConnection> for Conn<'a> {
-    fn from(conn: &'a mut ServerConnection) -> Self {
-        Conn::Server(conn)
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
     }
 }
 
@@ -123,14 +146,30 @@ enum Focus {
     Writable,
 }
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
-    pub fn new(io: &'a mut IO, conn: impl Into<Conn<'a>>) -> Self {
+impl<'a, IO, C, D> Stream<'a, IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
+    pub fn new(io: &'a mut IO, conn: &'a mut C) -> Self {
         Stream {
             io,
-            conn: conn.into(),
+            conn,
             // The state so far is only used to detect EOF, so either Stream
             // or EarlyData state should both be all right.
             eof: false,
+            flush: HandshakeFlush::Eager,
+            hello: None,
+            traffic: None,
+            exact_reads: false,
+            chunking: WriteChunking::Whole,
+            coalescer: None,
+            #[cfg(feature = "server")]
+            probe: None,
+            #[cfg(feature = "server")]
+            policy: None,
+            io_state: None,
         }
     }
 
@@ -139,30 +178,137 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
         self
     }
 
+    pub fn set_flush(mut self, flush: HandshakeFlush) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Watch the handshake with `sniffer`: the records the client receives,
+    /// or the server sends, as told by `HelloSniffer::client` and `server`.
+    pub fn set_hello(mut self, sniffer: &'a mut HelloSniffer) -> Self {
+        self.hello = Some(sniffer);
+        self
+    }
+
+    /// Count the bytes received and sent in `traffic`.
+    pub fn set_traffic(mut self, traffic: &'a mut Traffic) -> Self {
+        self.traffic = Some(traffic);
+        self
+    }
+
+    /// Read no further than the end of each record, see `exact_reads`.
+    pub fn set_exact_reads(mut self, exact: bool) -> Self {
+        self.exact_reads = exact;
+        self
+    }
+
+    pub fn set_chunking(mut self, chunking: WriteChunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Coalesce small writes in `coalescer`.
+    pub fn set_coalescer(mut self, coalescer: &'a mut Coalescer) -> Self {
+        self.coalescer = Some(coalescer);
+        self
+    }
+
+    /// Keep what is received first in `probe`, to tell clients not speaking TLS.
+    #[cfg(feature = "server")]
+    pub fn set_probe(mut self, probe: &'a mut ClientProbe) -> Self {
+        self.probe = Some(probe);
+        self
+    }
+
+    /// Refuse clients negotiating what `policy` does not allow, before
+    /// sending anything.
+    #[cfg(feature = "server")]
+    pub fn set_policy(mut self, policy: &'a Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Keep the state rustls tells once records were processed in `state`,
+    /// `None` once the connection failed.
+    pub fn set_io_state(mut self, state: &'a mut Option<IoState>) -> Self {
+        self.io_state = Some(state);
+        self
+    }
+
     pub fn as_mut_pin(&mut self) -> Pin<&mut Self> {
         Pin::new(self)
     }
 
     pub fn complete_io(&mut self, cx: &mut Context) -> Poll<io::Result<(usize, usize)>> {
-        self.complete_inner_io(cx, Focus::Empty)
+        let result = self.complete_inner_io(cx, Focus::Empty);
+        if let Poll::Ready(Err(_)) = result {
+            return result;
+        }
+        // The peer can only answer what reached it. This is the one flush of
+        // `HandshakeFlush::Batched`, and it runs under `Eager` as well: the
+        // stream keeps no state between polls, so an eager flush that was
+        // pending last time is only finished here. Flushing IO with nothing
+        // buffered is cheap.
+        ready!(Pin::new(&mut self.io).poll_flush(cx))?;
+        result
     }
 
-    fn complete_read_io(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
-        struct Reader<'a, 'b, T> {
-            io: &'a mut T,
-            cx: &'a mut Context<'b>,
+    /// Hand the writes kept back by the coalescer over to rustls, without any IO.
+    pub fn uncork(&mut self) -> io::Result<()> {
+        if let Some(coalescer) = self.coalescer.as_deref_mut() {
+            let len = self.conn.writer().write(coalescer.buffered())?;
+            coalescer.consume(len);
         }
+        Ok(())
+    }
 
-        impl<'a, 'b, T: AsyncRead + Unpin> Read for Reader<'a, 'b, T> {
-            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-                match Pin::new(&mut self.io).poll_read(self.cx, buf) {
-                    Poll::Ready(result) => result,
-                    Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
-                }
+    /// Encrypt and send the writes kept back by the coalescer.
+    fn poll_uncork(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while self
+            .coalescer
+            .as_deref()
+            .is_some_and(|c| !c.buffered().is_empty())
+        {
+            self.uncork()?;
+            while self.conn.wants_write() {
+                ready!(self.complete_inner_io(cx, Focus::Writable))?;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Keep `buf` back with the coalescer, sending what it holds once due.
+    fn poll_write_coalesced(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.coalescer.as_deref().is_some_and(Coalescer::is_due) {
+            ready!(self.poll_uncork(cx))?;
+        }
+        let len = match self.coalescer.as_deref_mut() {
+            Some(coalescer) => coalescer.push(buf),
+            None => 0,
+        };
+        if self.coalescer.as_deref().is_some_and(Coalescer::is_due) {
+            // `buf` is taken either way, the IO catches up with the next write
+            if let Poll::Ready(Err(err)) = self.poll_uncork(cx) {
+                return Poll::Ready(Err(err));
             }
         }
+        Poll::Ready(Ok(len))
+    }
 
-        let mut reader = Reader { io: self.io, cx };
+    fn complete_read_io(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let sniffer = (self.hello.as_deref_mut()).filter(|sniffer| sniffer.watches_received());
+        let limit = match self.traffic {
+            Some(ref traffic) if self.exact_reads => traffic.received_record_left(),
+            _ => usize::MAX,
+        };
+        let mut reader = Sniff {
+            inner: SyncReader { io: self.io, cx },
+            sniffer,
+            traffic: self.traffic.as_deref_mut(),
+            limit,
+            #[cfg(feature = "server")]
+            probe: self.probe.as_deref_mut(),
+        };
 
         let n = match self.conn.read_tls(&mut reader) {
             Ok(n) => n,
@@ -170,19 +316,81 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
             Err(err) => return Poll::Ready(Err(err)),
         };
 
-        self.conn.process_new_packets().map_err(|err| {
-            // In case we have an alert to send describing this error,
-            // try a last-gasp write -- but don't predate the primary
-            // error.
-            let _ = self.write_tls(cx);
+        let err = match self.conn.process_new_packets() {
+            Ok(state) => {
+                self.keep_io_state(Some(state.into()));
+                return Poll::Ready(Ok(n));
+            }
+            Err(err) => err,
+        };
+        self.keep_io_state(None);
+
+        // In case we have an alert to send describing this error,
+        // try a last-gasp write -- but don't predate the primary
+        // error. Several records may be queued, e.g. a ChangeCipherSpec
+        // ahead of the alert. Plaintext HTTP clients get none, so the
+        // server can answer them.
+        #[cfg(feature = "server")]
+        let plaintext = self.probe.as_ref().is_some_and(|probe| probe.is_http());
+        #[cfg(not(feature = "server"))]
+        let plaintext = false;
+        while !plaintext && self.conn.wants_write() {
+            match self.write_tls(cx) {
+                Ok(n) if n > 0 => (),
+                _ => break,
+            }
+        }
 
-            io::Error::new(io::ErrorKind::InvalidData, err)
-        })?;
+        Poll::Ready(Err(io::Error::from(Error::from(err))))
+    }
 
-        Poll::Ready(Ok(n))
+    fn keep_io_state(&mut self, state: Option<IoState>) {
+        if let Some(kept) = self.io_state.as_deref_mut() {
+            *kept = state;
+        }
+    }
+
+    /// Update the state kept, if any records were processed and the
+    /// connection did not fail since.
+    fn update_io_state(&mut self, update: impl FnOnce(&mut IoState)) {
+        if let Some(Some(state)) = self.io_state.as_deref_mut() {
+            update(state);
+        }
+    }
+
+    /// Fail with an alert instead of the answer rustls queued, if the version or
+    /// suite negotiated is not allowed.
+    #[cfg(feature = "server")]
+    fn check_policy(&mut self, cx: &mut Context) -> io::Result<()> {
+        let policy = match self.policy {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        let (alert, err) = match (
+            self.conn.protocol_version(),
+            self.conn.negotiated_cipher_suite(),
+        ) {
+            (Some(version), _) if !policy.allows(version) => {
+                let err = match self.probe {
+                    Some(ref probe) => probe.unsupported_version(version),
+                    None => ClientProbe::default().unsupported_version(version),
+                };
+                (AlertDescription::ProtocolVersion, err)
+            }
+            (_, Some(suite)) if !policy.allows_suite(suite) => {
+                let err = rustls::Error::from(PeerIncompatible::NoCipherSuitesInCommon);
+                (AlertDescription::HandshakeFailure, Error::from(err).into())
+            }
+            _ => return Ok(()),
+        };
+        // a last gasp, like for the alerts of rustls
+        let _ = Pin::new(&mut self.io).poll_write(cx, &admission::alert_record(alert));
+        Err(err)
     }
 
     fn complete_write_io(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
+        #[cfg(feature = "server")]
+        self.check_policy(cx)?;
         match self.write_tls(cx) {
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
             result => Poll::Ready(result),
@@ -210,6 +418,27 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
                     }
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                 }
+                if let (Focus::Empty, HandshakeFlush::Eager) = (focus, self.flush) {
+                    match Pin::new(&mut self.io).poll_flush(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Pending => {
+                            write_would_block = true;
+                            break;
+                        }
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    }
+                }
+            }
+
+            // Writing needs no reads. Reading anyway would register the task
+            // for readable IO, waking it for data only a reader waits for, and
+            // replacing the waker of a task reading at the same time.
+            if let Focus::Writable = focus {
+                return if write_would_block && wrlen == 0 {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(Ok((rdlen, wrlen)))
+                };
             }
 
             if !self.eof && self.conn.wants_read() {
@@ -223,20 +452,17 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
 
             let would_block = match focus {
                 Focus::Empty => write_would_block || read_would_block,
-                Focus::Readable => read_would_block,
-                Focus::Writable => write_would_block,
+                Focus::Readable | Focus::Writable => read_would_block,
             };
 
             match (self.eof, self.conn.is_handshaking(), would_block) {
                 (true, true, _) => {
-                    let err = io::Error::new(io::ErrorKind::UnexpectedEof, "tls handshake eof");
-                    return Poll::Ready(Err(err));
+                    return Poll::Ready(Err(Error::PeerClosed.into()));
                 }
                 (_, false, true) => {
                     let would_block = match focus {
                         Focus::Empty => rdlen == 0 && wrlen == 0,
-                        Focus::Readable => rdlen == 0,
-                        Focus::Writable => wrlen == 0,
+                        Focus::Readable | Focus::Writable => rdlen == 0,
                     };
 
                     return if would_block {
@@ -253,23 +479,38 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> Stream<'a, IO> {
     }
 }
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin> WriteTls<IO> for Stream<'a, IO> {
+impl<'a, IO, C, D> WriteTls<IO> for Stream<'a, IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
     fn write_tls(&mut self, cx: &mut Context) -> io::Result<usize> {
-        // TODO writev
-
         struct Writer<'a, 'b, T> {
             io: &'a mut T,
             cx: &'a mut Context<'b>,
+            /// The bytes rustls tried to write last.
+            offered: usize,
         }
 
         impl<'a, 'b, T: AsyncWrite + Unpin> Write for Writer<'a, 'b, T> {
             fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.offered = buf.len();
                 match Pin::new(&mut self.io).poll_write(self.cx, buf) {
                     Poll::Ready(result) => result,
                     Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
                 }
             }
 
+            // rustls hands over all queued records at once
+            fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+                self.offered = bufs.iter().map(|buf| buf.len()).sum();
+                match Pin::new(&mut self.io).poll_write_vectored(self.cx, bufs) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+                }
+            }
+
             fn flush(&mut self) -> io::Result<()> {
                 match Pin::new(&mut self.io).poll_flush(self.cx) {
                     Poll::Ready(result) => result,
@@ -278,12 +519,40 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> WriteTls<IO> for Stream<'a, IO> {
             }
         }
 
-        let mut writer = Writer { io: self.io, cx };
-        self.conn.write_tls(&mut writer)
+        let sniffer = (self.hello.as_deref_mut()).filter(|sniffer| !sniffer.watches_received());
+        let mut writer = Sniff {
+            inner: Writer {
+                io: self.io,
+                cx,
+                offered: 0,
+            },
+            sniffer,
+            traffic: self.traffic.as_deref_mut(),
+            limit: usize::MAX,
+            #[cfg(feature = "server")]
+            probe: None,
+        };
+        let result = self.conn.write_tls(&mut writer);
+
+        // rustls offers the records it queued, up to a few dozen at once, so
+        // what was not written of them is left
+        let offered = writer.inner.offered;
+        let left = match (self.conn.wants_write(), &result) {
+            (false, _) => 0,
+            (true, Ok(n)) => offered.saturating_sub(*n),
+            (true, Err(_)) => offered,
+        };
+        self.update_io_state(|state| state.sent(left));
+        result
     }
 }
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncRead for Stream<'a, IO> {
+impl<'a, IO, C, D> AsyncRead for Stream<'a, IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context,
@@ -291,6 +560,11 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncRead for Stream<'a, IO> {
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
 
+        // whatever waited long enough goes out with the IO reading does
+        if this.coalescer.as_deref().is_some_and(Coalescer::is_due) {
+            this.uncork()?;
+        }
+
         while !this.eof && this.conn.wants_read() {
             match this.complete_inner_io(cx, Focus::Readable) {
                 Poll::Ready(Ok((0, _))) => break,
@@ -300,22 +574,35 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncRead for Stream<'a, IO> {
             }
         }
 
-        let mut reader = this.conn.reader();
-        match reader.read(buf) {
+        let result = this.conn.reader().read(buf);
+        if let Ok(len) = result {
+            this.update_io_state(|state| state.read(len));
+        }
+        match result {
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
             Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
                 this.eof = true;
-                Poll::Ready(Err(err))
+                Poll::Ready(Err(Error::Truncated.into()))
             }
             result => Poll::Ready(result),
         }
     }
 }
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Stream<'a, IO> {
+impl<'a, IO, C, D> AsyncWrite for Stream<'a, IO, C>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    C: DerefMut<Target = ConnectionCommon<D>> + Unpin,
+    D: SideData,
+{
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
 
+        if this.coalescer.as_deref().is_some_and(|c| c.takes(buf)) {
+            return this.poll_write_coalesced(cx, buf);
+        }
+        let buf = this.chunking.chunk(buf);
+
         let len = match this.conn.writer().write(buf) {
             Ok(n) => n,
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
@@ -346,6 +633,7 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Stream<'a, IO> {
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
         let this = self.get_mut();
 
+        ready!(this.poll_uncork(cx))?;
         this.conn.writer().flush()?;
         while this.conn.wants_write() {
             ready!(this.complete_inner_io(cx, Focus::Writable))?;
@@ -356,6 +644,7 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Stream<'a, IO> {
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
 
+        ready!(this.poll_uncork(cx))?;
         while this.conn.wants_write() {
             ready!(this.complete_inner_io(cx, Focus::Writable))?;
         }
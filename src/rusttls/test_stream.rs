@@ -4,6 +4,7 @@ use futures_io::{AsyncRead, AsyncWrite};
 use futures_util::io::{AsyncReadExt, AsyncWriteExt};
 use futures_util::task::{noop_waker_ref, Context};
 use futures_util::{future, ready};
+use rustls::server::ServerConnectionData;
 use rustls::{
     Certificate, ClientConfig, ClientConnection, ConnectionCommon, PrivateKey, RootCertStore,
     ServerConfig, ServerConnection, ServerName,
@@ -11,6 +12,7 @@ use rustls::{
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use std::convert::TryFrom;
 use std::io::{self, BufReader, Cursor, Read, Write};
+use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::Poll;
@@ -118,6 +120,58 @@ async fn _stream_good() -> io::Result<()> {
     Ok(()) as io::Result<()>
 }
 
+/// Counts the reads from the wrapped IO.
+struct Reads<T>(T, usize);
+
+impl<T: AsyncRead + Unpin> AsyncRead for Reads<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.1 += 1;
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Reads<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+#[test]
+fn stream_write_without_reading() -> io::Result<()> {
+    block_on(async {
+        let (mut server, mut client) = make_pair();
+        future::poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+        let mut io = Reads(Good(&mut server), 0);
+        let mut stream = Stream::new(&mut io, &mut client);
+        stream.write_all(b"Hello World!").await?;
+        stream.flush().await?;
+        stream.close().await?;
+        assert_eq!(io.1, 0);
+
+        let mut buf = [0; 12];
+        server.reader().read_exact(&mut buf)?;
+        assert_eq!(&buf, b"Hello World!");
+        Ok(())
+    })
+}
+
 #[test]
 fn stream_bad() -> io::Result<()> {
     let fut = async {
@@ -175,6 +229,44 @@ fn stream_handshake() -> io::Result<()> {
     block_on(fut)
 }
 
+/// A connection type of its own, as rustls has none but clients and servers.
+struct Custom(ServerConnection);
+
+impl Deref for Custom {
+    type Target = ConnectionCommon<ServerConnectionData>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Custom {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[test]
+fn stream_custom_connection() -> io::Result<()> {
+    let (server, mut client) = make_pair();
+    let mut server = Custom(server);
+
+    {
+        let mut good = Good(&mut client);
+        let mut stream = Stream::new(&mut good, &mut server);
+        while stream.conn.is_handshaking() {
+            let (r, w) = block_on(future::poll_fn(|cx| stream.complete_io(cx)))?;
+            assert!(r > 0 || w > 0);
+        }
+        block_on(stream.write_all(b"Hello World!"))?;
+    }
+
+    let mut buf = [0; 12];
+    client.reader().read_exact(&mut buf)?;
+    assert_eq!(&buf, b"Hello World!");
+    Ok(())
+}
+
 #[test]
 fn stream_handshake_eof() -> io::Result<()> {
     let fut = async {
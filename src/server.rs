@@ -1,31 +1,317 @@
 //! The server end of a TLS connection.
 
+use crate::common::cancel;
+use crate::common::channel_binding;
+use crate::common::chunking::WriteChunking;
+use crate::common::close::{CloseMode, CloseTimer};
+use crate::common::coalesce::Coalescer;
+#[cfg(feature = "tokio")]
+use crate::common::compat;
+use crate::common::flush::HandshakeFlush;
+use crate::common::hello::HelloSniffer;
+use crate::common::io_state::IoState;
+use crate::common::policy::Policy;
+use crate::common::probe::ClientProbe;
 use crate::common::tls_state::TlsState;
+use crate::common::traffic::TrafficCounters;
+use crate::error::{HandshakeError, HandshakeStage};
+#[cfg(feature = "fingerprint")]
+use crate::fingerprint::ClientHelloFingerprint;
+use crate::observer::{HandshakeParams, HandshakeTimings, Observation};
 use crate::rusttls::stream::Stream;
+use crate::HandshakeKind;
 
+use futures_core::future::FusedFuture;
 use futures_core::ready;
 use futures_io::{AsyncRead, AsyncWrite};
-use rustls::ServerConnection;
-use std::future::Future;
+use rustls::{NamedGroup, ServerConnection};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io::Read;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+#[cfg(feature = "async-std")]
+use std::time::Duration;
+use std::time::Instant;
 use std::{io, mem};
 
 /// The server end of a TLS connection. Can be used like any other bidirectional IO stream.
 /// Wraps the underlying TCP stream.
-#[derive(Debug)]
+///
+/// Its `Debug` output shows the state of the connection and what was
+/// negotiated, never the data or keys it holds.
 pub struct TlsStream<IO> {
     pub(crate) io: IO,
     pub(crate) conn: ServerConnection,
     pub(crate) state: TlsState,
+    /// Whether an EOF without `close_notify` is an error.
+    pub(crate) strict_close_notify: bool,
+    /// Whether closing the stream sends `close_notify`.
+    pub(crate) send_close_notify: bool,
+    /// What closing the stream waits for.
+    pub(crate) close_mode: CloseMode,
+    pub(crate) close_timer: CloseTimer,
+    /// Whether reads stop at the end of each record, once shutting down.
+    pub(crate) exact_reads: bool,
+    /// When the handshake flushes the IO.
+    pub(crate) handshake_flush: HandshakeFlush,
+    /// How much of a caller's buffer a write takes.
+    pub(crate) write_chunking: WriteChunking,
+    /// Keeps small writes back, to encrypt them together.
+    pub(crate) coalescer: Coalescer,
+    /// Tells whether the session was resumed.
+    pub(crate) hello: HelloSniffer,
+    /// The TLS versions and cipher suites allowed, checked before answering.
+    pub(crate) policy: Policy,
+    /// Tells what clients not speaking TLS sent, to say why the handshake failed.
+    pub(crate) probe: ClientProbe,
+    pub(crate) observation: Observation,
+    /// Plaintext read by `peek`, which the next reads return.
+    pub(crate) peeked: Vec<u8>,
+    /// The state of the buffers, as rustls told it to the stream last.
+    pub(crate) io_state: Option<IoState>,
+    #[cfg(feature = "fingerprint")]
+    pub(crate) fingerprint: Option<ClientHelloFingerprint>,
 }
 
-pub(crate) enum MidHandshake<IO> {
+impl<IO: fmt::Debug> fmt::Debug for TlsStream<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsStream")
+            .field("io", &self.io)
+            .field("server_name", &self.conn.server_name())
+            .field("handshaking", &self.conn.is_handshaking())
+            .field("state", &self.state)
+            .field("protocol_version", &self.conn.protocol_version())
+            .field(
+                "cipher_suite",
+                &self.conn.negotiated_cipher_suite().map(|s| s.suite()),
+            )
+            .field(
+                "alpn_protocol",
+                &self.conn.alpn_protocol().map(String::from_utf8_lossy),
+            )
+            .field("counters", &self.counters())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> TlsStream<IO> {
+    /// Reads plaintext that was already received and decrypted, without any IO.
+    ///
+    /// Once reading from the stream failed, or the peer sent `close_notify`,
+    /// data the peer sent before can still be buffered. Returns the number of
+    /// bytes read, `0` when nothing is buffered.
+    pub fn read_buffered(&mut self, buf: &mut [u8]) -> usize {
+        if !self.peeked.is_empty() {
+            return self.take_peeked(buf);
+        }
+        let len = self.conn.reader().read(buf).unwrap_or(0);
+        if let Some(state) = self.io_state.as_mut() {
+            state.read(len);
+        }
+        len
+    }
+
+    /// Returns the state of the buffers of the connection, without any IO:
+    /// the plaintext received but not read yet, the TLS bytes waiting to be
+    /// sent, and whether the peer sent `close_notify`.
+    ///
+    /// Lets applications schedule by what is buffered, e.g. read plaintext
+    /// already received before polling other streams. rustls tells the state
+    /// as records are processed, and the stream keeps it up to date as
+    /// plaintext is read and records are sent. Writes kept back by
+    /// `coalesce_writes` are not encrypted yet, so not counted. `None` before
+    /// any records were processed, and once the connection failed.
+    pub fn io_state(&self) -> Option<&IoState> {
+        self.io_state.as_ref()
+    }
+
+    /// Returns how many bytes of plaintext were received but not read yet,
+    /// including those peeked at.
+    ///
+    /// See `io_state`, which does not count what was peeked at.
+    pub fn plaintext_bytes_to_read(&self) -> usize {
+        let buffered = self
+            .io_state()
+            .map_or(0, |state| state.plaintext_bytes_to_read());
+        self.peeked.len() + buffered
+    }
+
+    /// Move what was peeked at into `buf`, returning how much.
+    fn take_peeked(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.peeked.len());
+        buf[..len].copy_from_slice(&self.peeked[..len]);
+        self.peeked.drain(..len);
+        len
+    }
+
+    /// Returns how many bytes of TLS records wait to be sent.
+    ///
+    /// See `io_state`.
+    pub fn tls_bytes_to_write(&self) -> usize {
+        self.io_state()
+            .map_or(0, |state| state.tls_bytes_to_write())
+    }
+
+    /// Returns whether the peer sent `close_notify`, so nothing more is to be read.
+    ///
+    /// See `io_state`.
+    pub fn peer_has_closed(&self) -> bool {
+        self.io_state().is_some_and(|state| state.peer_has_closed())
+    }
+
+    /// Returns how the connection was set up: with a full handshake, or by
+    /// resuming a session from an earlier connection.
+    ///
+    /// `None` if the handshake could not be told apart.
+    pub fn handshake_kind(&self) -> Option<HandshakeKind> {
+        self.hello.kind()
+    }
+
+    /// Returns whether a session from an earlier connection was resumed.
+    pub fn is_resumed(&self) -> bool {
+        self.handshake_kind().is_some_and(HandshakeKind::is_resumed)
+    }
+
+    /// Returns the group the keys were exchanged with, e.g. `X25519`.
+    ///
+    /// `None` for resumed TLS 1.2 sessions, or if the group could not be told.
+    pub fn key_exchange_group(&self) -> Option<NamedGroup> {
+        self.hello.key_exchange_group()
+    }
+
+    /// Returns the `tls-exporter` channel binding of RFC 9266, for SASL
+    /// mechanisms such as SCRAM-SHA-256-PLUS.
+    ///
+    /// Fails with `Unsupported` for TLS 1.2 connections, where it is only safe
+    /// with the extended master secret, which rustls does not tell about.
+    /// `tls-unique` is not available either: rustls does not expose the
+    /// `Finished` messages it is made of.
+    pub fn tls_exporter_channel_binding(&self) -> io::Result<[u8; 32]> {
+        channel_binding::tls_exporter(&self.conn)
+    }
+
+    /// Returns the fingerprint of the client's `ClientHello`.
+    ///
+    /// Only available if the acceptor fingerprints clients (see
+    /// `TlsAcceptor::fingerprint_clients`), or from `StartHandshake`, and if
+    /// the `ClientHello` could be parsed.
+    #[cfg(feature = "fingerprint")]
+    pub fn client_hello_fingerprint(&self) -> Option<&ClientHelloFingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    /// Returns how long the handshake took.
+    pub fn handshake_timings(&self) -> Option<HandshakeTimings> {
+        self.observation.timings()
+    }
+
+    /// Returns how much was read from and written to the stream so far.
+    ///
+    /// Counts plaintext as well as TLS bytes and records, so proxies can do
+    /// accounting without wrapping the underlying stream.
+    pub fn counters(&self) -> TrafficCounters {
+        self.observation.counters()
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Handshake<IO> {
     Handshaking(TlsStream<IO>),
     End,
 }
 
-impl<IO> Future for MidHandshake<IO>
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Drive the handshake until it is done and its last messages are sent.
+    fn handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let polled_at = Instant::now();
+        let result = self.handshake_io(cx);
+        let wants_write = self.conn.wants_write();
+        self.observation.handshake_polled(polled_at, wants_write);
+        result
+    }
+
+    fn handshake_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let eof = !self.state.readable();
+        let mut stream = Stream::new(&mut self.io, &mut self.conn)
+            .set_io_state(&mut self.io_state)
+            .set_eof(eof)
+            .set_flush(self.handshake_flush)
+            .set_hello(&mut self.hello)
+            .set_traffic(&mut self.observation.traffic)
+            .set_probe(&mut self.probe)
+            .set_policy(&self.policy);
+
+        if stream.conn.is_handshaking() {
+            ready!(stream.complete_io(cx))?;
+        }
+
+        while stream.conn.wants_write() {
+            ready!(stream.complete_io(cx))?;
+        }
+
+        if self.conn.early_data().is_some() {
+            self.hello.set_early_data_accepted();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO> Handshake<IO> {
+    pub(crate) fn get_ref(&self) -> Option<&IO> {
+        match self {
+            Handshake::Handshaking(stream) => Some(&stream.io),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> Option<&mut IO> {
+        match self {
+            Handshake::Handshaking(stream) => Some(&mut stream.io),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn stage(&self) -> Option<HandshakeStage> {
+        match self {
+            Handshake::Handshaking(stream) => Some(HandshakeStage::of(&stream.conn)),
+            Handshake::End => None,
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self, Handshake::End)
+    }
+
+    /// Cancel the handshake, see `Accept::abort`.
+    pub(crate) async fn abort(self, notify: bool) -> Option<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = match self {
+            Handshake::Handshaking(stream) => stream,
+            Handshake::End => return None,
+        };
+        if notify {
+            cancel::cancel(&mut stream.io, Some(&mut stream.conn)).await;
+        }
+        Some(stream.io)
+    }
+
+    /// Take the stream out, so the handshake is over.
+    pub(crate) fn take_io(&mut self) -> Option<IO> {
+        match mem::replace(self, Handshake::End) {
+            Handshake::Handshaking(stream) => Some(stream.io),
+            Handshake::End => None,
+        }
+    }
+}
+
+impl<IO> Future for Handshake<IO>
 where
     IO: AsyncRead + AsyncWrite + Unpin,
 {
@@ -35,24 +321,260 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
 
-        if let MidHandshake::Handshaking(stream) = this {
-            let eof = !stream.state.readable();
-            let (io, session) = (&mut stream.io, &mut stream.conn);
-            let mut stream = Stream::new(io, session).set_eof(eof);
+        if let Handshake::Handshaking(stream) = this {
+            let result = ready!(stream.handshake(cx));
+            if let Err(err) = result {
+                let err = stream.probe.check(err);
+                let err = HandshakeError::wrap(err, stream.conn.server_name(), &stream.conn);
+                let err = HandshakeError::with_client_hello(err, stream.probe.client_hello());
+                stream.observation.handshake_error(&err);
+                return Poll::Ready(Err(err));
+            }
+        }
+
+        match mem::replace(this, Handshake::End) {
+            Handshake::Handshaking(mut stream) => {
+                // the bytes kept only tell why a handshake failed
+                stream.probe = ClientProbe::default();
+                let (conn, kind) = (&stream.conn, stream.hello.kind());
+                let group = stream.hello.key_exchange_group();
+                stream.observation.handshake_complete(|timings| {
+                    HandshakeParams::of(conn, kind, group, conn.server_name(), timings)
+                });
+                Poll::Ready(Ok(stream))
+            }
+            Handshake::End => panic!(),
+        }
+    }
+}
+
+/// A server handshake in flight, from `Accept::into_mid_handshake`.
+///
+/// Lets schedulers of their own drive handshakes: poll it with `poll` until
+/// it resolves to the stream, and store it in between, e.g. ordered by
+/// `stage` to time out or prioritize handshakes. It is also a future doing
+/// the same. `into_inner` gives up on the handshake and returns the IO.
+pub struct MidHandshake<IO>(pub(crate) Handshake<IO>);
+
+impl<IO> MidHandshake<IO> {
+    /// Returns a reference to the underlying IO stream, `None` once the
+    /// handshake resolved.
+    pub fn get_ref(&self) -> Option<&IO> {
+        self.0.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying IO stream, see `get_ref`.
+    pub fn get_mut(&mut self) -> Option<&mut IO> {
+        self.0.get_mut()
+    }
+
+    /// How far the handshake got, `None` once it resolved.
+    pub fn stage(&self) -> Option<HandshakeStage> {
+        self.0.stage()
+    }
+
+    /// Gives up on the handshake and returns the underlying IO, as is,
+    /// without telling the client. `None` once the handshake resolved.
+    pub fn into_inner(mut self) -> Option<IO> {
+        self.0.take_io()
+    }
+}
+
+impl<IO> MidHandshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Drives the handshake, resolving to the stream once it is done, or to
+    /// the `HandshakeError` it failed with.
+    ///
+    /// Once it resolved to the stream, polling again panics. Once it failed,
+    /// `into_inner` still returns the IO, e.g. to answer a plaintext HTTP
+    /// client.
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TlsStream<IO>>> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<IO> Future for MidHandshake<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = io::Result<TlsStream<IO>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().poll(cx)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FusedFuture for MidHandshake<IO> {
+    fn is_terminated(&self) -> bool {
+        self.0.is_done()
+    }
+}
 
-            if stream.conn.is_handshaking() {
-                ready!(stream.complete_io(cx))?;
+impl<IO> fmt::Debug for MidHandshake<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MidHandshake").field(&self.stage()).finish()
+    }
+}
+
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Reads plaintext into `buf` without consuming it: the next reads return
+    /// it again. Returns the number of bytes peeked at, `0` at the end of the
+    /// stream.
+    ///
+    /// Waits for data like reading does, and returns what was received so
+    /// far, which may be less than `buf` holds. Lets protocol sniffers and
+    /// routers look at what comes first without a `BufReader` in between.
+    pub fn peek<'a>(&'a mut self, buf: &'a mut [u8]) -> Peek<'a, IO> {
+        Peek { stream: self, buf }
+    }
+
+    /// Ends TLS with an exchange of `close_notify` alerts, and returns the
+    /// underlying IO, still open.
+    ///
+    /// Lets protocols step down from TLS to plaintext, or hand the connection
+    /// over to another part of the application. Data the peer still sends
+    /// before its `close_notify` is discarded, what follows it is left to read
+    /// from the IO. Unless the peer waits for the `close_notify` of this end
+    /// before using the connection, what it sends may have been received by
+    /// an earlier read, and is lost.
+    pub async fn shutdown(mut self) -> io::Result<IO> {
+        self.end_writing(true)?;
+        // what follows the peer's close_notify belongs to the IO
+        self.exact_reads = true;
+        poll_fn(|cx| self.poll_peer_close(cx)).await?;
+        self.observation.close();
+        Ok(self.io)
+    }
+
+    /// Closes the stream with an exchange of `close_notify` alerts, closing the
+    /// IO anyway after `timeout`. Returns whether the peer answered in time
+    /// with its `close_notify`.
+    ///
+    /// Bounds the time shutting down takes, e.g. when draining many
+    /// connections, whatever the `close_mode`. Data the peer still sends is
+    /// discarded.
+    #[cfg(feature = "async-std")]
+    pub async fn close_with_timeout(&mut self, timeout: Duration) -> io::Result<bool> {
+        let exchange = async {
+            self.end_writing(true)?;
+            poll_fn(|cx| self.poll_peer_close(cx)).await
+        };
+        let clean = matches!(
+            async_std::future::timeout(timeout, exchange).await,
+            Ok(Ok(()))
+        );
+        poll_fn(|cx| Pin::new(&mut self.io).poll_close(cx)).await?;
+        self.observation.close();
+        Ok(clean)
+    }
+
+    /// Queue `close_notify`, if `send`, after what was kept back, and stop writing.
+    fn end_writing(&mut self, send: bool) -> io::Result<()> {
+        if self.state.writeable() {
+            Stream::new(&mut self.io, &mut self.conn)
+                .set_io_state(&mut self.io_state)
+                .set_coalescer(&mut self.coalescer)
+                .uncork()?;
+            if send {
+                self.conn.send_close_notify();
             }
+            self.state.shutdown_write();
+        }
+        Ok(())
+    }
 
-            if stream.conn.wants_write() {
-                ready!(stream.complete_io(cx))?;
+    /// Send what is queued, then read until the peer's `close_notify`,
+    /// discarding what it still sends.
+    fn poll_peer_close(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut stream = Stream::new(&mut self.io, &mut self.conn)
+            .set_io_state(&mut self.io_state)
+            .set_eof(!self.state.readable())
+            .set_traffic(&mut self.observation.traffic);
+        ready!(stream.as_mut_pin().poll_flush(cx))?;
+        let mut buf = [0; 4096];
+        while self.state.readable() {
+            if self.close_timer.poll_elapsed(self.close_mode, cx) {
+                let err = "timed out waiting for the peer's close_notify";
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, err)));
             }
+            ready!(Pin::new(&mut *self).poll_read(cx, &mut buf))?;
         }
+        Poll::Ready(Ok(()))
+    }
 
-        match mem::replace(this, MidHandshake::End) {
-            MidHandshake::Handshaking(stream) => Poll::Ready(Ok(stream)),
-            MidHandshake::End => panic!(),
+    /// Polls for plaintext to peek at, see `peek`.
+    pub fn poll_peek(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.peeked.is_empty() && !buf.is_empty() {
+            let mut peeked = mem::take(&mut self.peeked);
+            peeked.resize(buf.len(), 0);
+            let result = Pin::new(&mut *self).poll_read(cx, &mut peeked);
+            peeked.truncate(match result {
+                Poll::Ready(Ok(len)) => len,
+                _ => 0,
+            });
+            self.peeked = peeked;
+            ready!(result)?;
         }
+        let len = buf.len().min(self.peeked.len());
+        buf[..len].copy_from_slice(&self.peeked[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    /// Drives the handshake until it is done, for poll loops of their own.
+    ///
+    /// Streams from `Accept` are done already, this only returns `Ready`.
+    pub fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.conn.is_handshaking() {
+            ready!(self.handshake(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Sends the TLS records queued and receives those the peer sent, e.g.
+    /// a key update, without reading or writing plaintext. Returns the number
+    /// of bytes received and sent.
+    ///
+    /// `(0, 0)` means nothing can be done without reading the plaintext
+    /// received: at the end of the stream, or once enough was buffered.
+    pub fn poll_complete_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(usize, usize)>> {
+        ready!(self.poll_handshake(cx))?;
+        Stream::new(&mut self.io, &mut self.conn)
+            .set_io_state(&mut self.io_state)
+            .set_eof(!self.state.readable())
+            .set_traffic(&mut self.observation.traffic)
+            .complete_io(cx)
+    }
+}
+
+/// Future returned from `TlsStream::peek`.
+pub struct Peek<'a, IO> {
+    stream: &'a mut TlsStream<IO>,
+    buf: &'a mut [u8],
+}
+
+impl<IO> fmt::Debug for Peek<'_, IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the buffer holds plaintext
+        f.debug_struct("Peek")
+            .field("len", &self.buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<IO> Future for Peek<'_, IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.stream.poll_peek(cx, this.buf)
     }
 }
 
@@ -65,8 +587,29 @@ where
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
+        if !self.peeked.is_empty() {
+            return Poll::Ready(Ok(self.get_mut().take_peeked(buf)));
+        }
+
         let this = self.get_mut();
-        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(!this.state.readable());
+
+        // accepted early data comes before anything sent after the handshake
+        if let Some(mut early_data) = this.conn.early_data() {
+            match early_data.read(buf) {
+                Ok(0) | Err(_) => (),
+                Ok(n) => {
+                    this.observation.read(n);
+                    return Poll::Ready(Ok(n));
+                }
+            }
+        }
+
+        let mut stream = Stream::new(&mut this.io, &mut this.conn)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_exact_reads(this.exact_reads)
+            .set_coalescer(&mut this.coalescer);
 
         match this.state {
             TlsState::Stream | TlsState::WriteShutdown => {
@@ -75,7 +618,18 @@ where
                         this.state.shutdown_read();
                         Poll::Ready(Ok(0))
                     }
-                    Poll::Ready(Ok(n)) => Poll::Ready(Ok(n)),
+                    Poll::Ready(Ok(n)) => {
+                        this.observation.read(n);
+                        Poll::Ready(Ok(n))
+                    }
+                    Poll::Ready(Err(ref err))
+                        if !this.strict_close_notify
+                            && err.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        // the peer closed without close_notify, which is tolerated
+                        this.state.shutdown_read();
+                        Poll::Ready(Ok(0))
+                    }
                     Poll::Ready(Err(ref err)) if err.kind() == io::ErrorKind::ConnectionAborted => {
                         this.state.shutdown_read();
                         if this.state.writeable() {
@@ -89,7 +643,7 @@ where
                 }
             }
             TlsState::ReadShutdown | TlsState::FullyShutdown => Poll::Ready(Ok(0)),
-            #[cfg(feature = "early-data")]
+            #[cfg(feature = "client")]
             s => unreachable!("server TLS can not hit this state: {:?}", s),
         }
     }
@@ -105,24 +659,81 @@ where
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
-        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(!this.state.readable());
-        stream.as_mut_pin().poll_write(cx, buf)
+        let mut stream = Stream::new(&mut this.io, &mut this.conn)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_chunking(this.write_chunking)
+            .set_coalescer(&mut this.coalescer);
+        let len = ready!(stream.as_mut_pin().poll_write(cx, buf))?;
+        this.observation.written(len);
+        Poll::Ready(Ok(len))
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(!this.state.readable());
+        let mut stream = Stream::new(&mut this.io, &mut this.conn)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_coalescer(&mut this.coalescer);
         stream.as_mut_pin().poll_flush(cx)
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        if self.state.writeable() {
-            self.conn.send_close_notify();
-            self.state.shutdown_write();
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        this.end_writing(this.send_close_notify)?;
+
+        if this.close_mode != CloseMode::Send {
+            ready!(this.poll_peer_close(cx))?;
         }
 
-        let this = self.get_mut();
-        let mut stream = Stream::new(&mut this.io, &mut this.conn).set_eof(!this.state.readable());
-        stream.as_mut_pin().poll_close(cx)
+        let mut stream = Stream::new(&mut this.io, &mut this.conn)
+            .set_io_state(&mut this.io_state)
+            .set_eof(!this.state.readable())
+            .set_traffic(&mut this.observation.traffic)
+            .set_coalescer(&mut this.coalescer);
+        ready!(stream.as_mut_pin().poll_close(cx))?;
+        this.observation.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// See the `AsyncRead` implementation of futures-io.
+#[cfg(feature = "tokio")]
+impl<IO> tokio::io::AsyncRead for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        compat::poll_read(self, cx, buf)
+    }
+}
+
+/// See the `AsyncWrite` implementation of futures-io, `poll_shutdown` is its `poll_close`.
+#[cfg(feature = "tokio")]
+impl<IO> tokio::io::AsyncWrite for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(self, cx)
     }
 }
@@ -0,0 +1,54 @@
+//! `TlsConnector` as a tower `Service`, connecting to URIs.
+
+use crate::client::TlsStream;
+use crate::remote_sign::BoxFuture;
+use crate::tcp;
+use crate::TlsConnector;
+
+use async_std::net::TcpStream;
+use http::Uri;
+use std::io;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Connect to `https` URIs, e.g. for connection pools, retry layers or hyper.
+///
+/// The host of the URI is resolved and connected to like `connect_tcp`, on
+/// port 443 unless the URI has one, and is also the server name. Other
+/// schemes and URIs without a host fail with `InvalidInput`.
+///
+/// With the `tokio` feature, the streams have tokio's IO traits, so the
+/// connector is also a tower `MakeConnection`.
+impl Service<Uri> for TlsConnector {
+    type Response = TlsStream<TcpStream>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move {
+            let (host, port) = host_port(&uri)?;
+            let stream = tcp::connect(host, port).await?;
+            connector.connect(host, stream).await
+        })
+    }
+}
+
+/// The host and port to connect to for `uri`.
+fn host_port(uri: &Uri) -> io::Result<(&str, u16)> {
+    if uri.scheme_str() != Some("https") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected an https URI, got {:?}", uri.to_string()),
+        ));
+    }
+    let host = uri
+        .host()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the URI has no host"))?;
+    Ok((host, uri.port_u16().unwrap_or(443)))
+}
@@ -0,0 +1,342 @@
+//! Building connectors and acceptors from a JSON description of their TLS settings.
+//!
+//! This lets services change their TLS policy through configuration
+//! management instead of code. All keys are optional:
+//!
+//! ```json
+//! {
+//!     "certificate": "server.crt",
+//!     "private_key": "server.key",
+//!     "roots": "internal-ca.pem",
+//!     "webpki_roots": true,
+//!     "alpn": ["h2", "http/1.1"],
+//!     "preset": "mozilla_intermediate",
+//!     "min_version": "1.2",
+//!     "max_version": "1.3",
+//!     "cipher_suites": ["TLS13_AES_128_GCM_SHA256", "TLS13_AES_256_GCM_SHA384"],
+//!     "client_auth": "required",
+//!     "client_ca": "clients-ca.pem"
+//! }
+//! ```
+//!
+//! * `certificate` and `private_key` are PEM files with the chain and key an
+//!   acceptor presents, or a connector presents for client authentication.
+//! * `roots` is a PEM bundle of the CAs a connector trusts. It replaces the
+//!   WebPKI roots, unless `webpki_roots` is `true`. `webpki_roots` defaults to
+//!   `true` without `roots`.
+//! * `alpn` lists the protocols to offer or accept, in order of preference.
+//! * `preset` is `mozilla_modern` or `mozilla_intermediate`, see
+//!   `SecurityPreset`. `min_version` and `max_version` are `1.2` or `1.3`, and
+//!   `cipher_suites` names the suites to use instead of those of the preset.
+//! * `client_auth` is `none`, `optional` or `required`, for acceptors.
+//!   Client certificates are verified against the PEM bundle `client_ca`.
+//!
+//! Relative paths are resolved against the directory of the settings file.
+//! Unknown keys are rejected, so that typos do not silently weaken the policy.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::settings::Settings;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let acceptor = Settings::from_file("/etc/myservice/tls.json")?.acceptor()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::pem;
+use crate::common::policy::{self, Policy};
+#[cfg(feature = "client")]
+use crate::connector;
+#[cfg(feature = "server")]
+use crate::TlsAcceptor;
+#[cfg(feature = "client")]
+use crate::TlsConnector;
+use crate::{Identity, SecurityPreset};
+
+#[cfg(feature = "server")]
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth,
+};
+#[cfg(feature = "client")]
+use rustls::ClientConfig;
+#[cfg(feature = "server")]
+use rustls::ServerConfig;
+use rustls::{
+    ConfigBuilder, ConfigSide, RootCertStore, SupportedCipherSuite, SupportedProtocolVersion,
+    WantsCipherSuites, WantsVerifier,
+};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The TLS settings of a connector or acceptor, see the module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    certificate: Option<PathBuf>,
+    private_key: Option<PathBuf>,
+    roots: Option<PathBuf>,
+    webpki_roots: Option<bool>,
+    alpn: Vec<Vec<u8>>,
+    preset: Option<SecurityPreset>,
+    min_version: Option<&'static SupportedProtocolVersion>,
+    max_version: Option<&'static SupportedProtocolVersion>,
+    cipher_suites: Option<Vec<SupportedCipherSuite>>,
+    client_auth: ClientAuth,
+    client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ClientAuth {
+    #[default]
+    None,
+    Optional,
+    Required,
+}
+
+impl Settings {
+    /// Parse settings, resolving relative paths against the working directory.
+    ///
+    /// Fails with `InvalidData` if `json` is not an object of known keys with
+    /// valid values.
+    pub fn from_json(json: &[u8]) -> io::Result<Settings> {
+        Settings::parse(json, Path::new(""))
+    }
+
+    /// Read and parse the settings file at `path`, resolving relative paths
+    /// against its directory.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Settings> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Settings::parse(&fs::read(path)?, dir)
+    }
+
+    fn parse(json: &[u8], dir: &Path) -> io::Result<Settings> {
+        let value: Value = serde_json::from_slice(json).map_err(invalid)?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| invalid("TLS settings must be an object"))?;
+        let mut settings = Settings::default();
+        for (key, value) in object {
+            match key.as_str() {
+                "certificate" => settings.certificate = Some(dir.join(string(key, value)?)),
+                "private_key" => settings.private_key = Some(dir.join(string(key, value)?)),
+                "roots" => settings.roots = Some(dir.join(string(key, value)?)),
+                "webpki_roots" => settings.webpki_roots = Some(boolean(key, value)?),
+                "alpn" => {
+                    settings.alpn = strings(key, value)?
+                        .into_iter()
+                        .map(|protocol| protocol.as_bytes().to_vec())
+                        .collect()
+                }
+                "preset" => {
+                    settings.preset = Some(match string(key, value)? {
+                        "mozilla_modern" => SecurityPreset::MozillaModern,
+                        "mozilla_intermediate" => SecurityPreset::MozillaIntermediate,
+                        other => return Err(invalid(format!("unknown preset {}", other))),
+                    })
+                }
+                "min_version" => settings.min_version = Some(version(key, value)?),
+                "max_version" => settings.max_version = Some(version(key, value)?),
+                "cipher_suites" => {
+                    let suites = strings(key, value)?
+                        .into_iter()
+                        .map(policy::suite_named)
+                        .collect::<io::Result<Vec<_>>>()
+                        .map_err(invalid)?;
+                    settings.cipher_suites = Some(suites);
+                }
+                "client_auth" => {
+                    settings.client_auth = match string(key, value)? {
+                        "none" => ClientAuth::None,
+                        "optional" => ClientAuth::Optional,
+                        "required" => ClientAuth::Required,
+                        other => return Err(invalid(format!("unknown client_auth {}", other))),
+                    }
+                }
+                "client_ca" => settings.client_ca = Some(dir.join(string(key, value)?)),
+                other => return Err(invalid(format!("unknown TLS setting {}", other))),
+            }
+        }
+        if settings.client_auth != ClientAuth::None && settings.client_ca.is_none() {
+            return Err(invalid("client_auth requires client_ca"));
+        }
+        Ok(settings)
+    }
+
+    /// Build a connector with these settings.
+    ///
+    /// Fails if a file cannot be read or parsed, or with `InvalidInput` if
+    /// the versions and suites have nothing in common.
+    #[cfg(feature = "client")]
+    pub fn connector(&self) -> io::Result<TlsConnector> {
+        let mut roots = RootCertStore::empty();
+        if self.webpki_roots.unwrap_or(self.roots.is_none()) {
+            roots = RootCertStore::clone(connector::webpki_roots());
+        }
+        if let Some(ref path) = self.roots {
+            add_roots(&mut roots, path)?;
+        }
+        let builder = self
+            .configure(ClientConfig::builder())?
+            .with_root_certificates(roots);
+        let mut config = match self.identity()? {
+            Some(identity) => {
+                let (chain, key) = identity.into_parts();
+                builder.with_client_auth_cert(chain, key).map_err(invalid)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self.alpn.clone();
+        Ok(TlsConnector::from(config))
+    }
+
+    /// Build an acceptor with these settings.
+    ///
+    /// Fails with `InvalidInput` without `certificate` and `private_key`,
+    /// or if the versions and suites have nothing in common, and if a file
+    /// cannot be read or parsed.
+    #[cfg(feature = "server")]
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let (chain, key) = self
+            .identity()?
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "an acceptor needs a certificate and private_key",
+                )
+            })?
+            .into_parts();
+        let verifier = match (self.client_auth, &self.client_ca) {
+            (ClientAuth::None, _) | (_, None) => NoClientAuth::boxed(),
+            (ClientAuth::Optional, Some(path)) => {
+                let mut roots = RootCertStore::empty();
+                add_roots(&mut roots, path)?;
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+            }
+            (ClientAuth::Required, Some(path)) => {
+                let mut roots = RootCertStore::empty();
+                add_roots(&mut roots, path)?;
+                AllowAnyAuthenticatedClient::new(roots).boxed()
+            }
+        };
+        let mut config = self
+            .configure(ServerConfig::builder())?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(chain, key)
+            .map_err(invalid)?;
+        config.alpn_protocols = self.alpn.clone();
+        Ok(TlsAcceptor::from(config))
+    }
+
+    /// Offer only the versions and suites allowed.
+    fn configure<S: ConfigSide>(
+        &self,
+        builder: ConfigBuilder<S, WantsCipherSuites>,
+    ) -> io::Result<ConfigBuilder<S, WantsVerifier>> {
+        let mut policy = self.preset.map(Policy::preset).unwrap_or_default();
+        if let Some(min) = self.min_version {
+            policy.set_min(min);
+        }
+        if let Some(max) = self.max_version {
+            policy.set_max(max);
+        }
+        if let Some(ref suites) = self.cipher_suites {
+            policy.set_suites(suites)?;
+        }
+        policy.configure(builder)
+    }
+
+    fn identity(&self) -> io::Result<Option<Identity>> {
+        match (&self.certificate, &self.private_key) {
+            (Some(cert), Some(key)) => Identity::from_pem_files(cert, key).map(Some),
+            (None, None) => Ok(None),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "certificate and private_key go together",
+            )),
+        }
+    }
+}
+
+fn add_roots(roots: &mut RootCertStore, path: &Path) -> io::Result<()> {
+    for cert in pem::certs(&fs::read(path)?)? {
+        roots.add(&cert).map_err(invalid)?;
+    }
+    Ok(())
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> io::Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| invalid(format!("{} must be a string", key)))
+}
+
+fn strings<'a>(key: &str, value: &'a Value) -> io::Result<Vec<&'a str>> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(format!("{} must be an array of strings", key)))?
+        .iter()
+        .map(|value| string(key, value))
+        .collect()
+}
+
+fn boolean(key: &str, value: &Value) -> io::Result<bool> {
+    value
+        .as_bool()
+        .ok_or_else(|| invalid(format!("{} must be true or false", key)))
+}
+
+fn version(key: &str, value: &Value) -> io::Result<&'static SupportedProtocolVersion> {
+    match string(key, value)? {
+        "1.2" => Ok(&rustls::version::TLS12),
+        "1.3" => Ok(&rustls::version::TLS13),
+        other => Err(invalid(format!("unsupported TLS version {}", other))),
+    }
+}
+
+fn invalid<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings() {
+        let settings = Settings::from_json(
+            br#"{
+                "alpn": ["h2", "http/1.1"],
+                "preset": "mozilla_modern",
+                "min_version": "1.3",
+                "cipher_suites": ["tls13_aes_128_gcm_sha256"],
+                "client_auth": "optional",
+                "client_ca": "ca.pem"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.alpn, vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        assert_eq!(settings.preset, Some(SecurityPreset::MozillaModern));
+        assert_eq!(settings.cipher_suites.as_ref().unwrap().len(), 1);
+        assert_eq!(settings.client_auth, ClientAuth::Optional);
+        assert_eq!(settings.client_ca, Some(PathBuf::from("ca.pem")));
+
+        for json in [
+            &b"[]"[..],
+            br#"{"alpn": "h2"}"#,
+            br#"{"min_version": "1.1"}"#,
+            br#"{"cipher_suites": ["TLS_NULL_WITH_NULL_NULL"]}"#,
+            br#"{"client_auth": "required"}"#,
+            br#"{"verify": false}"#,
+        ] {
+            let err = Settings::from_json(json).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
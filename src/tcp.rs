@@ -0,0 +1,165 @@
+//! Opening the TCP connection for `TlsConnector::connect_tcp`, racing the
+//! addresses of a host as in Happy Eyeballs (RFC 8305).
+
+use async_std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use async_std::task;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+/// How long an attempt gets before the next address is tried alongside it.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+type Attempt = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
+
+/// Split `addr` into the host and port, e.g. `example.com:443` or `[::1]:443`.
+///
+/// The host keeps the brackets of IPv6 addresses, which `connect` accepts as
+/// a server name.
+pub(crate) fn split_host_port(addr: &str) -> io::Result<(&str, u16)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected host:port, got {:?}", addr),
+        )
+    };
+    let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let unbracketed = host.trim_start_matches('[').trim_end_matches(']');
+    if unbracketed.is_empty() || (unbracketed.contains(':') && unbracketed.len() == host.len()) {
+        // IPv6 addresses need brackets to tell them from the port
+        return Err(invalid());
+    }
+    Ok((host, port))
+}
+
+/// Resolve `host` without blocking and connect to the first address that answers.
+///
+/// The addresses are tried in the resolver's order, alternating between IPv6
+/// and IPv4. Each attempt gets `CONNECTION_ATTEMPT_DELAY` before the next one
+/// starts, or less if it fails, and the first connection wins. A broken
+/// IPv6 network so costs a fraction of a second rather than a timeout.
+pub(crate) async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = interleave((host, port).to_socket_addrs().await?.collect());
+    let mut addrs = addrs.into_iter();
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let mut delay: Option<Pin<Box<dyn Future<Output = ()> + Send>>> = None;
+    let mut last_err = None;
+    loop {
+        if delay.is_none() {
+            match addrs.next() {
+                Some(addr) => {
+                    attempts.push(Box::pin(TcpStream::connect(addr)));
+                    delay = Some(Box::pin(task::sleep(CONNECTION_ATTEMPT_DELAY)));
+                }
+                None if attempts.is_empty() => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                    }));
+                }
+                None => (),
+            }
+        }
+
+        // `None` once it is time for the next attempt
+        let finished = poll_fn(|cx| {
+            if let Some(ref mut delay) = delay {
+                if delay.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+            }
+            for (i, attempt) in attempts.iter_mut().enumerate() {
+                if let Poll::Ready(result) = attempt.as_mut().poll(cx) {
+                    return Poll::Ready(Some((i, result)));
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        match finished {
+            None => delay = None,
+            Some((_, Ok(stream))) => return Ok(stream),
+            Some((i, Err(err))) => {
+                drop(attempts.remove(i));
+                last_err = Some(err);
+                delay = None;
+            }
+        }
+    }
+}
+
+/// Alternate between the address families, starting with that of the first address.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut interleaved = Vec::new();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(
+            split_host_port("example.com:443").unwrap(),
+            ("example.com", 443)
+        );
+        assert_eq!(split_host_port("[::1]:8443").unwrap(), ("[::1]", 8443));
+        assert_eq!(split_host_port("127.0.0.1:1").unwrap(), ("127.0.0.1", 1));
+        for addr in [
+            "example.com",
+            "example.com:https",
+            ":443",
+            "::1:443",
+            "[]:443",
+        ] {
+            let err = split_host_port(addr).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn interleaves_address_families() {
+        let addrs = |addrs: &[&str]| -> Vec<SocketAddr> {
+            addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+        };
+        assert_eq!(
+            interleave(addrs(&[
+                "[::1]:1",
+                "[::2]:1",
+                "[::3]:1",
+                "127.0.0.1:1",
+                "127.0.0.2:1"
+            ])),
+            addrs(&[
+                "[::1]:1",
+                "127.0.0.1:1",
+                "[::2]:1",
+                "127.0.0.2:1",
+                "[::3]:1"
+            ])
+        );
+        assert_eq!(
+            interleave(addrs(&["127.0.0.1:1", "127.0.0.2:1", "[::1]:1"])),
+            addrs(&["127.0.0.1:1", "[::1]:1", "127.0.0.2:1"])
+        );
+        assert!(interleave(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,459 @@
+//! Helpers for testing code built on async-tls, without sockets or fixture files.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use async_tls::test_utils;
+//! use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! # futures_executor::block_on(async {
+//! let (connector, acceptor) = test_utils::pair()?;
+//! let (mut client, mut server) = test_utils::connect(&connector, &acceptor, "localhost").await?;
+//!
+//! client.write_all(b"ping").await?;
+//! client.flush().await?;
+//! let mut buf = [0; 4];
+//! server.read_exact(&mut buf).await?;
+//! assert_eq!(&buf, b"ping");
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+
+use crate::dev_certs::Ca;
+use crate::{client, server, TlsAcceptor, TlsConnector};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use rustls::{Certificate, PrivateKey};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Create a connected pair of in-memory streams.
+///
+/// Each direction buffers at most `capacity` bytes before writes return
+/// `Pending`. Closing or dropping one end makes reads on the other end
+/// return EOF once the buffered data was consumed.
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    let a = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let b = Arc::new(Mutex::new(Pipe::new(capacity)));
+    (
+        DuplexStream {
+            read: a.clone(),
+            write: b.clone(),
+        },
+        DuplexStream { read: b, write: a },
+    )
+}
+
+/// One end of an in-memory stream created by `duplex`.
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    closed: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Self {
+        Pipe {
+            buf: VecDeque::new(),
+            capacity,
+            closed: false,
+            reader: None,
+            writer: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.writer.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buf.is_empty() {
+            if pipe.closed || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            pipe.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = io::Read::read(&mut pipe.buf, buf)?;
+        if let Some(waker) = pipe.writer.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let mut len = 0;
+        for buf in bufs {
+            let n = buf.len().min(pipe.capacity - pipe.buf.len());
+            pipe.buf.extend(&buf[..n]);
+            len += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        if len == 0 && bufs.iter().any(|buf| !buf.is_empty()) {
+            pipe.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.read.lock().unwrap().close();
+        self.write.lock().unwrap().close();
+    }
+}
+
+impl fmt::Debug for DuplexStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuplexStream").finish_non_exhaustive()
+    }
+}
+
+/// An IO wrapper misbehaving the ways real transports can, to test code
+/// reading and writing through it.
+///
+/// Out of the box it passes everything through unchanged; each setter adds one
+/// kind of fault. The faults are drawn from a generator seeded with `seed`, so
+/// a failing run is reproduced by running it again with the same seed.
+///
+/// ```rust
+/// use async_tls::test_utils::{self, Chaos};
+///
+/// let (client, server) = test_utils::duplex(4096);
+/// let client = Chaos::new(client, 42)
+///     .short_reads()
+///     .short_writes()
+///     .pending(4);
+/// let server = Chaos::new(server, 43).corrupt_at(100);
+/// ```
+pub struct Chaos<IO> {
+    inner: IO,
+    rng: u64,
+    short_reads: bool,
+    short_writes: bool,
+    pending: u32,
+    eof_after: Option<u64>,
+    corrupt: Vec<u64>,
+    bytes_read: u64,
+}
+
+impl<IO> Chaos<IO> {
+    /// Wrap `inner`, drawing the faults from `seed`.
+    pub fn new(inner: IO, seed: u64) -> Self {
+        Chaos {
+            inner,
+            rng: seed,
+            short_reads: false,
+            short_writes: false,
+            pending: 0,
+            eof_after: None,
+            corrupt: Vec::new(),
+            bytes_read: 0,
+        }
+    }
+
+    /// Read into a random prefix of the buffer, of at least one byte.
+    pub fn short_reads(mut self) -> Self {
+        self.short_reads = true;
+        self
+    }
+
+    /// Write a random prefix of the buffer, of at least one byte.
+    pub fn short_writes(mut self) -> Self {
+        self.short_writes = true;
+        self
+    }
+
+    /// Return `Pending` from about one in `one_in` polls, waking the task
+    /// right away. 0, the default, never does.
+    pub fn pending(mut self, one_in: u32) -> Self {
+        self.pending = one_in;
+        self
+    }
+
+    /// Report EOF once `bytes` were read, as if the peer went away.
+    ///
+    /// A few hundred bytes end the connection mid-handshake.
+    pub fn eof_after(mut self, bytes: u64) -> Self {
+        self.eof_after = Some(bytes);
+        self
+    }
+
+    /// Flip the bits of the byte read at `offset`, counting from the first
+    /// byte read. Can be called repeatedly to corrupt several bytes.
+    pub fn corrupt_at(mut self, offset: u64) -> Self {
+        self.corrupt.push(offset);
+        self
+    }
+
+    /// The number of bytes read through the wrapper so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Get a reference to the wrapped IO.
+    pub fn get_ref(&self) -> &IO {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped IO.
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.inner
+    }
+
+    /// Unwrap the IO.
+    pub fn into_inner(self) -> IO {
+        self.inner
+    }
+
+    /// The next number of the generator, xorshift64*.
+    fn next(&mut self) -> u64 {
+        if self.rng == 0 {
+            self.rng = 0x9e37_79b9_7f4a_7c15;
+        }
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Whether to return `Pending` from this poll, waking the task if so.
+    fn stall(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.pending == 0 || !self.next().is_multiple_of(u64::from(self.pending)) {
+            return false;
+        }
+        cx.waker().wake_by_ref();
+        true
+    }
+
+    /// A random length in `1..=len`, or `len` if it is 0.
+    fn shorten(&mut self, len: usize) -> usize {
+        match len {
+            0 => 0,
+            len => 1 + (self.next() % len as u64) as usize,
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for Chaos<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.stall(cx) {
+            return Poll::Pending;
+        }
+        let mut len = buf.len();
+        if let Some(eof_after) = this.eof_after {
+            len = len.min(eof_after.saturating_sub(this.bytes_read) as usize);
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+        }
+        if this.short_reads {
+            len = this.shorten(len);
+        }
+
+        let len = match Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]) {
+            Poll::Ready(Ok(len)) => len,
+            other => return other,
+        };
+        let start = this.bytes_read;
+        this.bytes_read += len as u64;
+        for &offset in &this.corrupt {
+            if (start..this.bytes_read).contains(&offset) {
+                buf[(offset - start) as usize] ^= 0xff;
+            }
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for Chaos<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.stall(cx) {
+            return Poll::Pending;
+        }
+        let len = match this.short_writes {
+            true => this.shorten(buf.len()),
+            false => buf.len(),
+        };
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.stall(cx) {
+            return Poll::Pending;
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.stall(cx) {
+            return Poll::Pending;
+        }
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl<IO: fmt::Debug> fmt::Debug for Chaos<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chaos")
+            .field("inner", &self.inner)
+            .field("bytes_read", &self.bytes_read)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A throwaway CA, separate from the `dev_certs` one.
+///
+/// Use one per test to check that peers with certificates from another CA
+/// are rejected.
+pub struct TestCa(Ca);
+
+impl TestCa {
+    /// Generate a new CA.
+    pub fn new() -> io::Result<Self> {
+        Ca::generate("async-tls test CA").map(TestCa)
+    }
+
+    /// The CA certificate, to be added to a `RootCertStore`.
+    pub fn certificate(&self) -> Certificate {
+        self.0.certificate()
+    }
+
+    /// Issue a certificate for `names`, returning the chain and PKCS#8 private key.
+    pub fn issue(&self, names: &[&str]) -> io::Result<(Vec<Certificate>, PrivateKey)> {
+        self.0.issue(names)
+    }
+
+    /// An acceptor serving a fresh certificate for `names`.
+    pub fn acceptor(&self, names: &[&str]) -> io::Result<TlsAcceptor> {
+        Ok(self.0.server_config(names)?.into())
+    }
+
+    /// A connector trusting only this CA.
+    pub fn connector(&self) -> io::Result<TlsConnector> {
+        Ok(self.0.client_config()?.into())
+    }
+}
+
+impl fmt::Debug for TestCa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestCa").finish_non_exhaustive()
+    }
+}
+
+/// A connector and acceptor that trust each other for `localhost`.
+pub fn pair() -> io::Result<(TlsConnector, TlsAcceptor)> {
+    let ca = TestCa::new()?;
+    Ok((ca.connector()?, ca.acceptor(&["localhost"])?))
+}
+
+/// Run a handshake between `connector` and `acceptor` over a `duplex` pair.
+///
+/// Fails with the first error of either side.
+pub async fn connect(
+    connector: &TlsConnector,
+    acceptor: &TlsAcceptor,
+    domain: &str,
+) -> io::Result<(
+    client::TlsStream<DuplexStream>,
+    server::TlsStream<DuplexStream>,
+)> {
+    let (client_io, server_io) = duplex(64 * 1024);
+    let mut connect = connector.connect(domain, client_io);
+    let mut accept = acceptor.accept(server_io);
+    let (mut client, mut server) = (None, None);
+
+    poll_fn(|cx| {
+        if client.is_none() {
+            if let Poll::Ready(result) = Pin::new(&mut connect).poll(cx) {
+                client = Some(result);
+            }
+        }
+        if server.is_none() {
+            if let Poll::Ready(result) = Pin::new(&mut accept).poll(cx) {
+                server = Some(result);
+            }
+        }
+        match (&client, &server) {
+            (Some(Err(_)), _) | (_, Some(Err(_))) | (Some(_), Some(_)) => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
+    })
+    .await;
+
+    match (client, server) {
+        (Some(Ok(client)), Some(Ok(server))) => Ok((client, server)),
+        (Some(Err(err)), _) | (_, Some(Err(err))) => Err(err),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,212 @@
+//! Session ticket keys that rotate on a schedule.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use rustls::server::ProducesTickets;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The longest lifetime a TLS 1.3 ticket may have, in seconds.
+const MAX_LIFETIME: u64 = 7 * 24 * 60 * 60;
+
+/// The length of the random name a ticket starts with, to find its key.
+const NAME_LEN: usize = 16;
+
+/// Encrypts session tickets with a key that is replaced every `rotation`.
+///
+/// Tickets of the `previous` keys are still accepted; older keys are erased,
+/// so a leaked key only exposes the sessions resumable with it. rustls's own
+/// `Ticketer` does the same with a fixed rotation of 12 hours and one
+/// previous key.
+///
+/// Keys are rotated when tickets are issued or checked, no task is spawned.
+/// Install it with `TlsAcceptor::rotate_ticket_keys`, or set it as the
+/// `ticketer` of a `ServerConfig`.
+pub struct RotatingTicketer {
+    rotation: Duration,
+    previous: usize,
+    rng: SystemRandom,
+    keys: Mutex<Keys>,
+}
+
+struct Keys {
+    /// The current key first.
+    keys: VecDeque<TicketKey>,
+    /// When the current key is replaced, `None` before the first ticket.
+    next_rotation: Option<Instant>,
+}
+
+struct TicketKey {
+    name: [u8; NAME_LEN],
+    key: LessSafeKey,
+}
+
+impl RotatingTicketer {
+    /// A ticketer replacing its key every `rotation`, keeping the `previous` keys.
+    ///
+    /// # Panics
+    ///
+    /// If `rotation` is zero.
+    pub fn new(rotation: Duration, previous: usize) -> Self {
+        assert!(!rotation.is_zero(), "rotation must not be zero");
+        RotatingTicketer {
+            rotation,
+            previous,
+            rng: SystemRandom::new(),
+            keys: Mutex::new(Keys {
+                keys: VecDeque::new(),
+                next_rotation: None,
+            }),
+        }
+    }
+
+    fn encrypt_at(&self, plain: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let mut keys = self.keys.lock().unwrap();
+        self.rotate(&mut keys, now)?;
+        let current = keys.keys.front()?;
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).ok()?;
+        let mut ticket = Vec::with_capacity(NAME_LEN + NONCE_LEN + plain.len() + 16);
+        ticket.extend_from_slice(&current.name);
+        ticket.extend_from_slice(&nonce);
+        let mut sealed = plain.to_vec();
+        current
+            .key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(current.name),
+                &mut sealed,
+            )
+            .ok()?;
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn decrypt_at(&self, ticket: &[u8], now: Instant) -> Option<Vec<u8>> {
+        let name = ticket.get(..NAME_LEN)?;
+        let nonce = ticket.get(NAME_LEN..NAME_LEN + NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+
+        let mut keys = self.keys.lock().unwrap();
+        self.rotate(&mut keys, now)?;
+        let key = keys.keys.iter().find(|key| key.name == name)?;
+
+        let mut plain = ticket[NAME_LEN + NONCE_LEN..].to_vec();
+        let len = key
+            .key
+            .open_in_place(nonce, Aad::from(&key.name), &mut plain)
+            .ok()?
+            .len();
+        plain.truncate(len);
+        Some(plain)
+    }
+
+    /// Replace the keys due at `now`. `None` if no key could be made.
+    fn rotate(&self, keys: &mut Keys, now: Instant) -> Option<()> {
+        let (mut next, due) = match keys.next_rotation {
+            Some(next) if now < next => return Some(()),
+            Some(next) => (next, 1 + (now - next).as_nanos() / self.rotation.as_nanos()),
+            None => (now, u128::MAX),
+        };
+
+        if due > self.previous as u128 {
+            // every key expired while no tickets were handled
+            keys.keys.clear();
+            keys.keys.push_front(self.new_key()?);
+            keys.next_rotation = Some(now + self.rotation);
+            return Some(());
+        }
+        for _ in 0..due {
+            keys.keys.push_front(self.new_key()?);
+            keys.keys.truncate(self.previous + 1);
+            next += self.rotation;
+        }
+        keys.next_rotation = Some(next);
+        Some(())
+    }
+
+    fn new_key(&self) -> Option<TicketKey> {
+        let mut name = [0; NAME_LEN];
+        self.rng.fill(&mut name).ok()?;
+        let mut key = [0; 32];
+        self.rng.fill(&mut key).ok()?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key).ok()?;
+        Some(TicketKey {
+            name,
+            key: LessSafeKey::new(key),
+        })
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Tickets are only resumable while their key is kept.
+    fn lifetime(&self) -> u32 {
+        let periods = self.previous.clamp(1, u32::MAX as usize) as u32;
+        let lifetime = self.rotation.saturating_mul(periods).as_secs();
+        u32::try_from(lifetime.min(MAX_LIFETIME)).unwrap()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.encrypt_at(plain, Instant::now())
+    }
+
+    fn decrypt(&self, ticket: &[u8]) -> Option<Vec<u8>> {
+        self.decrypt_at(ticket, Instant::now())
+    }
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingTicketer")
+            .field("rotation", &self.rotation)
+            .field("previous", &self.previous)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn accepts_tickets_of_previous_keys() {
+        let ticketer = RotatingTicketer::new(HOUR, 2);
+        let start = Instant::now();
+        let ticket = ticketer.encrypt_at(b"session", start).unwrap();
+        assert_eq!(ticketer.decrypt_at(&ticket, start).unwrap(), b"session");
+
+        let fresh = ticketer.encrypt_at(b"fresh", start + HOUR).unwrap();
+        assert_ne!(fresh[..NAME_LEN], ticket[..NAME_LEN]);
+        let later = start + 2 * HOUR + HOUR / 2;
+        assert_eq!(ticketer.decrypt_at(&ticket, later).unwrap(), b"session");
+        assert!(ticketer.decrypt_at(&ticket, start + 3 * HOUR).is_none());
+        assert_eq!(
+            ticketer.decrypt_at(&fresh, start + 3 * HOUR).unwrap(),
+            b"fresh"
+        );
+        assert!(ticketer.decrypt_at(&fresh, start + 10 * HOUR).is_none());
+        assert_eq!(ticketer.lifetime(), 2 * 60 * 60);
+    }
+
+    #[test]
+    fn rejects_forged_tickets() {
+        let ticketer = RotatingTicketer::new(HOUR, 0);
+        let now = Instant::now();
+        let mut ticket = ticketer.encrypt_at(b"session", now).unwrap();
+        let last = ticket.len() - 1;
+        ticket[last] ^= 1;
+        assert!(ticketer.decrypt_at(&ticket, now).is_none());
+        assert!(ticketer.decrypt_at(&ticket[..NAME_LEN], now).is_none());
+        assert!(ticketer.decrypt_at(&[], now).is_none());
+    }
+}
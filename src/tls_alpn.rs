@@ -0,0 +1,87 @@
+//! Answering ACME `tls-alpn-01` challenges ([RFC 8737]).
+//!
+//! [RFC 8737]: https://www.rfc-editor.org/rfc/rfc8737
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// The ALPN protocol ACME validation servers offer.
+pub const ACME_TLS_ALPN_NAME: &[u8] = b"acme-tls/1";
+
+/// Serves challenge certificates to ACME validation servers.
+///
+/// Register the challenge certificate for a domain with `insert` and attach the
+/// responder to an acceptor with `TlsAcceptor::tls_alpn_01`. Handshakes offering
+/// `acme-tls/1` for a registered domain are then answered with the challenge
+/// certificate, all others use the acceptor's normal config.
+///
+/// With the `acme` feature, the responder is also a `ChallengeSolver` that
+/// creates the challenge certificates itself.
+#[derive(Default)]
+pub struct TlsAlpn01Responder {
+    configs: RwLock<HashMap<String, Arc<ServerConfig>>>,
+}
+
+impl TlsAlpn01Responder {
+    /// Create a responder without pending challenges.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Answer challenges for `domain` with `cert`, which must carry the
+    /// `acmeIdentifier` extension for the current challenge.
+    pub fn insert(&self, domain: impl Into<String>, cert: CertifiedKey) {
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(ChallengeCert(Arc::new(cert))));
+        config.alpn_protocols = vec![ACME_TLS_ALPN_NAME.to_vec()];
+
+        self.configs
+            .write()
+            .unwrap()
+            .insert(domain.into().to_ascii_lowercase(), Arc::new(config));
+    }
+
+    /// Stop answering challenges for `domain`.
+    pub fn remove(&self, domain: &str) {
+        self.configs
+            .write()
+            .unwrap()
+            .remove(&domain.to_ascii_lowercase());
+    }
+
+    /// The config to use if `client_hello` is a validation request for a pending challenge.
+    pub fn config_for(&self, client_hello: &ClientHello) -> Option<Arc<ServerConfig>> {
+        let offers_acme = client_hello
+            .alpn()
+            .is_some_and(|mut alpn| alpn.any(|p| p == ACME_TLS_ALPN_NAME));
+        if !offers_acme {
+            return None;
+        }
+
+        let domain = client_hello.server_name()?.to_ascii_lowercase();
+        self.configs.read().unwrap().get(&domain).cloned()
+    }
+}
+
+impl fmt::Debug for TlsAlpn01Responder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let configs = self.configs.read().unwrap();
+        f.debug_struct("TlsAlpn01Responder")
+            .field("domains", &configs.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+struct ChallengeCert(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for ChallengeCert {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
@@ -0,0 +1,364 @@
+//! Trust on first use: pinning servers' keys without a certificate authority.
+//!
+//! A connector with a `TofuVerifier` accepts whatever certificate a server
+//! presents the first time it connects to a host, and records its fingerprint
+//! in a `FingerprintStore`. Later connections to the same host fail unless the
+//! server presents a matching certificate, like SSH does with `known_hosts`.
+//! This suits self-hosted services with self-signed certificates.
+//!
+//! By default the fingerprint covers the certificate's public key, so that
+//! certificates renewed with the same key keep matching. Neither the issuer,
+//! the validity period nor the names of the certificate are checked.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::tofu::{FileStore, TofuVerifier};
+//! use async_tls::TlsConnector;
+//!
+//! # async_std::task::block_on(async {
+//! let store = FileStore::new("/home/me/.config/my-client/known_hosts");
+//! let connector = TlsConnector::new().tofu(TofuVerifier::new(store));
+//!
+//! let tcp_stream = async_std::net::TcpStream::connect("nas.local:443").await?;
+//! let stream = connector.connect("nas.local", tcp_stream).await?;
+//! # Ok(()) as std::io::Result<()>
+//! # });
+//! ```
+
+use crate::common::der;
+use crate::BoxFuture;
+
+use ring::digest::{digest, SHA256};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, Error, ServerName};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The SHA-256 fingerprint of a certificate or of its public key.
+///
+/// Formats as, and parses from, 64 hex digits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    /// The fingerprint of the whole DER-encoded certificate.
+    pub fn of_certificate(cert: &Certificate) -> Self {
+        Fingerprint::of(&cert.0)
+    }
+
+    /// The fingerprint of the certificate's DER-encoded `SubjectPublicKeyInfo`.
+    pub fn of_public_key(cert: &Certificate) -> io::Result<Self> {
+        let tbs = der::tbs_certificate(&cert.0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed certificate"))?;
+        Ok(Fingerprint::of(tbs.spki))
+    }
+
+    /// The raw SHA-256 hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn of(data: &[u8]) -> Self {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(digest(&SHA256, data).as_ref());
+        Fingerprint(hash)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({})", self)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed fingerprint");
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let mut hash = [0; 32];
+        for (b, digits) in hash.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            *b = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        }
+        Ok(Fingerprint(hash))
+    }
+}
+
+/// Where the fingerprints of known hosts are kept.
+pub trait FingerprintStore: Send + Sync {
+    /// The fingerprint recorded for `host`, if any.
+    fn get<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Option<Fingerprint>>>;
+
+    /// Record the fingerprint of a host seen for the first time.
+    fn insert<'a>(
+        &'a self,
+        host: &'a str,
+        fingerprint: Fingerprint,
+    ) -> BoxFuture<'a, io::Result<()>>;
+}
+
+impl<S: FingerprintStore + ?Sized> FingerprintStore for Arc<S> {
+    fn get<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Option<Fingerprint>>> {
+        (**self).get(host)
+    }
+
+    fn insert<'a>(
+        &'a self,
+        host: &'a str,
+        fingerprint: Fingerprint,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        (**self).insert(host, fingerprint)
+    }
+}
+
+/// A store that forgets its hosts when dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    hosts: Mutex<HashMap<String, Fingerprint>>,
+}
+
+impl MemoryStore {
+    /// Create a store without known hosts.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl FingerprintStore for MemoryStore {
+    fn get<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Option<Fingerprint>>> {
+        let fingerprint = self.hosts.lock().unwrap().get(host).copied();
+        Box::pin(async move { Ok(fingerprint) })
+    }
+
+    fn insert<'a>(
+        &'a self,
+        host: &'a str,
+        fingerprint: Fingerprint,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), fingerprint);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A store persisted to a file, one `host fingerprint` line per known host.
+///
+/// The file and its parent directories are created on the first insert. Lines
+/// can be removed by hand to forget a host, e.g. after its key was replaced.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    /// Keep fingerprints in the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStore {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self, host: &str) -> io::Result<Option<Fingerprint>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            if fields.next() == Some(host) {
+                return fields.next().unwrap_or_default().parse().map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn append(&self, host: &str, fingerprint: Fingerprint) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{} {}", host, fingerprint)
+    }
+}
+
+impl FingerprintStore for FileStore {
+    fn get<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Option<Fingerprint>>> {
+        Box::pin(async move { self.read(host) })
+    }
+
+    fn insert<'a>(
+        &'a self,
+        host: &'a str,
+        fingerprint: Fingerprint,
+    ) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move { self.append(host, fingerprint) })
+    }
+}
+
+/// Pins the certificate a host presented on the first connection.
+///
+/// See the [module documentation](index.html) for the trust model.
+#[derive(Clone)]
+pub struct TofuVerifier {
+    store: Arc<dyn FingerprintStore>,
+    pin_certificate: bool,
+}
+
+impl TofuVerifier {
+    /// Record and look up fingerprints in `store`.
+    pub fn new(store: impl FingerprintStore + 'static) -> Self {
+        TofuVerifier {
+            store: Arc::new(store),
+            pin_certificate: false,
+        }
+    }
+
+    /// Pin the whole certificate instead of its public key.
+    ///
+    /// Renewing the certificate then requires forgetting the host.
+    pub fn pin_certificate(mut self, flag: bool) -> Self {
+        self.pin_certificate = flag;
+        self
+    }
+
+    /// Check the server's certificate against the one recorded for `host`.
+    pub(crate) fn check(
+        &self,
+        host: String,
+        chain: Option<&[Certificate]>,
+    ) -> BoxFuture<'static, io::Result<()>> {
+        let found = match chain.and_then(|chain| chain.first()) {
+            Some(cert) if self.pin_certificate => Ok(Fingerprint::of_certificate(cert)),
+            Some(cert) => Fingerprint::of_public_key(cert),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "server did not send a certificate",
+            )),
+        };
+        let store = self.store.clone();
+        Box::pin(async move {
+            let found = found?;
+            match store.get(&host).await? {
+                Some(expected) if expected == found => Ok(()),
+                Some(expected) => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    FingerprintMismatch {
+                        host,
+                        expected,
+                        found,
+                    },
+                )),
+                None => store.insert(&host, found).await,
+            }
+        })
+    }
+}
+
+impl fmt::Debug for TofuVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TofuVerifier")
+            .field("pin_certificate", &self.pin_certificate)
+            .finish()
+    }
+}
+
+/// The error a connection fails with when a known host presents another certificate.
+///
+/// It is wrapped in an `io::Error` of kind `InvalidData`, the `Error::Io` of
+/// the `HandshakeError` `Connect` fails with; use `get_ref` and `downcast_ref`
+/// to get at it.
+#[derive(Debug, Clone)]
+pub struct FingerprintMismatch {
+    /// The host connected to.
+    pub host: String,
+    /// The fingerprint recorded for the host.
+    pub expected: Fingerprint,
+    /// The fingerprint of the certificate the server presented.
+    pub found: Fingerprint,
+}
+
+impl fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "certificate of {} changed: expected fingerprint {}, found {}",
+            self.host, self.expected, self.found
+        )
+    }
+}
+
+impl StdError for FingerprintMismatch {}
+
+/// `config` with the certificate checks left to a `TofuVerifier`.
+pub(crate) fn accept_any(config: &ClientConfig) -> Arc<ClientConfig> {
+    let mut config = config.clone();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAny));
+    Arc::new(config)
+}
+
+/// Accepts any certificate; handshake signatures are still checked.
+struct AcceptAny;
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_and_parses_fingerprints() {
+        let fingerprint = Fingerprint::of(b"certificate");
+        let hex = fingerprint.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex.parse::<Fingerprint>().unwrap(), fingerprint);
+        assert_eq!(
+            hex.to_uppercase().parse::<Fingerprint>().unwrap(),
+            fingerprint
+        );
+
+        assert!(hex[1..].parse::<Fingerprint>().is_err());
+        assert!(format!("{}g", &hex[1..]).parse::<Fingerprint>().is_err());
+        assert!(format!("+{}", &hex[1..]).parse::<Fingerprint>().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Handshake signatures from a key held in a TPM 2.0.
+
+use crate::remote_sign::{BoxFuture, RemoteSigner, RemoteSigningKey};
+
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, SignatureAlgorithm, SignatureScheme};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The type of a key in the TPM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TpmKeyType {
+    /// An ECDSA key on the NIST P-256 curve.
+    EcdsaP256,
+    /// An ECDSA key on the NIST P-384 curve.
+    EcdsaP384,
+}
+
+impl TpmKeyType {
+    fn schemes(self) -> &'static [SignatureScheme] {
+        match self {
+            TpmKeyType::EcdsaP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+            TpmKeyType::EcdsaP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+        }
+    }
+}
+
+/// A private key that never leaves a TPM 2.0, for device identities that
+/// must be bound to the hardware.
+///
+/// Signatures are made by the `tpm2_sign` tool of [tpm2-tools], so neither
+/// the TSS libraries nor a C toolchain are needed to build async-tls. The key
+/// is referenced the way the tools do, e.g. by a persistent handle such as
+/// `0x81000001` or by a context file from `tpm2_load`.
+///
+/// Only ECDSA keys are supported: TPMs disagree on the RSA-PSS salt length,
+/// which TLS fixes to the hash length.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "server")]
+/// # fn main() -> std::io::Result<()> {
+/// use async_tls::{CertStore, TlsAcceptor, TpmKey, TpmKeyType};
+///
+/// let chain = std::fs::read("device.crt")?;
+/// let chain = rustls_pemfile::certs(&mut &chain[..])?;
+/// let chain = chain.into_iter().map(rustls::Certificate).collect();
+///
+/// let key = TpmKey::new("0x81000001", TpmKeyType::EcdsaP256);
+/// let store = CertStore::new();
+/// store.insert("device.example.com", key.certified(chain));
+/// let acceptor = TlsAcceptor::from(store);
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "server"))]
+/// # fn main() {}
+/// ```
+///
+/// [tpm2-tools]: https://github.com/tpm2-software/tpm2-tools
+#[derive(Clone)]
+pub struct TpmKey {
+    context: OsString,
+    key_type: TpmKeyType,
+    program: PathBuf,
+    auth: Option<String>,
+    tcti: Option<String>,
+}
+
+impl TpmKey {
+    /// Sign with the key `context`, as passed to `tpm2_sign -c`.
+    pub fn new(context: impl Into<OsString>, key_type: TpmKeyType) -> Self {
+        TpmKey {
+            context: context.into(),
+            key_type,
+            program: PathBuf::from("tpm2_sign"),
+            auth: None,
+            tcti: None,
+        }
+    }
+
+    /// Run this `tpm2_sign` instead of the one on the `PATH`.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Authorize the use of the key, in the syntax of `tpm2_sign -p`,
+    /// e.g. `file:/run/secrets/tpm-key`.
+    ///
+    /// The value is passed on the command line, where other processes can
+    /// see it, so prefer `file:` over `str:`.
+    pub fn auth(mut self, auth: impl Into<String>) -> Self {
+        self.auth = Some(auth.into());
+        self
+    }
+
+    /// Talk to the TPM through this TCTI, e.g. `device:/dev/tpmrm0` or
+    /// `tabrmd`, rather than the tools' default.
+    pub fn tcti(mut self, tcti: impl Into<String>) -> Self {
+        self.tcti = Some(tcti.into());
+        self
+    }
+
+    /// The key as a rustls `SigningKey`, e.g. to change the signing timeout.
+    pub fn signing_key(self) -> RemoteSigningKey {
+        RemoteSigningKey::new(Arc::new(self))
+    }
+
+    /// Pair the key with its certificate chain.
+    pub fn certified(self, chain: Vec<Certificate>) -> CertifiedKey {
+        self.signing_key().certified(chain)
+    }
+
+    /// Run `tpm2_sign` over `message`, returning the DER-encoded signature.
+    fn run(&self, scheme: SignatureScheme, message: &[u8]) -> io::Result<Vec<u8>> {
+        let hash = match scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 => "sha256",
+            SignatureScheme::ECDSA_NISTP384_SHA384 => "sha384",
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unsupported scheme",
+                ))
+            }
+        };
+
+        let dir = TempDir::new()?;
+        let message_path = dir.0.join("message");
+        let signature_path = dir.0.join("signature");
+        fs::write(&message_path, message)?;
+
+        let mut command = Command::new(&self.program);
+        command
+            .arg("-c")
+            .arg(&self.context)
+            .args(["-g", hash, "-s", "ecdsa", "-f", "plain", "-o"])
+            .arg(&signature_path)
+            .arg(&message_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+        if let Some(ref auth) = self.auth {
+            command.arg("-p").arg(auth);
+        }
+        if let Some(ref tcti) = self.tcti {
+            command.env("TPM2TOOLS_TCTI", tcti);
+        }
+
+        let output = command.output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!(
+                "tpm2_sign failed with {}: {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+        fs::read(&signature_path)
+    }
+}
+
+impl RemoteSigner for TpmKey {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::ECDSA
+    }
+
+    fn schemes(&self) -> &[SignatureScheme] {
+        self.key_type.schemes()
+    }
+
+    fn sign(
+        &self,
+        scheme: SignatureScheme,
+        message: Vec<u8>,
+    ) -> BoxFuture<'static, io::Result<Vec<u8>>> {
+        // the future runs on a thread of its own, see `RemoteSigningKey`
+        let key = self.clone();
+        Box::pin(async move { key.run(scheme, &message) })
+    }
+}
+
+impl fmt::Debug for TpmKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TpmKey")
+            .field("context", &self.context)
+            .field("key_type", &self.key_type)
+            .field("program", &self.program)
+            .field("tcti", &self.tcti)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A directory for the files exchanged with `tpm2_sign`, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "async-tls-tpm-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        fs::create_dir(&path)?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,72 @@
+//! WebSocket clients for `wss://` URLs, with async-tungstenite over a `TlsConnector`.
+//!
+//! `connect_async` takes the place of the `connect_async_with_tls_connector`
+//! functions of async-tungstenite, which only know other TLS crates: it
+//! connects like `TlsConnector::connect_tcp`, does the TLS handshake with the
+//! given connector and then the WebSocket one.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_tls::{ws, TlsConnector};
+//! use async_tungstenite::tungstenite::Message;
+//! use futures_util::StreamExt;
+//!
+//! # async_std::task::block_on(async {
+//! let connector = TlsConnector::new();
+//! let (mut socket, _) = ws::connect_async(&connector, "wss://example.com/socket").await?;
+//! socket.send(Message::text("hello")).await?;
+//! let reply = socket.next().await;
+//! # Ok(()) as async_tungstenite::tungstenite::Result<()>
+//! # });
+//! ```
+
+use crate::client::TlsStream;
+use crate::tcp;
+use crate::TlsConnector;
+
+use async_std::net::TcpStream;
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use async_tungstenite::tungstenite::error::{Error, UrlError};
+use async_tungstenite::tungstenite::handshake::client::Response;
+use async_tungstenite::tungstenite::protocol::WebSocketConfig;
+use async_tungstenite::WebSocketStream;
+
+/// Connect to the `wss://` URL of `request` with `connector`.
+///
+/// The host of the URL is also the server name, and the port is 443 unless the
+/// URL has one. Other schemes fail with `UrlError::UnsupportedUrlScheme`, and
+/// TLS and IO errors are returned as `Error::Io`.
+pub async fn connect_async<R>(
+    connector: &TlsConnector,
+    request: R,
+) -> Result<(WebSocketStream<TlsStream<TcpStream>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    connect_async_with_config(connector, request, None).await
+}
+
+/// Like `connect_async`, with a WebSocket `config`.
+pub async fn connect_async_with_config<R>(
+    connector: &TlsConnector,
+    request: R,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<TlsStream<TcpStream>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = request.into_client_request()?;
+    let uri = request.uri();
+    if uri.scheme_str() != Some("wss") {
+        return Err(Error::Url(UrlError::UnsupportedUrlScheme));
+    }
+    let host = match uri.host() {
+        Some("") => return Err(Error::Url(UrlError::EmptyHostName)),
+        Some(host) => host.to_string(),
+        None => return Err(Error::Url(UrlError::NoHostName)),
+    };
+    let stream = tcp::connect(&host, uri.port_u16().unwrap_or(443)).await?;
+    let stream = connector.connect(&host, stream).await?;
+    async_tungstenite::client_async_with_config(request, stream, config).await
+}
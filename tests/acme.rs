@@ -0,0 +1,376 @@
+use async_std::io::prelude::*;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::acme::{AcmeConfig, AcmeManager, Http01Solver};
+use async_tls::test_utils::TestCa;
+use async_tls::{TlsAcceptor, TlsConnector, ACME_TLS_ALPN_NAME};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rustls::client::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, DigitallySignedStruct, ServerName};
+use serde_json::{json, Value};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A minimal ACME server. It checks challenges but not request signatures.
+struct Directory {
+    base: String,
+    ca: TestCa,
+    issuer: rcgen::Certificate,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    thumbprint: String,
+    domains: Vec<String>,
+    /// Per authorization: `None` while pending, then whether it validated.
+    validated: Vec<Option<bool>>,
+    certificate: Option<String>,
+    orders: usize,
+    http_01: Option<Arc<Http01Solver>>,
+    tls_alpn_01: Option<SocketAddr>,
+}
+
+type Response = (u16, Vec<(&'static str, String)>, Vec<u8>);
+
+impl Directory {
+    async fn start() -> Arc<Directory> {
+        let ca = TestCa::new().unwrap();
+        let acceptor = ca.acceptor(&["localhost"]).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut params = rcgen::CertificateParams::new(Vec::new());
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let directory = Arc::new(Directory {
+            base: format!("https://localhost:{}", port),
+            ca,
+            issuer: rcgen::Certificate::from_params(params).unwrap(),
+            state: Mutex::new(State::default()),
+        });
+
+        let server = directory.clone();
+        task::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (acceptor, server) = (acceptor.clone(), server.clone());
+                task::spawn(async move { server.serve(&acceptor, stream).await });
+            }
+        });
+        directory
+    }
+
+    /// A manager talking to this directory.
+    fn manager(&self, domains: &[&str], cache_dir: &Path) -> AcmeConfig {
+        AcmeConfig::new(domains.iter().map(|d| d.to_string()).collect())
+            .directory(format!("{}/directory", self.base))
+            .cache_dir(cache_dir)
+            .connector(self.ca.connector().unwrap())
+    }
+
+    fn orders(&self) -> usize {
+        self.state.lock().unwrap().orders
+    }
+
+    async fn serve(&self, acceptor: &TlsAcceptor, stream: TcpStream) -> io::Result<()> {
+        let mut stream = acceptor.accept(stream).await?;
+
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        let header_end = loop {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buf[..n]);
+            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&request[..header_end]).into_owned();
+        let length = head
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .map_or(0, |(_, value)| value.parse().unwrap());
+        while request.len() < header_end + length {
+            let n = stream.read(&mut buf).await?;
+            request.extend_from_slice(&buf[..n]);
+        }
+        let mut request_line = head.split(' ');
+        let method = request_line.next().unwrap_or_default();
+        let path = request_line.next().unwrap_or_default();
+
+        let (status, headers, body) = self.handle(method, path, &request[header_end..]).await;
+        let mut response = format!(
+            "HTTP/1.1 {} Mock\r\nReplay-Nonce: nonce\r\nContent-Length: {}\r\n",
+            status,
+            body.len()
+        );
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+        stream.write_all(response.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        futures_util::AsyncWriteExt::close(&mut stream).await
+    }
+
+    async fn handle(&self, method: &str, path: &str, body: &[u8]) -> Response {
+        let base = &self.base;
+        if method == "GET" && path == "/directory" {
+            let directory = json!({
+                "newNonce": format!("{}/nonce", base),
+                "newAccount": format!("{}/new-account", base),
+                "newOrder": format!("{}/new-order", base),
+            });
+            return (200, Vec::new(), directory.to_string().into_bytes());
+        }
+        if method == "HEAD" {
+            return (200, Vec::new(), Vec::new());
+        }
+
+        let jws: Value = serde_json::from_slice(body).unwrap();
+        let protected: Value = serde_json::from_slice(&b64(&jws["protected"])).unwrap();
+        let payload = b64(&jws["payload"]);
+        let payload: Value = serde_json::from_slice(&payload).unwrap_or(Value::Null);
+
+        let segments: Vec<_> = path.trim_start_matches('/').split('/').collect();
+        match segments[..] {
+            ["new-account"] => {
+                let jwk = protected["jwk"].to_string();
+                let digest = ring::digest::digest(&ring::digest::SHA256, jwk.as_bytes());
+                self.state.lock().unwrap().thumbprint = URL_SAFE_NO_PAD.encode(digest);
+                let location = vec![("Location", format!("{}/account", base))];
+                (201, location, b"{}".to_vec())
+            }
+            ["new-order"] => {
+                let mut state = self.state.lock().unwrap();
+                state.orders += 1;
+                state.domains = (payload["identifiers"].as_array().unwrap().iter())
+                    .map(|id| id["value"].as_str().unwrap().to_string())
+                    .collect();
+                state.validated = vec![None; state.domains.len()];
+                state.certificate = None;
+                let location = vec![("Location", format!("{}/order", base))];
+                (201, location, self.order(&state).to_string().into_bytes())
+            }
+            ["order"] => {
+                let state = self.state.lock().unwrap();
+                (200, Vec::new(), self.order(&state).to_string().into_bytes())
+            }
+            ["authz", i] => {
+                let i: usize = i.parse().unwrap();
+                let state = self.state.lock().unwrap();
+                let status = match state.validated[i] {
+                    None => "pending",
+                    Some(true) => "valid",
+                    Some(false) => "invalid",
+                };
+                let challenges: Vec<_> = (["http-01", "tls-alpn-01"].iter())
+                    .map(|kind| {
+                        json!({
+                            "type": kind,
+                            "url": format!("{}/challenge/{}/{}", base, i, kind),
+                            "token": format!("token{}", i),
+                        })
+                    })
+                    .collect();
+                let authz = json!({
+                    "status": status,
+                    "identifier": { "type": "dns", "value": state.domains[i] },
+                    "challenges": challenges,
+                });
+                (200, Vec::new(), authz.to_string().into_bytes())
+            }
+            ["challenge", i, kind] => {
+                let i: usize = i.parse().unwrap();
+                let valid = self.validate(i, kind).await;
+                self.state.lock().unwrap().validated[i] = Some(valid);
+                (200, Vec::new(), b"{}".to_vec())
+            }
+            ["finalize"] => {
+                let csr = b64(&payload["csr"]);
+                let csr = rcgen::CertificateSigningRequest::from_der(&csr).unwrap();
+                let leaf = csr.serialize_pem_with_signer(&self.issuer).unwrap();
+                let issuer = self.issuer.serialize_pem().unwrap();
+                let mut state = self.state.lock().unwrap();
+                state.certificate = Some(leaf + &issuer);
+                (200, Vec::new(), self.order(&state).to_string().into_bytes())
+            }
+            ["certificate"] => {
+                let state = self.state.lock().unwrap();
+                let chain = state.certificate.clone().unwrap();
+                (200, Vec::new(), chain.into_bytes())
+            }
+            _ => (404, Vec::new(), Vec::new()),
+        }
+    }
+
+    fn order(&self, state: &State) -> Value {
+        let status = match state.certificate {
+            Some(_) => "valid",
+            None if state.validated.iter().all(|v| *v == Some(true)) => "ready",
+            None => "pending",
+        };
+        json!({
+            "status": status,
+            "authorizations": (0..state.domains.len())
+                .map(|i| format!("{}/authz/{}", self.base, i))
+                .collect::<Vec<_>>(),
+            "finalize": format!("{}/finalize", self.base),
+            "certificate": format!("{}/certificate", self.base),
+        })
+    }
+
+    async fn validate(&self, i: usize, kind: &str) -> bool {
+        let (domain, key_authorization, http_01, tls_alpn_01) = {
+            let state = self.state.lock().unwrap();
+            let key_authorization = format!("token{}.{}", i, state.thumbprint);
+            let domain = state.domains[i].clone();
+            (
+                domain,
+                key_authorization,
+                state.http_01.clone(),
+                state.tls_alpn_01,
+            )
+        };
+        match kind {
+            "http-01" => {
+                let solver = http_01.unwrap();
+                solver.key_authorization(&format!("token{}", i)) == Some(key_authorization)
+            }
+            "tls-alpn-01" => {
+                let digest =
+                    ring::digest::digest(&ring::digest::SHA256, key_authorization.as_bytes());
+                let verifier = ChallengeVerifier(digest.as_ref().to_vec());
+                let mut config = ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_custom_certificate_verifier(Arc::new(verifier))
+                    .with_no_client_auth();
+                config.alpn_protocols = vec![ACME_TLS_ALPN_NAME.to_vec()];
+                let stream = TcpStream::connect(tls_alpn_01.unwrap()).await.unwrap();
+                let connect = TlsConnector::from(config).connect(&domain, stream);
+                connect.await.is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Accepts only certificates carrying the expected `acmeIdentifier` digest.
+///
+/// webpki refuses the critical `acmeIdentifier` extension, so it does not
+/// get to check the handshake signatures either.
+struct ChallengeVerifier(Vec<u8>);
+
+impl ServerCertVerifier for ChallengeVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.0.windows(self.0.len()).any(|w| w == self.0) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("missing acmeIdentifier".into()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &Certificate,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &Certificate,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+}
+
+fn b64(value: &Value) -> Vec<u8> {
+    URL_SAFE_NO_PAD.decode(value.as_str().unwrap()).unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("async-tls-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn renew(manager: &mut AcmeManager) -> bool {
+    manager.renew_if_needed().await.unwrap()
+}
+
+#[test]
+fn orders_and_caches_certificates() {
+    task::block_on(async {
+        let directory = Directory::start().await;
+        let dir = temp_dir("acme");
+        let solver = Arc::new(Http01Solver::new());
+        directory.state.lock().unwrap().http_01 = Some(solver.clone());
+        let domains = ["example.com", "www.example.com"];
+
+        let mut manager = (directory.manager(&domains, &dir))
+            .challenge_solver(solver.clone())
+            .build();
+        assert!(renew(&mut manager).await);
+        assert!(manager.not_after().is_some());
+        assert_eq!(directory.orders(), 1);
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["account.pk8", "cert.pem", "key.pem"]);
+
+        // a restart uses the cached certificate
+        let mut manager = (directory.manager(&["www.example.com", "example.com"], &dir))
+            .challenge_solver(solver.clone())
+            .build();
+        assert!(!renew(&mut manager).await);
+        assert_eq!(directory.orders(), 1);
+
+        // unless the key does not belong to it
+        let other = rcgen::generate_simple_self_signed(vec!["example.com".into()]).unwrap();
+        std::fs::write(dir.join("key.pem"), other.serialize_private_key_pem()).unwrap();
+        let mut manager = (directory.manager(&domains, &dir))
+            .challenge_solver(solver.clone())
+            .build();
+        assert!(renew(&mut manager).await);
+        assert_eq!(directory.orders(), 2);
+
+        // or it was issued for other domains; this time with tls-alpn-01
+        let mut manager = directory.manager(&["other.example"], &dir).build();
+        let acceptor = manager.acceptor();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        directory.state.lock().unwrap().tls_alpn_01 = Some(listener.local_addr().unwrap());
+        task::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                task::spawn(async move { acceptor.accept(stream).await });
+            }
+        });
+        assert!(renew(&mut manager).await);
+        assert_eq!(directory.orders(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    });
+}
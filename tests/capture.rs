@@ -0,0 +1,95 @@
+use async_tls::capture::{KeyLogWriter, Recorder};
+use async_tls::test_utils::{self, TestCa};
+use async_tls::{TlsAcceptor, TlsConnector, TrafficCounters};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// A sink whose bytes can be looked at while it is written to.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The TCP payloads of the packets in a pcap file, and whether each was sent
+/// by the recorded end.
+fn payloads(pcap: &[u8]) -> Vec<(bool, &[u8])> {
+    assert_eq!(&pcap[..4], &0xa1b2_c3d4u32.to_le_bytes());
+    assert_eq!(&pcap[20..24], &228u32.to_le_bytes());
+    let mut rest = &pcap[24..];
+    let mut payloads = Vec::new();
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes([rest[8], rest[9], rest[10], rest[11]]) as usize;
+        let packet = &rest[16..16 + len];
+        payloads.push((packet[12..16] == [10, 0, 0, 1], &packet[40..]));
+        rest = &rest[16 + len..];
+    }
+    payloads
+}
+
+#[test]
+fn record_connections() {
+    let ca = TestCa::new().unwrap();
+    let (chain, key) = ca.issue(&["localhost"]).unwrap();
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap(),
+    );
+    let mut roots = RootCertStore::empty();
+    roots.add(&ca.certificate()).unwrap();
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let key_log = Shared::default();
+    config.key_log = Arc::new(KeyLogWriter::new(key_log.clone()));
+    let connector = TlsConnector::from(config);
+
+    let pcap = Shared::default();
+    let (client, server) = test_utils::duplex(4096);
+    let client = Recorder::new(client, pcap.clone()).unwrap();
+    let counters = futures_executor::block_on(async {
+        let server = async {
+            let mut stream = acceptor.accept(server).await?;
+            stream.write_all(b"hello").await?;
+            stream.flush().await
+        };
+        let client = async {
+            let mut stream = connector.connect("localhost", client).await?;
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await?;
+            assert!(stream.get_ref().is_recording());
+            Ok(stream.counters()) as io::Result<TrafficCounters>
+        };
+        let (client, server) = futures_util::future::join(client, server).await;
+        server?;
+        client
+    })
+    .unwrap();
+
+    let pcap = pcap.0.lock().unwrap();
+    let packets = payloads(&pcap);
+    let sent: usize = packets.iter().filter(|p| p.0).map(|p| p.1.len()).sum();
+    let received: usize = packets.iter().filter(|p| !p.0).map(|p| p.1.len()).sum();
+    assert_eq!(sent as u64, counters.tls_bytes_sent);
+    assert_eq!(received as u64, counters.tls_bytes_received);
+    // the ClientHello goes first
+    assert_eq!(&packets[0].1[..1], &[0x16]);
+
+    let key_log = String::from_utf8(key_log.0.lock().unwrap().clone()).unwrap();
+    assert!(key_log.contains("CLIENT_TRAFFIC_SECRET_0 "), "{}", key_log);
+    assert!(key_log.lines().all(|line| line.split(' ').count() == 3));
+}
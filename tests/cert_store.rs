@@ -0,0 +1,93 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{CertStore, TlsAcceptor, TlsConnector};
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pemfile::certs;
+use std::io::{self, BufReader, Cursor};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+const CERT: &str = include_str!("end.cert");
+const CHAIN: &str = include_str!("end.chain");
+const RSA: &str = include_str!("end.rsa");
+
+async fn serve(acceptor: TlsAcceptor) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            task::spawn(async move { acceptor.accept(stream).await });
+        }
+    });
+    addr
+}
+
+async fn handshake(addr: SocketAddr) -> io::Result<()> {
+    let mut roots = RootCertStore::empty();
+    let chain = certs(&mut BufReader::new(Cursor::new(CHAIN))).unwrap();
+    roots.add_parsable_certificates(&chain);
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect(addr).await?;
+    TlsConnector::from(config)
+        .connect("localhost", stream)
+        .await
+        .map(drop)
+}
+
+#[test]
+fn certificates_can_change_at_runtime() {
+    let store = CertStore::new();
+
+    task::block_on(async {
+        let addr = serve(TlsAcceptor::from(store.clone())).await;
+        assert!(handshake(addr).await.is_err());
+
+        store
+            .insert_pem("LocalHost.", CERT.as_bytes(), RSA.as_bytes())
+            .unwrap();
+        assert!(store.contains("localhost"));
+        handshake(addr).await.unwrap();
+
+        assert!(store.remove("localhost").is_some());
+        assert!(handshake(addr).await.is_err());
+    });
+}
+
+#[test]
+fn exact_match_wins_over_wildcards() {
+    let store = CertStore::new();
+    store
+        .insert_pem("*.example.com", CERT.as_bytes(), RSA.as_bytes())
+        .unwrap();
+    store
+        .insert_pem("*.eu.example.com", CERT.as_bytes(), RSA.as_bytes())
+        .unwrap();
+    store
+        .insert_pem("www.eu.example.com", CERT.as_bytes(), RSA.as_bytes())
+        .unwrap();
+
+    let wildcard = store.get("*.example.com").unwrap();
+    let eu_wildcard = store.get("*.eu.example.com").unwrap();
+    let exact = store.get("www.eu.example.com").unwrap();
+
+    assert!(Arc::ptr_eq(
+        &store.get("api.example.com").unwrap(),
+        &wildcard
+    ));
+    assert!(Arc::ptr_eq(
+        &store.get("api.eu.example.com").unwrap(),
+        &eu_wildcard
+    ));
+    assert!(Arc::ptr_eq(
+        &store.get("WWW.eu.example.com").unwrap(),
+        &exact
+    ));
+    assert!(store.get("example.com").is_none());
+    assert!(store.get("example.org").is_none());
+}
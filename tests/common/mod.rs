@@ -0,0 +1,44 @@
+//! Helpers shared by the integration tests.
+
+// each test uses a different subset
+#![allow(dead_code)]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{TlsAcceptor, TlsConnector};
+use std::io;
+
+/// Run a handshake over TCP, connecting to `localhost`, and return the
+/// results of the client and the server.
+pub fn handshake(
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+) -> (io::Result<()>, io::Result<()>) {
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect("localhost", stream).await;
+        // keep the client open until the server has written its session tickets
+        let server = server.await;
+        Ok((client.map(drop), server)) as io::Result<(io::Result<()>, io::Result<()>)>
+    })
+    .unwrap()
+}
+
+/// Encode a DER element from its tag and the concatenation of `parts`.
+pub fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let contents = parts.concat();
+    let mut out = vec![tag];
+    if contents.len() < 0x80 {
+        out.push(contents.len() as u8);
+    } else {
+        out.extend_from_slice(&[0x82, (contents.len() >> 8) as u8, contents.len() as u8]);
+    }
+    out.extend_from_slice(&contents);
+    out
+}
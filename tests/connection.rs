@@ -0,0 +1,86 @@
+use async_tls::connection::TlsStream;
+use async_tls::test_utils::{duplex, DuplexStream, TestCa};
+use futures_executor::block_on;
+use futures_util::future::try_join;
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use rustls::server::{Acceptor, ServerConnectionData};
+use rustls::{ConnectionCommon, ServerConfig, ServerConnection};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// A connection type of the application's own, unknown to async-tls.
+struct Wrapped(ServerConnection);
+
+impl Deref for Wrapped {
+    type Target = ConnectionCommon<ServerConnectionData>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Wrapped {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Read the `ClientHello` from `io` with rustls's `Acceptor`, and answer it
+/// with a certificate of `ca`.
+async fn accept(ca: &TestCa, mut io: DuplexStream) -> io::Result<TlsStream<DuplexStream, Wrapped>> {
+    let mut acceptor = Acceptor::default();
+    let mut buf = [0; 4096];
+    let accepted = loop {
+        let n = io.read(&mut buf).await?;
+        acceptor.read_tls(&mut &buf[..n])?;
+        let accepted = acceptor
+            .accept()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if let Some(accepted) = accepted {
+            break accepted;
+        }
+    };
+    assert_eq!(accepted.client_hello().server_name(), Some("localhost"));
+
+    let (chain, key) = ca.issue(&["localhost"])?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let conn = accepted
+        .into_connection(Arc::new(config))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut stream = TlsStream::new(io, Wrapped(conn));
+    stream.handshake().await?;
+    Ok(stream)
+}
+
+#[test]
+fn drive_custom_connections() -> io::Result<()> {
+    let ca = TestCa::new()?;
+    let connector = ca.connector()?;
+    let (client, server) = duplex(64 * 1024);
+
+    block_on(async {
+        let (mut client, mut server) =
+            try_join(connector.connect("localhost", client), accept(&ca, server)).await?;
+
+        client.write_all(b"ping").await?;
+        client.flush().await?;
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"ping");
+
+        server.write_all(b"pong").await?;
+        server.close().await?;
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await?;
+        assert_eq!(received, b"pong");
+
+        let (_, conn) = server.get_ref();
+        assert!(!conn.is_handshaking());
+        Ok(())
+    })
+}
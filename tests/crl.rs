@@ -0,0 +1,158 @@
+mod common;
+
+use async_tls::crl::{self, ClientCertVerifierBuilder, ServerCertVerifierBuilder};
+use async_tls::{TlsAcceptor, TlsConnector};
+use common::handshake;
+use rcgen::{
+    date_time_ymd, BasicConstraints, CertificateParams, CertificateRevocationList,
+    CertificateRevocationListParams, IsCa, KeyIdMethod, RevokedCertParams, SerialNumber,
+};
+use rustls::{Certificate, ClientConfig, PrivateKey, ServerConfig};
+
+const REVOKED: u8 = 1;
+const VALID: u8 = 2;
+
+struct Ca {
+    cert: rcgen::Certificate,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::<String>::new());
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        Ca {
+            cert: rcgen::Certificate::from_params(params).unwrap(),
+        }
+    }
+
+    fn der(&self) -> Certificate {
+        Certificate(self.cert.serialize_der().unwrap())
+    }
+
+    fn issue(&self, serial: u8) -> (Vec<Certificate>, PrivateKey) {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+        params.serial_number = Some(SerialNumber::from(vec![serial]));
+        let leaf = rcgen::Certificate::from_params(params).unwrap();
+        (
+            vec![Certificate(
+                leaf.serialize_der_with_signer(&self.cert).unwrap(),
+            )],
+            PrivateKey(leaf.serialize_private_key_der()),
+        )
+    }
+
+    fn crl(&self) -> Vec<u8> {
+        let params = CertificateRevocationListParams {
+            this_update: date_time_ymd(2020, 1, 1),
+            next_update: date_time_ymd(2100, 1, 1),
+            crl_number: SerialNumber::from(vec![1]),
+            issuing_distribution_point: None,
+            revoked_certs: vec![RevokedCertParams {
+                serial_number: SerialNumber::from(vec![REVOKED]),
+                revocation_time: date_time_ymd(2020, 1, 1),
+                reason_code: None,
+                invalidity_date: None,
+            }],
+            alg: &rcgen::PKCS_ECDSA_P256_SHA256,
+            key_identifier_method: KeyIdMethod::Sha256,
+        };
+        CertificateRevocationList::from_params(params)
+            .unwrap()
+            .serialize_der_with_signer(&self.cert)
+            .unwrap()
+    }
+}
+
+/// Run a handshake, returning the errors of the client and the server.
+fn acceptor(chain: Vec<Certificate>, key: PrivateKey) -> TlsAcceptor {
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .unwrap()
+        .into()
+}
+
+#[test]
+fn rejects_revoked_server_certificates() {
+    let ca = Ca::new();
+    let verifier = ServerCertVerifierBuilder::new(vec![ca.der()])
+        .crl(ca.crl())
+        .build()
+        .unwrap();
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth(),
+    );
+
+    let (chain, key) = ca.issue(VALID);
+    let (client, server) = handshake(acceptor(chain, key), connector.clone());
+    client.unwrap();
+    server.unwrap();
+
+    let (chain, key) = ca.issue(REVOKED);
+    let (client, _) = handshake(acceptor(chain, key), connector.clone());
+    let err = client.unwrap_err();
+    assert!(crl::is_revoked(&err), "{}", err);
+
+    // other failures are told apart
+    let (chain, key) = Ca::new().issue(VALID);
+    let (client, _) = handshake(acceptor(chain, key), connector);
+    let err = client.unwrap_err();
+    assert!(!crl::is_revoked(&err), "{}", err);
+}
+
+#[test]
+fn rejects_revoked_client_certificates() {
+    let ca = Ca::new();
+    let verifier = ClientCertVerifierBuilder::new(vec![ca.der()])
+        .crl(ca.crl())
+        .build()
+        .unwrap();
+    let (chain, key) = ca.issue(VALID);
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(chain, key)
+            .unwrap(),
+    );
+    let connector = |serial| {
+        let (chain, key) = ca.issue(serial);
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&ca.der()).unwrap();
+        TlsConnector::from(
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_client_auth_cert(chain, key)
+                .unwrap(),
+        )
+    };
+
+    let (client, server) = handshake(acceptor.clone(), connector(VALID));
+    client.unwrap();
+    server.unwrap();
+
+    let (_, server) = handshake(acceptor, connector(REVOKED));
+    let err = server.unwrap_err();
+    assert!(crl::is_revoked(&err), "{}", err);
+}
+
+#[test]
+fn rejects_malformed_crls() {
+    let ca = Ca::new();
+    assert!(ServerCertVerifierBuilder::new(vec![ca.der()])
+        .crl(vec![0x30, 0x00])
+        .build()
+        .is_err());
+    assert!(ClientCertVerifierBuilder::new(vec![ca.der()])
+        .crl(vec![0x30, 0x00])
+        .build()
+        .is_err());
+    assert!(ServerCertVerifierBuilder::new(vec![ca.der()])
+        .crls_pem(b"")
+        .is_err());
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::ct::{CtLog, CtPolicy};
+use async_tls::{CertStore, TlsAcceptor, TlsConnector};
+use common::der;
+use rcgen::{BasicConstraints, CertificateParams, CustomExtension, IsCa, KeyPair, SerialNumber};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_ASN1_SIGNING};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore};
+use std::io;
+use std::time::{Duration, SystemTime};
+
+const SCT_LIST: &[u64] = &[1, 3, 6, 1, 4, 1, 11129, 2, 4, 2];
+
+fn prefixed(len: usize, data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u64).to_be_bytes()[8 - len..].to_vec();
+    out.extend_from_slice(data);
+    out
+}
+
+/// A log with a P-256 key.
+struct Log {
+    key: EcdsaKeyPair,
+    spki: Vec<u8>,
+}
+
+impl Log {
+    fn new() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let algorithm = der(
+            0x30,
+            &[
+                &der(0x06, &[&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]]),
+                &der(0x06, &[&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]]),
+            ],
+        );
+        let spki = der(
+            0x30,
+            &[&algorithm, &der(0x03, &[&[0], key.public_key().as_ref()])],
+        );
+        Log { key, spki }
+    }
+
+    fn ct_log(&self, name: &str) -> CtLog {
+        CtLog::new(name, self.spki.clone()).unwrap()
+    }
+
+    /// An SCT for the precertificate `tbs`, issued by the owner of `issuer_spki`.
+    fn sct(&self, issuer_spki: &[u8], tbs: &[u8], timestamp: SystemTime) -> Vec<u8> {
+        let timestamp = timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let signed = [
+            &[0, 0][..],
+            &timestamp.to_be_bytes(),
+            &[0, 1],
+            digest(&SHA256, issuer_spki).as_ref(),
+            &prefixed(3, tbs),
+            &[0, 0],
+        ]
+        .concat();
+        let signature = self.key.sign(&SystemRandom::new(), &signed).unwrap();
+        [
+            &[0][..],
+            digest(&SHA256, &self.spki).as_ref(),
+            &timestamp.to_be_bytes(),
+            &[0, 0, 4, 3],
+            &prefixed(2, signature.as_ref()),
+        ]
+        .concat()
+    }
+}
+
+fn key_pair() -> Vec<u8> {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new());
+    pkcs8.unwrap().as_ref().to_vec()
+}
+
+fn leaf_params(pkcs8: &[u8]) -> CertificateParams {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params.serial_number = Some(SerialNumber::from(vec![7]));
+    params.key_pair = Some(KeyPair::from_der(pkcs8).unwrap());
+    params
+}
+
+/// The TBS certificate of a DER certificate.
+fn tbs(cert: &[u8]) -> &[u8] {
+    let skip_header = |der: &[u8]| match der[1] {
+        len @ 0x81..=0x82 => 2 + (len - 0x80) as usize,
+        _ => 2,
+    };
+    let tbs = &cert[skip_header(cert)..];
+    let len = match tbs[1] {
+        0x81 => tbs[2] as usize,
+        0x82 => ((tbs[2] as usize) << 8) | tbs[3] as usize,
+        len => len as usize,
+    };
+    &tbs[..skip_header(tbs) + len]
+}
+
+struct Issued {
+    cert: CertifiedKey,
+    ca: Certificate,
+}
+
+/// A certificate carrying SCTs made by `sct` for its precertificate.
+fn certificate(sct: impl Fn(&[u8], &[u8]) -> Vec<Vec<u8>>) -> Issued {
+    let mut ca = CertificateParams::new(Vec::<String>::new());
+    ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca.key_pair = Some(KeyPair::from_der(&key_pair()).unwrap());
+    let ca = rcgen::Certificate::from_params(ca).unwrap();
+    let ca_der = ca.serialize_der().unwrap();
+    let ca_spki = ca.get_key_pair().public_key_der();
+
+    let leaf_key = key_pair();
+    let precert = rcgen::Certificate::from_params(leaf_params(&leaf_key)).unwrap();
+    let precert = precert.serialize_der_with_signer(&ca).unwrap();
+
+    let scts = sct(&ca_spki, tbs(&precert))
+        .iter()
+        .map(|sct| prefixed(2, sct))
+        .collect::<Vec<_>>()
+        .concat();
+    let mut params = leaf_params(&leaf_key);
+    params
+        .custom_extensions
+        .push(CustomExtension::from_oid_content(
+            SCT_LIST,
+            der(0x04, &[&prefixed(2, &scts)]),
+        ));
+    let leaf = rcgen::Certificate::from_params(params).unwrap();
+
+    let chain = vec![
+        Certificate(leaf.serialize_der_with_signer(&ca).unwrap()),
+        Certificate(ca_der.clone()),
+    ];
+    let key = PrivateKey(leaf.serialize_private_key_der());
+    let key = rustls::sign::any_supported_type(&key).unwrap();
+    Issued {
+        cert: CertifiedKey::new(chain, key),
+        ca: Certificate(ca_der),
+    }
+}
+
+/// Serve `cert` and connect to it with `policy`, returning the names of the matched logs.
+fn handshake(issued: &Issued, policy: CtPolicy) -> io::Result<Vec<String>> {
+    let store = CertStore::new();
+    store.insert("localhost", issued.cert.clone());
+    let acceptor = TlsAcceptor::from(store);
+
+    let mut roots = RootCertStore::empty();
+    roots.add(&issued.ca).unwrap();
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    )
+    .ct_policy(policy);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the certificate, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect("localhost", stream).await;
+        server.await?;
+        let client = client?;
+        Ok(client
+            .ct_logs()
+            .iter()
+            .map(|log| log.name().to_string())
+            .collect())
+    })
+}
+
+#[test]
+fn accepts_logged_certificates() -> io::Result<()> {
+    let (a, b) = (Log::new(), Log::new());
+    let now = SystemTime::now();
+    let issued = certificate(|issuer, tbs| vec![a.sct(issuer, tbs, now), b.sct(issuer, tbs, now)]);
+
+    let logs = handshake(&issued, CtPolicy::new(vec![a.ct_log("a"), b.ct_log("b")]))?;
+    assert_eq!(logs, ["a", "b"]);
+
+    // SCTs of unknown logs are ignored
+    let logs = handshake(&issued, CtPolicy::new(vec![b.ct_log("b")]).min_scts(1))?;
+    assert_eq!(logs, ["b"]);
+    Ok(())
+}
+
+#[test]
+fn rejects_unlogged_certificates() {
+    let (a, b) = (Log::new(), Log::new());
+    let now = SystemTime::now();
+    let issued = certificate(|issuer, tbs| vec![a.sct(issuer, tbs, now)]);
+    let policy = || CtPolicy::new(vec![a.ct_log("a"), b.ct_log("b")]);
+
+    let err = handshake(&issued, policy()).unwrap_err();
+    assert!(err.to_string().contains("1 of 2"), "{}", err);
+    assert_eq!(handshake(&issued, policy().min_scts(1)).unwrap(), ["a"]);
+
+    // the same log does not count twice
+    let issued = certificate(|issuer, tbs| vec![a.sct(issuer, tbs, now), a.sct(issuer, tbs, now)]);
+    assert!(handshake(&issued, policy()).is_err());
+
+    let issued = certificate(|_, _| Vec::new());
+    assert!(handshake(&issued, policy().min_scts(1)).is_err());
+}
+
+#[test]
+fn rejects_invalid_scts() {
+    let a = Log::new();
+    let policy = || CtPolicy::new(vec![a.ct_log("a")]).min_scts(1);
+    let now = SystemTime::now();
+
+    // signed over another certificate
+    let issued = certificate(|issuer, _| vec![a.sct(issuer, b"forged", now)]);
+    assert!(handshake(&issued, policy()).is_err());
+
+    // issued by another CA
+    let issued = certificate(|_, tbs| vec![a.sct(b"other", tbs, now)]);
+    assert!(handshake(&issued, policy()).is_err());
+
+    // from the future
+    let later = now + Duration::from_secs(3600);
+    let issued = certificate(|issuer, tbs| vec![a.sct(issuer, tbs, later)]);
+    assert!(handshake(&issued, policy()).is_err());
+}
+
+#[test]
+fn rejects_unsupported_log_keys() {
+    assert!(CtLog::new("empty", Vec::new()).is_err());
+    // Ed25519 keys are not allowed for logs
+    let ed25519 = der(
+        0x30,
+        &[
+            &der(0x30, &[&der(0x06, &[&[0x2b, 0x65, 0x70]])]),
+            &der(0x03, &[&[0], &[0; 32]]),
+        ],
+    );
+    assert!(CtLog::new("ed25519", ed25519).is_err());
+}
@@ -0,0 +1,185 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::dane::{self, DaneVerifier, TlsaRecord};
+use async_tls::{TlsAcceptor, TlsConnector};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use ring::digest::{digest, SHA256, SHA512};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use std::io;
+
+struct Issued {
+    chain: Vec<Certificate>,
+    key: PrivateKey,
+    /// The DER `SubjectPublicKeyInfo` of the leaf.
+    spki: Vec<u8>,
+}
+
+fn issue() -> Issued {
+    let mut ca = CertificateParams::new(Vec::<String>::new());
+    ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(ca).unwrap();
+
+    let key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let spki = key.public_key_der();
+    let mut leaf = CertificateParams::new(vec!["localhost".to_string()]);
+    leaf.key_pair = Some(key);
+    let leaf = rcgen::Certificate::from_params(leaf).unwrap();
+    Issued {
+        chain: vec![
+            Certificate(leaf.serialize_der_with_signer(&ca).unwrap()),
+            Certificate(ca.serialize_der().unwrap()),
+        ],
+        key: PrivateKey(leaf.serialize_private_key_der()),
+        spki,
+    }
+}
+
+impl Issued {
+    fn roots(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(&self.chain[1]).unwrap();
+        roots
+    }
+}
+
+/// Serve `issued` and connect to it as `host`.
+fn handshake(issued: &Issued, host: &str, verifier: DaneVerifier) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(issued.chain.clone(), issued.key.clone())
+            .unwrap(),
+    );
+    let connector = TlsConnector::new().dane(verifier);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the certificate, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect(host, stream).await;
+        server.await?;
+        client.map(drop)
+    })
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    digest(&SHA256, data).as_ref().to_vec()
+}
+
+#[test]
+fn dane_ee_pins_the_server_certificate() {
+    let issued = issue();
+    let record = TlsaRecord::new(
+        dane::DANE_EE,
+        dane::SPKI,
+        dane::SHA2_256,
+        sha256(&issued.spki),
+    );
+    let verifier = || DaneVerifier::new(vec![record.clone()]);
+
+    handshake(&issued, "localhost", verifier()).unwrap();
+    // names are not checked
+    handshake(&issued, "example.com", verifier()).unwrap();
+
+    let other = issue();
+    assert!(handshake(&other, "localhost", verifier()).is_err());
+
+    // one matching record is enough
+    let records = vec![
+        TlsaRecord::new(
+            dane::DANE_EE,
+            dane::SPKI,
+            dane::SHA2_256,
+            sha256(&other.spki),
+        ),
+        TlsaRecord::new(
+            dane::DANE_EE,
+            dane::CERT,
+            dane::FULL,
+            issued.chain[0].0.clone(),
+        ),
+    ];
+    handshake(&issued, "localhost", DaneVerifier::new(records)).unwrap();
+}
+
+#[test]
+fn dane_ta_replaces_the_roots() {
+    let issued = issue();
+    let ca = digest(&SHA512, &issued.chain[1].0).as_ref().to_vec();
+    let verifier = || {
+        DaneVerifier::new(vec![TlsaRecord::new(
+            dane::DANE_TA,
+            dane::CERT,
+            dane::SHA2_512,
+            ca.clone(),
+        )])
+    };
+
+    handshake(&issued, "localhost", verifier()).unwrap();
+    assert!(handshake(&issued, "example.com", verifier()).is_err());
+
+    // the anchor is matched against the chain, not against the leaf
+    let leaf = sha256(&issued.spki);
+    let record = TlsaRecord::new(dane::DANE_TA, dane::SPKI, dane::SHA2_256, leaf);
+    assert!(handshake(&issued, "localhost", DaneVerifier::new(vec![record])).is_err());
+}
+
+#[test]
+fn pkix_records_need_roots() {
+    let issued = issue();
+    let record = TlsaRecord::new(
+        dane::PKIX_EE,
+        dane::SPKI,
+        dane::SHA2_256,
+        sha256(&issued.spki),
+    );
+    let verifier = || DaneVerifier::new(vec![record.clone()]);
+
+    assert!(handshake(&issued, "localhost", verifier()).is_err());
+    handshake(&issued, "localhost", verifier().pkix(issued.roots())).unwrap();
+    assert!(handshake(&issued, "example.com", verifier().pkix(issued.roots())).is_err());
+
+    // the record still has to match
+    let other = issue();
+    assert!(handshake(&other, "localhost", verifier().pkix(other.roots())).is_err());
+
+    let ca = sha256(&issued.chain[1].0);
+    let record = TlsaRecord::new(dane::PKIX_TA, dane::CERT, dane::SHA2_256, ca);
+    let verifier = DaneVerifier::new(vec![record]).pkix(issued.roots());
+    handshake(&issued, "localhost", verifier).unwrap();
+}
+
+#[test]
+fn falls_back_without_usable_records() {
+    let issued = issue();
+    let unknown = vec![TlsaRecord::new(
+        4,
+        dane::SPKI,
+        dane::SHA2_256,
+        sha256(&issued.spki),
+    )];
+
+    assert!(handshake(&issued, "localhost", DaneVerifier::new(unknown.clone())).is_err());
+    assert!(handshake(&issued, "localhost", DaneVerifier::new(Vec::new())).is_err());
+    handshake(
+        &issued,
+        "localhost",
+        DaneVerifier::new(unknown).pkix(issued.roots()),
+    )
+    .unwrap();
+
+    let other = issue();
+    assert!(handshake(
+        &other,
+        "localhost",
+        DaneVerifier::new(Vec::new()).pkix(issued.roots())
+    )
+    .is_err());
+}
@@ -0,0 +1,140 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{TlsAcceptor, TlsConnector};
+use rcgen::{BasicConstraints, CertificateParams, IsCa};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName};
+use std::io;
+
+/// A chain for `localhost` issued by a fresh CA, and that CA.
+fn issue() -> (Vec<Certificate>, PrivateKey, Certificate) {
+    let mut ca = CertificateParams::new(Vec::<String>::new());
+    ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(ca).unwrap();
+    let leaf = CertificateParams::new(vec!["localhost".to_string()]);
+    let leaf = rcgen::Certificate::from_params(leaf).unwrap();
+    (
+        vec![Certificate(leaf.serialize_der_with_signer(&ca).unwrap())],
+        PrivateKey(leaf.serialize_private_key_der()),
+        Certificate(ca.serialize_der().unwrap()),
+    )
+}
+
+/// Serve `chain` and connect to it as `host`.
+fn handshake(
+    chain: Vec<Certificate>,
+    key: PrivateKey,
+    host: &str,
+    connector: TlsConnector,
+) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .unwrap(),
+    );
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the certificate, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect(host, stream).await;
+        server.await?;
+        client.map(drop)
+    })
+}
+
+fn roots(ca: &Certificate) -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca).unwrap();
+    roots
+}
+
+#[test]
+fn accepts_invalid_certs() {
+    let (chain, key, _) = issue();
+    assert!(handshake(chain.clone(), key.clone(), "localhost", TlsConnector::new()).is_err());
+
+    let connector = TlsConnector::new().danger_accept_invalid_certs(true);
+    handshake(chain.clone(), key.clone(), "localhost", connector.clone()).unwrap();
+    handshake(chain.clone(), key.clone(), "example.com", connector.clone()).unwrap();
+
+    // the toggle can be turned off again
+    let connector = connector.danger_accept_invalid_certs(false);
+    assert!(handshake(chain, key, "localhost", connector).is_err());
+}
+
+#[test]
+fn keeps_roots_until_the_verifier_is_replaced() {
+    let (chain, key, ca) = issue();
+    let connector = TlsConnector::with_root_certificates(roots(&ca))
+        .danger_accept_invalid_certs(false)
+        .danger_accept_invalid_hostnames(false)
+        .unwrap();
+    let connector = connector.min_tls_version(&rustls::version::TLS13).unwrap();
+    handshake(chain.clone(), key.clone(), "localhost", connector.clone()).unwrap();
+
+    // roots are unknown while the verifier is replaced, and known again after
+    let connector = connector.danger_accept_invalid_certs(true);
+    assert!(connector.clone().add_root_certificate(&ca).is_err());
+    let connector = connector.danger_accept_invalid_certs(false);
+    let connector = connector.add_root_certificate(&ca).unwrap();
+    handshake(chain, key, "localhost", connector).unwrap();
+}
+
+#[test]
+fn accepts_invalid_hostnames() {
+    let (chain, key, ca) = issue();
+    let connector = TlsConnector::with_root_certificates(roots(&ca));
+    handshake(chain.clone(), key.clone(), "localhost", connector.clone()).unwrap();
+    assert!(handshake(chain.clone(), key.clone(), "example.com", connector.clone()).is_err());
+
+    let connector = connector.danger_accept_invalid_hostnames(true).unwrap();
+    handshake(chain.clone(), key.clone(), "example.com", connector.clone()).unwrap();
+
+    // the chain is still verified
+    let (other_chain, other_key, _) = issue();
+    assert!(handshake(other_chain, other_key, "localhost", connector).is_err());
+
+    // connectors from a config do not know its roots
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots(&ca))
+        .with_no_client_auth();
+    let err = TlsConnector::from(config)
+        .danger_accept_invalid_hostnames(true)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn verifies_hostnames_with_callbacks() {
+    let (chain, key, ca) = issue();
+    let leaf = chain[0].clone();
+    let connector =
+        TlsConnector::with_root_certificates(roots(&ca)).verify_hostname_with(move |name, cert| {
+            match name {
+                ServerName::DnsName(name) => name.as_ref().ends_with(".internal") && *cert == leaf,
+                _ => false,
+            }
+        });
+
+    handshake(chain.clone(), key.clone(), "db.internal", connector.clone()).unwrap();
+    // the callback replaces the default check
+    assert!(handshake(chain.clone(), key.clone(), "localhost", connector.clone()).is_err());
+
+    // the chain is still verified
+    let (other_chain, other_key, _) = issue();
+    assert!(handshake(other_chain, other_key, "db.internal", connector.clone()).is_err());
+
+    // the roots are not those of the replaced verifier any more
+    assert!(connector.clone().add_root_certificate(&ca).is_err());
+
+    let connector = connector.danger_accept_invalid_hostnames(true).unwrap();
+    handshake(chain, key, "localhost", connector).unwrap();
+}
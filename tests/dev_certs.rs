@@ -0,0 +1,60 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{dev_certs, TlsAcceptor, TlsConnector};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use std::io;
+
+#[test]
+fn self_signed_roundtrip() -> io::Result<()> {
+    let acceptor = TlsAcceptor::self_signed(&["localhost", "127.0.0.1"])?;
+    let connector = TlsConnector::trust_self_signed()?;
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hello").await?;
+            stream.flush().await?;
+            io::Result::Ok(())
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect("localhost", stream).await?;
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello");
+        server.await
+    })
+}
+
+#[test]
+fn other_names_are_rejected() -> io::Result<()> {
+    let acceptor = TlsAcceptor::self_signed(&["localhost"])?;
+    let connector = TlsConnector::trust_self_signed()?;
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        assert!(connector.connect("example.com", stream).await.is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn ca_is_shared_and_exportable() -> io::Result<()> {
+    let (chain, _) = dev_certs::issue(&["localhost"])?;
+    assert_eq!(chain[1], dev_certs::ca_certificate()?);
+
+    let pem = dev_certs::ca_pem()?;
+    let parsed = rustls_pemfile::certs(&mut pem.as_bytes())?;
+    assert_eq!(parsed, vec![dev_certs::ca_certificate()?.0]);
+    Ok(())
+}
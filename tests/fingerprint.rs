@@ -0,0 +1,139 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::server::TlsStream;
+use async_tls::{
+    Admission, ClientHelloFingerprint, Error, LazyConfigAcceptor, TlsAcceptor, TlsConnector,
+};
+use rcgen::CertificateParams;
+use rustls::client::Resumption;
+use rustls::server::Acceptor;
+use rustls::{
+    version, AlertDescription, Certificate, ClientConfig, PrivateKey, ProtocolVersion,
+    RootCertStore, ServerConfig,
+};
+use std::io;
+use std::sync::Arc;
+
+fn configs() -> (Arc<ServerConfig>, TlsConnector) {
+    let cert =
+        rcgen::Certificate::from_params(CertificateParams::new(vec!["localhost".into()])).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![der.clone()],
+            PrivateKey(cert.serialize_private_key_der()),
+        )
+        .unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(&der).unwrap();
+    let mut client = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    client.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    // a resumed handshake offers a PSK, and so fingerprints differently
+    client.resumption = Resumption::disabled();
+    (Arc::new(config), TlsConnector::from(client))
+}
+
+/// Connect once, returning the server's end.
+fn accept(acceptor: &TlsAcceptor, connector: &TlsConnector) -> io::Result<TlsStream<TcpStream>> {
+    let acceptor = acceptor.clone();
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await
+        });
+        let stream = TcpStream::connect(addr).await?;
+        // keep the client open until the server completed its handshake
+        let _client = connector.connect("localhost", stream).await?;
+        server.await
+    })
+}
+
+fn check(fingerprint: &ClientHelloFingerprint) {
+    assert_eq!(fingerprint.alpn_protocols, [&b"h2"[..], b"http/1.1"]);
+    assert!(fingerprint.supported_versions.contains(&0x0304));
+    assert!(!fingerprint.cipher_suites.is_empty());
+    assert!(
+        fingerprint.ja3().starts_with("771,"),
+        "{}",
+        fingerprint.ja3()
+    );
+    let ja4 = fingerprint.ja4();
+    assert!(ja4.starts_with("t13d"), "{}", ja4);
+    assert_eq!(&ja4[8..10], "h2", "{}", ja4);
+}
+
+#[test]
+fn fingerprint_clients() {
+    let (config, connector) = configs();
+    let acceptor = TlsAcceptor::from(config);
+    let stream = accept(&acceptor, &connector).unwrap();
+    assert!(stream.client_hello_fingerprint().is_none());
+
+    let acceptor = acceptor.fingerprint_clients(true);
+    let stream = accept(&acceptor, &connector).unwrap();
+    let fingerprint = stream.client_hello_fingerprint().unwrap();
+    check(fingerprint);
+
+    // the same client, the same fingerprint
+    let again = accept(&acceptor, &connector).unwrap();
+    let again = again.client_hello_fingerprint().unwrap();
+    assert_eq!(again.ja3_hash(), fingerprint.ja3_hash());
+    assert_eq!(again.ja4(), fingerprint.ja4());
+}
+
+#[test]
+fn fingerprint_lazily() {
+    let (config, connector) = configs();
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let start = LazyConfigAcceptor::new(Acceptor::default(), stream).await?;
+            let fingerprint = start.fingerprint().cloned();
+            let stream = start.into_stream(config).await?;
+            assert_eq!(stream.client_hello_fingerprint(), fingerprint.as_ref());
+            Ok(fingerprint) as io::Result<Option<ClientHelloFingerprint>>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let _client = connector.connect("localhost", stream).await?;
+        check(&server.await?.unwrap());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn admit_by_version() {
+    let (config, connector) = configs();
+    let acceptor = TlsAcceptor::from(config).admit(|client| {
+        let fingerprint = client.fingerprint().unwrap();
+        match fingerprint.max_version() {
+            ProtocolVersion::TLSv1_3 => Admission::Accept,
+            _ => Admission::Reject(AlertDescription::ProtocolVersion),
+        }
+    });
+    let stream = accept(&acceptor, &connector).unwrap();
+    // not fingerprinted unless asked to
+    assert!(stream.client_hello_fingerprint().is_none());
+
+    let tls12 = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&version::TLS12])
+        .unwrap()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let err = accept(&acceptor, &TlsConnector::from(tls12)).unwrap_err();
+    match Error::from(err) {
+        Error::AlertReceived(AlertDescription::ProtocolVersion) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
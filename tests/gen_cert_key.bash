@@ -25,4 +25,7 @@ openssl req -newkey rsa:2048 -new -nodes -x509 -days 3650 -out "$CERT" -keyout "
 # make key accessible #yolo
 chmod 664 "$KEY"
 # concat chain
-cat "$CERT" "$CACERT" > "$CHAIN"
\ No newline at end of file
+cat "$CERT" "$CACERT" > "$CHAIN"
+# encrypted copies of the key, passphrase "async-tls"
+openssl pkcs8 -topk8 -in "$KEY" -out "${DIR}/end.enc.pk8" -v2 aes-256-cbc -passout pass:async-tls
+openssl rsa -in "$KEY" -traditional -aes256 -passout pass:async-tls -out "${DIR}/end.enc.rsa"
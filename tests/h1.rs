@@ -0,0 +1,102 @@
+use async_std::io::{Read, Write};
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::h1::H1Client;
+use async_tls::test_utils::TestCa;
+use async_tls::TlsAcceptor;
+use http_client::http_types::{Method, Request, StatusCode};
+use http_client::{Config, HttpClient};
+use std::io;
+use std::time::Duration;
+
+/// Answer one request with its request line.
+async fn respond<S: Read + Write + Unpin>(mut stream: S) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).await?;
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let line = head.lines().next().unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+        line.len(),
+        line
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await
+}
+
+/// Serve each connection, over TLS if there is an acceptor.
+async fn serve(acceptor: Option<TlsAcceptor>) -> io::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    task::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            task::spawn(async move {
+                match acceptor {
+                    Some(acceptor) => respond(acceptor.accept(stream).await?).await,
+                    None => respond(stream).await,
+                }
+            });
+        }
+    });
+    Ok(port)
+}
+
+#[test]
+fn send_requests() {
+    let ca = TestCa::new().unwrap();
+    let acceptor = ca.acceptor(&["localhost"]).unwrap();
+    let client = H1Client::new(ca.connector().unwrap());
+    task::block_on(async {
+        let https = serve(Some(acceptor)).await?;
+        let http = serve(None).await?;
+        for url in [
+            format!("https://localhost:{}/secure", https),
+            format!("http://localhost:{}/plain", http),
+        ] {
+            let mut response = client.send(Request::new(Method::Get, &*url)).await?;
+            assert_eq!(response.status(), StatusCode::Ok);
+            let path = url.rsplit('/').next().unwrap();
+            let line = format!("GET /{} HTTP/1.1", path);
+            assert_eq!(response.body_string().await?, line);
+        }
+
+        // the certificate is still verified
+        let client = H1Client::new(TestCa::new()?.connector()?);
+        let url = format!("https://localhost:{}/", https);
+        assert!(client.send(Request::new(Method::Get, &*url)).await.is_err());
+        Ok(()) as http_client::http_types::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn time_out_and_reject_other_schemes() {
+    let mut client = H1Client::new(TestCa::new().unwrap().connector().unwrap());
+    let config = Config::new().set_timeout(Some(Duration::from_millis(100)));
+    client.set_config(config).unwrap();
+    assert_eq!(client.config().timeout, Some(Duration::from_millis(100)));
+    task::block_on(async {
+        // a listener that never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let err = client
+            .send(Request::new(Method::Get, &*url))
+            .await
+            .unwrap_err();
+        let err = err.downcast::<io::Error>().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let request = Request::new(Method::Get, "ftp://localhost/");
+        let err = client.send(request).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BadRequest);
+    });
+}
@@ -0,0 +1,46 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::h1;
+use async_tls::test_utils::TestCa;
+use http_types::{Request, Response, StatusCode};
+use std::io;
+
+#[test]
+fn serve_requests() {
+    let ca = TestCa::new().unwrap();
+    let acceptor = ca.acceptor(&["localhost"]).unwrap();
+    let connector = ca.connector().unwrap();
+    let response = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        task::spawn(h1::serve(
+            listener,
+            acceptor,
+            |mut request: Request| async move {
+                let body = request.body_string().await?;
+                let mut response = Response::new(StatusCode::Ok);
+                response.set_body(format!("{} {}", request.url().path(), body));
+                Ok(response)
+            },
+        ));
+
+        // a failed handshake does not stop the server
+        let other = TestCa::new()?.connector()?;
+        let stream = TcpStream::connect(addr).await?;
+        assert!(other.connect("localhost", stream).await.is_err());
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect("localhost", stream).await?;
+        let request = "POST /echo HTTP/1.1\r\nhost: localhost\r\ncontent-length: 5\r\n\
+                       connection: close\r\n\r\nhello";
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response) as io::Result<String>
+    })
+    .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\n/echo hello"), "{}", response);
+}
@@ -0,0 +1,52 @@
+use async_std::net::TcpListener;
+use async_std::task;
+use async_tls::hyper::{AsyncStdExecutor, HttpsConnector, HyperIo};
+use async_tls::test_utils::TestCa;
+use async_tls::TlsAcceptor;
+use http_body_util::BodyExt;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::client::legacy::Client;
+use std::convert::Infallible;
+use std::io;
+
+/// Answer requests with their path until the listener fails.
+async fn serve(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        task::spawn(async move {
+            let stream = acceptor.accept(stream).await?;
+            let hello = service_fn(|request: Request<hyper::body::Incoming>| async move {
+                Ok::<_, Infallible>(Response::new(format!("hello {}", request.uri().path())))
+            });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(HyperIo::new(stream), hello)
+                .await
+                .map_err(io::Error::other)
+        });
+    }
+}
+
+#[test]
+fn send_requests_through_hyper() {
+    let ca = TestCa::new().unwrap();
+    let acceptor = ca.acceptor(&["localhost"]).unwrap();
+    let connector = HttpsConnector::new(ca.connector().unwrap());
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        task::spawn(serve(listener, acceptor));
+
+        let client: Client<_, String> = Client::builder(AsyncStdExecutor).build(connector);
+        for path in ["/first", "/second"] {
+            let uri = format!("https://localhost:{}{}", port, path);
+            let response = client.get(uri.parse().unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, format!("hello {}", path));
+        }
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
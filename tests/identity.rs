@@ -0,0 +1,65 @@
+use async_tls::Identity;
+use std::io;
+
+const CERT: &[u8] = include_bytes!("end.chain");
+const KEY: &[u8] = include_bytes!("end.rsa");
+const PKCS8_ENCRYPTED: &[u8] = include_bytes!("end.enc.pk8");
+const LEGACY_ENCRYPTED: &[u8] = include_bytes!("end.enc.rsa");
+const PASSPHRASE: &str = "async-tls";
+
+fn assert_usable(identity: Identity) {
+    let (chain, key) = identity.into_parts();
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .unwrap();
+}
+
+#[test]
+fn decrypts_pkcs8() {
+    assert_usable(Identity::from_encrypted_pem(CERT, PKCS8_ENCRYPTED, PASSPHRASE).unwrap());
+}
+
+#[test]
+fn decrypts_legacy_pem() {
+    assert_usable(Identity::from_encrypted_pem(CERT, LEGACY_ENCRYPTED, PASSPHRASE).unwrap());
+}
+
+#[test]
+fn rejects_wrong_passphrase() {
+    for key in [PKCS8_ENCRYPTED, LEGACY_ENCRYPTED] {
+        let err = Identity::from_encrypted_pem(CERT, key, "wrong").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn asks_for_passphrase_only_when_encrypted() {
+    let mut asked = false;
+    Identity::from_encrypted_pem_with(CERT, KEY, || {
+        asked = true;
+        Ok(PASSPHRASE)
+    })
+    .unwrap();
+    assert!(!asked);
+
+    Identity::from_encrypted_pem_with(CERT, PKCS8_ENCRYPTED, || {
+        asked = true;
+        Ok(PASSPHRASE)
+    })
+    .unwrap();
+    assert!(asked);
+}
+
+#[test]
+fn passes_through_callback_errors() {
+    let err = Identity::from_encrypted_pem_with(CERT, PKCS8_ENCRYPTED, || -> io::Result<&str> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no passphrase configured",
+        ))
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,52 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{TlsAcceptor, TlsConnector};
+use rcgen::CertificateParams;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use std::io;
+
+/// Serve a self-signed certificate for `name` and connect to it as `host`.
+fn handshake(name: &str, host: &str) -> io::Result<()> {
+    let cert =
+        rcgen::Certificate::from_params(CertificateParams::new(vec![name.to_string()])).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![der.clone()],
+                PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap(),
+    );
+    let mut roots = RootCertStore::empty();
+    roots.add(&der).unwrap();
+    let connector = TlsConnector::with_root_certificates(roots);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the certificate, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect(host, stream).await;
+        server.await?;
+        client.map(drop)
+    })
+}
+
+#[test]
+fn connects_to_unicode_names() {
+    handshake("xn--bcher-kva.example", "bücher.example").unwrap();
+    handshake("xn--bcher-kva.example", "BÜCHER.example").unwrap();
+    handshake("xn--bcher-kva.example", "xn--bcher-kva.example").unwrap();
+    assert!(handshake("xn--bcher-kva.example", "bucher.example").is_err());
+
+    let err = handshake("xn--bcher-kva.example", "bü\u{0}cher.example").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
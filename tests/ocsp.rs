@@ -0,0 +1,424 @@
+mod common;
+
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::client::TlsStream;
+use async_tls::ocsp::{OcspConfig, OcspVerifier};
+use async_tls::{CertStore, TlsAcceptor, TlsConnector};
+use common::der;
+use rcgen::{BasicConstraints, CertificateParams, CustomExtension, IsCa, KeyPair, SerialNumber};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const SERIAL: u8 = 42;
+const GOOD: &[u8] = &[0x80, 0x00];
+const UNKNOWN: &[u8] = &[0x82, 0x00];
+
+fn revoked() -> Vec<u8> {
+    der(0xa1, &[&der(0x18, &[b"20000301000000Z"])])
+}
+
+fn generalized_time(time: SystemTime) -> Vec<u8> {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // civil_from_days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let value = format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    );
+    der(0x18, &[value.as_bytes()])
+}
+
+/// An OCSP response signed by `key`.
+fn ocsp_response(status: &[u8], valid_for: Duration, key: &EcdsaKeyPair) -> Vec<u8> {
+    let now = SystemTime::now();
+    let cert_id = der(
+        0x30,
+        &[
+            &der(
+                0x30,
+                &[
+                    &der(0x06, &[&[0x2b, 0x0e, 0x03, 0x02, 0x1a]]),
+                    &[0x05, 0x00],
+                ],
+            ),
+            &der(0x04, &[&[0; 20]]),
+            &der(0x04, &[&[0; 20]]),
+            &der(0x02, &[&[SERIAL]]),
+        ],
+    );
+    let single = der(
+        0x30,
+        &[
+            &cert_id,
+            status,
+            &generalized_time(now),
+            &der(0xa0, &[&generalized_time(now + valid_for)]),
+        ],
+    );
+    let data = der(
+        0x30,
+        &[
+            &der(0xa2, &[&der(0x04, &[&[0; 20]])]),
+            &generalized_time(now),
+            &der(0x30, &[&single]),
+        ],
+    );
+    let signature = key.sign(&SystemRandom::new(), &data).unwrap();
+    let ecdsa_with_sha256 = [0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+    let basic = der(
+        0x30,
+        &[
+            &data,
+            &der(0x30, &[&der(0x06, &[&ecdsa_with_sha256])]),
+            &der(0x03, &[&[0], signature.as_ref()]),
+        ],
+    );
+    let ocsp_basic = [0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+    let bytes = der(0x30, &[&der(0x06, &[&ocsp_basic]), &der(0x04, &[&basic])]);
+    der(0x30, &[&der(0x0a, &[&[0]]), &der(0xa0, &[&bytes])])
+}
+
+/// Answers every request with the current contents of `response`.
+async fn responder(
+    response: Arc<Mutex<Vec<u8>>>,
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut raw = Vec::new();
+            let mut buf = [0; 1024];
+            let body = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                raw.extend_from_slice(&buf[..n]);
+                let head_end = match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                    Some(end) => end + 4,
+                    None => continue,
+                };
+                let head = String::from_utf8_lossy(&raw[..head_end]).to_lowercase();
+                let len: usize = head
+                    .split("content-length:")
+                    .nth(1)
+                    .and_then(|rest| rest.split("\r\n").next())
+                    .and_then(|len| len.trim().parse().ok())
+                    .unwrap();
+                if raw.len() >= head_end + len {
+                    break raw[head_end..head_end + len].to_vec();
+                }
+            };
+            requests.lock().unwrap().push(body);
+
+            let body = response.lock().unwrap().clone();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/ocsp-response\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        }
+    });
+    addr
+}
+
+fn key_pair() -> (EcdsaKeyPair, KeyPair) {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng);
+    (key.unwrap(), KeyPair::from_der(pkcs8.as_ref()).unwrap())
+}
+
+struct Issued {
+    cert: CertifiedKey,
+    ca: Certificate,
+    ca_key: EcdsaKeyPair,
+}
+
+fn certificate(responder: SocketAddr, must_staple: bool) -> Issued {
+    let (ca_key, key_pair) = key_pair();
+    let mut ca = CertificateParams::new(Vec::<String>::new());
+    ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca.key_pair = Some(key_pair);
+    let ca = rcgen::Certificate::from_params(ca).unwrap();
+
+    let url = format!("http://{}/", responder);
+    let access_description = der(
+        0x30,
+        &[
+            &der(0x06, &[&[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01]]),
+            &der(0x86, &[url.as_bytes()]),
+        ],
+    );
+    let mut leaf = CertificateParams::new(vec!["localhost".to_string()]);
+    leaf.serial_number = Some(SerialNumber::from(vec![SERIAL]));
+    leaf.custom_extensions
+        .push(CustomExtension::from_oid_content(
+            &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+            der(0x30, &[&access_description]),
+        ));
+    if must_staple {
+        // TLS feature: status_request
+        leaf.custom_extensions
+            .push(CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 5, 5, 7, 1, 24],
+                der(0x30, &[&der(0x02, &[&[5]])]),
+            ));
+    }
+    let leaf = rcgen::Certificate::from_params(leaf).unwrap();
+
+    let ca_der = Certificate(ca.serialize_der().unwrap());
+    let chain = vec![
+        Certificate(leaf.serialize_der_with_signer(&ca).unwrap()),
+        ca_der.clone(),
+    ];
+    let key = PrivateKey(leaf.serialize_private_key_der());
+    let key = rustls::sign::any_supported_type(&key).unwrap();
+    Issued {
+        cert: CertifiedKey::new(chain, key),
+        ca: ca_der,
+        ca_key,
+    }
+}
+
+fn connector(ca: &Certificate, verifier: bool, must_staple: bool) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca).unwrap();
+    if verifier {
+        TlsConnector::new().ocsp(OcspVerifier::new(roots).enforce_must_staple(must_staple))
+    } else {
+        TlsConnector::from(
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    }
+}
+
+/// Serve `cert` with `staple` and connect to it, returning the staple seen by the client.
+fn handshake(
+    cert: &CertifiedKey,
+    staple: Option<Vec<u8>>,
+    connector: TlsConnector,
+) -> io::Result<Option<Vec<u8>>> {
+    let client = connect(cert, staple, connector)?;
+    Ok(client.ocsp_response().map(<[u8]>::to_vec))
+}
+
+/// Serve `cert` with `staple` and connect to it.
+fn connect(
+    cert: &CertifiedKey,
+    staple: Option<Vec<u8>>,
+    connector: TlsConnector,
+) -> io::Result<TlsStream<TcpStream>> {
+    let mut cert = cert.clone();
+    cert.ocsp = staple;
+    let store = CertStore::new();
+    store.insert("localhost", cert);
+    let acceptor = TlsAcceptor::from(store);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the handshake, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect("localhost", stream).await;
+        server.await?;
+        client
+    })
+}
+
+#[test]
+fn staples_and_replaces_responses() -> io::Result<()> {
+    let response = Arc::new(Mutex::new(Vec::new()));
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    task::block_on(async {
+        let addr = responder(response.clone(), requests.clone()).await;
+        let issued = certificate(addr, false);
+        *response.lock().unwrap() = ocsp_response(GOOD, Duration::from_secs(3600), &issued.ca_key);
+        let mut stapler = OcspConfig::new(issued.cert.clone()).build()?;
+        assert_eq!(stapler.response(), None);
+
+        let refresh_at = stapler.refresh().await?;
+        assert_eq!(
+            stapler.response().as_ref(),
+            Some(&*response.lock().unwrap())
+        );
+        assert!(refresh_at < stapler.next_update().unwrap());
+
+        // the request is a DER SEQUENCE carrying the leaf serial at the end
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request[0], 0x30);
+        assert!(request.ends_with(&[0x02, 0x01, SERIAL]));
+
+        // an unknown status keeps the previous staple
+        let previous = stapler.response();
+        *response.lock().unwrap() =
+            ocsp_response(UNKNOWN, Duration::from_secs(3600), &issued.ca_key);
+        assert!(stapler.refresh().await.is_err());
+        assert_eq!(stapler.response(), previous);
+
+        // the acceptor serves the stapled certificate
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let server_addr = listener.local_addr()?;
+        let acceptor = stapler.acceptor();
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+        let stream = TcpStream::connect(server_addr).await?;
+        let client = connector(&issued.ca, true, true)
+            .connect("localhost", stream)
+            .await;
+        server.await?;
+        assert_eq!(client?.ocsp_response(), previous.as_deref());
+        Ok(())
+    })
+}
+
+#[test]
+fn requires_issuer_and_responder() {
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), false);
+    let mut leaf_only = issued.cert.clone();
+    leaf_only.cert.truncate(1);
+    assert!(OcspConfig::new(leaf_only).build().is_err());
+    assert!(OcspConfig::new(issued.cert).build().is_ok());
+}
+
+#[test]
+fn exposes_valid_staples() -> io::Result<()> {
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), false);
+    let good = ocsp_response(GOOD, Duration::from_secs(3600), &issued.ca_key);
+
+    let staple = handshake(
+        &issued.cert,
+        Some(good.clone()),
+        connector(&issued.ca, true, false),
+    )?;
+    assert_eq!(staple, Some(good.clone()));
+
+    // without a verifier, staples are not checked
+    let stream = handshake(
+        &issued.cert,
+        Some(good),
+        connector(&issued.ca, false, false),
+    );
+    assert_eq!(stream?, None);
+
+    // a response signed by someone else is ignored
+    let (other_key, _) = key_pair();
+    let forged = ocsp_response(GOOD, Duration::from_secs(3600), &other_key);
+    let staple = handshake(
+        &issued.cert,
+        Some(forged),
+        connector(&issued.ca, true, true),
+    )?;
+    assert_eq!(staple, None);
+    Ok(())
+}
+
+#[test]
+fn exposes_raw_staples() -> io::Result<()> {
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), false);
+    let good = ocsp_response(GOOD, Duration::from_secs(3600), &issued.ca_key);
+    let (other_key, _) = key_pair();
+    let forged = ocsp_response(GOOD, Duration::from_secs(3600), &other_key);
+
+    let client = connect(
+        &issued.cert,
+        Some(good.clone()),
+        connector(&issued.ca, true, false),
+    )?;
+    assert_eq!(client.stapled_ocsp_response(), Some(&good[..]));
+
+    // invalid responses are kept too, but not reported as checked
+    let client = connect(
+        &issued.cert,
+        Some(forged.clone()),
+        connector(&issued.ca, true, false),
+    )?;
+    assert_eq!(client.stapled_ocsp_response(), Some(&forged[..]));
+    assert_eq!(client.ocsp_response(), None);
+
+    let client = connect(&issued.cert, None, connector(&issued.ca, true, false))?;
+    assert_eq!(client.stapled_ocsp_response(), None);
+    Ok(())
+}
+
+#[test]
+fn rejects_revoked_certificates() {
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), false);
+    let revoked = ocsp_response(&revoked(), Duration::from_secs(3600), &issued.ca_key);
+    let err = handshake(
+        &issued.cert,
+        Some(revoked),
+        connector(&issued.ca, true, false),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("Revoked"), "{}", err);
+}
+
+#[test]
+fn enforces_must_staple() -> io::Result<()> {
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), true);
+    let good = ocsp_response(GOOD, Duration::from_secs(3600), &issued.ca_key);
+    let (other_key, _) = key_pair();
+    let forged = ocsp_response(GOOD, Duration::from_secs(3600), &other_key);
+    let expired = ocsp_response(GOOD, Duration::ZERO, &issued.ca_key);
+
+    for staple in [None, Some(forged), Some(expired)] {
+        let err = handshake(
+            &issued.cert,
+            staple.clone(),
+            connector(&issued.ca, true, true),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("must-staple"), "{}", err);
+        // enforcement is opt-in
+        handshake(&issued.cert, staple, connector(&issued.ca, true, false))?;
+    }
+
+    let staple = handshake(
+        &issued.cert,
+        Some(good.clone()),
+        connector(&issued.ca, true, true),
+    )?;
+    assert_eq!(staple, Some(good));
+
+    // certificates without the extension are not affected
+    let issued = certificate("127.0.0.1:1".parse().unwrap(), false);
+    handshake(&issued.cert, None, connector(&issued.ca, true, true))?;
+    Ok(())
+}
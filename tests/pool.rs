@@ -0,0 +1,95 @@
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::pool::Pool;
+use async_tls::{test_utils, TlsAcceptor};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Answer each byte read with the number of the connection, counting from 1.
+async fn serve(acceptor: TlsAcceptor) -> io::Result<(String, Arc<AtomicUsize>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = format!("localhost:{}", listener.local_addr()?.port());
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    task::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let n = counter.fetch_add(1, Ordering::SeqCst) as u8 + 1;
+            let acceptor = acceptor.clone();
+            task::spawn(async move {
+                let mut stream = acceptor.accept(stream).await?;
+                let mut buf = [0];
+                while stream.read(&mut buf).await? > 0 {
+                    stream.write_all(&[n]).await?;
+                }
+                Ok(()) as io::Result<()>
+            });
+        }
+    });
+    Ok((addr, accepted))
+}
+
+async fn request<IO>(stream: &mut async_tls::client::TlsStream<IO>) -> io::Result<u8>
+where
+    IO: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin,
+{
+    stream.write_all(b"?").await?;
+    let mut buf = [0];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf[0])
+}
+
+#[test]
+fn reuse_idle_connections() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    task::block_on(async {
+        let (addr, accepted) = serve(acceptor).await?;
+        let pool = Pool::new().max_idle(1);
+
+        let mut first = pool.connect(&connector, &addr).await?;
+        let mut second = pool.connect(&connector, &addr).await?;
+        assert_eq!(request(&mut first).await?, 1);
+        assert_eq!(request(&mut second).await?, 2);
+        pool.put(&connector, &addr, first)?;
+        pool.put(&connector, &addr, second)?;
+        assert_eq!(pool.idle_count(), 1);
+
+        // the second connection was kept, and clones of the connector share it
+        let mut stream = pool.connect(&connector.clone(), &addr).await?;
+        assert_eq!(request(&mut stream).await?, 2);
+        pool.put(&connector, &addr, stream)?;
+
+        // other configs get connections of their own
+        let (other, _) = test_utils::pair().unwrap();
+        assert!(pool.get(&other, &addr)?.is_none());
+        assert_eq!(pool.idle_count(), 1);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn evict_idle_connections() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    task::block_on(async {
+        let (addr, accepted) = serve(acceptor).await?;
+        let pool = Pool::new().idle_timeout(Duration::from_millis(50));
+
+        let stream = pool.connect(&connector, &addr).await?;
+        pool.put(&connector, &addr, stream)?;
+        task::sleep(Duration::from_millis(100)).await;
+        assert!(pool.get(&connector, &addr)?.is_none());
+
+        let stream = pool.connect(&connector, &addr).await?;
+        pool.put(&connector, &addr, stream)?;
+        task::sleep(Duration::from_millis(100)).await;
+        pool.evict_expired();
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
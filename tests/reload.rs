@@ -0,0 +1,87 @@
+#![cfg(unix)]
+
+use async_tls::PollingReloader;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const CERT: &str = include_str!("end.cert");
+const RSA: &str = include_str!("end.rsa");
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("async-tls-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Mimic how the kubelet publishes a new secret version: write a fresh
+/// directory and atomically repoint the `..data` symlink at it.
+fn publish(dir: &Path, version: &str, cert: &str, key: &str) {
+    let target = dir.join(version);
+    fs::create_dir(&target).unwrap();
+    fs::write(target.join("tls.crt"), cert).unwrap();
+    fs::write(target.join("tls.key"), key).unwrap();
+
+    let tmp = dir.join("..data_tmp");
+    symlink(version, &tmp).unwrap();
+    fs::rename(&tmp, dir.join("..data")).unwrap();
+}
+
+#[test]
+fn reload_follows_symlink_swap() {
+    let dir = temp_dir("reload");
+    publish(&dir, "..v1", CERT, RSA);
+    symlink("..data/tls.crt", dir.join("tls.crt")).unwrap();
+    symlink("..data/tls.key", dir.join("tls.key")).unwrap();
+
+    let acceptor = PollingReloader::new(dir.join("tls.crt"), dir.join("tls.key"))
+        .interval(Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    assert!(!acceptor.reload().unwrap());
+
+    publish(&dir, "..v2", &format!("{}\n", CERT), RSA);
+    assert!(acceptor.reload().unwrap());
+    assert!(!acceptor.reload().unwrap());
+
+    // A broken update is rejected and the previous config stays in place.
+    publish(&dir, "..v3", "garbage", RSA);
+    assert!(acceptor.reload().is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn accept_reloads_in_background() {
+    let dir = temp_dir("reload-background");
+    publish(&dir, "..v1", CERT, RSA);
+    symlink("..data/tls.crt", dir.join("tls.crt")).unwrap();
+    symlink("..data/tls.key", dir.join("tls.key")).unwrap();
+
+    let acceptor = PollingReloader::new(dir.join("tls.crt"), dir.join("tls.key"))
+        .interval(Duration::from_millis(10))
+        .build()
+        .unwrap();
+    publish(&dir, "..v2", &format!("{}\n", CERT), RSA);
+    thread::sleep(Duration::from_millis(20));
+
+    // The accept goes on with the current config and leaves the files to a
+    // background thread, which has picked up the change by the time we look.
+    drop(acceptor.accept(futures_util::io::Cursor::new(Vec::new())));
+    thread::sleep(Duration::from_millis(500));
+    assert!(!acceptor.reload().unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn initial_load_must_succeed() {
+    let dir = temp_dir("reload-missing");
+    let result = PollingReloader::new(dir.join("tls.crt"), dir.join("tls.key")).build();
+    assert!(result.is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,116 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{BoxFuture, CertStore, RemoteSigner, RemoteSigningKey, TlsAcceptor, TlsConnector};
+use rustls::sign::SigningKey;
+use rustls::{ClientConfig, PrivateKey, RootCertStore, SignatureAlgorithm, SignatureScheme};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::io::{self, BufReader, Cursor};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CHAIN: &str = include_str!("end.chain");
+const RSA: &str = include_str!("end.rsa");
+
+/// Stands in for a key management service: signs with a local key after a delay.
+struct SlowSigner {
+    key: Arc<dyn SigningKey>,
+    delay: Duration,
+    fail: bool,
+}
+
+impl SlowSigner {
+    fn boxed(delay: Duration, fail: bool) -> Arc<dyn RemoteSigner> {
+        let key = pkcs8_private_keys(&mut BufReader::new(Cursor::new(RSA)))
+            .unwrap()
+            .remove(0);
+        Arc::new(SlowSigner {
+            key: rustls::sign::any_supported_type(&PrivateKey(key)).unwrap(),
+            delay,
+            fail,
+        })
+    }
+}
+
+impl RemoteSigner for SlowSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::RSA
+    }
+
+    fn schemes(&self) -> &[SignatureScheme] {
+        &[
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA256,
+        ]
+    }
+
+    fn sign(
+        &self,
+        scheme: SignatureScheme,
+        message: Vec<u8>,
+    ) -> BoxFuture<'static, io::Result<Vec<u8>>> {
+        let signer = self.key.choose_scheme(&[scheme]).unwrap();
+        let (delay, fail) = (self.delay, self.fail);
+        Box::pin(async move {
+            task::sleep(delay).await;
+            if fail {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "access denied",
+                ));
+            }
+            signer.sign(&message).map_err(io::Error::other)
+        })
+    }
+}
+
+fn handshake(key: RemoteSigningKey) -> io::Result<()> {
+    let chain = certs(&mut BufReader::new(Cursor::new(CHAIN))).unwrap();
+    let store = CertStore::new();
+    store.insert(
+        "localhost",
+        key.certified(chain.iter().cloned().map(rustls::Certificate).collect()),
+    );
+    let acceptor = TlsAcceptor::from(store);
+
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(&chain);
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    );
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        // keep the client open until the server is done writing
+        let client = connector.connect("localhost", stream).await;
+        server.await.and(client.map(drop))
+    })
+}
+
+#[test]
+fn handshake_with_remote_key() {
+    let signer = SlowSigner::boxed(Duration::from_millis(50), false);
+    handshake(RemoteSigningKey::new(signer)).unwrap();
+}
+
+#[test]
+fn signer_errors_fail_the_handshake() {
+    let signer = SlowSigner::boxed(Duration::from_millis(0), true);
+    assert!(handshake(RemoteSigningKey::new(signer)).is_err());
+}
+
+#[test]
+fn slow_signers_time_out() {
+    let signer = SlowSigner::boxed(Duration::from_secs(5), false);
+    let key = RemoteSigningKey::new(signer).timeout(Duration::from_millis(100));
+    assert!(handshake(key).is_err());
+}
@@ -0,0 +1,80 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::{HandshakeParams, Observer, TlsAcceptor, TlsConnector};
+use rcgen::CertificateParams;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use serde_json::{json, Value};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Params(Mutex<Option<HandshakeParams>>);
+
+impl Observer for Params {
+    fn on_handshake_complete(&self, _: Duration, params: &HandshakeParams) {
+        *self.0.lock().unwrap() = Some(params.clone());
+    }
+}
+
+#[test]
+fn serialize_connection_metadata() {
+    let cert =
+        rcgen::Certificate::from_params(CertificateParams::new(vec!["localhost".into()])).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![der.clone()],
+            PrivateKey(cert.serialize_private_key_der()),
+        )
+        .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(config);
+    let mut roots = RootCertStore::empty();
+    roots.add(&der).unwrap();
+    let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let params = Arc::new(Params::default());
+    let connector = TlsConnector::from(config).observer(params.clone());
+
+    let counters = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hello").await?;
+            stream.flush().await
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect("localhost", stream).await?;
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await?;
+        server.await?;
+        Ok(stream.counters()) as io::Result<_>
+    })
+    .unwrap();
+
+    let params = params.0.lock().unwrap().take().unwrap();
+    let params = serde_json::to_value(&params).unwrap();
+    assert_eq!(params["protocol_version"], json!("TLSv1_3"));
+    assert!(params["cipher_suite"]
+        .as_str()
+        .unwrap()
+        .starts_with("TLS13_"));
+    assert_eq!(params["key_exchange_group"], json!("X25519"));
+    assert_eq!(params["alpn_protocol"], json!("h2"));
+    assert_eq!(params["kind"], json!("Full"));
+    assert_eq!(params["server_name"], json!("localhost"));
+    assert!(params["timings"]["total"].is_object());
+
+    let counters = serde_json::to_value(counters).unwrap();
+    assert_eq!(counters["bytes_read"], json!(5));
+    assert!(matches!(counters["tls_bytes_received"], Value::Number(_)));
+}
@@ -0,0 +1,42 @@
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::test_utils::TestCa;
+use std::future::poll_fn;
+use std::io;
+use tower_service::Service;
+
+#[test]
+fn connect_to_uris() {
+    let ca = TestCa::new().unwrap();
+    let acceptor = ca.acceptor(&["localhost"]).unwrap();
+    let mut connector = ca.connector().unwrap();
+    let greeting = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await
+        });
+        poll_fn(|cx| connector.poll_ready(cx)).await?;
+        let uri = format!("https://localhost:{}/ignored", port);
+        let mut stream = connector.call(uri.parse().unwrap()).await?;
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await?;
+        server.await?;
+        Ok(greeting) as io::Result<String>
+    })
+    .unwrap();
+    assert_eq!(greeting, "hi");
+}
+
+#[test]
+fn connect_to_https_uris_only() {
+    let mut connector = TestCa::new().unwrap().connector().unwrap();
+    for uri in ["http://localhost/", "/path"] {
+        let err = task::block_on(connector.call(uri.parse().unwrap())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", uri);
+    }
+}
@@ -0,0 +1,66 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::{HandshakeKind, TlsAcceptor, TlsConnector};
+use rcgen::CertificateParams;
+use rustls::server::NoServerSessionStorage;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Connect once, returning how the server set the connection up.
+fn handshake(acceptor: &TlsAcceptor, connector: &TlsConnector) -> io::Result<HandshakeKind> {
+    let acceptor = acceptor.clone();
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.handshake_kind()) as io::Result<Option<HandshakeKind>>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect("localhost", stream).await?;
+        // tickets arrive after the handshake
+        stream.read_to_end(&mut Vec::new()).await?;
+        Ok(server.await?.unwrap())
+    })
+}
+
+#[test]
+fn resumes_with_rotating_tickets() {
+    let cert =
+        rcgen::Certificate::from_params(CertificateParams::new(vec!["localhost".into()])).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![der.clone()],
+            PrivateKey(cert.serialize_private_key_der()),
+        )
+        .unwrap();
+    // only tickets can resume sessions
+    config.session_storage = Arc::new(NoServerSessionStorage {});
+    let mut roots = RootCertStore::empty();
+    roots.add(&der).unwrap();
+
+    let plain = TlsAcceptor::from(config);
+    let connector = TlsConnector::with_root_certificates(roots.clone());
+    assert_eq!(handshake(&plain, &connector).unwrap(), HandshakeKind::Full);
+    assert_eq!(handshake(&plain, &connector).unwrap(), HandshakeKind::Full);
+
+    let acceptor = plain.rotate_ticket_keys(Duration::from_secs(60 * 60), 1);
+    let connector = TlsConnector::with_root_certificates(roots);
+    assert_eq!(
+        handshake(&acceptor, &connector).unwrap(),
+        HandshakeKind::Full
+    );
+    assert_eq!(
+        handshake(&acceptor, &connector).unwrap(),
+        HandshakeKind::Resumed
+    );
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use async_tls::settings::Settings;
+use async_tls::{HandshakeParams, Observer};
+use common::handshake;
+use rcgen::{BasicConstraints, CertificateParams, IsCa};
+use rustls::ProtocolVersion;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A directory with a CA, and a server and a client certificate issued by it.
+fn certificates(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("async-tls-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(params).unwrap();
+    std::fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+    for (file, names) in [("server", vec!["localhost".into()]), ("client", vec![])] {
+        let cert = rcgen::Certificate::from_params(CertificateParams::new(names)).unwrap();
+        let pem = cert.serialize_pem_with_signer(&ca).unwrap();
+        std::fs::write(dir.join(format!("{}.crt", file)), pem).unwrap();
+        let key = cert.serialize_private_key_pem();
+        std::fs::write(dir.join(format!("{}.key", file)), key).unwrap();
+    }
+    dir
+}
+
+fn settings(dir: &Path, file: &str, json: &str) -> Settings {
+    let path = dir.join(file);
+    std::fs::write(&path, json).unwrap();
+    Settings::from_file(&path).unwrap()
+}
+
+#[derive(Default)]
+struct Params(Mutex<Option<HandshakeParams>>);
+
+impl Observer for Params {
+    fn on_handshake_complete(&self, _: Duration, params: &HandshakeParams) {
+        *self.0.lock().unwrap() = Some(params.clone());
+    }
+}
+
+#[test]
+fn build_from_settings() {
+    let dir = certificates("settings");
+    let server = settings(
+        &dir,
+        "server.json",
+        r#"{
+            "certificate": "server.crt",
+            "private_key": "server.key",
+            "alpn": ["h2"],
+            "preset": "mozilla_intermediate",
+            "max_version": "1.2",
+            "client_auth": "required",
+            "client_ca": "ca.pem"
+        }"#,
+    );
+    let client = settings(
+        &dir,
+        "client.json",
+        r#"{
+            "certificate": "client.crt",
+            "private_key": "client.key",
+            "roots": "ca.pem",
+            "alpn": ["h2", "http/1.1"]
+        }"#,
+    );
+    let anonymous = settings(&dir, "anonymous.json", r#"{"roots": "ca.pem"}"#);
+    let (acceptor, connector) = (server.acceptor().unwrap(), client.connector().unwrap());
+    let anonymous = anonymous.connector().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let params = Arc::new(Params::default());
+    let (client, server) = handshake(acceptor.clone(), connector.observer(params.clone()));
+    client.unwrap();
+    server.unwrap();
+    let params = params.0.lock().unwrap().take().unwrap();
+    assert_eq!(params.alpn_protocol.as_deref(), Some(&b"h2"[..]));
+    assert_eq!(params.protocol_version, Some(ProtocolVersion::TLSv1_2));
+
+    // the client certificate is required
+    let (_, server) = handshake(acceptor.clone(), anonymous);
+    server.unwrap_err();
+    // and the server's CA only trusted with `roots`
+    let public = Settings::from_json(br#"{"webpki_roots": true}"#).unwrap();
+    let (client, _) = handshake(acceptor, public.connector().unwrap());
+    client.unwrap_err();
+
+    let err = Settings::from_json(br#"{"certificate": "server.crt"}"#)
+        .unwrap()
+        .acceptor()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
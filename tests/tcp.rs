@@ -0,0 +1,84 @@
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use async_std::task;
+use async_tls::{test_utils, RetryPolicy, TlsConnector};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn connect_by_host_and_port() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    let greeting = task::block_on(async {
+        // only on IPv4, so addresses of other families are tried and fail first
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await
+        });
+        let mut stream = connector
+            .connect_tcp(&format!("localhost:{}", port))
+            .await?;
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await?;
+        server.await?;
+        Ok(greeting) as io::Result<String>
+    })
+    .unwrap();
+    assert_eq!(greeting, "hi");
+
+    let err = task::block_on(test_utils::pair().unwrap().0.connect_tcp("localhost")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn retry_transient_failures() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    let policy = RetryPolicy::new().initial_backoff(Duration::from_millis(10));
+    let greeting = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = task::spawn(async move {
+            // hang up on the first attempt
+            drop(listener.accept().await?);
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await
+        });
+        let mut stream = connector
+            .connect_with_retry(&format!("localhost:{}", port), &policy)
+            .await?;
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await?;
+        server.await?;
+        Ok(greeting) as io::Result<String>
+    })
+    .unwrap();
+    assert_eq!(greeting, "hi");
+
+    // an untrusted certificate fails again, so it is not retried
+    let (_, acceptor) = test_utils::pair().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let err = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let counter = accepted.clone();
+        task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = acceptor.accept(stream).await;
+            }
+        });
+        TlsConnector::new()
+            .connect_with_retry(&format!("localhost:{}", port), &policy)
+            .await
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
@@ -1,15 +1,40 @@
+mod common;
+
 use async_std::channel::bounded;
 use async_std::io;
 use async_std::net::{TcpListener, TcpStream};
 use async_std::prelude::*;
 use async_std::task;
-use async_tls::{TlsAcceptor, TlsConnector};
+use async_tls::{
+    client::{EarlyDataOverflow, RejectedEarlyData},
+    Admission, CertStore, CloseMode, Error, ExpiryMonitor, HandshakeError, HandshakeFlush,
+    HandshakeKind, HandshakeParams, HandshakeStage, HandshakeTimings, Identity, LazyConfigAcceptor,
+    Observer, Route, Routed, Router, SecurityPreset, SniffClientHello, TlsAcceptor, TlsConnector,
+    TrafficCounters, WriteChunking,
+};
+use common::handshake;
+use futures_util::future::{self, FusedFuture};
+use futures_util::io::BufWriter;
 use lazy_static::lazy_static;
-use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig};
+use rustls::client::ClientSessionMemoryCache;
+use rustls::server::{
+    Acceptor, AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient,
+};
+use rustls::sign::CertifiedKey;
+use rustls::{
+    cipher_suite, kx_group, version, AlertDescription, Certificate, CertificateError, ClientConfig,
+    NamedGroup, PeerIncompatible, PrivateKey, ProtocolVersion, RootCertStore, ServerConfig,
+    ServerName, SupportedProtocolVersion,
+};
 use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::convert::TryFrom;
 use std::io::{BufReader, Cursor};
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 const CERT: &str = include_str!("end.cert");
 const CHAIN: &str = include_str!("end.chain");
@@ -17,17 +42,8 @@ const RSA: &str = include_str!("end.rsa");
 
 lazy_static! {
     static ref TEST_SERVER: (SocketAddr, &'static str, Vec<Vec<u8>>) = {
-        let cert = certs(&mut BufReader::new(Cursor::new(CERT))).unwrap();
-        let cert = cert.into_iter().map(Certificate).collect();
         let chain = certs(&mut BufReader::new(Cursor::new(CHAIN))).unwrap();
-        let mut keys = pkcs8_private_keys(&mut BufReader::new(Cursor::new(RSA))).unwrap();
-        let key = PrivateKey(keys.pop().unwrap());
-        let sconfig = ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(cert, key)
-            .unwrap();
-        let acceptor = TlsAcceptor::from(Arc::new(sconfig));
+        let acceptor = TlsAcceptor::from(server_config());
 
         let (send, recv) = bounded(1);
 
@@ -57,10 +73,30 @@ lazy_static! {
     };
 }
 
+fn server_config() -> ServerConfig {
+    server_config_with_versions(rustls::ALL_VERSIONS)
+}
+
+fn server_config_with_versions(versions: &[&'static SupportedProtocolVersion]) -> ServerConfig {
+    let cert = certs(&mut BufReader::new(Cursor::new(CERT))).unwrap();
+    let cert = cert.into_iter().map(Certificate).collect();
+    let mut keys = pkcs8_private_keys(&mut BufReader::new(Cursor::new(RSA))).unwrap();
+    let key = PrivateKey(keys.pop().unwrap());
+    ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(cert, key)
+        .unwrap()
+}
+
 fn start_server() -> &'static (SocketAddr, &'static str, Vec<Vec<u8>>) {
-    &*TEST_SERVER
+    &TEST_SERVER
 }
 
+/// Handshake `acceptor` with `connector`, returning the result of each side.
 async fn start_client(addr: SocketAddr, domain: &str, config: Arc<ClientConfig>) -> io::Result<()> {
     const FILE: &[u8] = include_bytes!("../README.md");
 
@@ -82,7 +118,7 @@ async fn start_client(addr: SocketAddr, domain: &str, config: Arc<ClientConfig>)
 fn pass() {
     let (addr, domain, chain) = start_server();
     let mut root_store = RootCertStore::empty();
-    let (added, ignored) = root_store.add_parsable_certificates(&chain);
+    let (added, ignored) = root_store.add_parsable_certificates(chain);
     assert!(added >= 1 && ignored == 0);
     let config = ClientConfig::builder()
         .with_safe_defaults()
@@ -95,7 +131,7 @@ fn pass() {
 fn fail() {
     let (addr, domain, chain) = start_server();
     let mut root_store = RootCertStore::empty();
-    let (added, ignored) = root_store.add_parsable_certificates(&chain);
+    let (added, ignored) = root_store.add_parsable_certificates(chain);
     assert!(added >= 1 && ignored == 0);
     let config = ClientConfig::builder()
         .with_safe_defaults()
@@ -104,5 +140,2342 @@ fn fail() {
     let config = Arc::new(config);
 
     assert_ne!(domain, &"google.com");
-    assert!(task::block_on(start_client(*addr, "google.com", config)).is_err());
+    let err = task::block_on(start_client(*addr, "google.com", config.clone())).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    let context = context.expect("no handshake context");
+    assert_eq!(context.server_name.as_deref(), Some("google.com"));
+    assert_eq!(context.stage, HandshakeStage::Verifying);
+    assert!(err.to_string().contains("google.com"), "{}", err);
+    match Error::from(err) {
+        Error::Certificate(CertificateError::NotValidForName) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let err = task::block_on(start_client(*addr, "not a domain", config)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    assert_eq!(context.unwrap().stage, HandshakeStage::Hello);
+    assert!(matches!(Error::from(err), Error::InvalidDnsName(name) if name == "not a domain"));
+}
+
+#[test]
+fn trust_ca_bundles() {
+    let connector = TlsConnector::with_ca_pem(CHAIN.as_bytes()).unwrap();
+    let (client, server) = handshake(TlsAcceptor::from(server_config()), connector);
+    client.unwrap();
+    server.unwrap();
+
+    let err = TlsConnector::with_ca_pem(b"not a certificate")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let connector = TlsConnector::new().add_root_pem(CHAIN.as_bytes()).unwrap();
+    let (client, server) = handshake(TlsAcceptor::from(server_config()), connector);
+    client.unwrap();
+    server.unwrap();
+
+    let err = TlsConnector::new()
+        .add_root_pem(b"not a certificate")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn trust_extra_roots() {
+    let (_, _, chain) = start_server();
+    let chain: Vec<Certificate> = chain.iter().cloned().map(Certificate).collect();
+    let acceptor = || TlsAcceptor::from(server_config());
+
+    let (client, _) = handshake(acceptor(), TlsConnector::with_extra_roots(&chain).unwrap());
+    client.unwrap();
+    let (client, _) = handshake(acceptor(), TlsConnector::new());
+    client.unwrap_err();
+
+    let mut connector = TlsConnector::new().enable_sni(false);
+    for cert in &chain {
+        connector = connector.add_root_certificate(cert).unwrap();
+    }
+    let (client, server) = handshake(acceptor(), connector);
+    client.unwrap();
+    server.unwrap();
+
+    let invalid = [Certificate(b"not a certificate".to_vec())];
+    let err = TlsConnector::with_extra_roots(&invalid).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let err = TlsConnector::from(config)
+        .add_root_certificate(&chain[0])
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn trust_ca_files() {
+    let path = std::env::temp_dir().join(format!("async-tls-ca-{}.pem", std::process::id()));
+    std::fs::write(&path, CHAIN).unwrap();
+    let connector = task::block_on(TlsConnector::with_ca_file(&path));
+    std::fs::remove_file(&path).unwrap();
+    let (client, server) = handshake(TlsAcceptor::from(server_config()), connector.unwrap());
+    client.unwrap();
+    server.unwrap();
+
+    let err = task::block_on(TlsConnector::with_ca_file(&path))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn pass_ip_address() {
+    let (addr, _, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let config = Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+
+    for ip in ["127.0.0.1", "::1", "[::1]"] {
+        task::block_on(start_client(*addr, ip, config.clone())).unwrap();
+    }
+    // checked against the IP addresses in the certificate, not its DNS names
+    assert!(task::block_on(start_client(*addr, "127.0.0.2", config)).is_err());
+}
+
+#[test]
+fn pass_server_name() {
+    let (addr, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+
+    let name = ServerName::try_from(*domain).unwrap();
+    task::block_on(async {
+        for _ in 0..2 {
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect_with_name(name.clone(), stream).await?;
+            stream.write_all(b"ping").await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"ping");
+        }
+        let stream = TcpStream::connect(addr).await?;
+        let other = ServerName::try_from("google.com").unwrap();
+        assert!(connector.connect_with_name(other, stream).await.is_err());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn pass_session_options() {
+    let (addr, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+
+    let name = ServerName::try_from(*domain).unwrap();
+    task::block_on(async {
+        let stream = TcpStream::connect(addr).await?;
+        let mut called = false;
+        let mut stream = connector
+            .connect_with(name, stream, |session| {
+                assert!(session.is_handshaking());
+                session.set_buffer_limit(Some(16));
+                called = true;
+            })
+            .await?;
+        assert!(called);
+
+        // writes beyond the limit wait for the buffer to drain
+        let msg = [7; 64];
+        stream.write_all(&msg).await?;
+        let mut buf = [0; 64];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(buf, msg);
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn pass_server_session_options() {
+    let (_, domain, chain) = start_server();
+    let acceptor = TlsAcceptor::from(server_config());
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let called = Arc::new(AtomicBool::new(false));
+        let server = task::spawn({
+            let called = called.clone();
+            async move {
+                let (stream, _) = listener.accept().await?;
+                let stream = acceptor
+                    .accept_with(stream, move |session| {
+                        assert!(session.is_handshaking());
+                        session.set_buffer_limit(Some(16));
+                        called.store(true, Ordering::SeqCst);
+                    })
+                    .await?;
+                use futures_util::io::AsyncReadExt;
+                let (mut reader, mut writer) = stream.split();
+                io::copy(&mut reader, &mut writer).await?;
+                Ok(()) as io::Result<()>
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(*domain, stream).await?;
+        // writes beyond the limit wait for the buffer to drain
+        let msg = [7; 64];
+        stream.write_all(&msg).await?;
+        let mut buf = [0; 64];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(buf, msg);
+        assert!(called.load(Ordering::SeqCst));
+
+        futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+        server.await
+    })
+    .unwrap();
+}
+
+#[test]
+fn pass_without_sni() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+
+    let sni = |connector: TlsConnector, domain: &str| {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let start = LazyConfigAcceptor::new(Acceptor::default(), stream).await?;
+                let sni = start.client_hello().server_name().map(str::to_string);
+                let _ = start.into_stream(Arc::new(server_config())).await;
+                Ok(sni) as io::Result<Option<String>>
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let client = connector.connect(domain, stream).await;
+            let sni = server.await?;
+            client.map(|_| sni)
+        })
+    };
+
+    let connector = TlsConnector::with_root_certificates(root_store);
+    assert_eq!(
+        sni(connector.clone(), domain).unwrap().as_deref(),
+        Some(*domain)
+    );
+    let connector = connector.enable_sni(false);
+    assert_eq!(sni(connector.clone(), domain).unwrap(), None);
+
+    // the name is still verified
+    assert!(sni(connector, "example.com").is_err());
+}
+
+#[test]
+fn sniff_sni_for_passthrough() {
+    let (addr, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"echo".to_vec()];
+
+    task::block_on(async {
+        // forwards the connection to the echo server, without terminating TLS
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let proxy = listener.local_addr()?;
+        let backend = *addr;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let sniffed = SniffClientHello::new(stream).await?;
+            let offered = (
+                sniffed.server_name().map(str::to_string),
+                sniffed.alpn().to_vec(),
+            );
+            assert_eq!(sniffed.client_hello().versions[0], ProtocolVersion::TLSv1_3);
+            let (consumed, client) = sniffed.into_parts();
+            assert_eq!(consumed[0], 0x16);
+
+            let mut backend = TcpStream::connect(backend).await?;
+            backend.write_all(&consumed).await?;
+            let (mut client_read, mut backend_write) = (client.clone(), backend.clone());
+            task::spawn(async move { io::copy(&mut client_read, &mut backend_write).await });
+            let (mut client_write, mut backend_read) = (client, backend);
+            task::spawn(async move { io::copy(&mut backend_read, &mut client_write).await });
+            Ok(offered) as io::Result<(Option<String>, Vec<Vec<u8>>)>
+        });
+
+        start_client(proxy, domain, Arc::new(config)).await?;
+        let (server_name, alpn) = server.await?;
+        assert_eq!(server_name.as_deref(), Some(*domain));
+        assert_eq!(alpn, [b"echo".to_vec()]);
+
+        // no TLS is no `ClientHello`
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let proxy = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            SniffClientHello::new(stream).await.map(drop)
+        });
+        let mut stream = TcpStream::connect(proxy).await?;
+        stream.write_all(b"SSH-2.0-OpenSSH\r\n").await?;
+        let err = server.await.unwrap_err();
+        assert!(matches!(Error::from(err), Error::NotTls(_)));
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn route_by_sni() {
+    let (addr, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+
+    let route = |router: Router<SocketAddr>, domain: &'static str| {
+        let connector = connector.clone();
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let proxy = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                match router.accept(stream).await? {
+                    Routed::Terminated(stream) => {
+                        use futures_util::io::AsyncReadExt;
+                        let (mut reader, mut writer) = stream.split();
+                        task::spawn(async move { io::copy(&mut reader, &mut writer).await });
+                        Ok(None)
+                    }
+                    Routed::Passthrough { target, replay, io } => {
+                        let mut backend = TcpStream::connect(target).await?;
+                        backend.write_all(&replay).await?;
+                        let (mut reader, mut writer) = (io.clone(), backend.clone());
+                        task::spawn(async move { io::copy(&mut reader, &mut writer).await });
+                        let (mut reader, mut writer) = (backend, io);
+                        task::spawn(async move { io::copy(&mut reader, &mut writer).await });
+                        Ok(Some(target))
+                    }
+                }
+            });
+            let stream = TcpStream::connect(proxy).await?;
+            let mut stream = connector.connect(domain, stream).await?;
+            stream.write_all(b"ping").await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"ping");
+            server.await as io::Result<Option<SocketAddr>>
+        })
+    };
+
+    let terminate = Arc::new(server_config());
+    let router = Router::new()
+        .terminate("example.com", terminate.clone())
+        .passthrough("LOCALHOST", *addr);
+    assert_eq!(route(router.clone(), domain).unwrap(), Some(*addr));
+    let router = router.terminate("localhost.", terminate.clone());
+    assert_eq!(route(router, domain).unwrap(), None);
+
+    let router = Router::new().passthrough("*.localhost", *addr);
+    assert!(router.route(Some("a.b.localhost")).is_some());
+    assert!(router.route(Some("localhost")).is_none());
+    let err = route(router.clone(), domain).unwrap_err();
+    assert!(matches!(
+        Error::from(err),
+        Error::AlertReceived(AlertDescription::UnrecognisedName)
+    ));
+    let router = router.fallback(Route::Terminate(terminate));
+    assert_eq!(route(router, domain).unwrap(), None);
+}
+
+#[test]
+fn choose_config_by_local_addr() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"admin".to_vec()];
+    let connector = TlsConnector::from(config);
+
+    task::block_on(async {
+        let (public, admin) = (
+            TcpListener::bind("127.0.0.1:0").await?,
+            TcpListener::bind("127.0.0.1:0").await?,
+        );
+        let admin_addr = admin.local_addr()?;
+        let mut admin_config = server_config();
+        admin_config.alpn_protocols = vec![b"admin".to_vec()];
+        let admin_config = Arc::new(admin_config);
+        let acceptor = TlsAcceptor::from(server_config()).config_by_local_addr(move |addr| {
+            match addr == admin_addr {
+                true => Some(admin_config.clone()),
+                false => None,
+            }
+        });
+
+        for listener in [public, admin] {
+            let acceptor = acceptor.clone();
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept_at(stream.local_addr()?, stream).await
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let stream = connector.connect(*domain, stream).await?;
+            server.await?;
+            let debug = format!("{:?}", stream);
+            let admin = debug.contains(r#"alpn_protocol: Some("admin")"#);
+            assert_eq!(admin, addr == admin_addr, "{}", debug);
+        }
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn fail_with_alert() {
+    let (_, domain, chain) = start_server();
+    let handshake = |config: ServerConfig, connector: TlsConnector| {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let acceptor = TlsAcceptor::from(config);
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept(stream).await.map(drop)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let client = connector.connect(*domain, stream).await.map(drop);
+            Ok((client, server.await)) as io::Result<(io::Result<()>, io::Result<()>)>
+        })
+        .unwrap()
+    };
+
+    // the client rejects the server's certificate and tells it why
+    let connector = TlsConnector::with_root_certificates(RootCertStore::empty());
+    let (client, server) = handshake(server_config(), connector);
+    assert!(client.is_err());
+    let err = server.unwrap_err();
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    assert_eq!(context.unwrap().server_name.as_deref(), Some(*domain));
+    match Error::from(err) {
+        Error::AlertReceived(AlertDescription::UnknownCA) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    // the server does not speak the client's version
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::from(
+        ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&version::TLS12])
+            .unwrap()
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    );
+    let config = ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_cert_resolver(server_config().cert_resolver);
+    let (client, _) = handshake(config, connector);
+    let err = client.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match Error::from(err) {
+        Error::AlertReceived(AlertDescription::ProtocolVersion) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn admit_clients() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let handshake = |alpn: &[u8]| {
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store.clone())
+            .with_no_client_auth();
+        config.alpn_protocols = vec![alpn.to_vec()];
+        let connector = TlsConnector::from(config);
+        let acceptor = TlsAcceptor::from(server_config()).admit(|client| {
+            assert_eq!(client.server_name(), Some("localhost"));
+            assert!(!client.cipher_suites().is_empty());
+            match client.alpn().contains(&&b"http/1.0"[..]) {
+                true => Admission::Reject(AlertDescription::NoApplicationProtocol),
+                false => Admission::Accept,
+            }
+        });
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept(stream).await.map(drop)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let client = connector.connect(*domain, stream).await;
+            let server = server.await;
+            Ok((client.map(drop), server)) as io::Result<(io::Result<()>, io::Result<()>)>
+        })
+        .unwrap()
+    };
+
+    let (client, server) = handshake(b"http/1.1");
+    client.unwrap();
+    server.unwrap();
+
+    let (client, server) = handshake(b"http/1.0");
+    match Error::from(client.unwrap_err()) {
+        Error::AlertReceived(AlertDescription::NoApplicationProtocol) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+    let err = server.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    let context = context.unwrap();
+    assert_eq!(context.stage, HandshakeStage::Hello);
+    let client_hello = context.client_hello.as_ref().unwrap();
+    assert_eq!(client_hello.alpn, [b"http/1.0".to_vec()]);
+    match Error::from(err) {
+        Error::Rejected(AlertDescription::NoApplicationProtocol) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn summarize_failing_client_hellos() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS12])
+        .unwrap()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(server_config_with_versions(&[&rustls::version::TLS13]));
+
+    let (client, server) = handshake(acceptor, TlsConnector::from(config));
+    assert!(client.is_err());
+    let err = server.unwrap_err();
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    let client_hello = context.unwrap().client_hello.as_ref().unwrap();
+    assert_eq!(client_hello.server_name.as_deref(), Some(*domain));
+    assert_eq!(client_hello.alpn, [b"h2".to_vec()]);
+    assert_eq!(client_hello.versions, [ProtocolVersion::TLSv1_2]);
+    assert!(err.to_string().contains("ALPN h2"), "{}", err);
+}
+
+#[test]
+fn redirect_plaintext_http() {
+    let redirect = |acceptor: TlsAcceptor| {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut accept = acceptor.accept(stream);
+                let err = (&mut accept).await.map(drop).unwrap_err();
+                let url = match Error::from(err) {
+                    Error::PlaintextHttp(request) => {
+                        assert_eq!(request.method, "GET");
+                        request.https_url().unwrap()
+                    }
+                    err => panic!("unexpected error: {:?}", err),
+                };
+                let mut stream = accept.take_io().unwrap();
+                assert!(accept.take_io().is_none());
+                let response = format!(
+                    "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                    url
+                );
+                stream.write_all(response.as_bytes()).await?;
+                Ok(()) as io::Result<()>
+            });
+            let mut stream = TcpStream::connect(addr).await?;
+            let request = format!("GET /a?b HTTP/1.1\r\nHost: {}\r\n\r\n", addr);
+            stream.write_all(request.as_bytes()).await?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await?;
+            server.await?;
+            Ok(response) as io::Result<String>
+        })
+        .unwrap()
+    };
+
+    let acceptor = TlsAcceptor::from(server_config());
+    let response = redirect(acceptor.clone());
+    assert!(response.starts_with("HTTP/1.1 301 "), "{:?}", response);
+    assert!(response.contains("\r\nLocation: https://127.0.0.1:"));
+    assert!(response.contains("/a?b\r\n"));
+
+    // the ClientHello is read before the handshake with an admission callback
+    let response = redirect(acceptor.admit(|_| Admission::Accept));
+    assert!(response.starts_with("HTTP/1.1 301 "), "{:?}", response);
+}
+
+#[test]
+fn tell_clients_not_speaking_tls() {
+    let (_, domain, chain) = start_server();
+    let tls13 = ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_cert_resolver(server_config().cert_resolver);
+    let accept = |acceptor: TlsAcceptor, hello: &'static [u8]| {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept(stream).await.map(drop)
+            });
+            let mut stream = TcpStream::connect(addr).await?;
+            if hello.is_empty() {
+                let mut root_store = RootCertStore::empty();
+                root_store.add_parsable_certificates(chain);
+                let connector = TlsConnector::from(
+                    ClientConfig::builder()
+                        .with_safe_default_cipher_suites()
+                        .with_safe_default_kx_groups()
+                        .with_protocol_versions(&[&version::TLS12])
+                        .unwrap()
+                        .with_root_certificates(root_store)
+                        .with_no_client_auth(),
+                );
+                connector.connect(*domain, stream).await.unwrap_err();
+            } else {
+                stream.write_all(hello).await?;
+            }
+            Ok(Error::from(server.await.unwrap_err())) as io::Result<Error>
+        })
+        .unwrap()
+    };
+
+    for acceptor in [
+        TlsAcceptor::from(tls13.clone()),
+        TlsAcceptor::from(tls13).admit(|_| Admission::Accept),
+    ] {
+        match accept(acceptor.clone(), b"SSH-2.0-OpenSSH_9.6\r\n") {
+            Error::NotTls(header) => assert_eq!(header.0, *b"SSH-2"),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        let sslv2 = &[0x80, 0x2e, 1, 0, 2, 0, 0x15, 0, 0, 0, 0x10];
+        match accept(acceptor.clone(), sslv2) {
+            Error::Sslv2Hello(header) => assert_eq!(header.to_string(), "80 2e 01 00 02"),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        match accept(acceptor, b"") {
+            Error::UnsupportedVersion(header, version) => {
+                assert_eq!(header.content_type(), 0x16);
+                assert_eq!(version, ProtocolVersion::TLSv1_2);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
+
+#[test]
+fn bound_tls_versions() {
+    let (_, _, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let tls12 = || TlsAcceptor::from(server_config_with_versions(&[&version::TLS12]));
+    let refused = |result: io::Result<()>| match Error::from(result.unwrap_err()) {
+        Error::AlertReceived(AlertDescription::ProtocolVersion) => (),
+        err => panic!("unexpected error: {:?}", err),
+    };
+
+    let tls12_only = connector.clone().max_tls_version(&version::TLS12).unwrap();
+    let (client, server) = handshake(tls12(), tls12_only.clone());
+    client.unwrap();
+    server.unwrap();
+
+    // the connector offers TLS 1.2 only, so the acceptor's bound is met
+    let acceptor = TlsAcceptor::from(server_config()).max_tls_version(&version::TLS12);
+    let (client, server) = handshake(acceptor, tls12_only);
+    client.unwrap();
+    server.unwrap();
+
+    let (client, _) = handshake(tls12(), connector.clone().tls13_only().unwrap());
+    refused(client);
+
+    let acceptor = TlsAcceptor::from(server_config()).max_tls_version(&version::TLS12);
+    let (client, server) = handshake(acceptor, connector.clone());
+    refused(client);
+    match Error::from(server.unwrap_err()) {
+        Error::UnsupportedVersion(header, ProtocolVersion::TLSv1_3) => {
+            assert_eq!(header.content_type(), 0x16)
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let acceptor = TlsAcceptor::from(server_config()).tls13_only();
+    let (client, server) = handshake(acceptor, connector);
+    client.unwrap();
+    server.unwrap();
+
+    // the roots of a config built elsewhere are unknown
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let err = TlsConnector::from(config).tls13_only().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn restrict_cipher_suites() {
+    let (_, _, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+
+    let invalid = |result: io::Result<TlsConnector>| result.err().map(|err| err.kind());
+    assert_eq!(
+        invalid(connector.clone().cipher_suites(&[])),
+        Some(io::ErrorKind::InvalidInput)
+    );
+    assert_eq!(
+        invalid(
+            connector
+                .clone()
+                .cipher_suite_names(&["TLS_RSA_WITH_RC4_128_MD5"])
+        ),
+        Some(io::ErrorKind::InvalidInput)
+    );
+    assert_eq!(
+        invalid(connector.clone().tls13_only().and_then(|connector| {
+            connector.cipher_suites(&[cipher_suite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384])
+        })),
+        Some(io::ErrorKind::InvalidInput)
+    );
+
+    let acceptor = TlsAcceptor::from(server_config())
+        .cipher_suites(&[cipher_suite::TLS13_AES_256_GCM_SHA384])
+        .unwrap();
+    let (client, server) = handshake(acceptor, connector.clone());
+    client.unwrap();
+    server.unwrap();
+
+    let chacha = || {
+        TlsAcceptor::from(server_config())
+            .cipher_suite_names(&["tls13_chacha20_poly1305_sha256"])
+            .unwrap()
+    };
+    let (client, server) = handshake(chacha(), connector.clone());
+    match Error::from(client.unwrap_err()) {
+        Error::AlertReceived(AlertDescription::HandshakeFailure) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+    match Error::from(server.unwrap_err()) {
+        Error::HandshakeFailed(rustls::Error::PeerIncompatible(
+            PeerIncompatible::NoCipherSuitesInCommon,
+        )) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    // the connector offers ChaCha20 only, so the acceptor negotiates it
+    let restricted = connector
+        .cipher_suite_names(&["tls13_chacha20_poly1305_sha256"])
+        .unwrap();
+    let (client, server) = handshake(chacha(), restricted);
+    client.unwrap();
+    server.unwrap();
+}
+
+#[test]
+fn follow_security_presets() {
+    let (_, _, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store.clone());
+    let tls12 = || TlsAcceptor::from(server_config_with_versions(&[&version::TLS12]));
+
+    let modern = connector
+        .clone()
+        .security_preset(SecurityPreset::MozillaModern)
+        .unwrap();
+    let (client, _) = handshake(tls12(), modern);
+    match Error::from(client.unwrap_err()) {
+        Error::AlertReceived(AlertDescription::ProtocolVersion) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let intermediate = connector
+        .security_preset(SecurityPreset::MozillaIntermediate)
+        .unwrap();
+    let (client, server) = handshake(tls12(), intermediate);
+    client.unwrap();
+    server.unwrap();
+
+    let config = SecurityPreset::MozillaModern
+        .configure(ClientConfig::builder())
+        .with_root_certificates(root_store.clone())
+        .with_no_client_auth();
+    let acceptor =
+        TlsAcceptor::from(server_config()).security_preset(SecurityPreset::MozillaModern);
+    let (client, server) = handshake(acceptor, TlsConnector::from(config.clone()));
+    client.unwrap();
+    server.unwrap();
+    let err = TlsConnector::from(config)
+        .security_preset(SecurityPreset::MozillaModern)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&version::TLS12])
+        .unwrap()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    let acceptor =
+        TlsAcceptor::from(server_config()).security_preset(SecurityPreset::MozillaModern);
+    let (_, server) = handshake(acceptor, TlsConnector::from(config));
+    match Error::from(server.unwrap_err()) {
+        Error::UnsupportedVersion(_, ProtocolVersion::TLSv1_2) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn fail_on_hangup() {
+    let connector = TlsConnector::new();
+    let err = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move { listener.accept().await.map(drop) });
+        let stream = TcpStream::connect(addr).await?;
+        server.await?;
+        Ok(connector.connect("example.com", stream).await.unwrap_err()) as io::Result<io::Error>
+    })
+    .unwrap();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    let context = err.get_ref().unwrap().downcast_ref::<HandshakeError>();
+    assert_eq!(context.unwrap().stage, HandshakeStage::Hello);
+    assert!(matches!(Error::from(err), Error::PeerClosed));
+}
+
+#[test]
+fn fail_on_truncation() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+
+    // each end sends a message and closes the connection without close_notify,
+    // the client first and the server once it read until the client's EOF
+    let read = |acceptor: TlsAcceptor, connector: TlsConnector| {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                stream.write_all(b"hello").await?;
+                stream.flush().await?;
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.map(|_| buf)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect(*domain, stream).await?;
+            stream.write_all(b"bye").await?;
+            stream.flush().await?;
+            stream.get_ref().shutdown(Shutdown::Write)?;
+            let mut buf = Vec::new();
+            let client = stream.read_to_end(&mut buf).await.map(|_| buf);
+            Ok((client, server.await)) as io::Result<_>
+        })
+        .unwrap()
+    };
+    let truncated = |result: io::Result<Vec<u8>>| {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        matches!(Error::from(err), Error::Truncated)
+    };
+
+    let acceptor = TlsAcceptor::from(server_config());
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let (client, server) = read(acceptor.clone(), connector.clone());
+    assert!(truncated(client));
+    assert!(truncated(server));
+
+    let acceptor = acceptor.strict_close_notify(false);
+    let connector = connector.strict_close_notify(false);
+    let (client, server) = read(acceptor, connector);
+    assert_eq!(client.unwrap(), b"hello");
+    assert_eq!(server.unwrap(), b"bye");
+}
+
+#[test]
+fn read_buffered_after_error() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let acceptor = TlsAcceptor::from(server_config());
+    let connector = TlsConnector::with_root_certificates(root_store);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (accepted, wait_accepted) = bounded(1);
+        let (sent, wait_sent) = bounded(1);
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            accepted.send(()).await.unwrap();
+            wait_sent.recv().await.unwrap();
+
+            // the message arrives with a forged record, which fails the read
+            let mut buf = [0; 5];
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(stream.read_buffered(&mut buf[..2]), 2);
+            assert_eq!(stream.read_buffered(&mut buf[2..]), 3);
+            assert_eq!(&buf, b"hello");
+            assert_eq!(stream.read_buffered(&mut buf), 0);
+            Ok(()) as io::Result<()>
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        // so the forged record is not held back until "hello" is acknowledged
+        stream.set_nodelay(true)?;
+        let mut stream = connector.connect(*domain, stream).await?;
+        wait_accepted.recv().await.unwrap();
+        stream.write_all(b"hello").await?;
+        stream.flush().await?;
+        let mut forged = vec![0x17, 0x03, 0x03, 0x00, 0x20];
+        forged.resize(5 + 0x20, 0);
+        stream.get_mut().write_all(&forged).await?;
+        sent.send(()).await.unwrap();
+        server.await
+    })
+    .unwrap();
+}
+
+#[test]
+fn close_without_close_notify() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let close = |connector: TlsConnector| {
+        let acceptor = acceptor.clone();
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.map(|_| buf)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect(*domain, stream).await?;
+            stream.write_all(b"bye").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            // a closed stream does not shut the connection down by itself
+            stream.get_ref().shutdown(Shutdown::Write)?;
+            Ok(server.await) as io::Result<io::Result<Vec<u8>>>
+        })
+        .unwrap()
+    };
+
+    let connector = TlsConnector::with_root_certificates(root_store);
+    assert_eq!(close(connector.clone()).unwrap(), b"bye");
+
+    // the server cannot tell whether it got all the data
+    let err = close(connector.send_close_notify(false)).unwrap_err();
+    assert!(matches!(Error::from(err), Error::Truncated));
+}
+
+#[test]
+fn await_peer_close_notify() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let close = |mode: CloseMode, answer: bool| {
+        let connector = connector.clone().close_mode(mode);
+        let acceptor = acceptor.clone();
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let (closed_tx, closed_rx) = bounded(1);
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                stream.read_to_end(&mut Vec::new()).await?;
+                if !answer {
+                    return closed_rx.recv().await.map_err(io::Error::other);
+                }
+                // still sent after the client closed, and discarded
+                stream.write_all(b"late").await?;
+                task::sleep(Duration::from_millis(50)).await;
+                futures_util::io::AsyncWriteExt::close(&mut stream).await
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect(*domain, stream).await?;
+            let started = std::time::Instant::now();
+            let closed = futures_util::io::AsyncWriteExt::close(&mut stream).await;
+            let waited = started.elapsed();
+            closed_tx.send(()).await.ok();
+            server.await?;
+            Ok(closed.map(|()| waited)) as io::Result<io::Result<Duration>>
+        })
+        .unwrap()
+    };
+
+    close(CloseMode::Send, false).unwrap();
+    // the server answers after a while
+    let waited = close(CloseMode::AwaitPeer, true).unwrap();
+    assert!(waited >= Duration::from_millis(50), "{:?}", waited);
+    #[cfg(feature = "async-std")]
+    {
+        let timeout = Duration::from_millis(20);
+        let err = close(CloseMode::AwaitPeerFor(timeout), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn close_with_timeout() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let close = |answer: bool| {
+        let (connector, acceptor) = (connector.clone(), acceptor.clone());
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let (closed_tx, closed_rx) = bounded(1);
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                stream.read_to_end(&mut Vec::new()).await?;
+                if answer {
+                    futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+                }
+                closed_rx.recv().await.map_err(io::Error::other)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect(*domain, stream).await?;
+            let clean = stream.close_with_timeout(Duration::from_millis(50)).await?;
+            closed_tx.send(()).await.ok();
+            server.await?;
+            Ok(clean) as io::Result<bool>
+        })
+        .unwrap()
+    };
+
+    assert!(close(true));
+    // the server never answers
+    assert!(!close(false));
+}
+
+#[test]
+fn step_down_to_plaintext() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let (tls, plain) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut tls = [0; 6];
+            stream.read_exact(&mut tls).await?;
+            let mut stream = stream.shutdown().await?;
+            let mut plain = Vec::new();
+            stream.read_to_end(&mut plain).await?;
+            Ok((tls, plain)) as io::Result<([u8; 6], Vec<u8>)>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(*domain, stream).await?;
+        stream.write_all(b"secret").await?;
+        let stream = stream.shutdown().await?;
+        (&stream).write_all(b"in the clear").await?;
+        stream.shutdown(Shutdown::Write)?;
+        server.await
+    })
+    .unwrap();
+
+    assert_eq!(&tls, b"secret");
+    assert_eq!(plain, b"in the clear");
+}
+
+/// A connection holding writes back until flushed, counting the flushes.
+struct Buffered(BufWriter<TcpStream>, Arc<AtomicUsize>);
+
+impl futures_util::io::AsyncRead for Buffered {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl futures_util::io::AsyncWrite for Buffered {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+#[test]
+fn flush_handshakes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    // the handshake would wait forever for records never flushed
+    let flushes = |flush: HandshakeFlush| {
+        let (connector, acceptor) = (connector.clone(), acceptor.clone());
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let server_flushes = flushes.clone();
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let stream = Buffered(BufWriter::new(stream), server_flushes);
+                acceptor.handshake_flush(flush).accept(stream).await
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let stream = Buffered(BufWriter::new(stream), Arc::default());
+            let client = connector.handshake_flush(flush).connect(*domain, stream);
+            // the server still sends session tickets once the client is done
+            let _client = client.await?;
+            server.await.map(drop)
+        })
+        .unwrap();
+        flushes.load(Ordering::SeqCst)
+    };
+
+    assert!(flushes(HandshakeFlush::Batched) < flushes(HandshakeFlush::Eager));
+}
+
+/// Connect once, returning how the client and the server set the connection up.
+fn handshake_kinds(
+    acceptor: &TlsAcceptor,
+    connector: &TlsConnector,
+    domain: &str,
+) -> (Option<HandshakeKind>, Option<HandshakeKind>) {
+    let acceptor = acceptor.clone();
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.handshake_kind()) as io::Result<Option<HandshakeKind>>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        // TLS 1.3 tickets arrive after the handshake
+        stream.read_to_end(&mut Vec::new()).await?;
+        Ok((stream.handshake_kind(), server.await?)) as io::Result<_>
+    })
+    .unwrap()
+}
+
+#[test]
+fn report_resumption() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+
+    for versions in [&[&version::TLS13], &[&version::TLS12]] {
+        let acceptor = TlsAcceptor::from(server_config_with_versions(versions));
+        let connector = TlsConnector::with_root_certificates(root_store.clone());
+
+        let full = Some(HandshakeKind::Full);
+        let kinds = handshake_kinds(&acceptor, &connector, domain);
+        assert_eq!(kinds, (full, full), "{:?}", versions);
+        let resumed = Some(HandshakeKind::Resumed);
+        let kinds = handshake_kinds(&acceptor, &connector, domain);
+        assert_eq!(kinds, (resumed, resumed), "{:?}", versions);
+    }
+}
+
+#[test]
+fn report_key_exchange_group() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let (cert, key) = Identity::from_pem(CERT.as_bytes(), RSA.as_bytes())
+        .unwrap()
+        .into_parts();
+
+    for versions in [&[&version::TLS13], &[&version::TLS12]] {
+        let config = ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_kx_groups(&[&kx_group::SECP256R1])
+            .with_protocol_versions(versions)
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(cert.clone(), key.clone())
+            .unwrap();
+        let acceptor = TlsAcceptor::from(config);
+        let connector = TlsConnector::with_root_certificates(root_store.clone());
+
+        let groups = task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let stream = acceptor.accept(stream).await?;
+                Ok(stream.key_exchange_group()) as io::Result<Option<NamedGroup>>
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let stream = connector.connect(domain, stream).await?;
+            Ok((stream.key_exchange_group(), server.await?)) as io::Result<_>
+        })
+        .unwrap();
+        let group = Some(NamedGroup::secp256r1);
+        assert_eq!(groups, (group, group), "{:?}", versions);
+    }
+}
+
+#[test]
+fn share_session_store() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let store = Arc::new(ClientSessionMemoryCache::new(32));
+    let connector =
+        || TlsConnector::with_root_certificates(root_store.clone()).session_store(store.clone());
+    let (client, _) = handshake_kinds(&acceptor, &connector(), domain);
+    assert_eq!(client, Some(HandshakeKind::Full));
+    let (client, _) = handshake_kinds(&acceptor, &connector(), domain);
+    assert_eq!(client, Some(HandshakeKind::Resumed));
+
+    // connectors do not share sessions by default
+    let connector = TlsConnector::with_root_certificates(root_store.clone());
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, Some(HandshakeKind::Full));
+
+    // enabling resumption again keeps the store
+    let connector = TlsConnector::with_root_certificates(root_store)
+        .session_store(store)
+        .session_resumption(false);
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, Some(HandshakeKind::Full));
+    let connector = connector.session_resumption(true).session_resumption(true);
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, Some(HandshakeKind::Resumed));
+}
+
+#[test]
+fn rebuilt_configs_keep_settings() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let store = Arc::new(ClientSessionMemoryCache::new(32));
+    let connector = TlsConnector::with_root_certificates(root_store)
+        .session_store(store)
+        .max_fragment_size(1024)
+        .unwrap();
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, Some(HandshakeKind::Full));
+
+    // each of these builds the config anew, the store is still used
+    let connector = (connector.min_tls_version(&version::TLS12))
+        .and_then(|connector| connector.cipher_suite_names(&["TLS13_AES_256_GCM_SHA384"]))
+        .and_then(|connector| connector.add_root_certificate(&Certificate(chain[0].clone())))
+        .unwrap();
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, Some(HandshakeKind::Resumed));
+}
+
+#[test]
+fn disable_session_resumption() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let full = Some(HandshakeKind::Full);
+    let resumed = Some(HandshakeKind::Resumed);
+
+    let acceptor = TlsAcceptor::from(server_config());
+    let no_resumption = connector.clone().session_resumption(false);
+    handshake_kinds(&acceptor, &no_resumption, domain);
+    let (client, _) = handshake_kinds(&acceptor, &no_resumption, domain);
+    assert_eq!(client, full);
+
+    let acceptor = TlsAcceptor::from(server_config()).session_tickets(false);
+    handshake_kinds(&acceptor, &connector, domain);
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, full);
+
+    // TLS 1.2 sessions are resumed by id instead
+    let acceptor = TlsAcceptor::from(server_config_with_versions(&[&version::TLS12]));
+    let acceptor = acceptor.session_tickets(false);
+    handshake_kinds(&acceptor, &connector, domain);
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, resumed);
+
+    let acceptor = TlsAcceptor::from(server_config())
+        .session_tickets(false)
+        .session_tickets(true);
+    handshake_kinds(&acceptor, &connector, domain);
+    let (client, _) = handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(client, resumed);
+}
+
+/// Resume a session with 0-RTT, returning whether the server took the early data.
+fn send_early_data(acceptor: &TlsAcceptor, connector: &TlsConnector, domain: &str) -> Option<bool> {
+    let acceptor = acceptor.clone();
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.handshake_kind()) as io::Result<Option<HandshakeKind>>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let (mut stream, early_data) = connector.connect_0rtt(domain, stream).await?;
+        assert_eq!(early_data.early_data_accepted(), None);
+        stream.write_all(b"early").await?;
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+        assert_eq!(buf, b"early");
+
+        let accepted = early_data.handshake_complete().await;
+        assert_eq!(accepted, early_data.early_data_accepted());
+        let kind = stream.handshake_kind();
+        assert_eq!(kind, server.await?);
+        assert_eq!(
+            kind == Some(HandshakeKind::ResumedWithEarlyData),
+            accepted == Some(true)
+        );
+        Ok(accepted) as io::Result<_>
+    })
+    .unwrap()
+}
+
+#[test]
+fn connect_with_early_data() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let mut server = server_config();
+    server.max_early_data_size = 1024;
+    let acceptor = TlsAcceptor::from(server);
+    // no session to resume yet
+    assert_eq!(send_early_data(&acceptor, &connector, domain), Some(false));
+    assert_eq!(send_early_data(&acceptor, &connector, domain), Some(true));
+
+    // the data is sent again once the handshake is done
+    let acceptor = TlsAcceptor::from(server_config());
+    handshake_kinds(&acceptor, &connector, domain);
+    assert_eq!(send_early_data(&acceptor, &connector, domain), Some(false));
+}
+
+#[test]
+fn redact_debug_output() {
+    const SECRET: &[u8] = b"hunter2 hunter2";
+    let leaks = |debug: String| debug.contains("hunter2") || debug.contains("104, 117, 110");
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(config));
+    let mut server = server_config();
+    server.max_early_data_size = 1024;
+    let acceptor = TlsAcceptor::from(server);
+    send_early_data(&acceptor, &connector, domain);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut buf = [0; SECRET.len()];
+            stream.read_exact(&mut buf).await?;
+            assert!(!leaks(format!("{:?}", stream)));
+            stream.write_all(&buf).await?;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let (mut stream, _) = connector.connect_0rtt(domain, stream).await?;
+        stream.write_all(SECRET).await?;
+        let debug = format!("{:?}", stream);
+        assert!(debug.contains("early_data_buffered: 15"), "{}", debug);
+        assert!(!leaks(debug));
+        let mut buf = [0; SECRET.len()];
+        stream.read_exact(&mut buf).await?;
+        assert!(!leaks(format!("{:?}", stream)));
+        server.await
+    })
+    .unwrap();
+}
+
+#[test]
+fn debug_public_types() {
+    let mut config = server_config();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let debug = format!("{:?}", (TlsConnector::new(), TlsAcceptor::from(config)));
+    assert!(debug.contains("TlsConnector { roots: Some("), "{}", debug);
+    assert!(debug.contains(r#"alpn_protocols: ["h2"]"#), "{}", debug);
+
+    let (addr, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    task::block_on(async {
+        let stream = TcpStream::connect(addr).await?;
+        let connect = connector.connect(*domain, stream);
+        assert_eq!(format!("{:?}", connect), r#"Connect("Handshaking")"#);
+        let stream = connect.await?;
+        let debug = format!("{:?}", stream);
+        assert!(debug.contains("handshaking: false"), "{}", debug);
+        assert!(debug.contains("TLSv1_3"), "{}", debug);
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn bound_early_data_buffer() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.enable_early_data = true;
+    let mut server = server_config();
+    server.max_early_data_size = 1024;
+    let acceptor = TlsAcceptor::from(server);
+
+    // the rest of the data waits for the handshake
+    let connector = TlsConnector::from(Arc::new(config));
+    let connector = connector.early_data_buffer(3, EarlyDataOverflow::Wait);
+    send_early_data(&acceptor, &connector, domain);
+    assert_eq!(send_early_data(&acceptor, &connector, domain), Some(true));
+
+    let connector = connector.early_data_buffer(3, EarlyDataOverflow::Fail);
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let acceptor = acceptor.clone();
+        task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let (mut stream, _) = connector.connect_0rtt(domain, stream).await?;
+        assert_eq!(stream.write_early_data(b"early")?, 3);
+        assert!(stream.write_early_data(b"ly").is_err());
+        assert!(stream.write(b"ly").await.is_err());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn reject_early_data() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let mut server = server_config();
+    server.max_early_data_size = 1024;
+    let accepting = TlsAcceptor::from(server.clone());
+    // the same sessions, without early data
+    server.max_early_data_size = 0;
+    let rejecting = TlsAcceptor::from(server);
+
+    send_early_data(&accepting, &connector, domain);
+    assert_eq!(send_early_data(&rejecting, &connector, domain), Some(false));
+
+    let connector = connector.rejected_early_data(RejectedEarlyData::Fail);
+    send_early_data(&accepting, &connector, domain);
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = rejecting.accept(stream).await?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(b"ok").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(buf) as io::Result<[u8; 4]>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let (mut stream, early_data) = connector.connect_0rtt(domain, stream).await?;
+        stream.write_all(b"early").await?;
+        let mut buf = Vec::new();
+        let err = stream.read_to_end(&mut buf).await.unwrap_err();
+        assert!(matches!(Error::from(err), Error::EarlyDataRejected));
+        assert_eq!(early_data.early_data_accepted(), Some(false));
+
+        // the application decides what to send
+        stream.write_all(b"late").await?;
+        stream.read_to_end(&mut buf).await?;
+        assert_eq!(buf, b"ok");
+        assert_eq!(&server.await?, b"late");
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+/// Records what it observes.
+#[derive(Default)]
+struct Events(Mutex<Vec<String>>);
+
+impl Events {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl Observer for Events {
+    fn on_handshake_start(&self) {
+        self.0.lock().unwrap().push("start".into());
+    }
+
+    fn on_handshake_complete(&self, _: Duration, params: &HandshakeParams) {
+        let event = format!("{:?} {:?}", params.kind, params.protocol_version);
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn on_handshake_error(&self, error: &io::Error) {
+        self.0.lock().unwrap().push(format!("error {}", error));
+    }
+
+    fn on_close(&self, bytes_in: u64, bytes_out: u64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("close {} {}", bytes_in, bytes_out));
+    }
+}
+
+#[test]
+fn observe_connections() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let (client, server) = (Arc::new(Events::default()), Arc::new(Events::default()));
+    let connector = TlsConnector::with_root_certificates(root_store).observer(client.clone());
+    let acceptor = TlsAcceptor::from(server_config()).observer(server.clone());
+
+    handshake_kinds(&acceptor, &connector, domain);
+    let complete = "Some(Full) Some(TLSv1_3)".to_string();
+    assert_eq!(client.take(), ["start", &complete, "close 2 0"]);
+    assert_eq!(server.take(), ["start", &complete, "close 0 2"]);
+
+    let connector = TlsConnector::with_root_certificates(RootCertStore::empty());
+    let connector = connector.observer(client.clone());
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let acceptor = acceptor.clone();
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            acceptor.accept(stream).await.map(drop)
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let err = connector.connect(domain, stream).await.unwrap_err();
+        assert!(matches!(Error::from(err), Error::Certificate(_)));
+        assert!(server.await.is_err());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+    // failed connections are not reported as closed
+    for events in [client.take(), server.take()] {
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(events[1].starts_with("error"), "{:?}", events);
+    }
+}
+
+#[test]
+fn report_handshake_timings() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+    let delay = Duration::from_millis(100);
+
+    let (client, server) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // a slow network, or a busy server
+            task::sleep(delay).await;
+            let stream = acceptor.accept(stream).await?;
+            Ok(stream.handshake_timings().unwrap()) as io::Result<HandshakeTimings>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let stream = connector.connect(domain, stream).await?;
+        let client = stream.handshake_timings().unwrap();
+        Ok((client, server.await?)) as io::Result<(HandshakeTimings, HandshakeTimings)>
+    })
+    .unwrap();
+
+    assert!(client.first_flight.unwrap() < delay, "{:?}", client);
+    assert!(client.waiting >= delay, "{:?}", client);
+    assert_eq!(client.busy() + client.waiting, client.total);
+    assert!(server.first_flight.unwrap() <= server.total, "{:?}", server);
+    assert!(server.waiting < delay, "{:?}", server);
+}
+
+#[test]
+fn report_buffered_bytes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.write_all(b"hello").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            assert_eq!(stream.tls_bytes_to_write(), 0);
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        let mut buf = [0; 1];
+        stream.read_exact(&mut buf).await?;
+        // the record came in whole
+        assert_eq!(stream.plaintext_bytes_to_read(), 4);
+        let state = stream.io_state().unwrap();
+        assert_eq!(state.plaintext_bytes_to_read(), 4);
+        stream.read_to_end(&mut Vec::new()).await?;
+        assert_eq!(stream.plaintext_bytes_to_read(), 0);
+        assert!(stream.peer_has_closed());
+        server.await
+    })
+    .unwrap();
+}
+
+#[test]
+fn access_io_mid_handshake() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, peer) = listener.accept().await?;
+            let mut accept = acceptor.accept(stream);
+            assert_eq!(accept.get_ref().unwrap().peer_addr()?, peer);
+            accept.get_mut().unwrap().set_nodelay(true)?;
+            assert!(accept.get_ref().unwrap().nodelay()?);
+            accept.await.map(drop)
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let connect = connector.connect(*domain, stream);
+        assert_eq!(connect.get_ref().unwrap().peer_addr()?, addr);
+        connect.await?;
+        server.await?;
+
+        let stream = futures_util::io::Cursor::new(Vec::new());
+        assert!(connector
+            .connect("not a domain", stream)
+            .get_ref()
+            .is_none());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn abort_handshakes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    let config = Arc::new(config);
+    let connector = TlsConnector::from(config.clone());
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        // nothing is encrypted once the client sent its hello
+        let mut connect = connector.connect(*domain, TcpStream::connect(addr).await?);
+        assert!(futures_util::poll!(&mut connect).is_pending());
+        drop(connect.abort(true).await.unwrap());
+        let (mut stream, _) = listener.accept().await?;
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await?;
+        assert!(
+            received.ends_with(&[0x15, 3, 3, 0, 2, 1, 90]),
+            "{:?}",
+            received
+        );
+
+        // the server agreed on a cipher suite once it read the hello
+        let server_name = ServerName::try_from(*domain).unwrap();
+        let mut client = rustls::ClientConnection::new(config, server_name).unwrap();
+        let mut hello = Vec::new();
+        client.write_tls(&mut hello)?;
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(&hello).await?;
+        let (io, _) = listener.accept().await?;
+        let mut accept = acceptor.accept(io);
+        assert!(futures_util::poll!(&mut accept).is_pending());
+        drop(accept.abort(true).await.unwrap());
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await?;
+        let mut received = &received[..];
+        while !received.is_empty() {
+            client.read_tls(&mut received)?;
+        }
+        let state = client.process_new_packets().unwrap();
+        assert!(state.peer_has_closed());
+        Ok(()) as io::Result<()>
+    })
+    .unwrap();
+}
+
+#[test]
+fn poll_handshakes_by_hand() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut accept = acceptor.accept(stream);
+            assert_eq!(accept.stage(), Some(HandshakeStage::Hello));
+            (&mut accept).await?;
+            assert!(accept.is_terminated());
+            assert_eq!(accept.stage(), None);
+            Ok(()) as io::Result<()>
+        });
+        let mut connect = connector.connect(*domain, TcpStream::connect(addr).await?);
+        assert_eq!(connect.stage(), Some(HandshakeStage::Hello));
+        assert!(!connect.is_terminated());
+        (&mut connect).await?;
+        assert!(connect.is_terminated());
+        assert_eq!(connect.stage(), None);
+        server.await
+    })
+    .unwrap();
+
+    let stream = futures_util::io::Cursor::new(Vec::new());
+    let mut connect = TlsConnector::new().connect("not a domain", stream);
+    assert_eq!(connect.stage(), None);
+    assert!(task::block_on(&mut connect).is_err());
+    assert!(connect.is_terminated());
+}
+
+#[test]
+fn drive_mid_handshakes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let accept = acceptor.accept(stream);
+            let mut handshake = accept.into_mid_handshake().unwrap();
+            let mut stream = future::poll_fn(|cx| handshake.poll(cx)).await?;
+            assert!(handshake.is_terminated());
+            assert!(handshake.into_inner().is_none());
+            stream.write_all(b"hello").await?;
+            stream.flush().await
+        });
+        let connect = connector.connect(*domain, TcpStream::connect(addr).await?);
+        let mut handshake = connect.into_mid_handshake()?;
+        assert_eq!(handshake.stage(), Some(HandshakeStage::Hello));
+        assert!(handshake.get_ref().is_some());
+        let mut stream = future::poll_fn(|cx| handshake.poll(cx)).await?;
+        assert_eq!(handshake.stage(), None);
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello");
+        server.await
+    })
+    .unwrap();
+
+    // giving up hands the IO back as is
+    let stream = futures_util::io::Cursor::new(Vec::new());
+    let connect = TlsConnector::new().connect("example.com", stream);
+    let handshake = connect.into_mid_handshake().unwrap();
+    assert!(handshake.into_inner().unwrap().get_ref().is_empty());
+    let stream = futures_util::io::Cursor::new(Vec::new());
+    let connect = TlsConnector::new().connect("not a domain", stream);
+    assert!(connect.into_mid_handshake().is_err());
+}
+
+#[test]
+fn complete_io_by_hand() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            future::poll_fn(|cx| stream.poll_handshake(cx)).await?;
+            stream.write_all(b"hello").await?;
+            stream.flush().await
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(*domain, stream).await?;
+        future::poll_fn(|cx| stream.poll_handshake(cx)).await?;
+        // records come in without reading them, the session tickets first
+        while stream.plaintext_bytes_to_read() == 0 {
+            let (received, _) = future::poll_fn(|cx| stream.poll_complete_io(cx)).await?;
+            assert!(received > 0);
+        }
+        assert_eq!(stream.plaintext_bytes_to_read(), 5);
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello");
+        server.await
+    })
+    .unwrap();
+}
+
+#[test]
+fn peek_plaintext() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let (peeked, read) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut peeked = [0; 4];
+            let len = stream.peek(&mut peeked).await?;
+            // peeking again sees the same bytes
+            assert_eq!(stream.peek(&mut [0; 2]).await?, 2);
+            assert!(stream.plaintext_bytes_to_read() >= len);
+            let mut read = Vec::new();
+            stream.read_to_end(&mut read).await?;
+            assert_eq!(stream.peek(&mut [0; 2]).await?, 0);
+            Ok((peeked[..len].to_vec(), read)) as io::Result<(Vec<u8>, Vec<u8>)>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await?;
+        futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+        server.await
+    })
+    .unwrap();
+
+    assert_eq!(peeked, b"GET ");
+    assert_eq!(read, b"GET / HTTP/1.1\r\n\r\n");
+}
+
+#[test]
+fn count_traffic() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let (client, server) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            stream.read_exact(&mut [0; 3]).await?;
+            stream.write_all(b"hi").await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.counters()) as io::Result<TrafficCounters>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(b"hey").await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+        let client = stream.counters();
+        Ok((client, server.await?)) as io::Result<(TrafficCounters, TrafficCounters)>
+    })
+    .unwrap();
+
+    assert_eq!((client.bytes_read, client.bytes_written), (2, 3));
+    assert_eq!((server.bytes_read, server.bytes_written), (3, 2));
+    // both ends see the same records
+    assert_eq!(client.records_sent, server.records_received);
+    assert_eq!(client.records_received, server.records_sent);
+    assert_eq!(client.tls_bytes_sent, server.tls_bytes_received);
+    assert_eq!(client.tls_bytes_received, server.tls_bytes_sent);
+    // the handshake, the data, and the close_notify alert
+    assert!(server.records_sent > 3, "{:?}", server);
+    assert!(client.tls_bytes_sent > client.bytes_written);
+}
+
+#[test]
+fn resolve_client_certs() {
+    // two CAs, and a client identity issued by each
+    let (cas, identities): (Vec<_>, Vec<_>) = (0..2)
+        .map(|i| {
+            let mut params = rcgen::CertificateParams::new(Vec::<String>::new());
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            (params.distinguished_name).push(rcgen::DnType::CommonName, format!("CA {}", i));
+            let ca = rcgen::Certificate::from_params(params).unwrap();
+            let client = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+                "client".to_string(),
+            ]))
+            .unwrap();
+            let chain = vec![Certificate(client.serialize_der_with_signer(&ca).unwrap())];
+            let key = PrivateKey(client.serialize_private_key_der());
+            let ca = Certificate(ca.serialize_der().unwrap());
+            (ca, Identity::new(chain, key))
+        })
+        .unzip();
+
+    let (_, _, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let keys: Vec<_> = (identities.iter())
+        .map(|identity| identity.certified_key().unwrap())
+        .collect();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let connector =
+        TlsConnector::with_root_certificates(root_store).client_cert_resolver(move |request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(request.acceptable_issuers().len(), 1);
+            assert!(!request.signature_schemes().is_empty());
+            keys.iter().find(|key| request.accepts(&key.cert)).cloned()
+        });
+
+    for ca in &cas {
+        let mut roots = RootCertStore::empty();
+        roots.add(ca).unwrap();
+        let (cert, key) = Identity::from_pem(CERT.as_bytes(), RSA.as_bytes())
+            .unwrap()
+            .into_parts();
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            .with_single_cert(cert, key)
+            .unwrap();
+        let (client, server) = handshake(TlsAcceptor::from(config), connector.clone());
+        client.unwrap();
+        server.unwrap();
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn record_certificate_requests() {
+    let mut params = rcgen::CertificateParams::new(Vec::<String>::new());
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    (params.distinguished_name).push(rcgen::DnType::CommonName, "Smartcard CA");
+    let ca = rcgen::Certificate::from_params(params).unwrap();
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(&Certificate(ca.serialize_der().unwrap()))
+        .unwrap();
+    let (cert, key) = Identity::from_pem(CERT.as_bytes(), RSA.as_bytes())
+        .unwrap()
+        .into_parts();
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+        .with_single_cert(cert, key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(config);
+
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+
+    for record in [false, true] {
+        let acceptor = acceptor.clone();
+        // a fresh session cache, resumed sessions skip client authentication
+        let connector = TlsConnector::with_root_certificates(root_store.clone())
+            .record_certificate_requests(record);
+        let issuers = task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept(stream).await.map(drop)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let stream = connector.connect(*domain, stream).await?;
+            server.await?;
+            let issuers = stream.acceptable_issuers().map(<[_]>::to_vec);
+            Ok(issuers) as io::Result<Option<Vec<Vec<u8>>>>
+        });
+        let issuers = issuers.unwrap();
+        match record {
+            false => assert_eq!(issuers, None),
+            true => {
+                // no certificate was sent, the CA is known nevertheless
+                let issuers = issuers.unwrap();
+                assert_eq!(issuers.len(), 1);
+                let name = b"Smartcard CA";
+                assert!(issuers[0].windows(name.len()).any(|w| w == name));
+            }
+        }
+    }
+}
+
+#[test]
+fn warn_before_certificates_expire() {
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+    let issue = |name: &str, not_after: SystemTime| {
+        let mut params = rcgen::CertificateParams::new(vec![name.to_string()]);
+        params.not_after = not_after.into();
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        let chain = vec![Certificate(cert.serialize_der().unwrap())];
+        let key = PrivateKey(cert.serialize_private_key_der());
+        Identity::new(chain, key).certified_key().unwrap()
+    };
+    let store = CertStore::new();
+    let soon = issue("soon.localhost", SystemTime::now() + 5 * DAY);
+    store.insert("soon.localhost", CertifiedKey::clone(&soon));
+    let expired = issue("expired.localhost", SystemTime::now() - DAY);
+    store.insert("expired.localhost", CertifiedKey::clone(&expired));
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let monitor = Arc::new(ExpiryMonitor::new({
+        let warnings = warnings.clone();
+        move |warning| warnings.lock().unwrap().push(warning.clone())
+    }));
+    let acceptor = TlsAcceptor::from(store).expiry_monitor(monitor.clone());
+
+    let connect = |name: &'static str| {
+        let acceptor = acceptor.clone();
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                acceptor.accept(stream).await.map(drop)
+            });
+            let stream = TcpStream::connect(addr).await?;
+            // the certificates are not trusted, they are served nevertheless
+            let _ = TlsConnector::new().connect(name, stream).await;
+            let _ = server.await;
+            Ok(()) as io::Result<()>
+        })
+        .unwrap();
+        std::mem::take(&mut *warnings.lock().unwrap())
+    };
+
+    let warned = connect("soon.localhost");
+    assert_eq!(warned.len(), 1);
+    assert_eq!(warned[0].certificate, soon.cert[0]);
+    assert_eq!(warned[0].server_name.as_deref(), Some("soon.localhost"));
+    assert_eq!(warned[0].threshold, Some(7 * DAY));
+    assert!(warned[0].remaining <= 5 * DAY && warned[0].remaining > 4 * DAY);
+    // each threshold is warned about once
+    assert!(connect("soon.localhost").is_empty());
+
+    let warned = connect("expired.localhost");
+    assert_eq!(warned.len(), 1);
+    assert_eq!(warned[0].certificate, expired.cert[0]);
+    assert!(warned[0].is_expired());
+    monitor.check();
+    assert!(warnings.lock().unwrap().is_empty());
+}
+
+#[test]
+fn bind_channels() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+
+    let bindings = |config: ServerConfig| {
+        let acceptor = TlsAcceptor::from(config);
+        let connector = connector.clone();
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let stream = acceptor.accept(stream).await?;
+                stream.tls_exporter_channel_binding()
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let stream = connector.connect(domain, stream).await?;
+            let client = stream.tls_exporter_channel_binding();
+            Ok((client, server.await)) as io::Result<(io::Result<[u8; 32]>, io::Result<[u8; 32]>)>
+        })
+        .unwrap()
+    };
+
+    let (client, server) = bindings(server_config());
+    let client = client.unwrap();
+    assert_eq!(client, server.unwrap());
+    let (other, _) = bindings(server_config());
+    assert_ne!(client, other.unwrap());
+
+    let (client, server) = bindings(server_config_with_versions(&[&version::TLS12]));
+    assert_eq!(client.unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(server.unwrap_err().kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn coalesce_writes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store)
+        .coalesce_writes(1024, Duration::from_secs(3600));
+    let acceptor =
+        TlsAcceptor::from(server_config()).coalesce_writes(100, Duration::from_secs(3600));
+
+    let (client, server, echoed) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut buf = [0; 1000];
+            stream.read_exact(&mut buf).await?;
+            for chunk in buf.chunks(10) {
+                stream.write_all(chunk).await?;
+            }
+            // what is kept back goes before close_notify
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.counters()) as io::Result<TrafficCounters>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        for i in 0..100u8 {
+            stream.write_all(&[i; 10]).await?;
+        }
+        stream.flush().await?;
+        let mut echoed = Vec::new();
+        stream.read_to_end(&mut echoed).await?;
+        let client = stream.counters();
+        Ok((client, server.await?, echoed))
+            as io::Result<(TrafficCounters, TrafficCounters, Vec<u8>)>
+    })
+    .unwrap();
+
+    let sent: Vec<u8> = (0..100u8).flat_map(|i| [i; 10]).collect();
+    assert_eq!(echoed, sent);
+    assert_eq!(client.bytes_written, 1000);
+    // all data in one record, and ten of at most 100 bytes
+    assert!(client.records_sent < 10, "{:?}", client);
+    assert!(server.records_sent < 30, "{:?}", server);
+}
+
+#[test]
+fn chunk_writes() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+
+    let written = |chunking: WriteChunking| {
+        let connector = connector.clone().write_chunking(chunking);
+        let acceptor = acceptor.clone().write_chunking(chunking);
+        task::block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let server = task::spawn(async move {
+                let (stream, _) = listener.accept().await?;
+                let mut stream = acceptor.accept(stream).await?;
+                let mut buf = [0; 1000];
+                stream.read_exact(&mut buf).await?;
+                stream.write(&buf).await
+            });
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = connector.connect(domain, stream).await?;
+            stream.write_all(&[1; 1000]).await?;
+            let written = stream.write(&[1; 1000]).await?;
+            Ok((written, server.await?)) as io::Result<(usize, usize)>
+        })
+        .unwrap()
+    };
+
+    assert_eq!(written(WriteChunking::Whole), (1000, 1000));
+    assert_eq!(written(WriteChunking::Chunk(100)), (100, 100));
+    assert_eq!(written(WriteChunking::Chunk(0)), (1, 1));
+}
+
+#[test]
+fn limit_record_size() {
+    let (_, domain, chain) = start_server();
+    let mut root_store = RootCertStore::empty();
+    root_store.add_parsable_certificates(chain);
+    let connector = TlsConnector::with_root_certificates(root_store);
+    let acceptor = TlsAcceptor::from(server_config());
+    for size in [31, 16390] {
+        let err = connector.clone().max_fragment_size(size).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = acceptor.clone().max_fragment_size(size).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    let connector = connector.max_fragment_size(64).unwrap();
+    let acceptor = acceptor.max_fragment_size(128).unwrap();
+
+    let (client, server) = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut buf = [0; 1000];
+            stream.read_exact(&mut buf).await?;
+            stream.write_all(&buf).await?;
+            futures_util::io::AsyncWriteExt::close(&mut stream).await?;
+            Ok(stream.counters()) as io::Result<TrafficCounters>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(&[1; 1000]).await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+        let client = stream.counters();
+        Ok((client, server.await?)) as io::Result<(TrafficCounters, TrafficCounters)>
+    })
+    .unwrap();
+
+    // at most 59 and 123 bytes of data per record
+    assert!(client.records_sent > 1000 / 59, "{:?}", client);
+    assert!(server.records_sent > 1000 / 123, "{:?}", server);
 }
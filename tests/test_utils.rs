@@ -0,0 +1,159 @@
+use async_tls::test_utils::{self, duplex, Chaos, DuplexStream, TestCa};
+use async_tls::{client, server};
+use futures_executor::block_on;
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use std::io;
+
+#[test]
+fn duplex_roundtrip_and_eof() -> io::Result<()> {
+    block_on(async {
+        let (mut a, mut b) = duplex(4);
+        a.write_all(b"abc").await?;
+        let mut buf = [0; 3];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"abc");
+
+        a.close().await?;
+        assert_eq!(b.read(&mut buf).await?, 0);
+
+        drop(b);
+        let err = a.write_all(b"more").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        Ok(())
+    })
+}
+
+#[test]
+fn paired_streams_exchange_data() -> io::Result<()> {
+    block_on(async {
+        let (connector, acceptor) = test_utils::pair()?;
+        let (mut client, mut server) =
+            test_utils::connect(&connector, &acceptor, "localhost").await?;
+
+        // larger than the duplex buffer, so both directions need backpressure
+        let data = vec![7u8; 256 * 1024];
+        let write = async {
+            client.write_all(&data).await?;
+            client.flush().await
+        };
+        let mut received = vec![0; data.len()];
+        let read = server.read_exact(&mut received);
+        let (written, read) = futures_util::future::join(write, read).await;
+        written?;
+        read?;
+        assert_eq!(received, data);
+        Ok(())
+    })
+}
+
+#[test]
+fn paired_streams_track_buffered_bytes() -> io::Result<()> {
+    block_on(async {
+        let (connector, acceptor) = test_utils::pair()?;
+        let (mut client, mut server) =
+            test_utils::connect(&connector, &acceptor, "localhost").await?;
+
+        // the duplex buffer takes part of the records, the rest waits
+        let data = vec![7u8; 256 * 1024];
+        let len = client.write(&data).await?;
+        assert!(client.tls_bytes_to_write() > 0);
+
+        let mut received = vec![0; len];
+        let read = server.read_exact(&mut received);
+        let (flushed, read) = futures_util::future::join(client.flush(), read).await;
+        flushed?;
+        read?;
+        assert_eq!(client.tls_bytes_to_write(), 0);
+
+        client.write_all(b"hello").await?;
+        client.flush().await?;
+        let mut buf = [0; 1];
+        server.read_exact(&mut buf).await?;
+        assert_eq!(server.plaintext_bytes_to_read(), 4);
+        assert_eq!(server.read_buffered(&mut buf), 1);
+        assert_eq!(server.plaintext_bytes_to_read(), 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_ca_is_rejected() -> io::Result<()> {
+    let connector = TestCa::new()?.connector()?;
+    let acceptor = TestCa::new()?.acceptor(&["localhost"])?;
+    let result = block_on(test_utils::connect(&connector, &acceptor, "localhost"));
+    assert!(result.is_err());
+    Ok(())
+}
+
+/// Run a handshake over `client` and `server`, then send `data` both ways.
+async fn exchange(
+    client: Chaos<DuplexStream>,
+    server: Chaos<DuplexStream>,
+    data: &[u8],
+) -> io::Result<()> {
+    let (connector, acceptor) = test_utils::pair()?;
+    let (client, server) = futures_util::future::join(
+        connector.connect("localhost", client),
+        acceptor.accept(server),
+    )
+    .await;
+    let (mut client, mut server): (client::TlsStream<_>, server::TlsStream<_>) = (client?, server?);
+
+    let echo = async move {
+        let mut received = vec![0; data.len()];
+        server.read_exact(&mut received).await?;
+        server.write_all(&received).await?;
+        server.flush().await
+    };
+    let send = async move {
+        client.write_all(data).await?;
+        client.flush().await?;
+        let mut received = vec![0; data.len()];
+        client.read_exact(&mut received).await?;
+        assert_eq!(received, data);
+        Ok(())
+    };
+    // each side drops its stream when it fails, failing the other one too
+    let (echoed, sent) = futures_util::future::join(echo, send).await;
+    echoed.and(sent)
+}
+
+#[test]
+fn survive_chaotic_io() -> io::Result<()> {
+    let data = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+    // stalling the write of the client's Finished once completed the
+    // handshake without sending it, hanging the server
+    for seed in 0..8 {
+        let (client, server) = duplex(4096);
+        let client = Chaos::new(client, seed)
+            .short_reads()
+            .short_writes()
+            .pending(3);
+        let server = Chaos::new(server, !seed)
+            .short_reads()
+            .short_writes()
+            .pending(5);
+        block_on(exchange(client, server, &data))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn fail_on_eof_and_corruption() {
+    // the ServerHello is cut short
+    let (client, server) = duplex(4096);
+    let client = Chaos::new(client, 1).eof_after(50);
+    let err = block_on(exchange(client, Chaos::new(server, 2), b"x")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    // a byte of the ClientHello is flipped
+    let (client, server) = duplex(4096);
+    let server = Chaos::new(server, 3).short_reads().corrupt_at(60);
+    block_on(exchange(Chaos::new(client, 4), server, b"x")).unwrap_err();
+
+    // a byte of application data is flipped
+    let (client, server) = duplex(4096);
+    let server = Chaos::new(server, 5).corrupt_at(3000);
+    let err = block_on(exchange(Chaos::new(client, 6), server, &[0; 4000])).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,91 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::{TlsAcceptor, TlsAlpn01Responder, TlsConnector, ACME_TLS_ALPN_NAME};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::io::{self, BufReader, Cursor};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+const CERT: &str = include_str!("end.cert");
+const CHAIN: &str = include_str!("end.chain");
+const RSA: &str = include_str!("end.rsa");
+
+fn normal_config() -> ServerConfig {
+    let cert = certs(&mut BufReader::new(Cursor::new(CERT))).unwrap();
+    let cert = cert.into_iter().map(Certificate).collect();
+    let mut keys = pkcs8_private_keys(&mut BufReader::new(Cursor::new(RSA))).unwrap();
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert, PrivateKey(keys.pop().unwrap()))
+        .unwrap()
+}
+
+fn challenge_cert() -> (CertifiedKey, Certificate) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let key = PrivateKey(cert.serialize_private_key_der());
+    let key = rustls::sign::any_ecdsa_type(&key).unwrap();
+    (CertifiedKey::new(vec![der.clone()], key), der)
+}
+
+async fn serve(acceptor: TlsAcceptor) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            task::spawn(async move { acceptor.accept(stream).await });
+        }
+    });
+    addr
+}
+
+async fn handshake(addr: SocketAddr, roots: RootCertStore, alpn: Option<&[u8]>) -> io::Result<()> {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.into_iter().map(<[u8]>::to_vec).collect();
+    let connector = TlsConnector::from(config);
+
+    let stream = TcpStream::connect(addr).await?;
+    connector.connect("localhost", stream).await.map(drop)
+}
+
+#[test]
+fn serves_challenge_only_to_acme_clients() {
+    let (challenge, challenge_der) = challenge_cert();
+    let responder = Arc::new(TlsAlpn01Responder::new());
+    responder.insert("localhost", challenge);
+
+    let mut normal_roots = RootCertStore::empty();
+    let chain = certs(&mut BufReader::new(Cursor::new(CHAIN))).unwrap();
+    normal_roots.add_parsable_certificates(&chain);
+    let mut challenge_roots = RootCertStore::empty();
+    challenge_roots.add(&challenge_der).unwrap();
+
+    task::block_on(async {
+        let acceptor = TlsAcceptor::from(normal_config()).tls_alpn_01(responder.clone());
+        let addr = serve(acceptor).await;
+
+        handshake(addr, normal_roots.clone(), None).await.unwrap();
+        handshake(addr, normal_roots.clone(), Some(b"http/1.1"))
+            .await
+            .unwrap();
+        handshake(addr, challenge_roots.clone(), Some(ACME_TLS_ALPN_NAME))
+            .await
+            .unwrap();
+        assert!(handshake(addr, challenge_roots.clone(), None)
+            .await
+            .is_err());
+
+        responder.remove("localhost");
+        assert!(handshake(addr, challenge_roots, Some(ACME_TLS_ALPN_NAME))
+            .await
+            .is_err());
+    });
+}
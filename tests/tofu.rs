@@ -0,0 +1,139 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::tofu::{
+    FileStore, Fingerprint, FingerprintMismatch, FingerprintStore, MemoryStore, TofuVerifier,
+};
+use async_tls::{Error, HandshakeError, HandshakeStage, TlsAcceptor, TlsConnector};
+use rcgen::{CertificateParams, KeyPair};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("async-tls-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A self-signed certificate for `key`, with `name` to tell certificates of the same key apart.
+fn self_signed(key: &KeyPair, name: &str) -> (Certificate, PrivateKey) {
+    let mut params = CertificateParams::new(vec![name.to_string()]);
+    params.key_pair = Some(KeyPair::from_der(&key.serialize_der()).unwrap());
+    let cert = rcgen::Certificate::from_params(params).unwrap();
+    (
+        Certificate(cert.serialize_der().unwrap()),
+        PrivateKey(cert.serialize_private_key_der()),
+    )
+}
+
+/// Serve `cert` and connect to it as `host`.
+fn handshake(
+    cert: &(Certificate, PrivateKey),
+    host: &str,
+    verifier: TofuVerifier,
+) -> io::Result<()> {
+    let acceptor = TlsAcceptor::from(
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.0.clone()], cert.1.clone())
+            .unwrap(),
+    );
+    let connector = TlsConnector::new().tofu(verifier);
+
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // the client may reject the certificate, which is what is tested
+            let _ = acceptor.accept(stream).await;
+            Ok(()) as io::Result<()>
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let client = connector.connect(host, stream).await;
+        server.await?;
+        client.map(drop)
+    })
+}
+
+fn mismatch(err: &io::Error) -> Option<&FingerprintMismatch> {
+    let context = err.get_ref()?.downcast_ref::<HandshakeError>()?;
+    assert_eq!(context.stage, HandshakeStage::Verifying);
+    match context.error {
+        Error::Io(ref err) => err.get_ref()?.downcast_ref(),
+        _ => None,
+    }
+}
+
+#[test]
+fn pins_keys_on_first_use() -> io::Result<()> {
+    let store = Arc::new(MemoryStore::new());
+    let verifier = TofuVerifier::new(store.clone());
+    let key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let cert = self_signed(&key, "nas.local");
+
+    handshake(&cert, "nas.local", verifier.clone())?;
+    let pinned = task::block_on(store.get("nas.local"))?;
+    assert_eq!(pinned, Some(Fingerprint::of_public_key(&cert.0)?));
+    handshake(&cert, "nas.local", verifier.clone())?;
+
+    // a renewed certificate with the same key still matches
+    handshake(&self_signed(&key, "renewed"), "nas.local", verifier.clone())?;
+
+    let other_key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let other = self_signed(&other_key, "nas.local");
+    let err = handshake(&other, "nas.local", verifier.clone()).unwrap_err();
+    let mismatch = mismatch(&err).expect("not a mismatch");
+    assert_eq!(mismatch.host, "nas.local");
+    assert_eq!(Some(mismatch.expected), pinned);
+    assert_eq!(mismatch.found, Fingerprint::of_public_key(&other.0)?);
+
+    // hosts are pinned separately
+    handshake(&other, "printer.local", verifier)?;
+    Ok(())
+}
+
+#[test]
+fn pins_certificates() -> io::Result<()> {
+    let verifier = TofuVerifier::new(MemoryStore::new()).pin_certificate(true);
+    let key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+
+    handshake(
+        &self_signed(&key, "nas.local"),
+        "nas.local",
+        verifier.clone(),
+    )?;
+    let err = handshake(&self_signed(&key, "renewed"), "nas.local", verifier).unwrap_err();
+    assert!(mismatch(&err).is_some(), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn persists_fingerprints() -> io::Result<()> {
+    let path = temp_dir("tofu").join("known_hosts");
+    let key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let cert = self_signed(&key, "nas.local");
+
+    handshake(&cert, "nas.local", TofuVerifier::new(FileStore::new(&path)))?;
+    let contents = fs::read_to_string(&path)?;
+    assert_eq!(
+        contents,
+        format!("nas.local {}\n", Fingerprint::of_public_key(&cert.0)?)
+    );
+
+    // a new store reads what the first one wrote
+    let store = FileStore::new(&path);
+    let other_key = KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let other = self_signed(&other_key, "nas.local");
+    let err = handshake(&other, "nas.local", TofuVerifier::new(store)).unwrap_err();
+    assert!(mismatch(&err).is_some(), "{}", err);
+    handshake(&cert, "nas.local", TofuVerifier::new(FileStore::new(&path)))?;
+
+    fs::write(&path, "nas.local not-a-fingerprint\n")?;
+    let err = handshake(&cert, "nas.local", TofuVerifier::new(FileStore::new(&path))).unwrap_err();
+    assert!(mismatch(&err).is_none());
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tls::test_utils;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Stands in for a tokio IO object such as `tokio::net::TcpStream`.
+struct TokioStream(TcpStream);
+
+impl AsyncRead for TokioStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = futures_core::ready!(futures_io::AsyncRead::poll_read(
+            Pin::new(&mut self.0),
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for TokioStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures_io::AsyncWrite::poll_write(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_io::AsyncWrite::poll_close(Pin::new(&mut self.0), cx)
+    }
+}
+
+/// Echo what is read until end of file, with nothing but the traits of tokio.
+async fn echo<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> io::Result<()> {
+    let mut buf = [0; 64];
+    loop {
+        let mut read = ReadBuf::new(&mut buf);
+        poll_fn(|cx| Pin::new(&mut stream).poll_read(cx, &mut read)).await?;
+        let mut data = read.filled();
+        if data.is_empty() {
+            break;
+        }
+        while !data.is_empty() {
+            let n = poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, data)).await?;
+            data = &data[n..];
+        }
+    }
+    poll_fn(|cx| Pin::new(&mut stream).poll_shutdown(cx)).await
+}
+
+/// Write `data`, shut down writing, and read the peer's response to the end.
+async fn request<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    mut data: &[u8],
+) -> io::Result<Vec<u8>> {
+    while !data.is_empty() {
+        let n = poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, data)).await?;
+        data = &data[n..];
+    }
+    poll_fn(|cx| Pin::new(&mut stream).poll_shutdown(cx)).await?;
+
+    let mut response = Vec::new();
+    let mut buf = [0; 64];
+    loop {
+        let mut read = ReadBuf::new(&mut buf);
+        poll_fn(|cx| Pin::new(&mut stream).poll_read(cx, &mut read)).await?;
+        if read.filled().is_empty() {
+            return Ok(response);
+        }
+        response.extend_from_slice(read.filled());
+    }
+}
+
+#[test]
+fn use_streams_with_tokio() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    let echoed = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            echo(acceptor.accept(stream).await?).await
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let stream = connector.connect("localhost", stream).await?;
+        let echoed = request(stream, b"hello over tokio traits").await?;
+        server.await?;
+        Ok(echoed) as io::Result<Vec<u8>>
+    })
+    .unwrap();
+    assert_eq!(echoed, b"hello over tokio traits");
+}
+
+#[test]
+fn handshake_over_tokio_io() {
+    let (connector, acceptor) = test_utils::pair().unwrap();
+    let echoed = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            echo(acceptor.accept_tokio(TokioStream(stream)).await?).await
+        });
+        let stream = TokioStream(TcpStream::connect(addr).await?);
+        let stream = connector.connect_tokio("localhost", stream).await?;
+        assert!(stream.get_ref().get_ref().0.peer_addr().is_ok());
+        let echoed = request(stream, b"hello from tokio").await?;
+        server.await?;
+        Ok(echoed) as io::Result<Vec<u8>>
+    })
+    .unwrap();
+    assert_eq!(echoed, b"hello from tokio");
+}
@@ -0,0 +1,68 @@
+#![cfg(unix)]
+
+use async_std::task;
+use async_tls::test_utils::{connect, TestCa};
+use async_tls::{CertStore, TlsAcceptor, TpmKey, TpmKeyType};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Stands in for `tpm2_sign`: checks the arguments and signs with openssl.
+const FAKE_TPM2_SIGN: &str = r#"#!/bin/sh
+[ "$TPM2TOOLS_TCTI" = "mssim:port=2321" ] || exit 1
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -c) [ "$2" = "0x81000001" ] || { echo "no such handle" >&2; exit 1; }; shift 2 ;;
+        -p) [ "$2" = "file:/dev/null" ] || exit 1; shift 2 ;;
+        -s) [ "$2" = "ecdsa" ] || exit 1; shift 2 ;;
+        -f) [ "$2" = "plain" ] || exit 1; shift 2 ;;
+        -g) hash="$2"; shift 2 ;;
+        -o) out="$2"; shift 2 ;;
+        *) message="$1"; shift ;;
+    esac
+done
+exec openssl dgst -"$hash" -keyform DER -sign "$(dirname "$0")/key.der" -out "$out" "$message"
+"#;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("async-tls-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn tpm_acceptor(ca: &TestCa, dir: &Path, handle: &str) -> TlsAcceptor {
+    let (chain, key) = ca.issue(&["localhost"]).unwrap();
+    fs::write(dir.join("key.der"), key.0).unwrap();
+
+    let key = TpmKey::new(handle, TpmKeyType::EcdsaP256)
+        .program(dir.join("tpm2_sign"))
+        .auth("file:/dev/null")
+        .tcti("mssim:port=2321");
+    let store = CertStore::new();
+    store.insert("localhost", key.certified(chain));
+    TlsAcceptor::from(store)
+}
+
+#[test]
+fn signs_handshakes_in_the_tpm() {
+    let dir = temp_dir("tpm");
+    let program = dir.join("tpm2_sign");
+    fs::write(&program, FAKE_TPM2_SIGN).unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let ca = TestCa::new().unwrap();
+    let connector = ca.connector().unwrap();
+    task::block_on(async {
+        let acceptor = tpm_acceptor(&ca, &dir, "0x81000001");
+        connect(&connector, &acceptor, "localhost").await.unwrap();
+
+        let acceptor = tpm_acceptor(&ca, &dir, "0x81000002");
+        let err = connect(&connector, &acceptor, "localhost")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no such handle"), "{}", err);
+    });
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,48 @@
+use async_std::net::TcpListener;
+use async_std::task;
+use async_tls::test_utils::TestCa;
+use async_tls::ws;
+use async_tungstenite::tungstenite::error::{Error, UrlError};
+use async_tungstenite::tungstenite::Message;
+use futures_util::StreamExt;
+
+#[test]
+fn echo_over_wss() {
+    let ca = TestCa::new().unwrap();
+    let acceptor = ca.acceptor(&["localhost"]).unwrap();
+    let connector = ca.connector().unwrap();
+    let reply = task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let stream = acceptor.accept(stream).await?;
+            let mut socket = async_tungstenite::accept_async(stream).await?;
+            while let Some(message) = socket.next().await {
+                socket.send(message?).await?;
+            }
+            Ok(()) as Result<(), Error>
+        });
+
+        let url = format!("wss://localhost:{}/echo", port);
+        let (mut socket, response) = ws::connect_async(&connector, url).await?;
+        assert_eq!(response.status(), 101);
+        socket.send(Message::text("hello")).await?;
+        let reply = socket.next().await.unwrap()?;
+        socket.close(None).await?;
+        Ok(reply) as Result<Message, Error>
+    })
+    .unwrap();
+    assert_eq!(reply, Message::text("hello"));
+}
+
+#[test]
+fn connect_to_wss_urls_only() {
+    let connector = TestCa::new().unwrap().connector().unwrap();
+    let err = task::block_on(ws::connect_async(&connector, "ws://localhost/")).unwrap_err();
+    assert!(
+        matches!(err, Error::Url(UrlError::UnsupportedUrlScheme)),
+        "{}",
+        err
+    );
+}